@@ -1,17 +1,20 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use myterm::config::Config;
-use myterm::terminal::{Grid, Cell, CellFlags, TerminalPerformer};
+use myterm::font::FontSet;
+use myterm::render::{argb_pixel, draw_grid, fill, Frame, GlyphCache, GridLayout, RetainedCanvas, BYTES_PER_PIXEL, GLYPH_CACHE_BYTES};
+use myterm::terminal::{Grid, Cell, CellFlags, TerminalPerformer, UnderlineStyle};
+use std::time::{Duration, Instant};
 use vte::Parser;
 
 fn benchmark_grid_operations(c: &mut Criterion) {
     let mut group = c.benchmark_group("grid");
     
     group.bench_function("create_grid", |b| {
-        b.iter(|| Grid::new(black_box(24), black_box(80), black_box(1000)))
+        b.iter(|| Grid::new(black_box(24), black_box(80), black_box(Some(1000))))
     });
     
     group.bench_function("resize_grid", |b| {
-        let mut grid = Grid::new(24, 80, 1000);
+        let mut grid = Grid::new(24, 80, Some(1000));
         b.iter(|| {
             grid.resize(black_box(30), black_box(120));
             grid.resize(black_box(24), black_box(80));
@@ -19,7 +22,7 @@ fn benchmark_grid_operations(c: &mut Criterion) {
     });
     
     group.bench_function("scroll_up", |b| {
-        let mut grid = Grid::new(24, 80, 1000);
+        let mut grid = Grid::new(24, 80, Some(1000));
         // Fill grid with some data
         for row in 0..24 {
             for col in 0..80 {
@@ -33,7 +36,7 @@ fn benchmark_grid_operations(c: &mut Criterion) {
     });
     
     group.bench_function("clear_grid", |b| {
-        let mut grid = Grid::new(24, 80, 1000);
+        let mut grid = Grid::new(24, 80, Some(1000));
         // Fill grid with some data
         for row in 0..24 {
             for col in 0..80 {
@@ -83,6 +86,46 @@ fn benchmark_vte_parsing(c: &mut Criterion) {
     group.finish();
 }
 
+fn benchmark_alt_screen(c: &mut Criterion) {
+    let mut group = c.benchmark_group("alt_screen");
+    
+    // A 200x60 primary screen with history, which every switch must leave alone
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(60, 200, &config);
+    let mut parser = Parser::new();
+    let text = "The quick brown fox jumps over the lazy dog. ".repeat(600);
+    for &byte in text.as_bytes() {
+        parser.advance(&mut performer, byte);
+    }
+    
+    // Entering and leaving, the cost an editor pays to start and quit
+    group.bench_function("switch", |b| {
+        b.iter(|| {
+            for &byte in black_box(b"\x1b[?1049h\x1b[?1049l") {
+                parser.advance(&mut performer, byte);
+            }
+        })
+    });
+    
+    // An editor session: enter, draw a screenful, scroll it, clear and leave
+    let mut session = b"\x1b[?1049h\x1b[H".to_vec();
+    for row in 0..60 {
+        session.extend_from_slice(format!("\x1b[{}H\x1b[34m~\x1b[0m {}", row + 1, "let x = 1; ".repeat(18)).as_bytes());
+    }
+    session.extend_from_slice(b"\x1b[60H");
+    session.extend_from_slice(&b"\nmore text\r".repeat(10));
+    session.extend_from_slice(b"\x1b[2J\x1b[?1049l");
+    group.bench_function("vim_session", |b| {
+        b.iter(|| {
+            for &byte in black_box(&session) {
+                parser.advance(&mut performer, byte);
+            }
+        })
+    });
+    
+    group.finish();
+}
+
 fn benchmark_cell_operations(c: &mut Criterion) {
     let mut group = c.benchmark_group("cell");
     
@@ -92,6 +135,8 @@ fn benchmark_cell_operations(c: &mut Criterion) {
             fg: black_box(rgb::RGB8::new(255, 255, 255)),
             bg: black_box(rgb::RGB8::new(0, 0, 0)),
             flags: black_box(CellFlags::BOLD),
+            underline: black_box(UnderlineStyle::Single),
+            underline_color: black_box(None),
         })
     });
     
@@ -101,6 +146,8 @@ fn benchmark_cell_operations(c: &mut Criterion) {
             fg: rgb::RGB8::new(255, 255, 255),
             bg: rgb::RGB8::new(0, 0, 0),
             flags: CellFlags::BOLD,
+            underline: UnderlineStyle::Single,
+            underline_color: None,
         };
         
         b.iter(|| black_box(&cell).clone())
@@ -120,10 +167,233 @@ fn benchmark_cell_operations(c: &mut Criterion) {
     group.finish();
 }
 
+fn benchmark_rendering(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    
+    // A full 80x24 screen of repeated text
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(24, 80, &config);
+    let mut parser = Parser::new();
+    let text = "The quick brown fox jumps over the lazy dog. ".repeat(43);
+    for &byte in text.as_bytes() {
+        parser.advance(&mut performer, byte);
+    }
+    let rows = performer.styled_snapshot();
+    let palette = &performer.palette;
+    
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let metrics = glyphs.metrics();
+    let layout = GridLayout { origin: (0, 0), cell_width: metrics.cell_width as usize, cell_height: metrics.cell_height as usize };
+    let stride = 80 * layout.cell_width * BYTES_PER_PIXEL;
+    let mut canvas = vec![0u8; stride * 24 * layout.cell_height];
+    let background = argb_pixel(palette.background, 1.0);
+    
+    // Every glyph comes from the cache, leaving only the blitting
+    group.bench_function("full_screen_cached", |b| {
+        b.iter(|| {
+            fill(&mut canvas, background);
+            draw_grid(&mut canvas, stride, &layout, black_box(&rows), palette, None, &mut glyphs);
+        })
+    });
+    
+    // Every distinct glyph is rasterized again each frame
+    group.bench_function("full_screen_uncached", |b| {
+        b.iter(|| {
+            glyphs.clear();
+            fill(&mut canvas, background);
+            draw_grid(&mut canvas, stride, &layout, black_box(&rows), palette, None, &mut glyphs);
+        })
+    });
+    
+    group.finish();
+}
+
+fn benchmark_repaint(c: &mut Criterion) {
+    let mut group = c.benchmark_group("repaint");
+    
+    // A full 200x60 screen, then the same screen with one more character typed
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(60, 200, &config);
+    let mut parser = Parser::new();
+    let text = "The quick brown fox jumps over the lazy dog. ".repeat(266);
+    for &byte in text.as_bytes() {
+        parser.advance(&mut performer, byte);
+    }
+    let before = performer.styled_snapshot();
+    parser.advance(&mut performer, b'x');
+    let after = performer.styled_snapshot();
+    
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let metrics = glyphs.metrics();
+    let layout = GridLayout { origin: (0, 0), cell_width: metrics.cell_width as usize, cell_height: metrics.cell_height as usize };
+    let frame = |rows: &Vec<Vec<Cell>>| Frame {
+        size: (200 * layout.cell_width, 60 * layout.cell_height),
+        layout,
+        background: argb_pixel(performer.palette.background, 1.0),
+        titlebar: None,
+        palette: performer.palette.clone(),
+        rows: rows.clone(),
+        cursor: None,
+        images: Vec::new(),
+        scrollbar: None,
+        flash: None,
+    };
+    let (before, after) = (frame(&before), frame(&after));
+    let mut canvas = RetainedCanvas::new();
+    
+    group.bench_function("single_char_full", |b| {
+        b.iter(|| {
+            canvas.invalidate();
+            canvas.draw(black_box(after.clone()), &mut glyphs)
+        })
+    });
+    
+    // Typing the character and taking it back again, drawing only the changed row and its
+    // neighbors each time
+    group.bench_function("single_char_damage", |b| {
+        b.iter(|| {
+            canvas.draw(before.clone(), &mut glyphs);
+            canvas.draw(black_box(after.clone()), &mut glyphs)
+        })
+    });
+    
+    group.finish();
+}
+
+fn benchmark_scroll(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scroll");
+    
+    // A full 200x60 screen scrolling a line at a time through numbered lines of text
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(60, 200, &config);
+    let mut parser = Parser::new();
+    let mut screens = Vec::new();
+    for line in 0..120 {
+        let text = format!("{:>5} The quick brown fox jumps over the lazy dog.\r\n", line);
+        for &byte in text.as_bytes() {
+            parser.advance(&mut performer, byte);
+        }
+        if line >= 60 {
+            screens.push(performer.styled_snapshot());
+        }
+    }
+    
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let metrics = glyphs.metrics();
+    let layout = GridLayout { origin: (0, 0), cell_width: metrics.cell_width as usize, cell_height: metrics.cell_height as usize };
+    let frames: Vec<Frame> = screens.into_iter().map(|rows| Frame {
+        size: (200 * layout.cell_width, 60 * layout.cell_height),
+        layout,
+        background: argb_pixel(performer.palette.background, 1.0),
+        titlebar: None,
+        palette: performer.palette.clone(),
+        rows,
+        cursor: None,
+        images: Vec::new(),
+        scrollbar: None,
+        flash: None,
+    }).collect();
+    
+    // Moving the scrolled rows and drawing the new one
+    let mut canvas = RetainedCanvas::new();
+    let mut next = frames.iter().cycle();
+    group.bench_function("software", |b| {
+        b.iter(|| canvas.draw(black_box(next.next().unwrap().clone()), &mut glyphs))
+    });
+    
+    // Drawing every frame in full, waiting for the GPU to finish each
+    #[cfg(feature = "gpu")]
+    {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        match runtime.block_on(myterm::gpu::GpuRenderer::headless()) {
+            Ok(mut gpu) => {
+                group.bench_function("gpu", |b| {
+                    b.iter(|| gpu.render_offscreen(black_box(next.next().unwrap()), &mut glyphs).unwrap())
+                });
+            }
+            Err(e) => eprintln!("Skipping GPU benchmark: {}", e),
+        }
+    }
+    
+    group.finish();
+}
+
+fn benchmark_cat(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cat");
+    group.sample_size(10);
+    
+    // A 1.3 MB file of numbered lines, read from the PTY 4 KiB at a time onto a 200x60 screen
+    let config = Config::default();
+    let file: Vec<u8> = (0..20_000)
+        .flat_map(|line| format!("{:>6} The quick brown fox jumps over the lazy dog.\r\n", line).into_bytes())
+        .collect();
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let metrics = glyphs.metrics();
+    let layout = GridLayout { origin: (0, 0), cell_width: metrics.cell_width as usize, cell_height: metrics.cell_height as usize };
+    let frame = |performer: &TerminalPerformer| Frame {
+        size: (200 * layout.cell_width, 60 * layout.cell_height),
+        layout,
+        background: argb_pixel(performer.palette.background, 1.0),
+        titlebar: None,
+        palette: performer.palette.clone(),
+        rows: performer.styled_snapshot(),
+        cursor: None,
+        images: Vec::new(),
+        scrollbar: None,
+        flash: None,
+    };
+    
+    // Drawing after every read, as before frame callbacks
+    group.bench_function("draw_per_read", |b| {
+        b.iter(|| {
+            let mut performer = TerminalPerformer::new(60, 200, &config);
+            let mut parser = Parser::new();
+            let mut canvas = RetainedCanvas::new();
+            for chunk in file.chunks(4096) {
+                for &byte in chunk {
+                    parser.advance(&mut performer, byte);
+                }
+                canvas.draw(frame(&performer), &mut glyphs);
+            }
+        })
+    });
+    
+    // Drawing what the reads left on screen once per 60 Hz frame callback, and at the end
+    group.bench_function("draw_per_frame", |b| {
+        b.iter(|| {
+            let mut performer = TerminalPerformer::new(60, 200, &config);
+            let mut parser = Parser::new();
+            let mut canvas = RetainedCanvas::new();
+            let mut next_frame = Instant::now();
+            for chunk in file.chunks(4096) {
+                for &byte in chunk {
+                    parser.advance(&mut performer, byte);
+                }
+                if Instant::now() >= next_frame {
+                    canvas.draw(frame(&performer), &mut glyphs);
+                    next_frame = Instant::now() + Duration::from_micros(16_667);
+                }
+            }
+            canvas.draw(frame(&performer), &mut glyphs);
+        })
+    });
+    
+    group.finish();
+}
+
 criterion_group!(
     benches,
     benchmark_grid_operations,
     benchmark_vte_parsing,
-    benchmark_cell_operations
+    benchmark_alt_screen,
+    benchmark_cell_operations,
+    benchmark_rendering,
+    benchmark_repaint,
+    benchmark_scroll,
+    benchmark_cat
 );
 criterion_main!(benches);
\ No newline at end of file
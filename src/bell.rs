@@ -0,0 +1,149 @@
+use anyhow::{Context, Result};
+use rgb::RGB8;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::config::{parse_color, BellConfig, BellMode};
+
+/// Minimum time between two runs of the bell command, so a stream of BELs
+/// (e.g. `yes $'\a'`) can't spawn processes in a tight loop.
+pub const BELL_COMMAND_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Allows an action at most once per interval.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+/// What the application should do in response to a BEL.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BellResponse {
+    /// How long to flash the window, if at all.
+    pub visual: Option<Duration>,
+    /// Ask the compositor to mark the window urgent.
+    pub urgent: bool,
+    /// Run the configured bell command.
+    pub run_command: bool,
+}
+
+/// The visual bell's flash over the window, from a BEL until its duration is up.
+///
+/// A bell only starts a new flash once the last one has been over for as long as it showed, so
+/// a stream of bells flashes the window steadily instead of strobing it.
+#[derive(Debug, Clone, Default)]
+pub struct Flash {
+    /// When the last flash started and how long it showed.
+    last: Option<(Instant, Duration)>,
+    showing: bool,
+}
+
+pub struct Bell {
+    mode: BellMode,
+    visual_duration: Duration,
+    visual_color: RGB8,
+    command: Vec<String>,
+    limiter: RateLimiter,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, last: None }
+    }
+    
+    /// Returns whether the action may run at `now`, recording it if so.
+    pub fn allow(&mut self, now: Instant) -> bool {
+        if let Some(last) = self.last {
+            if now.saturating_duration_since(last) < self.interval {
+                return false;
+            }
+        }
+        self.last = Some(now);
+        true
+    }
+}
+
+impl Flash {
+    /// Starts a flash of `duration` at `now` for a bell, returning whether one started rather
+    /// than the bell being merged into the last.
+    pub fn trigger(&mut self, now: Instant, duration: Duration) -> bool {
+        if let Some((start, shown)) = self.last {
+            if now.saturating_duration_since(start) < shown * 2 {
+                return false;
+            }
+        }
+        self.last = Some((now, duration));
+        self.showing = true;
+        true
+    }
+    
+    /// When the flash showing should be taken down.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.last.filter(|_| self.showing).map(|(start, shown)| start + shown)
+    }
+    
+    /// Takes the flash down if it's over at `now`, returning whether it was.
+    pub fn expire(&mut self, now: Instant) -> bool {
+        if self.deadline().is_some_and(|deadline| now >= deadline) {
+            self.showing = false;
+            return true;
+        }
+        false
+    }
+}
+
+impl Bell {
+    pub fn new(config: &BellConfig) -> Self {
+        let visual_color = parse_color(&config.visual_color).unwrap_or_else(|e| {
+            log::warn!("{}, flashing white", e);
+            RGB8::new(255, 255, 255)
+        });
+        Self {
+            mode: config.mode,
+            visual_duration: Duration::from_millis(config.visual_duration_ms),
+            visual_color,
+            command: config.command.clone(),
+            limiter: RateLimiter::new(BELL_COMMAND_INTERVAL),
+        }
+    }
+    
+    /// Decides how to react to a BEL received at `now`.
+    ///
+    /// The command runs regardless of `mode`; urgency is only requested while unfocused.
+    pub fn ring(&mut self, now: Instant, focused: bool) -> BellResponse {
+        let visual = matches!(self.mode, BellMode::Visual | BellMode::Both)
+            && !self.visual_duration.is_zero();
+        let urgent = matches!(self.mode, BellMode::Urgent | BellMode::Both) && !focused;
+        let run_command = !self.command.is_empty() && self.limiter.allow(now);
+        
+        BellResponse {
+            visual: visual.then_some(self.visual_duration),
+            urgent,
+            run_command,
+        }
+    }
+    
+    /// Color the window is tinted with while the visual bell flashes.
+    pub fn visual_color(&self) -> RGB8 {
+        self.visual_color
+    }
+    
+    /// Starts the bell command without waiting for it to finish.
+    pub fn spawn_command(&self) -> Result<()> {
+        let Some((program, args)) = self.command.split_first() else {
+            return Ok(());
+        };
+        
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to run bell command {:?}", program))?;
+        
+        // Reap the child in the background so it doesn't linger as a zombie
+        std::thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
\ No newline at end of file
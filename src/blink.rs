@@ -0,0 +1,40 @@
+use std::time::{Duration, Instant};
+
+/// Stops the cursor blinking once nothing was typed for `terminal.cursor_blink_timeout_ms`,
+/// so an idle window stops waking the compositor twice a second. The next key press starts
+/// it blinking again.
+#[derive(Debug, Clone)]
+pub struct BlinkTimeout {
+    /// `None` blinks for as long as the cursor asks to.
+    timeout: Option<Duration>,
+    last_input: Instant,
+}
+
+impl BlinkTimeout {
+    /// Counts from `now` as if a key was just pressed; a timeout of 0 never stops the blink.
+    pub fn new(timeout_ms: u64, now: Instant) -> Self {
+        let mut blink = Self { timeout: None, last_input: now };
+        blink.set_timeout(timeout_ms);
+        blink
+    }
+    
+    /// Changes the timeout, which then counts from the last key press as before.
+    pub fn set_timeout(&mut self, timeout_ms: u64) {
+        self.timeout = (timeout_ms > 0).then(|| Duration::from_millis(timeout_ms));
+    }
+    
+    /// A key was pressed at `now`.
+    pub fn input(&mut self, now: Instant) {
+        self.last_input = now;
+    }
+    
+    /// Whether the cursor may blink at `now`, or should stay solid.
+    pub fn blinking(&self, now: Instant) -> bool {
+        self.deadline().is_none_or(|deadline| now < deadline)
+    }
+    
+    /// When the cursor stops blinking if nothing is typed first.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| self.last_input + timeout)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,131 @@
+use anyhow::anyhow;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::error::{MyTermError, Result};
+use crate::headless::{self, DumpFormat};
+
+/// Set to `1` to run headless whenever `WAYLAND_DISPLAY` is unset.
+pub const HEADLESS_VAR: &str = "MYTERM_HEADLESS";
+
+pub const USAGE: &str = "\
+Usage: myterm [OPTIONS] [-e <COMMAND>...]
+
+Options:
+  -c, --config <PATH>     Load this config file instead of searching for one
+      --class <APP_ID>    Set the window's app_id, as display.app_id does
+      --log-pty <PATH>    Record the program's output in this file, as
+                          terminal.typescript does
+      --headless          Run without a window, writing standard input to the shell;
+                          also when MYTERM_HEADLESS=1 and there's no WAYLAND_DISPLAY
+      --size <COLS>x<ROWS>
+                          With --headless, size the terminal in cells
+      --script <PATH>     With --headless, take input from a script instead, - for
+                          standard input: lines of type <text>, key <key>, wait <ms>,
+                          resize <COLS>x<ROWS>, dump [text|styled] and close
+      --dump <FORMAT>     With --headless, print the screen as text or styled at exit
+      --screenshot <PATH> Run without a window and save the screen as a PNG once the
+                          command exits, then quit
+      --after-ms <MS>     With --screenshot, save the screen after this long instead
+  -e, --command <COMMAND>...
+                          Run this command and its arguments instead of the shell
+  -h, --help              Print this help and exit
+";
+
+/// Command-line options.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    /// Config file taking precedence over `MYTERM_CONFIG` and the default locations.
+    pub config: Option<PathBuf>,
+    /// Window app_id, overriding `display.app_id`.
+    pub class: Option<String>,
+    /// Typescript file, overriding `terminal.typescript`.
+    pub log_pty: Option<PathBuf>,
+    /// Drive the PTY and parser without connecting to a compositor.
+    pub headless: bool,
+    /// Headless terminal size in cells, columns first.
+    pub size: Option<(u16, u16)>,
+    /// Headless script, `-` for standard input.
+    pub script: Option<PathBuf>,
+    /// Print the screen like this when a headless run ends.
+    pub dump: Option<DumpFormat>,
+    /// Save the screen here as a PNG, without a window, and quit.
+    pub screenshot: Option<PathBuf>,
+    /// How long after starting to take the screenshot, rather than when the command exits.
+    pub after_ms: Option<u64>,
+    /// Program and arguments to run in place of the shell, from `-e` on.
+    pub command: Vec<String>,
+    pub help: bool,
+}
+
+impl Options {
+    pub fn from_env() -> Result<Self> {
+        let mut options = Self::parse(std::env::args().skip(1))?;
+        // CI can run everything headless where there's no compositor to open windows on
+        options.headless |= std::env::var_os("WAYLAND_DISPLAY").is_none()
+            && std::env::var(HEADLESS_VAR).is_ok_and(|value| value == "1");
+        Ok(options)
+    }
+    
+    /// Parses arguments, not including the program name. Values follow their flag either as
+    /// the next argument or after `=`.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            
+            match flag {
+                "-c" | "--config" => {
+                    options.config = Some(value(flag, inline_value, &mut args)?.into());
+                }
+                "--class" => options.class = Some(value(flag, inline_value, &mut args)?),
+                "--log-pty" => options.log_pty = Some(value(flag, inline_value, &mut args)?.into()),
+                "--headless" => options.headless = true,
+                "--size" => {
+                    let size = value(flag, inline_value, &mut args)?;
+                    options.size = Some(headless::parse_size(&size).ok_or_else(|| MyTermError::parse(anyhow!("--size needs <COLS>x<ROWS>, not {:?}", size)))?);
+                }
+                "--script" => options.script = Some(value(flag, inline_value, &mut args)?.into()),
+                "--dump" => options.dump = Some(DumpFormat::parse(&value(flag, inline_value, &mut args)?)?),
+                "--screenshot" => options.screenshot = Some(value(flag, inline_value, &mut args)?.into()),
+                "--after-ms" => {
+                    let ms = value(flag, inline_value, &mut args)?;
+                    options.after_ms = Some(ms.parse().map_err(|_| MyTermError::parse(anyhow!("--after-ms needs milliseconds, not {:?}", ms)))?);
+                }
+                // Everything after is the command, its own options included
+                "-e" | "--command" => {
+                    options.command = inline_value.into_iter().chain(args.by_ref()).collect();
+                    if options.command.is_empty() {
+                        return Err(MyTermError::parse(anyhow!("{} needs a command", flag)));
+                    }
+                }
+                "-h" | "--help" => options.help = true,
+                _ => return Err(MyTermError::parse(anyhow!("Unknown argument: {}", arg))),
+            }
+        }
+        
+        Ok(options)
+    }
+    
+    /// Overrides the settings given on the command line, for the config as loaded and as
+    /// reloaded.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(class) = &self.class {
+            config.display.app_id = Some(class.clone());
+        }
+        if let Some(path) = &self.log_pty {
+            config.terminal.typescript = Some(path.clone());
+        }
+    }
+}
+
+fn value(flag: &str, inline_value: Option<String>, args: &mut impl Iterator<Item = String>) -> Result<String> {
+    inline_value
+        .or_else(|| args.next())
+        .ok_or_else(|| MyTermError::parse(anyhow!("{} needs a value", flag)))
+}
\ No newline at end of file
@@ -0,0 +1,69 @@
+use std::io;
+use std::os::fd::OwnedFd;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::unix::pipe;
+use tokio::sync::mpsc;
+
+use crate::display::Event;
+
+/// The text types we offer when copying, and take when pasting, most preferred first.
+/// `UTF8_STRING` is what X11 programs under Xwayland offer.
+pub const TEXT_MIME_TYPES: [&str; 3] = ["text/plain;charset=utf-8", "UTF8_STRING", "text/plain"];
+
+/// How much of a copied selection goes to the pipe at a time, so sending a large one to a
+/// slow reader doesn't hold the runtime up.
+pub const WRITE_CHUNK_BYTES: usize = 64 * 1024;
+
+/// The type to ask for from an offer of `offered`, or `None` if it has no text.
+pub fn best_mime(offered: &[String]) -> Option<&'static str> {
+    TEXT_MIME_TYPES.into_iter().find(|mime| offered.iter().any(|offered| offered == mime))
+}
+
+/// Writes `bytes` to `writer` a chunk at a time, yielding in between, and closes it.
+pub async fn write_chunked(mut writer: impl AsyncWrite + Unpin, bytes: &[u8]) -> io::Result<()> {
+    for chunk in bytes.chunks(WRITE_CHUNK_BYTES) {
+        writer.write_all(chunk).await?;
+        tokio::task::yield_now().await;
+    }
+    writer.shutdown().await
+}
+
+/// Reads `reader` to its end as text, with anything that isn't UTF-8 replaced.
+pub async fn read_text(mut reader: impl AsyncRead + Unpin) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).await?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Sends `text` to the program pasting it, which reads it from `fd`, on a task of its own.
+pub fn send_selection(fd: OwnedFd, text: Arc<str>) {
+    tokio::spawn(async move {
+        let result = match pipe::Sender::from_owned_fd(fd) {
+            Ok(writer) => write_chunked(writer, text.as_bytes()).await,
+            Err(e) => Err(e),
+        };
+        // The reader may stop reading halfway, e.g. when its paste is cancelled
+        if let Err(e) = result {
+            log::debug!("Stopped sending the clipboard: {}", e);
+        }
+    });
+}
+
+/// Reads the selection being pasted from `fd` on a task of its own, and hands it to the app as
+/// an `Event::Paste`.
+pub fn receive_selection(fd: OwnedFd, events: mpsc::UnboundedSender<Event>) {
+    tokio::spawn(async move {
+        let text = match pipe::Receiver::from_owned_fd(fd) {
+            Ok(reader) => read_text(reader).await,
+            Err(e) => Err(e),
+        };
+        match text {
+            Ok(text) => {
+                log::debug!("Received {} bytes from the clipboard", text.len());
+                let _ = events.send(Event::Paste(text));
+            }
+            Err(e) => log::warn!("Failed to read the clipboard: {}", e),
+        }
+    });
+}
\ No newline at end of file
@@ -1,75 +1,353 @@
-use anyhow::{Context, Result};
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+use crate::display::OutputInfo;
+use crate::error::{MyTermError, Result};
+use crate::input::{self, Action};
+use crate::terminal::window_size_for;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Config {
     pub display: DisplayConfig,
     pub terminal: TerminalConfig,
     pub font: FontConfig,
     pub colors: ColorConfig,
-    pub keybindings: KeybindingConfig,
+    pub bell: BellConfig,
+    pub mouse: MouseConfig,
+    /// User keybindings, applied on top of `KeyBinding::defaults()`.
+    pub keybindings: Vec<KeyBinding>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DisplayConfig {
     pub width: u32,
     pub height: u32,
+    /// Initial grid width in cells, used instead of `width` when set.
+    pub columns: Option<u32>,
+    /// Initial grid height in cells, used instead of `height` when set.
+    pub rows: Option<u32>,
+    /// Opacity of the background, from 0.0 to 1.0; text is always drawn opaque.
     pub opacity: f32,
-    pub decorations: bool,
+    /// Whether the window has decorations at all. `true` and `false` are taken for "full" and
+    /// "none".
+    #[serde(deserialize_with = "decorations::deserialize")]
+    pub decorations: Decorations,
+    /// Who draws the decorations when `decorations` is enabled.
+    pub decoration_mode: DecorationMode,
     pub startup_mode: StartupMode,
+    /// Output to start on with `startup_mode` Fullscreen, by name such as "HDMI-A-1", instead
+    /// of the one the compositor picks. `""` is taken for none.
+    #[serde(deserialize_with = "empty_is_none::deserialize")]
+    pub fullscreen_output: Option<String>,
+    /// Start with the window size of the last run instead of `width` and `height`.
+    pub remember_size: bool,
+    /// Space in pixels between the window edge and the grid, filled with the background color.
+    pub padding: Padding,
+    /// Center the grid, splitting the strip too narrow for a cell between opposite sides.
+    pub dynamic_padding: bool,
+    /// Scale `font.size` by the DPI of the output the window is on, relative to 96 DPI.
+    pub scale_with_dpi: bool,
+    /// How far the window's colors fade toward the background while it's unfocused, from 0,
+    /// which leaves them, to 1.
+    pub unfocused_dim: f32,
+    /// Settings for specific outputs, keyed by output name such as "eDP-1".
+    pub output_overrides: BTreeMap<String, OutputOverride>,
+    /// What shows that the view is scrolled up into the scrollback.
+    pub scrollback_indicator: ScrollbackIndicator,
+    /// The window's app_id, which Sway rules match on, instead of "myterm".
+    pub app_id: Option<String>,
+    /// The window title until the shell sets one, instead of "MyTerm".
+    pub title: Option<String>,
+    /// The window title made from `{title}`, `{cwd}` and `{command}`, instead of the title
+    /// programs set or else the program running.
+    pub title_format: Option<String>,
+    /// Minimum time between two title changes, as some programs set the title with every
+    /// line they print. Only the latest title set meanwhile is shown.
+    pub title_interval_ms: u64,
+    /// What draws the frames. The GPU renderer needs the `gpu` build feature.
+    pub renderer: RendererKind,
+}
+
+/// Settings that apply while the window is on a particular output.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputOverride {
+    /// Font size in points, used instead of `font.size` and any DPI scaling.
+    pub font_size: Option<f32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Padding around the grid; a single number in the config applies to all sides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(from = "PaddingRepr")]
+pub struct Padding {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TerminalConfig {
-    pub scrollback_lines: u32,
+    /// Lines of history to keep: `0` disables scrollback, `None` ("unlimited") is limited only
+    /// by `terminal::SCROLLBACK_BUDGET_BYTES`.
+    #[serde(with = "scrollback_lines")]
+    pub scrollback_lines: Option<usize>,
+    /// How scrollback lines are stored in memory.
+    pub scrollback_storage: ScrollbackStorage,
     pub shell: Option<String>,
+    /// Typed into the shell once it starts, followed by Enter, leaving the shell interactive.
+    pub startup_command: Option<String>,
     pub working_directory: Option<PathBuf>,
+    /// Value of TERM advertised to programs running in the terminal.
+    pub term: String,
     pub cursor_blink: bool,
+    /// Stop blinking the cursor after this long without a key press, 0 to blink on.
+    pub cursor_blink_timeout_ms: u64,
     pub cursor_shape: CursorShape,
+    /// Blink text marked with SGR 5; when off it is shown steadily.
+    #[serde(alias = "allow_blinking")]
+    pub allow_blink: bool,
+    /// Jump back to the live screen when a key is typed while scrolled up.
+    pub scroll_on_keystroke: bool,
+    /// Jump back to the live screen whenever the program produces output.
+    pub scroll_on_output: bool,
+    /// Sent back to the program when it writes ENQ (0x05); empty sends nothing.
+    pub answerback: String,
+    /// Recognize 8-bit C1 controls such as 0x9B for CSI in program output, outside of UTF-8
+    /// sequences, instead of showing them as invalid UTF-8.
+    pub eight_bit_controls: bool,
+    /// Most program output parsed before the event loop gets back to input, resizes and
+    /// drawing; the rest is read on its next turn.
+    pub read_chunk_bytes: usize,
+    /// Ask before closing the window while a program other than the shell runs in the
+    /// foreground.
+    pub confirm_close: bool,
+    /// Drop the blanks at the end of each line of a copied selection.
+    pub trim_copy_trailing_whitespace: bool,
+    /// Keep trailing blanks with a background color other than the default when trimming, such
+    /// as the end of a colored bar.
+    pub keep_colored_trailing_whitespace: bool,
+    /// Cells taken by East Asian Ambiguous characters such as `±`, `①` and Greek letters.
+    pub ambiguous_width: AmbiguousWidth,
+    /// What Backspace sends, and with it what Delete does.
+    pub backspace_sends: BackspaceSends,
+    /// File to record the program's output in as it's read from the PTY, for debugging. `""`
+    /// is taken for none.
+    #[serde(deserialize_with = "empty_is_none::deserialize")]
+    pub typescript: Option<PathBuf>,
+    /// Record what's written to the PTY in `typescript` too, telling the two apart.
+    pub typescript_input: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct FontConfig {
     pub family: String,
     pub size: f32,
     pub bold_family: Option<String>,
     pub italic_family: Option<String>,
     pub bold_italic_family: Option<String>,
+    /// Families searched in order for characters the font has no glyph for, before the
+    /// system's fonts.
+    pub fallback: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ColorConfig {
     pub foreground: String,
+    /// `#rrggbb`, or `#rrggbbaa` to make the background translucent.
     pub background: String,
     pub cursor: String,
-    pub selection_background: String,
-    pub selection_foreground: String,
+    /// Color of the text under a block cursor, defaulting to the cell's background.
+    pub cursor_text: Option<String>,
+    /// A color, or "CellForeground"/"CellBackground" to keep the cell's own color.
+    /// Selected cells are shown inverted when neither selection color is set.
+    pub selection_background: Option<String>,
+    pub selection_foreground: Option<String>,
     pub normal: [String; 8],
     pub bright: [String; 8],
     pub dim: [String; 8],
+    /// File of color settings merged over these, as a table of the fields above. `~` and
+    /// environment variables are expanded, and relative paths start at the config directory.
+    /// Once loaded, the path as resolved, which is watched for changes like the config files.
+    pub theme: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BellConfig {
+    pub mode: BellMode,
+    /// How long the visual bell flash lasts.
+    pub visual_duration_ms: u64,
+    /// Color the window is tinted with during the flash.
+    pub visual_color: String,
+    /// Program and arguments to run on BEL, rate limited; empty runs nothing.
+    pub command: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BellMode {
+    None,
+    /// Flash the window.
+    Visual,
+    /// Mark the window urgent when it isn't focused.
+    Urgent,
+    Both,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MouseConfig {
+    /// Hide the pointer while typing; it reappears when the mouse is used or the window loses
+    /// focus.
+    pub hide_when_typing: bool,
+    /// Lines scrolled per wheel step.
+    pub scroll_multiplier: f32,
+    /// Turn the wheel into Up/Down keys in the alternate screen, for pagers and editors.
+    pub alternate_scroll: bool,
+    /// User mouse bindings, applied on top of `MouseBinding::defaults()`.
+    pub bindings: Vec<MouseBinding>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MouseBinding {
+    /// A button name with optional modifiers, such as "Middle" or "Shift+Right".
+    pub button: String,
+    pub action: Action,
+    /// Optional mode guard, as for keybindings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+/// A key bound to an action, written either with `action` or as `send = "..."`, which is
+/// `Action::SendText` of the text with its escapes replaced.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "KeyBindingRepr")]
+pub struct KeyBinding {
+    pub key: String,
+    pub action: Action,
+    /// Optional mode guard such as "AltScreen" or "~AltScreen|Selection".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct KeyBindingRepr {
+    #[serde(alias = "keys")]
+    key: String,
+    action: Option<Action>,
+    send: Option<String>,
+    mode: Option<String>,
+}
+
+impl TryFrom<KeyBindingRepr> for KeyBinding {
+    type Error = String;
+    
+    fn try_from(repr: KeyBindingRepr) -> std::result::Result<Self, String> {
+        let action = match (repr.action, repr.send) {
+            (Some(action), None) => action,
+            (None, Some(send)) => Action::SendText(input::parse_escapes(&send).map_err(|e| e.to_string())?),
+            (Some(_), Some(_)) => return Err(format!("binding for {} has both an action and send", repr.key)),
+            (None, None) => return Err(format!("binding for {} needs an action or send", repr.key)),
+        };
+        Ok(Self { key: repr.key, action, mode: repr.mode })
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct KeybindingConfig {
-    pub copy: String,
-    pub paste: String,
-    pub search: String,
-    pub new_tab: String,
-    pub close_tab: String,
-    pub next_tab: String,
-    pub prev_tab: String,
+/// Settings that changed between two configs, grouped by how they can be applied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigDelta {
+    /// Applied immediately.
+    pub live: Vec<&'static str>,
+    /// Need new cell metrics and a grid resize.
+    pub resize: Vec<&'static str>,
+    /// Only take effect after a restart.
+    pub restart: Vec<&'static str>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decorations {
+    /// A titlebar, drawn as `decoration_mode` says.
+    Full,
+    /// A borderless window.
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DecorationMode {
+    /// Ask the compositor to draw decorations, and go without if it declines.
+    Server,
+    /// Draw our own titlebar.
+    Client,
+    /// Prefer server decorations, falling back to a minimal client titlebar.
+    Auto,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrollbackStorage {
+    /// Keep every cell as it was on screen.
+    Full,
+    /// Keep text and runs of attributes, trading CPU when scrolling back for memory.
+    Compact,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AmbiguousWidth {
+    /// One cell, as most programs expect.
+    Narrow,
+    /// Two cells, as many CJK programs expect.
+    Wide,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackspaceSends {
+    /// DEL (0x7F, `^?`), with Delete sending `ESC [ 3 ~`, as most systems expect.
+    Delete,
+    /// BS (0x08, `^H`), with Delete sending DEL, for systems whose erase character is `^H`.
+    Backspace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrollbackIndicator {
+    /// A thin scrollbar on the right edge, fading out a second after the view last scrolled.
+    Bar,
+    /// A "[offset/lines]" badge over the top right corner of the grid.
+    Badge,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RendererKind {
+    /// The CPU, into shared memory buffers.
+    Software,
+    /// The GPU through wgpu, falling back to software where no adapter can draw to the window.
+    Gpu,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StartupMode {
     Windowed,
     Maximized,
     Fullscreen,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CursorShape {
     Block,
     Underline,
@@ -82,25 +360,127 @@ impl Default for DisplayConfig {
         Self {
             width: 800,
             height: 600,
+            columns: None,
+            rows: None,
             opacity: 1.0,
-            decorations: true,
+            decorations: Decorations::Full,
+            decoration_mode: DecorationMode::Auto,
             startup_mode: StartupMode::Windowed,
+            fullscreen_output: None,
+            remember_size: false,
+            padding: Padding::default(),
+            dynamic_padding: false,
+            scale_with_dpi: false,
+            unfocused_dim: 0.0,
+            output_overrides: BTreeMap::new(),
+            scrollback_indicator: ScrollbackIndicator::Bar,
+            app_id: None,
+            title: None,
+            title_format: None,
+            title_interval_ms: 100,
+            renderer: RendererKind::Software,
         }
     }
 }
 
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PaddingRepr {
+    Uniform(u32),
+    Sides {
+        #[serde(default)]
+        top: u32,
+        #[serde(default)]
+        bottom: u32,
+        #[serde(default)]
+        left: u32,
+        #[serde(default)]
+        right: u32,
+    },
+}
+
+impl From<PaddingRepr> for Padding {
+    fn from(repr: PaddingRepr) -> Self {
+        match repr {
+            PaddingRepr::Uniform(n) => Self::uniform(n),
+            PaddingRepr::Sides { top, bottom, left, right } => Self { top, bottom, left, right },
+        }
+    }
+}
+
+impl DisplayConfig {
+    /// Initial window size for cells of `cell_size`: `columns` and `rows` cells plus padding
+    /// where set, `width` and `height` otherwise.
+    pub fn window_size(&self, cell_size: (u32, u32)) -> (u32, u32) {
+        let (width, height) = window_size_for(
+            self.columns.unwrap_or(0),
+            self.rows.unwrap_or(0),
+            cell_size,
+            self.padding,
+        );
+        (
+            if self.columns.is_some() { width } else { self.width },
+            if self.rows.is_some() { height } else { self.height },
+        )
+    }
+}
+
+impl Padding {
+    pub fn uniform(n: u32) -> Self {
+        Self { top: n, bottom: n, left: n, right: n }
+    }
+    
+    pub fn horizontal(&self) -> u32 {
+        self.left.saturating_add(self.right)
+    }
+    
+    pub fn vertical(&self) -> u32 {
+        self.top.saturating_add(self.bottom)
+    }
+}
+
 impl Default for TerminalConfig {
     fn default() -> Self {
         Self {
-            scrollback_lines: 10000,
+            scrollback_lines: Some(10000),
+            scrollback_storage: ScrollbackStorage::Full,
             shell: None,
+            startup_command: None,
             working_directory: None,
+            term: "xterm-256color".to_string(),
             cursor_blink: true,
+            cursor_blink_timeout_ms: 0,
             cursor_shape: CursorShape::Block,
+            allow_blink: true,
+            scroll_on_keystroke: true,
+            scroll_on_output: false,
+            answerback: String::new(),
+            eight_bit_controls: false,
+            read_chunk_bytes: 64 * 1024,
+            confirm_close: false,
+            trim_copy_trailing_whitespace: true,
+            keep_colored_trailing_whitespace: false,
+            ambiguous_width: AmbiguousWidth::Narrow,
+            backspace_sends: BackspaceSends::Delete,
+            typescript: None,
+            typescript_input: false,
         }
     }
 }
 
+impl FontConfig {
+    /// Font size in buffer pixels at the given output scale, treating `size` as points at 96 DPI.
+    #[allow(dead_code)]
+    pub fn pixel_size(&self, scale: f64) -> f64 {
+        points_to_pixels(self.size, scale)
+    }
+}
+
+/// Converts a font size in points to buffer pixels at 96 DPI and the given output scale.
+pub fn points_to_pixels(points: f32, scale: f64) -> f64 {
+    points as f64 * 96.0 / 72.0 * scale
+}
+
 impl Default for FontConfig {
     fn default() -> Self {
         Self {
@@ -109,6 +489,7 @@ impl Default for FontConfig {
             bold_family: None,
             italic_family: None,
             bold_italic_family: None,
+            fallback: Vec::new(),
         }
     }
 }
@@ -119,8 +500,9 @@ impl Default for ColorConfig {
             foreground: "#ffffff".to_string(),
             background: "#000000".to_string(),
             cursor: "#ffffff".to_string(),
-            selection_background: "#444444".to_string(),
-            selection_foreground: "#ffffff".to_string(),
+            cursor_text: None,
+            selection_background: None,
+            selection_foreground: None,
             normal: [
                 "#000000".to_string(), // Black
                 "#800000".to_string(), // Red
@@ -151,81 +533,589 @@ impl Default for ColorConfig {
                 "#004040".to_string(), // Dim Cyan
                 "#606060".to_string(), // Dim White
             ],
+            theme: None,
         }
     }
 }
 
-impl Default for KeybindingConfig {
+impl Default for BellConfig {
     fn default() -> Self {
         Self {
-            copy: "Ctrl+Shift+C".to_string(),
-            paste: "Ctrl+Shift+V".to_string(),
-            search: "Ctrl+Shift+F".to_string(),
-            new_tab: "Ctrl+Shift+T".to_string(),
-            close_tab: "Ctrl+Shift+W".to_string(),
-            next_tab: "Ctrl+Tab".to_string(),
-            prev_tab: "Ctrl+Shift+Tab".to_string(),
+            mode: BellMode::Visual,
+            visual_duration_ms: 150,
+            visual_color: "#ffffff".to_string(),
+            command: Vec::new(),
+        }
+    }
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        Self {
+            hide_when_typing: false,
+            scroll_multiplier: 3.0,
+            alternate_scroll: true,
+            bindings: Vec::new(),
+        }
+    }
+}
+
+impl MouseBinding {
+    pub fn new(button: &str, action: Action, mode: Option<&str>) -> Self {
+        Self {
+            button: button.to_string(),
+            action,
+            mode: mode.map(str::to_string),
+        }
+    }
+    
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::new("Middle", Action::PastePrimary, None),
+        ]
+    }
+}
+
+impl KeyBinding {
+    pub fn new(key: &str, action: Action, mode: Option<&str>) -> Self {
+        Self {
+            key: key.to_string(),
+            action,
+            mode: mode.map(str::to_string),
         }
     }
+    
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::new("Ctrl+Shift+C", Action::Copy, None),
+            Self::new("Ctrl+Shift+V", Action::Paste, None),
+            Self::new("Ctrl+Shift+F", Action::Search, None),
+            Self::new("Ctrl+Shift+T", Action::NewTab, None),
+            Self::new("Ctrl+Shift+W", Action::CloseTab, None),
+            Self::new("Ctrl+Tab", Action::NextTab, None),
+            Self::new("Ctrl+Shift+Tab", Action::PrevTab, None),
+            Self::new("Shift+PageUp", Action::ScrollPageUp, Some("~AltScreen")),
+            Self::new("Shift+PageDown", Action::ScrollPageDown, Some("~AltScreen")),
+            Self::new("Shift+Home", Action::ScrollToTop, Some("~AltScreen")),
+            Self::new("Shift+End", Action::ScrollToBottom, Some("~AltScreen")),
+            Self::new("Ctrl+Shift+K", Action::ClearAll, None),
+            Self::new("Ctrl+Shift+F5", Action::ReloadConfig, None),
+        ]
+    }
+}
+
+impl ConfigDelta {
+    pub fn is_empty(&self) -> bool {
+        self.live.is_empty() && self.resize.is_empty() && self.restart.is_empty()
+    }
+}
+
+/// System-wide defaults that every user's config is merged over.
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/myterm/config.toml";
+
+/// The config files to load, lowest precedence first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigPaths {
+    pub system: Option<PathBuf>,
+    /// The user's config, merged over `system` and watched for changes.
+    pub user: PathBuf,
+    /// Whether `user` was named by `--config` or `MYTERM_CONFIG`, in which case it must exist.
+    pub explicit: bool,
+}
+
+impl ConfigPaths {
+    /// Resolves the config files from the `--config` flag and the process environment.
+    pub fn from_env(cli_config: Option<&Path>) -> Result<Self> {
+        Self::resolve(cli_config, |name| std::env::var_os(name), dirs::home_dir())
+    }
+    
+    /// Picks the user config from, in order: `cli_config`, `MYTERM_CONFIG`,
+    /// `$MYTERM_CONFIG_HOME/config.toml`, then the first existing of
+    /// `$XDG_CONFIG_HOME/myterm/config.toml` and `~/.config/myterm/config.toml`.
+    ///
+    /// Without any config file the first search location is returned, for the first-run template.
+    pub fn resolve(cli_config: Option<&Path>, env: impl Fn(&str) -> Option<OsString>, home: Option<PathBuf>) -> Result<Self> {
+        // Empty variables count as unset, as with XDG_CONFIG_HOME
+        let env = |name: &str| env(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+        let system = Some(PathBuf::from(SYSTEM_CONFIG_PATH));
+        
+        if let Some(user) = cli_config.map(Path::to_path_buf).or_else(|| env("MYTERM_CONFIG")) {
+            return Ok(Self { system, user, explicit: true });
+        }
+        if let Some(dir) = env("MYTERM_CONFIG_HOME") {
+            return Ok(Self { system, user: dir.join("config.toml"), explicit: false });
+        }
+        
+        let candidates: Vec<PathBuf> = [
+            env("XDG_CONFIG_HOME").filter(|dir| dir.is_absolute()),
+            home.map(|home| home.join(".config")),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("myterm").join("config.toml"))
+        .collect();
+        
+        let user = candidates.iter().find(|path| path.exists())
+            .or(candidates.first())
+            .cloned()
+            .ok_or_else(|| MyTermError::config(anyhow::anyhow!("Could not find config directory")))?;
+        Ok(Self { system, user, explicit: false })
+    }
+    
+    /// The files that exist, in merge order.
+    pub fn existing(&self) -> Vec<&Path> {
+        self.system.iter()
+            .chain(std::iter::once(&self.user))
+            .map(PathBuf::as_path)
+            .filter(|path| path.exists())
+            .collect()
+    }
+}
+
+/// Hands the settings that only take effect after a restart to `$callback`, after `$args`.
+macro_rules! restart_settings {
+    ($callback:ident!($($args:tt)*)) => {
+        $callback!($($args)*
+            display.width,
+            display.height,
+            display.columns,
+            display.rows,
+            display.decorations,
+            display.decoration_mode,
+            display.startup_mode,
+            display.fullscreen_output,
+            display.app_id,
+            display.title,
+            display.renderer,
+            terminal.scrollback_storage,
+            terminal.shell,
+            terminal.startup_command,
+            terminal.working_directory,
+            terminal.term,
+            terminal.typescript,
+            terminal.typescript_input,
+        )
+    };
 }
 
 impl Config {
-    pub fn load() -> Result<Self> {
-        let config_path = Self::config_path()?;
+    /// Font size in points for a window on `output`.
+    ///
+    /// An override for the output's name wins; otherwise `font.size` is scaled by the output's
+    /// DPI when `display.scale_with_dpi` is set and the output reports a physical size.
+    pub fn font_size_for(&self, output: Option<&OutputInfo>) -> f32 {
+        let Some(output) = output else {
+            return self.font.size;
+        };
         
-        if !config_path.exists() {
-            let default_config = Self::default();
-            default_config.save()?;
-            return Ok(default_config);
+        let override_size = output.name.as_ref()
+            .and_then(|name| self.display.output_overrides.get(name))
+            .and_then(|output_override| output_override.font_size);
+        if let Some(size) = override_size {
+            return size;
         }
         
-        let content = fs::read_to_string(&config_path)
-            .with_context(|| format!("Failed to read config file: {:?}", config_path))?;
-            
-        let config: Config = toml::from_str(&content)
-            .with_context(|| "Failed to parse config file")?;
+        match output.dpi() {
+            Some(dpi) if self.display.scale_with_dpi => (self.font.size as f64 * dpi / 96.0) as f32,
+            _ => self.font.size,
+        }
+    }
+    
+    /// Loads the system and user configs, writing the documented template if there is no user
+    /// config yet.
+    pub fn load(paths: &ConfigPaths) -> Result<Self> {
+        if !paths.user.exists() {
+            if paths.explicit {
+                return Err(MyTermError::config(anyhow::anyhow!("Config file not found: {:?}", paths.user)));
+            }
+            if let Err(e) = Self::write_documented_template(&paths.user) {
+                log::warn!("Could not write the config template: {:#}", e);
+            }
+        }
+        
+        Self::load_merged(paths)
+    }
+    
+    /// Loads whichever of the config files exist, the user's merged over the system's.
+    pub fn load_merged(paths: &ConfigPaths) -> Result<Self> {
+        let existing = paths.existing();
+        let mut table = toml::Table::new();
+        for path in &existing {
+            merge_tables(&mut table, load_table(path, &mut Vec::new()).map_err(MyTermError::config)?);
+        }
+        apply_theme(&mut table, paths.user.parent().unwrap_or(Path::new(".")));
+        
+        let config: Config = toml::Value::Table(table).try_into()
+            .with_context(|| format!("Invalid config in {:?}", existing))
+            .map_err(MyTermError::config)?;
+        
+        log::info!("Loaded config from {:?}", existing);
+        Ok(config)
+    }
+    
+    /// Loads a config file, merging in the files listed in its `import` array first.
+    #[allow(dead_code)]
+    pub fn load_from(config_path: &Path) -> Result<Self> {
+        let mut table = load_table(config_path, &mut Vec::new()).map_err(MyTermError::config)?;
+        apply_theme(&mut table, config_path.parent().unwrap_or(Path::new(".")));
+        
+        let config: Config = toml::Value::Table(table).try_into()
+            .with_context(|| format!("Invalid config in {:?}", config_path))
+            .map_err(MyTermError::config)?;
             
         Ok(config)
     }
     
+    /// Serializes the whole config to the config path, for programmatic round-trips.
+    #[allow(dead_code)]
     pub fn save(&self) -> Result<()> {
         let config_path = Self::config_path()?;
         
         if let Some(parent) = config_path.parent() {
             fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create config directory: {:?}", parent))?;
+                .with_context(|| format!("Failed to create config directory: {:?}", parent))
+                .map_err(MyTermError::config)?;
         }
         
         let content = toml::to_string_pretty(self)
-            .with_context(|| "Failed to serialize config")?;
+            .with_context(|| "Failed to serialize config")
+            .map_err(MyTermError::config)?;
             
         fs::write(&config_path, content)
-            .with_context(|| format!("Failed to write config file: {:?}", config_path))?;
+            .with_context(|| format!("Failed to write config file: {:?}", config_path))
+            .map_err(MyTermError::config)?;
             
         Ok(())
     }
     
-    fn config_path() -> Result<PathBuf> {
-        let mut path = dirs::config_dir()
-            .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?;
-        path.push("myterm");
-        path.push("config.toml");
-        Ok(path)
+    /// Writes the commented first-run config, every setting listed at its default.
+    pub fn write_documented_template(path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create config directory: {:?}", parent))
+                .map_err(MyTermError::config)?;
+        }
+        
+        fs::write(path, DOCUMENTED_TEMPLATE)
+            .with_context(|| format!("Failed to write config file: {:?}", path))
+            .map_err(MyTermError::config)?;
+        
+        Ok(())
+    }
+    
+    /// Classifies the settings that differ between `old` and `new`.
+    pub fn diff(old: &Config, new: &Config) -> ConfigDelta {
+        let mut delta = ConfigDelta::default();
+        
+        macro_rules! classify {
+            ($list:ident: $($($field:ident).+),+ $(,)?) => {
+                $(
+                    if old.$($field).+ != new.$($field).+ {
+                        delta.$list.push(stringify!($($field).+));
+                    }
+                )+
+            };
+        }
+        
+        classify!(live:
+            display.opacity,
+            display.unfocused_dim,
+            display.padding,
+            display.dynamic_padding,
+            display.remember_size,
+            display.scrollback_indicator,
+            display.title_format,
+            display.title_interval_ms,
+            terminal.cursor_blink,
+            terminal.cursor_blink_timeout_ms,
+            terminal.cursor_shape,
+            terminal.allow_blink,
+            terminal.scroll_on_keystroke,
+            terminal.scroll_on_output,
+            terminal.answerback,
+            terminal.eight_bit_controls,
+            terminal.read_chunk_bytes,
+            terminal.confirm_close,
+            terminal.trim_copy_trailing_whitespace,
+            terminal.keep_colored_trailing_whitespace,
+            terminal.ambiguous_width,
+            terminal.backspace_sends,
+            font.bold_family,
+            font.italic_family,
+            font.bold_italic_family,
+            font.fallback,
+            colors,
+            bell,
+            mouse,
+            keybindings,
+        );
+        classify!(resize:
+            display.scale_with_dpi,
+            display.output_overrides,
+            font.family,
+            font.size,
+        );
+        restart_settings!(classify!(restart:));
+        
+        // Growing the scrollback is free, shrinking it would drop history
+        let limit = |config: &Config| config.terminal.scrollback_lines.unwrap_or(usize::MAX);
+        if limit(new) > limit(old) {
+            delta.live.push("terminal.scrollback_lines");
+        } else if limit(new) < limit(old) {
+            delta.restart.push("terminal.scrollback_lines");
+        }
+        
+        delta
+    }
+    
+    /// Puts back the settings `diff` classifies as needing a restart as `running` has them, a
+    /// smaller scrollback included, so the config keeps saying what the terminal runs with.
+    pub fn keep_restart_settings(&mut self, running: &Config) {
+        let config = self;
+        macro_rules! keep {
+            ($($($field:ident).+),+ $(,)?) => {
+                $(
+                    config.$($field).+ = running.$($field).+.clone();
+                )+
+            };
+        }
+        
+        restart_settings!(keep!());
+        let limit = |lines: Option<usize>| lines.unwrap_or(usize::MAX);
+        if limit(config.terminal.scrollback_lines) < limit(running.terminal.scrollback_lines) {
+            config.terminal.scrollback_lines = running.terminal.scrollback_lines;
+        }
+    }
+    
+    /// The user config path, ignoring any `--config` flag.
+    pub fn config_path() -> Result<PathBuf> {
+        ConfigPaths::from_env(None).map(|paths| paths.user)
+    }
+}
+
+/// The config written on first run.
+///
+/// Settings appear as `#key = default`, with no space after the `#`, so that uncommenting
+/// all of them must still produce `Config::default()`; prose and examples use `# `.
+pub const DOCUMENTED_TEMPLATE: &str = include_str!("config_template.toml");
+
+/// How deep `import` directives may nest.
+const MAX_IMPORT_DEPTH: usize = 8;
+
+/// Reads a config file into a table with its imports merged underneath its own keys.
+///
+/// `stack` holds the files currently being loaded, to detect import cycles.
+fn load_table(path: &Path, stack: &mut Vec<PathBuf>) -> anyhow::Result<toml::Table> {
+    let canonical = fs::canonicalize(path)
+        .with_context(|| format!("Failed to read config file: {:?}", path))?;
+    if stack.contains(&canonical) {
+        return Err(anyhow::anyhow!("Config import cycle: {:?} imports itself", path));
+    }
+    if stack.len() > MAX_IMPORT_DEPTH {
+        return Err(anyhow::anyhow!("Config imports nested deeper than {} levels at {:?}", MAX_IMPORT_DEPTH, path));
+    }
+    
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {:?}", path))?;
+    let mut table: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse config file {:?}", path))?;
+    
+    let imports = match table.remove("import") {
+        None => Vec::new(),
+        Some(toml::Value::Array(imports)) => imports,
+        Some(_) => return Err(anyhow::anyhow!("`import` in {:?} must be an array of paths", path)),
+    };
+    
+    stack.push(canonical);
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let mut merged = toml::Table::new();
+    for import in imports {
+        let toml::Value::String(import) = import else {
+            return Err(anyhow::anyhow!("`import` in {:?} must be an array of paths", path));
+        };
+        
+        let import_path = resolve_import(&import, base_dir);
+        if !import_path.exists() {
+            log::warn!("Skipping missing config import {:?} from {:?}", import_path, path);
+            continue;
+        }
+        merge_tables(&mut merged, load_table(&import_path, stack)?);
+    }
+    stack.pop();
+    
+    merge_tables(&mut merged, table);
+    Ok(merged)
+}
+
+/// Expands `~` and resolves relative imports against the importing file's directory.
+fn resolve_import(import: &str, base_dir: &Path) -> PathBuf {
+    if let Some(rest) = import.strip_prefix("~/") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest);
+        }
+    }
+    base_dir.join(import)
+}
+
+/// Merges the file named by `colors.theme` over the `colors` table, resolving a relative path
+/// against `base_dir` and leaving the resolved path in `colors.theme`. A theme that can't be
+/// read or holds invalid colors is skipped with a warning, leaving the inline colors.
+fn apply_theme(table: &mut toml::Table, base_dir: &Path) {
+    let Some(toml::Value::Table(colors)) = table.get_mut("colors") else {
+        return;
+    };
+    let Some(toml::Value::String(theme)) = colors.get("theme") else {
+        return;
+    };
+    
+    let path = resolve_import(&expand_vars(theme, |name| std::env::var(name).ok()), base_dir);
+    colors.insert("theme".to_string(), toml::Value::String(path.to_string_lossy().into_owned()));
+    let theme = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read theme {:?}", path))
+        .and_then(|content| toml::from_str::<toml::Table>(&content)
+            .with_context(|| format!("Failed to parse theme {:?}", path)))
+        .and_then(|theme| {
+            // Checked on its own, so that a bad theme can't take the rest of the config with it
+            toml::Value::Table(theme.clone()).try_into::<ColorConfig>()
+                .with_context(|| format!("Invalid colors in theme {:?}", path))?;
+            Ok(theme)
+        });
+    match theme {
+        Ok(mut theme) => {
+            theme.remove("theme");
+            log::info!("Loaded theme from {:?}", path);
+            merge_tables(colors, theme);
+        }
+        Err(e) => log::warn!("{:#}, using the inline colors", e),
+    }
+}
+
+/// Replaces `$NAME` and `${NAME}` with the variable's value from `env`, or nothing if unset.
+pub fn expand_vars(path: &str, env: impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, remainder) = match after.strip_prefix('{').and_then(|inner| inner.split_once('}')) {
+            Some((name, remainder)) => (name, remainder),
+            None => {
+                let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+                after.split_at(end)
+            }
+        };
+        if name.is_empty() {
+            expanded.push('$');
+        } else {
+            expanded.push_str(&env(name).unwrap_or_default());
+        }
+        rest = remainder;
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Recursively merges `overlay` into `base`; tables merge key by key, anything else is replaced.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_tables(base_table, overlay_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// (De)serializes `scrollback_lines` as either a line count or the string "unlimited".
+mod scrollback_lines {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Lines(usize),
+        Named(String),
+    }
+    
+    pub fn serialize<S: Serializer>(value: &Option<usize>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(lines) => serializer.serialize_u64(*lines as u64),
+            None => serializer.serialize_str("unlimited"),
+        }
+    }
+    
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+        match Repr::deserialize(deserializer)? {
+            Repr::Lines(lines) => Ok(Some(lines)),
+            Repr::Named(name) if name.eq_ignore_ascii_case("unlimited") => Ok(None),
+            Repr::Named(name) => Err(de::Error::custom(format!(
+                "invalid scrollback_lines {:?}, expected a line count or \"unlimited\"",
+                name
+            ))),
+        }
+    }
+}
+
+mod decorations {
+    use super::Decorations;
+    use serde::{Deserialize, Deserializer};
+    
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Enabled(bool),
+        Named(Decorations),
+    }
+    
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decorations, D::Error> {
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Enabled(true) => Decorations::Full,
+            Repr::Enabled(false) => Decorations::None,
+            Repr::Named(decorations) => decorations,
+        })
+    }
+}
+
+mod empty_is_none {
+    use serde::{Deserialize, Deserializer};
+    
+    pub fn deserialize<'de, D: Deserializer<'de>, T: From<String>>(deserializer: D) -> Result<Option<T>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Ok((!value.is_empty()).then(|| T::from(value)))
     }
 }
 
 pub fn parse_color(color_str: &str) -> Result<rgb::RGB8> {
+    match color_str.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 => Ok(parse_color_with_alpha(color_str)?.rgb()),
+        Some(_) => Err(MyTermError::parse(anyhow::anyhow!("Invalid color format: {}", color_str))),
+        None => Err(MyTermError::parse(anyhow::anyhow!("Unsupported color format: {}", color_str))),
+    }
+}
+
+/// Parses `#rrggbb` (fully opaque) or `#rrggbbaa`.
+pub fn parse_color_with_alpha(color_str: &str) -> Result<rgb::RGBA8> {
     if let Some(hex) = color_str.strip_prefix('#') {
-        if hex.len() != 6 {
-            return Err(anyhow::anyhow!("Invalid color format: {}", color_str));
+        if !hex.is_ascii() || (hex.len() != 6 && hex.len() != 8) {
+            return Err(MyTermError::parse(anyhow::anyhow!("Invalid color format: {}", color_str)));
         }
         
-        let r = u8::from_str_radix(&hex[0..2], 16)?;
-        let g = u8::from_str_radix(&hex[2..4], 16)?;
-        let b = u8::from_str_radix(&hex[4..6], 16)?;
+        let channel = |digits: &str| u8::from_str_radix(digits, 16)
+            .with_context(|| format!("Invalid color: {}", color_str))
+            .map_err(MyTermError::parse);
+        let r = channel(&hex[0..2])?;
+        let g = channel(&hex[2..4])?;
+        let b = channel(&hex[4..6])?;
+        let a = match hex.get(6..8) {
+            Some(a) => channel(a)?,
+            None => 255,
+        };
         
-        Ok(rgb::RGB8::new(r, g, b))
+        Ok(rgb::RGBA8::new(r, g, b, a))
     } else {
-        Err(anyhow::anyhow!("Unsupported color format: {}", color_str))
+        Err(MyTermError::parse(anyhow::anyhow!("Unsupported color format: {}", color_str)))
     }
 }
\ No newline at end of file
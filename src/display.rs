@@ -1,106 +1,855 @@
-use anyhow::{Context, Result};
+use anyhow::Context;
 use crossbeam_channel::Receiver;
+use rgb::RGB8;
+use std::collections::VecDeque;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::unix::AsyncFd;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use wayland_client::backend::WaylandError;
 use wayland_client::{Connection, EventQueue};
 use smithay_client_toolkit::shell::WaylandSurface;
 
-use crate::config::Config;
-use crate::terminal::Terminal;
+use crate::config::{self, Config, FontConfig, Padding, ScrollbackIndicator};
+use crate::error::{MyTermError, Result};
+use crate::font::FontSet;
+use crate::metrics::{self, FrameTimings, RenderMetrics};
+use crate::pacing::{FramePacer, Presentation};
+use crate::palette::Palette;
+use crate::render::{self, Bitmap, CursorSprite, Frame, FrameImage, GlyphCache, GlyphCacheStats, GridLayout, Rect, Scrollbar, Titlebar};
+use crate::renderer::{self, Renderer, Target};
+use crate::screenshot;
+use crate::search::{Search, SearchMatch};
+use crate::selection::Point;
+use crate::sixel::SixelImage;
+use crate::terminal::{self, GridGeometry, Terminal};
 use crate::wayland::WaylandState;
 
-pub struct Display {
+/// How long to wait for the compositor to ask for a frame before drawing one anyway, as it
+/// may never ask while the window is hidden.
+pub const FRAME_CALLBACK_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long the scrollbar stays after the view last scrolled, and how long it then takes to
+/// fade out.
+const SCROLLBAR_SHOWN: Duration = Duration::from_secs(1);
+const SCROLLBAR_FADE: Duration = Duration::from_millis(300);
+
+pub struct WaylandDisplay {
+    /// Dropped first, as a GPU renderer's surface refers to the window's.
+    renderer: Box<dyn Renderer>,
     wayland_state: WaylandState,
     connection: Connection,
     event_queue: EventQueue<WaylandState>,
-    #[allow(dead_code)]
-    event_receiver: Option<Receiver<Event>>,
+    /// Readiness of the Wayland socket, so waiting for events yields to the runtime.
+    wayland_fd: AsyncFd<RawFd>,
+    /// Events queued by the Wayland handlers during dispatch.
+    events: HandlerEvents,
+    /// Pastes, read from the clipboard on tasks of their own.
+    paste_sender: mpsc::UnboundedSender<Event>,
+    pastes: mpsc::UnboundedReceiver<Event>,
+    glyphs: GlyphCache,
+    /// Font size in points, scaled to the output when rendering.
+    font_size: f32,
+    /// Surface-local cell size of the font at that size.
+    cell_size: (u32, u32),
+    /// From `display.padding` and `display.dynamic_padding`, for fitting the grid.
+    padding: Padding,
+    dynamic_padding: bool,
+    opacity: f32,
+    blink_visible: bool,
+    /// Whether blinking text is shown in the current blink phase.
+    text_blink_visible: bool,
+    /// From `terminal.allow_blink`.
+    allow_blink: bool,
+    /// The last frame had blinking text in it.
+    has_blinking_text: bool,
+    scrollback_indicator: ScrollbackIndicator,
+    /// The display offset of the last frame.
+    display_offset: usize,
+    /// When the view last scrolled, until the scrollbar has faded out.
+    scrolled_at: Option<Instant>,
+    /// Color of the visual bell's flash while it shows.
+    bell_flash: Option<RGB8>,
+    /// Images in view in the last frame, scaled to the buffer.
+    scaled_images: Vec<(Arc<SixelImage>, Arc<Bitmap>)>,
+    /// Question shown across the bottom row, in place of the cursor.
+    prompt: Option<String>,
+    /// Search mode's matches, highlighted, and status line, shown like the prompt.
+    search: Option<Search>,
+    metrics: RenderMetrics,
+    /// Show the metrics in the bottom right corner, from `MYTERM_DEBUG_HUD=1`.
+    debug_hud: bool,
+    /// Time spent parsing output since the last frame.
+    parse_time: Duration,
+    /// When the oldest output not drawn yet arrived.
+    output_since: Option<std::time::Instant>,
+    /// Holds frames back to the output's refresh rate, given presentation feedback.
+    pacer: FramePacer,
+    /// Something changed since the last frame was drawn.
+    needs_redraw: bool,
+    /// When the last frame was committed, if the compositor hasn't asked for the next yet.
+    frame_requested_at: Option<Instant>,
+    /// A configured size was handed out and not drawn yet.
+    configure_pending: bool,
 }
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub enum Event {
     Resize(u32, u32),
+    /// The preferred buffer scale changed, possibly to a fractional value.
+    ScaleChanged(f64),
     Key(crate::input::Key),
+    /// A mouse button was pressed over the terminal grid.
+    MousePress(crate::input::MouseInput),
+    /// The left button went down at a surface-local position below the titlebar, starting a
+    /// selection that is rectangular if Alt was held.
+    SelectionStart { position: (f64, f64), rectangular: bool },
+    /// The pointer moved to a position, as in `SelectionStart`, with the left button down.
+    SelectionUpdate((f64, f64)),
+    /// The left button was released.
+    SelectionEnd,
+    /// The compositor is ready for a new frame.
+    Frame,
+    /// The compositor presented or discarded a frame.
+    Presented(Presentation),
+    /// The window gained or lost keyboard focus.
+    Focus(bool),
+    /// Wheel or touchpad scrolling in wheel steps, positive towards older output.
+    Scroll(f64),
+    /// The window moved to another output, or that output's properties changed.
+    OutputChanged(Option<OutputInfo>),
+    /// Bytes to write to the PTY as they are, from a headless script or standard input.
+    Input(Vec<u8>),
+    /// Text pasted from the clipboard.
+    Paste(String),
+    /// A headless script asked for the screen on standard output.
+    Dump(crate::headless::DumpFormat),
     Close,
 }
 
-impl Display {
-    pub async fn new(config: &Config) -> Result<Self> {
-        let (mut wayland_state, connection, mut event_queue) = 
-            WaylandState::new(config).context("Failed to create Wayland state")?;
+/// What font sizing needs to know about the output the window is on.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct OutputInfo {
+    /// Connector name such as "eDP-1", if the compositor advertises one.
+    pub name: Option<String>,
+    /// Physical size in millimeters, `(0, 0)` when unknown.
+    pub physical_size: (i32, i32),
+    /// Size of the current mode in pixels.
+    pub mode_size: (i32, i32),
+    /// Integer scale the compositor applies to the output.
+    pub scale_factor: i32,
+}
+
+impl OutputInfo {
+    /// Dots per inch of a surface-local pixel, i.e. the physical DPI divided by the output scale.
+    pub fn dpi(&self) -> Option<f64> {
+        let (width_mm, height_mm) = self.physical_size;
+        let (width, height) = self.mode_size;
+        if width_mm <= 0 || height_mm <= 0 || width <= 0 || height <= 0 {
+            return None;
+        }
+        
+        // Diagonals don't care whether the output is rotated
+        let inches = (width_mm as f64).hypot(height_mm as f64) / 25.4;
+        let pixels = (width as f64).hypot(height as f64);
+        Some(pixels / inches / self.scale_factor.max(1) as f64)
+    }
+}
+
+/// The output a window overlapping `entered`, oldest first, is sized for: the one with the
+/// highest scale, as the compositor asks for buffers at that scale, and of those the one
+/// entered last.
+pub fn effective_output(entered: &[OutputInfo]) -> Option<&OutputInfo> {
+    entered.iter().max_by_key(|output| output.scale_factor)
+}
+
+/// The events the Wayland handlers send during dispatch, handed out one at a time in the
+/// order they were sent, however many came in one dispatch.
+pub struct HandlerEvents {
+    receiver: Receiver<Event>,
+    /// Events taken from the channel and not handed out yet, with resizes coalesced.
+    queued: VecDeque<Event>,
+}
+
+impl HandlerEvents {
+    pub fn new(receiver: Receiver<Event>) -> Self {
+        Self { receiver, queued: VecDeque::new() }
+    }
+    
+    /// The oldest event not handed out yet, without waiting for more.
+    pub fn pop(&mut self) -> Option<Event> {
+        self.take();
+        self.queued.pop_front()
+    }
+    
+    /// Whether a resize is waiting to be handed out.
+    pub fn resize_pending(&mut self) -> bool {
+        self.take();
+        self.queued.iter().any(|event| matches!(event, Event::Resize(..)))
+    }
+    
+    fn take(&mut self) {
+        for event in self.receiver.try_iter() {
+            queue_event(&mut self.queued, event);
+        }
+    }
+}
+
+/// Queues `event` to be handed out after those in `queue`. A resize replaces any still
+/// queued, as configures come faster than a drag can be drawn and only the latest counts.
+pub fn queue_event(queue: &mut VecDeque<Event>, event: Event) {
+    if matches!(event, Event::Resize(..)) {
+        queue.retain(|queued| !matches!(queued, Event::Resize(..)));
+    }
+    queue.push_back(event);
+}
+
+/// What the app shows the terminal on and takes input from: a Wayland window, or a
+/// `HeadlessDisplay` for tests and scripting. Everything but input and sizing can be left
+/// out by a display that shows nothing.
+// The app runs on the thread that owns the display, so the futures needn't be `Send`
+#[allow(async_fn_in_trait)]
+pub trait Display {
+    /// Waits for the next input or window event.
+    async fn next_event(&mut self) -> Result<Event>;
+    
+    /// Surface-local size available to the terminal grid.
+    fn content_size(&self) -> (u32, u32);
+    
+    /// The last window size that was neither maximized nor fullscreen.
+    fn windowed_size(&self) -> (u32, u32);
+    
+    /// Fits the grid to a window content size of `width`x`height`, returning its cells and
+    /// padding for `Terminal::set_geometry`, so the grid, the PTY and the frames agree.
+    fn resize(&mut self, width: u32, height: u32) -> GridGeometry;
+    
+    /// Switches to the font for `font.family`, keeping the current one if it can't be loaded.
+    ///
+    /// Returns the surface-local cell size of the new font.
+    fn set_font(&mut self, font: &FontConfig) -> Result<(u32, u32)>;
+    
+    /// Sets the font size in points, returning the surface-local cell size it gives.
+    fn set_font_size(&mut self, points: f32) -> Result<(u32, u32)>;
+    
+    /// Takes effect on the next `resize`.
+    fn set_padding(&mut self, padding: Padding, dynamic: bool);
+    
+    /// Saves the screen as `screenshot::save` does, at the surface-local size of the window.
+    fn screenshot(&mut self, terminal: &Terminal, path: &Path) -> Result<()>;
+    
+    /// Marks the window as out of date, to be drawn by `draw_if_due`.
+    fn request_redraw(&mut self) {}
+    
+    /// When to stop waiting for the frame that's due to be drawn.
+    fn frame_deadline(&self) -> Option<Instant> {
+        None
+    }
+    
+    /// Stops waiting, so the next frame is drawn as soon as it may be.
+    fn frame_timed_out(&mut self) {}
+    
+    /// Draws a frame if something changed and the window is ready for it.
+    async fn draw_if_due(&mut self, _terminal: &Terminal) -> Result<()> {
+        Ok(())
+    }
+    
+    /// Takes effect on the next rendered frame.
+    fn set_opacity(&mut self, _opacity: f32) {}
+    
+    fn is_focused(&self) -> bool {
+        true
+    }
+    
+    /// Hides the pointer over the window until the mouse is used again.
+    fn hide_pointer(&mut self) {}
+    
+    fn set_title(&mut self, _title: &str) {}
+    
+    /// Puts `text` on the clipboard.
+    fn set_clipboard(&mut self, _text: String) {}
+    
+    /// Asks for the clipboard's text, handed out later as an `Event::Paste`.
+    fn request_paste(&mut self) {}
+    
+    /// Makes `text` the primary selection, which middle-click pastes.
+    fn set_primary_selection(&mut self, _text: String) {}
+    
+    /// Asks for the primary selection's text, handed out later as an `Event::Paste`.
+    fn request_primary_paste(&mut self) {}
+    
+    fn request_urgency(&mut self) {}
+    
+    /// Flips the cursor and text blink phases, returning whether the cursor is now shown.
+    fn toggle_blink(&mut self) -> bool {
+        true
+    }
+    
+    /// Takes effect on the next rendered frame.
+    fn set_allow_blink(&mut self, _allow: bool) {}
+    
+    /// Takes effect on the next rendered frame.
+    fn set_scrollback_indicator(&mut self, _indicator: ScrollbackIndicator) {}
+    
+    /// Tints the window for the visual bell from the next frame on, or stops with `None`.
+    fn set_bell_flash(&mut self, _color: Option<RGB8>) {}
+    
+    /// Counts `time` spent parsing output toward the next frame's metrics.
+    fn add_parse_time(&mut self, _time: Duration) {}
+    
+    /// Notes output that arrived at `arrival` for the next frame, whose latency is measured
+    /// from the oldest output it shows.
+    fn add_output(&mut self, _arrival: Option<std::time::Instant>) {}
+    
+    /// Shows `prompt` over the bottom row from the next frame on, or stops with `None`.
+    fn set_prompt(&mut self, _prompt: Option<&str>) {}
+    
+    /// Shows `search` from the next frame on, or stops with `None`, drawing a frame if that
+    /// changes anything.
+    fn set_search(&mut self, _search: Option<&Search>) {}
+    
+    /// When the scrollbar starts to fade out, for waking up to draw it. The fade itself is
+    /// drawn a frame at a time.
+    fn scrollbar_deadline(&self) -> Option<Instant> {
+        None
+    }
+    
+    /// Whether the last frame had text that blinks, so the blink timer has to keep running.
+    fn has_blinking_text(&self) -> bool {
+        false
+    }
+    
+    /// Shows the cursor again, restarting the blink cycle.
+    fn reset_blink(&mut self) {}
+}
+
+impl WaylandDisplay {
+    /// Opens the window, asking for `size`, or the size the config asks for if `None`, unless
+    /// the config starts it maximized or fullscreen.
+    pub async fn new(config: &Config, size: Option<(u32, u32)>) -> Result<Self> {
+        // The font comes first, as sizing the window in cells needs its metrics
+        let mut glyphs = GlyphCache::new(FontSet::load(&config.font)?, render::GLYPH_CACHE_BYTES);
+        glyphs.set_pixel_size(config::points_to_pixels(config.font.size, 1.0).round() as u32)?;
+        let metrics = glyphs.metrics();
+        let size = size.unwrap_or_else(|| config.display.window_size((metrics.cell_width, metrics.cell_height)));
+        
+        let (mut wayland_state, connection, mut event_queue, event_receiver) = 
+            WaylandState::new(config)?;
+        let (paste_sender, pastes) = mpsc::unbounded_channel();
             
         let qh = event_queue.handle();
-        wayland_state.create_window(&qh).context("Failed to create window")?;
+        // Outputs announce their names before the window may ask to go fullscreen on one
+        event_queue.roundtrip(&mut wayland_state)
+            .context("Failed to process output events").map_err(MyTermError::wayland)?;
+        wayland_state.create_window(&qh, size)?;
         
         // Process initial events to set up the window
         event_queue.roundtrip(&mut wayland_state)
-            .context("Failed to process initial events")?;
+            .context("Failed to process initial events").map_err(MyTermError::wayland)?;
+        
+        let fd = connection.backend().poll_fd().as_raw_fd();
+        let wayland_fd = AsyncFd::new(fd)
+            .context("Failed to register Wayland socket")
+            .map_err(MyTermError::wayland)?;
+        
+        let surface = wayland_state.window.as_ref().map(|w| w.wl_surface().clone())
+            .context("Window was not created").map_err(MyTermError::wayland)?;
+        let renderer = renderer::create(config.display.renderer, &connection, &surface).await;
+        log::info!("Rendering with the {} renderer", renderer.name());
         
         Ok(Self {
+            renderer,
             wayland_state,
             connection,
             event_queue,
-            event_receiver: None,
+            wayland_fd,
+            events: HandlerEvents::new(event_receiver),
+            paste_sender,
+            pastes,
+            glyphs,
+            font_size: config.font.size,
+            cell_size: (metrics.cell_width, metrics.cell_height),
+            padding: config.display.padding,
+            dynamic_padding: config.display.dynamic_padding,
+            opacity: config.display.opacity,
+            blink_visible: true,
+            text_blink_visible: true,
+            allow_blink: config.terminal.allow_blink,
+            has_blinking_text: false,
+            scrollback_indicator: config.display.scrollback_indicator,
+            display_offset: 0,
+            scrolled_at: None,
+            bell_flash: None,
+            scaled_images: Vec::new(),
+            prompt: None,
+            search: None,
+            metrics: RenderMetrics::new(std::time::Instant::now()),
+            debug_hud: std::env::var(metrics::DEBUG_HUD_VAR).is_ok_and(|value| value == "1"),
+            parse_time: Duration::ZERO,
+            output_since: None,
+            pacer: FramePacer::new(),
+            needs_redraw: true,
+            frame_requested_at: None,
+            configure_pending: false,
         })
     }
     
-    pub async fn next_event(&mut self) -> Result<Event> {
+    fn note_event(&mut self, event: Event) -> Event {
+        match &event {
+            Event::Resize(..) => self.configure_pending = true,
+            Event::Frame => self.frame_requested_at = None,
+            Event::Presented(presentation) => {
+                self.pacer.presented(presentation);
+                if let (Some(at), Some(since)) = (presentation.at, presentation.output_since) {
+                    if self.debug_hud || log::log_enabled!(log::Level::Debug) {
+                        self.metrics.record_latency(at.saturating_duration_since(since));
+                    }
+                }
+            }
+            _ => {}
+        }
+        event
+    }
+    
+    /// Glyph cache counters, for the debug overlay.
+    #[allow(dead_code)]
+    pub fn glyph_cache_stats(&self) -> GlyphCacheStats {
+        self.glyphs.stats()
+    }
+    
+    /// The images in view at their place in the buffer, scaled again only when new or when the
+    /// scale changed.
+    fn frame_images(&mut self, terminal: &Terminal, layout: &GridLayout, scale: f64) -> Vec<FrameImage> {
+        let grid = terminal.grid();
+        let mut scaled = Vec::new();
+        let mut images = Vec::new();
+        for placed in terminal.images() {
+            let row = placed.row + grid.display_offset as isize;
+            if row >= grid.rows as isize || row + placed.rows as isize <= 0 {
+                continue;
+            }
+            let size = |length: usize| (length as f64 * scale).round() as usize;
+            let (width, height) = (size(placed.image.width), size(placed.image.height));
+            let bitmap = self.scaled_images.iter()
+                .find(|(image, bitmap)| Arc::ptr_eq(image, &placed.image) && (bitmap.width, bitmap.height) == (width, height))
+                .map(|(_, bitmap)| bitmap.clone())
+                .unwrap_or_else(|| Arc::new(render::scale_image(&placed.image, width, height)));
+            scaled.push((placed.image.clone(), bitmap.clone()));
+            images.push(FrameImage {
+                origin: (
+                    layout.origin.0 as i32 + (placed.col as i32 - grid.col_offset as i32) * layout.cell_width as i32,
+                    layout.origin.1 as i32 + row as i32 * layout.cell_height as i32,
+                ),
+                bitmap,
+            });
+        }
+        self.scaled_images = scaled;
+        images
+    }
+    
+    /// The scrollbar for the last frame's view, if one is showing.
+    fn scrollbar(&mut self, terminal: &Terminal, layout: &GridLayout, width: usize) -> Option<Scrollbar> {
+        let elapsed = self.scrolled_at?.elapsed();
+        let Some(fading) = elapsed.checked_sub(SCROLLBAR_SHOWN) else {
+            return self.scrollbar_at(terminal, layout, width, 1.0);
+        };
+        if fading >= SCROLLBAR_FADE {
+            self.scrolled_at = None;
+            return None;
+        }
+        self.scrollbar_at(terminal, layout, width, 1.0 - fading.as_secs_f32() / SCROLLBAR_FADE.as_secs_f32())
+    }
+    
+    /// Our titlebar, `height` buffer pixels across a `width` wide frame, dimmed while the window
+    /// is unfocused.
+    fn titlebar(&self, width: usize, height: usize, cell_width: usize, palette: &Palette) -> Titlebar {
+        let (mut bar, mut fg) = (palette.bright[0], palette.foreground);
+        if !self.wayland_state.is_focused() {
+            bar = render::dim(bar, palette.background);
+            fg = render::dim(fg, bar);
+        }
+        render::titlebar(width, height, cell_width, self.wayland_state.title(), render::argb_pixel(bar, 1.0), fg, palette.background)
+    }
+    
+    fn scrollbar_at(&self, terminal: &Terminal, layout: &GridLayout, width: usize, alpha: f32) -> Option<Scrollbar> {
+        let grid = terminal.grid();
+        let track = grid.rows * layout.cell_height;
+        let thumb = render::scrollbar_thumb(track, grid.rows, grid.scrollback.len(), grid.display_offset)?;
+        let bar_width = (render::SCROLLBAR_WIDTH as f64 * self.wayland_state.scale()).round() as usize;
+        let bar_width = bar_width.clamp(1, width.max(1));
+        Some(Scrollbar {
+            thumb: Rect { x: width - bar_width, y: layout.origin.1 + thumb.start, width: bar_width, height: thumb.len() },
+            pixel: render::argb_pixel(terminal.screen_palette().foreground, 0.5 * alpha),
+        })
+    }
+    
+    /// Draws a frame now. Nothing is drawn, leaving the frame due, before the first configure
+    /// and while a synchronized update is in progress.
+    async fn render(&mut self, terminal: &Terminal) -> Result<()> {
+        // Attaching a buffer before the first configure is a protocol error
+        if !self.wayland_state.is_configured() {
+            return Ok(());
+        }
+        // Half-drawn frames of a synchronized update are never shown
+        if terminal.modes().synchronized_output {
+            return Ok(());
+        }
+        let Some(surface) = self.wayland_state.window.as_ref().map(|w| w.wl_surface().clone()) else {
+            return Ok(());
+        };
+        
+        let (buffer_width, buffer_height) = self.wayland_state.buffer_size();
+        log::debug!("Rendering terminal with {} rows, {} columns into a {}x{} buffer at scale {}", 
+                   terminal.grid().rows, terminal.grid().cols,
+                   buffer_width, buffer_height, self.wayland_state.scale());
+        
+        // Only the background is translucent; text is drawn over it at full alpha
+        let palette = &terminal.screen_palette();
+        let alpha = render::background_alpha(palette.background_alpha, self.opacity);
+        let background = render::argb_pixel(palette.background, alpha);
+        
+        let scale = self.wayland_state.scale();
+        let to_buffer = |length: u32| (length as f64 * scale).round() as usize;
+        let titlebar_rows = to_buffer(self.wayland_state.titlebar_height());
+        
+        let (cell_width, cell_height) = terminal.cell_size();
+        let padding = terminal.grid_padding();
+        let layout = GridLayout {
+            origin: (to_buffer(padding.left), titlebar_rows + to_buffer(padding.top)),
+            cell_width: to_buffer(cell_width).max(1),
+            cell_height: to_buffer(cell_height).max(1),
+        };
+        self.glyphs.set_pixel_size(config::points_to_pixels(self.font_size, scale).round() as u32)?;
+        
+        let grid = terminal.grid();
+        let cursor = terminal.cursor();
+        let row = cursor.row + grid.display_offset;
+        let col = cursor.col.checked_sub(grid.col_offset);
+        let cursor = (self.prompt.is_none() && self.search.is_none() && terminal.modes().cursor_visible && (self.blink_visible || !cursor.blink) && row < grid.rows)
+            .then_some(col).flatten()
+            .map(|col| CursorSprite {
+                point: Point::new(row, col),
+                shape: cursor.shape.clone(),
+                hollow: !self.wayland_state.is_focused(),
+            });
+        
+        let mut rows = terminal.styled_snapshot();
+        self.has_blinking_text = render::blink_text(&mut rows, self.text_blink_visible || !self.allow_blink);
+        // Search matches in view are shown in yellow, and the one the view follows in red
+        if let Some(search) = &self.search {
+            let top = grid.absolute_line(0);
+            let in_view = |m: &SearchMatch| (top..top + grid.rows).contains(&m.line).then(|| (m.line - top, m.start.saturating_sub(grid.col_offset)..m.end.saturating_sub(grid.col_offset)));
+            let spans: Vec<_> = search.matches().iter().filter_map(in_view).collect();
+            render::highlight_spans(&mut rows, &spans, palette.background, palette.normal[3]);
+            let focused: Vec<_> = search.focused().and_then(in_view).into_iter().collect();
+            render::highlight_spans(&mut rows, &focused, palette.background, palette.normal[1]);
+        }
+        
+        // Scrolling shows the scrollbar again; it is gone as soon as the view is back down
+        if grid.display_offset != self.display_offset {
+            self.display_offset = grid.display_offset;
+            self.scrolled_at = (grid.display_offset > 0).then(Instant::now);
+        }
+        let scrollbar = match self.scrollback_indicator {
+            ScrollbackIndicator::Bar => self.scrollbar(terminal, &layout, buffer_width as usize),
+            ScrollbackIndicator::Badge => {
+                if grid.display_offset > 0 {
+                    let badge = format!("[{}/{}]", grid.display_offset, grid.scrollback.len());
+                    render::overlay_badge(&mut rows, &badge, palette.background, palette.foreground);
+                }
+                None
+            }
+            ScrollbackIndicator::None => None,
+        };
+        if self.debug_hud {
+            render::overlay_hud(&mut rows, &self.metrics.summary(), palette.background, palette.foreground);
+        }
+        if let Some(search) = &self.search {
+            render::overlay_prompt(&mut rows, &search.status(), palette.background, palette.normal[3]);
+        }
+        if let Some(prompt) = &self.prompt {
+            render::overlay_prompt(&mut rows, prompt, palette.background, palette.foreground);
+        }
+        let fading = scrollbar.is_some() && self.scrollbar_deadline().is_none();
+        let images = self.frame_images(terminal, &layout, scale);
+        
+        let frame = Frame {
+            size: (buffer_width as usize, buffer_height as usize),
+            layout,
+            background,
+            titlebar: (titlebar_rows > 0).then(|| self.titlebar(buffer_width as usize, titlebar_rows, layout.cell_width, palette)),
+            palette: palette.clone(),
+            rows,
+            cursor,
+            images,
+            scrollbar,
+            flash: self.bell_flash.map(|color| render::argb_pixel(color, render::FLASH_ALPHA)),
+        };
+        
+        self.wayland_state.apply_buffer_scale(&surface);
+        self.wayland_state.set_opaque(alpha >= 1.0);
+        let started = std::time::Instant::now();
+        let size = frame.size;
+        let target = Target { surface: &surface, shm: self.wayland_state.shm(), qh: &self.event_queue.handle() };
+        // Asked for before the renderer commits, so it comes with this frame, or the next if
+        // there's nothing free to draw into
+        if self.wayland_state.request_presentation_feedback(&surface, &self.event_queue.handle(), self.output_since.take()) {
+            self.pacer.committing();
+        }
+        // With nothing free to draw into, as while the compositor holds every buffer, the frame
+        // stays due and is retried on the next frame callback or timeout
+        let Some(presented) = self.renderer.present(frame, &mut self.glyphs, &target)? else {
+            self.frame_requested_at = Some(Instant::now());
+            return Ok(());
+        };
+        let coverage = metrics::damage_coverage(&presented.damage, size);
+        
+        // A few clock reads per frame cost next to nothing; the metrics are only kept while
+        // something shows them
+        let committed = std::time::Instant::now();
+        if self.debug_hud || log::log_enabled!(log::Level::Debug) {
+            let timings = FrameTimings {
+                parse: self.parse_time,
+                raster: presented.raster,
+                blit: presented.blit,
+                commit: (committed - started).saturating_sub(presented.raster + presented.blit),
+            };
+            self.metrics.record(committed, timings, coverage);
+            if self.metrics.log_due(committed, self.glyphs.stats()) {
+                log::debug!("Render metrics: {}", self.metrics.summary());
+            }
+        }
+        self.parse_time = Duration::ZERO;
+        // The fade goes on with the next frame
+        self.needs_redraw = fading;
+        self.frame_requested_at = Some(Instant::now());
+        self.configure_pending = false;
+        
+        Ok(())
+    }
+}
+
+impl Display for WaylandDisplay {
+    async fn next_event(&mut self) -> Result<Event> {
         loop {
-            // Process Wayland events
-            if let Err(e) = self.event_queue.dispatch_pending(&mut self.wayland_state) {
-                // Handle dispatch errors appropriately
-                return Err(e.into());
+            // Hand out events left over from an earlier dispatch first
+            if let Some(event) = self.events.pop() {
+                return Ok(self.note_event(event));
             }
             
-            // Check for exit condition
-            if self.wayland_state.should_exit() {
-                return Ok(Event::Close);
+            // Run the handlers for anything already read from the socket
+            self.event_queue.dispatch_pending(&mut self.wayland_state)
+                .context("Failed to dispatch Wayland events").map_err(MyTermError::wayland)?;
+            
+            if let Some(event) = self.events.pop() {
+                return Ok(self.note_event(event));
             }
             
-            // Wait for more events
-            self.connection.flush().context("Failed to flush connection")?;
+            self.connection.flush()
+                .context("Failed to flush connection")
+                .map_err(MyTermError::wayland)?;
             
-            match self.event_queue.prepare_read() {
-                Some(guard) => {
-                    match guard.read() {
-                        Ok(_) => {},
-                        Err(e) => {
-                            // Check if this is a WouldBlock error and handle it gracefully
-                            let error_string = format!("{:?}", e);
-                            if error_string.contains("EAGAIN") || error_string.contains("WouldBlock") {
-                                continue;
-                            }
-                            return Err(anyhow::Error::from(e)).context("Failed to read events");
-                        }
-                    }
-                }
-                None => {
-                    // Events are pending, process them in the next loop iteration
-                    continue;
+            // Events already queued are handled on the next iteration
+            let Some(guard) = self.event_queue.prepare_read() else {
+                continue;
+            };
+            
+            // Sleep until the compositor sends something instead of polling the socket, or
+            // a paste has been read
+            let mut ready = tokio::select! {
+                ready = self.wayland_fd.readable() => ready
+                    .context("Failed to wait for Wayland events").map_err(MyTermError::wayland)?,
+                Some(event) = self.pastes.recv() => return Ok(event),
+            };
+            
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    ready.clear_ready();
                 }
+                Err(e) => return Err(MyTermError::wayland(anyhow::Error::from(e).context("Failed to read events"))),
             }
         }
     }
     
-    pub async fn render(&mut self, terminal: &Terminal) -> Result<()> {
-        // For now, this is a stub. In a complete implementation, this would:
-        // 1. Create a shared memory buffer
-        // 2. Render the terminal grid to the buffer using font rendering
-        // 3. Attach the buffer to the surface and commit
-        
-        log::debug!("Rendering terminal with {} rows, {} columns", 
-                   terminal.grid().rows, terminal.grid().cols);
-        
-        // Commit any pending changes to the surface
-        if let Some(ref window) = self.wayland_state.window {
-            window.wl_surface().commit();
+    fn request_redraw(&mut self) {
+        self.needs_redraw = true;
+    }
+    
+    fn frame_deadline(&self) -> Option<Instant> {
+        if !self.needs_redraw {
+            return None;
+        }
+        match self.frame_requested_at {
+            Some(requested) => Some(requested + FRAME_CALLBACK_TIMEOUT),
+            None => self.pacer.hold_until(std::time::Instant::now()).map(Instant::from_std),
         }
-        
-        Ok(())
     }
     
-    #[allow(dead_code)]
-    pub fn resize(&mut self, width: u32, height: u32) -> Result<()> {
-        log::debug!("Display resize: {}x{}", width, height);
+    fn frame_timed_out(&mut self) {
+        if self.frame_requested_at.take().is_some() {
+            log::debug!("No frame callback within {:?}, drawing anyway", FRAME_CALLBACK_TIMEOUT);
+        }
+    }
+    
+    async fn draw_if_due(&mut self, terminal: &Terminal) -> Result<()> {
+        // The first change after an idle period is drawn immediately; changes arriving while
+        // the compositor is busy with that frame are drawn together once it asks for the next,
+        // and while the output floods, no sooner than the next refresh
+        let paced = self.pacer.hold_until(std::time::Instant::now()).is_some();
+        // The window's size already follows the latest configure, so the grid is fitted to it
+        // before anything is drawn into a buffer of that size
+        if self.events.resize_pending() {
+            return Ok(());
+        }
+        // The compositor keeps showing the window at its old size until a buffer of the
+        // configured one arrives, so that frame doesn't wait for a frame callback or a refresh
+        let due = self.configure_pending || (self.frame_requested_at.is_none() && !paced);
+        if self.needs_redraw && due {
+            self.render(terminal).await?;
+        }
         Ok(())
     }
+    
+    fn content_size(&self) -> (u32, u32) {
+        self.wayland_state.content_size()
+    }
+    
+    fn windowed_size(&self) -> (u32, u32) {
+        self.wayland_state.windowed_size()
+    }
+    
+    fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity;
+    }
+    
+    fn is_focused(&self) -> bool {
+        self.wayland_state.is_focused()
+    }
+    
+    fn hide_pointer(&mut self) {
+        self.wayland_state.hide_pointer(&self.connection);
+    }
+    
+    fn set_title(&mut self, title: &str) {
+        self.wayland_state.set_title(title);
+        if self.wayland_state.titlebar_height() > 0 {
+            self.needs_redraw = true;
+        }
+    }
+    
+    fn set_clipboard(&mut self, text: String) {
+        let qh = self.event_queue.handle();
+        self.wayland_state.set_clipboard(&qh, text);
+    }
+    
+    fn request_paste(&mut self) {
+        self.wayland_state.request_paste(&self.paste_sender);
+    }
+    
+    fn set_primary_selection(&mut self, text: String) {
+        let qh = self.event_queue.handle();
+        self.wayland_state.set_primary_selection(&qh, text);
+    }
+    
+    fn request_primary_paste(&mut self) {
+        self.wayland_state.request_primary_paste(&self.paste_sender);
+    }
+    
+    fn request_urgency(&mut self) {
+        let qh = self.event_queue.handle();
+        self.wayland_state.request_urgency(&qh);
+    }
+    
+    fn set_font(&mut self, font: &FontConfig) -> Result<(u32, u32)> {
+        self.glyphs.set_fonts(FontSet::load(font)?)?;
+        self.renderer.invalidate();
+        self.set_font_size(self.font_size)
+    }
+    
+    fn set_font_size(&mut self, points: f32) -> Result<(u32, u32)> {
+        self.font_size = points;
+        self.glyphs.set_pixel_size(config::points_to_pixels(points, 1.0).round() as u32)?;
+        let metrics = self.glyphs.metrics();
+        self.cell_size = (metrics.cell_width, metrics.cell_height);
+        self.wayland_state.set_cell_grid(self.cell_size, self.padding);
+        Ok(self.cell_size)
+    }
+    
+    fn screenshot(&mut self, terminal: &Terminal, path: &Path) -> Result<()> {
+        self.glyphs.set_pixel_size(config::points_to_pixels(self.font_size, 1.0).round() as u32)?;
+        screenshot::save(path, terminal, self.opacity, &mut self.glyphs)
+    }
+    
+    fn set_padding(&mut self, padding: Padding, dynamic: bool) {
+        self.padding = padding;
+        self.dynamic_padding = dynamic;
+        self.wayland_state.set_cell_grid(self.cell_size, padding);
+    }
+    
+    fn toggle_blink(&mut self) -> bool {
+        self.blink_visible = !self.blink_visible;
+        self.text_blink_visible = !self.text_blink_visible;
+        self.blink_visible
+    }
+    
+    fn set_allow_blink(&mut self, allow: bool) {
+        self.allow_blink = allow;
+    }
+    
+    fn set_scrollback_indicator(&mut self, indicator: ScrollbackIndicator) {
+        self.scrollback_indicator = indicator;
+    }
+    
+    fn set_bell_flash(&mut self, color: Option<RGB8>) {
+        self.bell_flash = color;
+    }
+    
+    fn add_parse_time(&mut self, time: Duration) {
+        self.parse_time += time;
+    }
+    
+    fn add_output(&mut self, arrival: Option<std::time::Instant>) {
+        self.output_since = self.output_since.or(arrival);
+    }
+    
+    fn set_prompt(&mut self, prompt: Option<&str>) {
+        self.prompt = prompt.map(str::to_string);
+    }
+    
+    fn set_search(&mut self, search: Option<&Search>) {
+        if self.search.as_ref() != search {
+            self.search = search.cloned();
+            self.needs_redraw = true;
+        }
+    }
+    
+    fn scrollbar_deadline(&self) -> Option<Instant> {
+        self.scrolled_at
+            .map(|scrolled_at| scrolled_at + SCROLLBAR_SHOWN)
+            .filter(|&deadline| deadline > Instant::now())
+    }
+    
+    fn has_blinking_text(&self) -> bool {
+        self.allow_blink && self.has_blinking_text
+    }
+    
+    fn reset_blink(&mut self) {
+        if !self.blink_visible {
+            self.blink_visible = true;
+            self.needs_redraw = true;
+        }
+    }
+    
+    /// The next frame is drawn in full, into buffers of the new size that the renderer
+    /// allocates as it draws it.
+    fn resize(&mut self, width: u32, height: u32) -> GridGeometry {
+        let geometry = terminal::grid_geometry((width, height), self.cell_size, self.padding, self.dynamic_padding);
+        log::debug!("Display resize: {}x{}, {}x{} cells", width, height, geometry.cols, geometry.rows);
+        self.renderer.invalidate();
+        self.needs_redraw = true;
+        geometry
+    }
 }
\ No newline at end of file
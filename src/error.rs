@@ -0,0 +1,73 @@
+use crate::wayland::ConnectionError;
+
+pub type Result<T, E = MyTermError> = std::result::Result<T, E>;
+
+/// Errors returned by the library API, by what failed.
+///
+/// Each variant wraps the underlying error with its context, and displays as it does.
+#[derive(Debug, thiserror::Error)]
+pub enum MyTermError {
+    /// A config file couldn't be located, read or parsed, or holds invalid settings.
+    #[error(transparent)]
+    Config(anyhow::Error),
+    /// Opening, spawning into, reading from or writing to the pseudoterminal failed.
+    #[error(transparent)]
+    Pty(anyhow::Error),
+    /// No connection to a compositor could be made, telling a missing display apart.
+    #[error(transparent)]
+    Connection(#[from] ConnectionError),
+    /// Talking to the compositor failed.
+    #[error(transparent)]
+    Wayland(anyhow::Error),
+    /// No usable font was found for a configured family, or it couldn't be opened.
+    #[error(transparent)]
+    Font(anyhow::Error),
+    /// A key binding, color or command-line argument is malformed.
+    #[error(transparent)]
+    Parse(anyhow::Error),
+    /// A screenshot couldn't be written.
+    #[error(transparent)]
+    Screenshot(anyhow::Error),
+    /// The GPU renderer couldn't be set up, or drawing with it failed.
+    #[cfg(feature = "gpu")]
+    #[error(transparent)]
+    Gpu(anyhow::Error),
+}
+
+impl MyTermError {
+    pub(crate) fn config(error: impl Into<anyhow::Error>) -> Self {
+        Self::Config(error.into())
+    }
+    
+    pub(crate) fn pty(error: impl Into<anyhow::Error>) -> Self {
+        Self::Pty(error.into())
+    }
+    
+    pub(crate) fn wayland(error: impl Into<anyhow::Error>) -> Self {
+        Self::Wayland(error.into())
+    }
+    
+    pub(crate) fn font(error: impl Into<anyhow::Error>) -> Self {
+        Self::Font(error.into())
+    }
+    
+    pub(crate) fn parse(error: impl Into<anyhow::Error>) -> Self {
+        Self::Parse(error.into())
+    }
+    
+    pub(crate) fn screenshot(error: impl Into<anyhow::Error>) -> Self {
+        Self::Screenshot(error.into())
+    }
+    
+    #[cfg(feature = "gpu")]
+    pub(crate) fn gpu(error: impl Into<anyhow::Error>) -> Self {
+        Self::Gpu(error.into())
+    }
+}
+
+/// Errors from nix calls, which are only made on the PTY.
+impl From<nix::Error> for MyTermError {
+    fn from(error: nix::Error) -> Self {
+        Self::Pty(error.into())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,615 @@
+use anyhow::anyhow;
+use fontconfig::{sort_fonts, Fontconfig, Pattern, FC_FAMILY, FC_SLANT, FC_SLANT_ITALIC, FC_SLANT_ROMAN, FC_WEIGHT, FC_WEIGHT_BOLD, FC_WEIGHT_REGULAR};
+use freetype::freetype as ft;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::config::FontConfig;
+use crate::error::{MyTermError, Result};
+
+/// DejaVu Sans Mono, used when no configured or system monospace font can be loaded.
+pub const EMBEDDED_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSansMono.ttf");
+
+/// Family names fontconfig resolves to whatever font the system prefers, rather than naming one.
+const GENERIC_FAMILIES: &[&str] = &["monospace", "mono", "sans-serif", "sans", "serif"];
+
+/// A FreeType face rasterizing glyphs at one pixel size at a time.
+pub struct Font {
+    library: ft::FT_Library,
+    face: ft::FT_Face,
+    /// Pixel size the face is set to, 0 until `set_pixel_size` is called.
+    pixel_size: u32,
+    /// Thicken outlines, for a regular face standing in for a missing bold one.
+    embolden: bool,
+    /// Slant outlines, for an upright face standing in for a missing italic one.
+    oblique: bool,
+}
+
+/// The regular, bold, italic and bold italic faces of a font, sized together.
+pub struct FontSet {
+    faces: [Font; 4],
+    fallback: Fallback,
+}
+
+/// Code points per block that a fallback font is remembered for, so that the characters
+/// around one that needed a fallback find it first.
+const FALLBACK_BLOCK: u32 = 128;
+
+/// Fonts searched in turn for characters the terminal font has no glyph for.
+pub struct Fallback {
+    /// Fonts opened so far, with the terminal font pixel size each was last sized for.
+    fonts: Vec<(Font, u32)>,
+    /// System fonts not opened yet, as files and face indices, best first.
+    system: VecDeque<(PathBuf, i32)>,
+    /// The font that had the last glyph looked up in each block.
+    blocks: HashMap<u32, usize>,
+    /// Blocks a glyph was missing from every font in, already logged.
+    missing: HashSet<u32>,
+    /// Pixel size and cell height of the terminal font.
+    size: (u32, u32),
+}
+
+/// The four faces a terminal font comes in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontStyle {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+}
+
+/// Cell size and baseline of a font at its current size, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metrics {
+    /// Advance of every glyph in a monospace font.
+    pub cell_width: u32,
+    /// Ascent plus descent, without any line gap.
+    pub cell_height: u32,
+    /// Distance from the top of the cell to the baseline.
+    pub ascent: i32,
+    /// Distance from the baseline to the bottom of the cell.
+    pub descent: i32,
+    /// Distance from the baseline down to the top of an underline.
+    pub underline_position: i32,
+    /// Thickness of underlines and strikethrough lines.
+    pub underline_thickness: u32,
+    /// Distance from the baseline up to the middle of a strikethrough line, half the x-height.
+    pub strikeout_position: i32,
+}
+
+/// A rendered glyph as 8-bit coverage, positioned relative to the pen on the baseline.
+#[derive(Debug, Clone)]
+pub struct Glyph {
+    pub width: usize,
+    #[allow(dead_code)]
+    pub height: usize,
+    /// Offset from the pen position to the left edge of the bitmap.
+    pub left: i32,
+    /// Distance from the baseline up to the top edge of the bitmap.
+    pub top: i32,
+    /// `width * height` coverage values, row by row.
+    pub coverage: Vec<u8>,
+}
+
+impl FontStyle {
+    /// The family configured for this style, which defaults to the regular family.
+    pub fn family(self, font: &FontConfig) -> &str {
+        let family = match self {
+            FontStyle::Regular => None,
+            FontStyle::Bold => font.bold_family.as_deref(),
+            FontStyle::Italic => font.italic_family.as_deref(),
+            FontStyle::BoldItalic => font.bold_italic_family.as_deref(),
+        };
+        family.unwrap_or(&font.family)
+    }
+    
+    pub fn from_flags(bold: bool, italic: bool) -> Self {
+        match (bold, italic) {
+            (false, false) => FontStyle::Regular,
+            (true, false) => FontStyle::Bold,
+            (false, true) => FontStyle::Italic,
+            (true, true) => FontStyle::BoldItalic,
+        }
+    }
+    
+    fn is_bold(self) -> bool {
+        matches!(self, FontStyle::Bold | FontStyle::BoldItalic)
+    }
+    
+    fn is_italic(self) -> bool {
+        matches!(self, FontStyle::Italic | FontStyle::BoldItalic)
+    }
+}
+
+impl Font {
+    /// Loads `style` of the configured family.
+    ///
+    /// A family that isn't installed or isn't monospace falls back to the system's monospace
+    /// font. Failing that, styled faces are synthesized from the regular one and the regular
+    /// face falls back to the embedded font, so this only fails if FreeType does.
+    pub fn load(font: &FontConfig, style: FontStyle) -> Result<Self> {
+        let family = style.family(font);
+        let mut candidates = vec![family];
+        if !family.eq_ignore_ascii_case("monospace") {
+            candidates.push("monospace");
+        }
+        
+        for candidate in candidates {
+            match Self::find(candidate, style) {
+                Ok(font) => return Ok(font),
+                Err(e) => log::warn!("{:#}", e),
+            }
+        }
+        
+        if style != FontStyle::Regular {
+            log::warn!("Synthesizing {:?} from the regular face in place of {:?}", style, family);
+            let mut regular = Self::load(font, FontStyle::Regular)?;
+            regular.synthesize(style);
+            return Ok(regular);
+        }
+        
+        log::warn!("Using the embedded font in place of {:?}", family);
+        Self::embedded()
+    }
+    
+    /// Finds `style` of `family` through fontconfig, failing unless that family is installed
+    /// with the style and is monospace.
+    pub fn find(family: &str, style: FontStyle) -> Result<Self> {
+        let fontconfig = Fontconfig::new()
+            .ok_or_else(|| MyTermError::font(anyhow!("Failed to initialize fontconfig")))?;
+        let name = CString::new(family)
+            .map_err(|_| MyTermError::font(anyhow!("Invalid font family name {:?}", family)))?;
+        
+        let mut pattern = Pattern::new(&fontconfig);
+        pattern.add_string(FC_FAMILY, &name);
+        pattern.add_integer(FC_WEIGHT, if style.is_bold() { FC_WEIGHT_BOLD } else { FC_WEIGHT_REGULAR });
+        pattern.add_integer(FC_SLANT, if style.is_italic() { FC_SLANT_ITALIC } else { FC_SLANT_ROMAN });
+        let found = pattern.font_match();
+        
+        // fontconfig always matches something, so check it's what was asked for
+        let found_family = found.get_string(FC_FAMILY).unwrap_or_default();
+        let generic = GENERIC_FAMILIES.iter().any(|generic| generic.eq_ignore_ascii_case(family));
+        if !generic && !found_family.eq_ignore_ascii_case(family) {
+            return Err(MyTermError::font(anyhow!("Font family {:?} is not installed", family)));
+        }
+        let weight = found.weight().unwrap_or(FC_WEIGHT_REGULAR);
+        let slant = found.slant().unwrap_or(FC_SLANT_ROMAN);
+        if (weight >= FC_WEIGHT_BOLD) != style.is_bold() || (slant != FC_SLANT_ROMAN) != style.is_italic() {
+            return Err(MyTermError::font(anyhow!("Font family {:?} has no {:?} face", family, style)));
+        }
+        
+        let path = found.filename()
+            .ok_or_else(|| MyTermError::font(anyhow!("Font family {:?} has no file", family)))?;
+        let font = Self::from_file(Path::new(path), found.face_index().unwrap_or(0))?;
+        if !font.is_monospace() {
+            return Err(MyTermError::font(anyhow!("Font family {:?} ({}) is not monospace", family, path)));
+        }
+        
+        log::info!("Using {} from {} for {:?} ({:?})", found.name().unwrap_or(found_family), path, family, style);
+        Ok(font)
+    }
+    
+    /// Finds `family` through fontconfig as a fallback font, taking its regular face whether or
+    /// not it is monospace.
+    pub fn find_fallback(family: &str) -> Result<Self> {
+        let fontconfig = Fontconfig::new()
+            .ok_or_else(|| MyTermError::font(anyhow!("Failed to initialize fontconfig")))?;
+        let name = CString::new(family)
+            .map_err(|_| MyTermError::font(anyhow!("Invalid font family name {:?}", family)))?;
+        
+        let mut pattern = Pattern::new(&fontconfig);
+        pattern.add_string(FC_FAMILY, &name);
+        let found = pattern.font_match();
+        if !found.get_string(FC_FAMILY).unwrap_or_default().eq_ignore_ascii_case(family) {
+            return Err(MyTermError::font(anyhow!("Fallback font family {:?} is not installed", family)));
+        }
+        let path = found.filename()
+            .ok_or_else(|| MyTermError::font(anyhow!("Font family {:?} has no file", family)))?;
+        Self::from_file(Path::new(path), found.face_index().unwrap_or(0))
+    }
+    
+    /// Opens face `index` of a font file.
+    pub fn from_file(path: &Path, index: i32) -> Result<Self> {
+        let c_path = CString::new(path.as_os_str().as_bytes()).map_err(MyTermError::font)?;
+        Self::open(|library, face| {
+            // SAFETY: the path is NUL-terminated and outlives the call
+            unsafe { ft::FT_New_Face(library, c_path.as_ptr(), index as ft::FT_Long, face) }
+        })
+        .map_err(|error| MyTermError::font(anyhow!("Failed to open font {:?} (FreeType error {})", path, error)))
+    }
+    
+    /// Opens the embedded DejaVu Sans Mono.
+    pub fn embedded() -> Result<Self> {
+        Self::open(|library, face| {
+            // SAFETY: the font data is static, as FreeType requires of memory faces
+            unsafe { ft::FT_New_Memory_Face(library, EMBEDDED_FONT.as_ptr(), EMBEDDED_FONT.len() as ft::FT_Long, 0, face) }
+        })
+        .map_err(|error| MyTermError::font(anyhow!("Failed to open the embedded font (FreeType error {})", error)))
+    }
+    
+    /// Creates a library and opens a face in it with `new_face`, returning the FreeType error
+    /// code on failure.
+    fn open(new_face: impl FnOnce(ft::FT_Library, *mut ft::FT_Face) -> freetype::FT_Error) -> std::result::Result<Self, freetype::FT_Error> {
+        let mut library = std::ptr::null_mut();
+        // SAFETY: FT_Init_FreeType only writes the new handle through the pointer
+        let error = unsafe { ft::FT_Init_FreeType(&mut library) };
+        if !freetype::succeeded(error) {
+            return Err(error);
+        }
+        
+        let mut face = std::ptr::null_mut();
+        let error = new_face(library, &mut face);
+        if !freetype::succeeded(error) {
+            // SAFETY: nothing else refers to the library yet
+            unsafe { ft::FT_Done_FreeType(library) };
+            return Err(error);
+        }
+        
+        Ok(Self { library, face, pixel_size: 0, embolden: false, oblique: false })
+    }
+    
+    /// Fakes `style` by thickening and slanting outlines from now on, for a face that is
+    /// regular. Bitmap glyphs are thickened by drawing them twice, one pixel apart.
+    pub fn synthesize(&mut self, style: FontStyle) {
+        self.embolden = style.is_bold();
+        self.oblique = style.is_italic();
+    }
+    
+    /// Whether the font has a glyph of its own for `c`, rather than only its missing glyph box.
+    pub fn has_glyph(&self, c: char) -> bool {
+        // SAFETY: the face is valid for the lifetime of self
+        unsafe { ft::FT_Get_Char_Index(self.face, c as ft::FT_ULong) != 0 }
+    }
+    
+    /// Whether every glyph has the same advance, judged by a few that differ in proportional fonts.
+    fn is_monospace(&self) -> bool {
+        // SAFETY: the face is valid for the lifetime of self
+        let flags = unsafe { (*self.face).face_flags };
+        if flags & ft::FT_FACE_FLAG_FIXED_WIDTH as ft::FT_Long != 0 {
+            return true;
+        }
+        
+        let advances: Vec<_> = ['i', 'M', 'W', '.'].iter().filter_map(|&c| self.advance_units(c)).collect();
+        advances.windows(2).all(|pair| pair[0] == pair[1])
+    }
+    
+    /// Horizontal advance of `c` in font units.
+    fn advance_units(&self, c: char) -> Option<ft::FT_Pos> {
+        // SAFETY: the face is valid and FT_Load_Char fills in its glyph slot
+        let error = unsafe { ft::FT_Load_Char(self.face, c as ft::FT_ULong, ft::FT_LOAD_NO_SCALE as ft::FT_Int32) };
+        // SAFETY: the slot is valid after a successful load
+        freetype::succeeded(error).then(|| unsafe { (*(*self.face).glyph).advance.x })
+    }
+    
+    /// Sets the size glyphs are rasterized at, as the em height in buffer pixels.
+    pub fn set_pixel_size(&mut self, size: u32) -> Result<()> {
+        let size = size.max(1);
+        if size == self.pixel_size {
+            return Ok(());
+        }
+        
+        // SAFETY: the face is valid for the lifetime of self
+        let error = unsafe { ft::FT_Set_Pixel_Sizes(self.face, 0, size) };
+        if !freetype::succeeded(error) {
+            return Err(MyTermError::font(anyhow!("Failed to set font size to {}px (FreeType error {})", size, error)));
+        }
+        self.pixel_size = size;
+        Ok(())
+    }
+    
+    /// Pixel size set by `set_pixel_size`, 0 before the first call.
+    pub fn pixel_size(&self) -> u32 {
+        self.pixel_size
+    }
+    
+    /// Metrics at the current pixel size.
+    pub fn metrics(&self) -> Metrics {
+        // SAFETY: a face always has an active size once opened
+        let size = unsafe { (*(*self.face).size).metrics };
+        // 26.6 fixed point, rounded outwards
+        let ceil = |value: ft::FT_Pos| ((value + 63) >> 6) as i32;
+        let ascent = ceil(size.ascender);
+        let descent = ceil(-size.descender);
+        
+        // SAFETY: as in advance_units, but hinted at the current size
+        let error = unsafe { ft::FT_Load_Char(self.face, 'M' as ft::FT_ULong, ft::FT_LOAD_DEFAULT as ft::FT_Int32) };
+        let advance = if freetype::succeeded(error) {
+            // SAFETY: the slot is valid after a successful load
+            ceil(unsafe { (*(*self.face).glyph).advance.x })
+        } else {
+            ceil(size.max_advance)
+        };
+        
+        // Underline position and thickness are in font units, the position being the line's
+        // center; bitmap fonts have neither
+        // SAFETY: the face is valid for the lifetime of self
+        let face = unsafe { &*self.face };
+        let (thickness, center) = if face.units_per_EM > 0 && face.underline_thickness > 0 {
+            let scale = |units: ft::FT_Short| (units as ft::FT_Fixed * size.y_scale) >> 16;
+            let round = |value: ft::FT_Fixed| ((value + 32) >> 6) as i32;
+            (round(scale(face.underline_thickness)).max(1), round(-scale(face.underline_position)))
+        } else {
+            ((ascent + descent) / 14, descent / 2)
+        };
+        let thickness = thickness.max(1);
+        
+        // SAFETY: as above, for the x-height
+        let error = unsafe { ft::FT_Load_Char(self.face, 'x' as ft::FT_ULong, ft::FT_LOAD_DEFAULT as ft::FT_Int32) };
+        let x_height = if freetype::succeeded(error) {
+            // SAFETY: the slot is valid after a successful load
+            ceil(unsafe { (*(*self.face).glyph).metrics.horiBearingY })
+        } else {
+            ascent / 2
+        };
+        
+        Metrics {
+            cell_width: advance.max(1) as u32,
+            cell_height: (ascent + descent).max(1) as u32,
+            ascent,
+            descent,
+            underline_position: (center - thickness / 2).max(1),
+            underline_thickness: thickness as u32,
+            strikeout_position: x_height / 2,
+        }
+    }
+    
+    /// Rasterizes `c` at the current size, or `None` if the font can't render it.
+    pub fn rasterize(&mut self, c: char) -> Option<Glyph> {
+        // SAFETY: the face is valid and FT_Load_Char fills in its glyph slot
+        let error = unsafe { ft::FT_Load_Char(self.face, c as ft::FT_ULong, ft::FT_LOAD_DEFAULT as ft::FT_Int32) };
+        if !freetype::succeeded(error) {
+            return None;
+        }
+        
+        // SAFETY: the slot is valid after a successful load and only its outline is modified
+        let slot = unsafe { &mut *(*self.face).glyph };
+        let outline = slot.format == ft::FT_Glyph_Format::FT_GLYPH_FORMAT_OUTLINE;
+        if outline && self.oblique {
+            // The shear FreeType uses for oblique faces, about 12 degrees, in 16.16 fixed point
+            let shear = ft::FT_Matrix { xx: 0x10000, xy: 0x0366a, yx: 0, yy: 0x10000 };
+            // SAFETY: the outline belongs to the loaded glyph
+            unsafe { ft::FT_Outline_Transform(&slot.outline, &shear) };
+        }
+        if outline && self.embolden {
+            // A 24th of the em as FreeType does, but at least a pixel to stay visible at small sizes
+            let strength = (self.pixel_size as ft::FT_Pos * 64 / 24).max(64);
+            // SAFETY: as above; only widening keeps the glyph within the line height
+            unsafe { ft::FT_Outline_EmboldenXY(&mut slot.outline, strength, 0) };
+        }
+        // SAFETY: rendering replaces the outline with a bitmap in the same slot
+        let error = unsafe { ft::FT_Render_Glyph(slot, ft::FT_Render_Mode::FT_RENDER_MODE_NORMAL) };
+        if !freetype::succeeded(error) {
+            return None;
+        }
+        let slot = &*slot;
+        let bitmap = &slot.bitmap;
+        let (width, height) = (bitmap.width as usize, bitmap.rows as usize);
+        let pitch = bitmap.pitch.unsigned_abs() as usize;
+        let mut coverage = Vec::with_capacity(width * height);
+        
+        if width > 0 && height > 0 {
+            // SAFETY: FreeType allocates `rows * |pitch|` bytes for the bitmap
+            let buffer = unsafe { std::slice::from_raw_parts(bitmap.buffer, pitch * height) };
+            let mode = bitmap.pixel_mode as u32;
+            for row in buffer.chunks_exact(pitch) {
+                if mode == ft::FT_Pixel_Mode::FT_PIXEL_MODE_GRAY as u32 {
+                    coverage.extend_from_slice(&row[..width]);
+                } else if mode == ft::FT_Pixel_Mode::FT_PIXEL_MODE_MONO as u32 {
+                    // Bitmap fonts: one bit per pixel, most significant first
+                    coverage.extend((0..width).map(|x| if row[x / 8] & (0x80 >> (x % 8)) != 0 { 255 } else { 0 }));
+                } else {
+                    return None;
+                }
+            }
+        }
+        
+        let mut glyph = Glyph {
+            width,
+            height,
+            left: slot.bitmap_left,
+            top: slot.bitmap_top,
+            coverage,
+        };
+        if !outline && self.embolden {
+            glyph.double_strike();
+        }
+        Some(glyph)
+    }
+}
+
+impl Glyph {
+    /// Widens the glyph by a pixel, drawing it over itself shifted right.
+    fn double_strike(&mut self) {
+        let width = self.width + 1;
+        let mut coverage = Vec::with_capacity(width * self.height);
+        for row in self.coverage.chunks_exact(self.width.max(1)) {
+            coverage.extend((0..width).map(|x| {
+                let here = row.get(x).copied().unwrap_or(0);
+                let left = x.checked_sub(1).map_or(0, |x| row[x]);
+                here.max(left)
+            }));
+        }
+        self.width = width;
+        self.coverage = coverage;
+    }
+}
+
+impl Fallback {
+    /// Searches `fonts` in order, and then the system's fonts if `system` is set.
+    pub fn new(fonts: Vec<Font>, system: bool) -> Self {
+        Self {
+            fonts: fonts.into_iter().map(|font| (font, 0)).collect(),
+            system: if system { system_fonts() } else { VecDeque::new() },
+            blocks: HashMap::new(),
+            missing: HashSet::new(),
+            size: (0, 0),
+        }
+    }
+    
+    /// The configured `font.fallback` families that are installed, then the system's fonts.
+    pub fn load(font: &FontConfig) -> Self {
+        let fonts = font.fallback.iter()
+            .filter_map(|family| Font::find_fallback(family).map_err(|e| log::warn!("{:#}", e)).ok())
+            .collect();
+        Self::new(fonts, true)
+    }
+    
+    /// Sizes glyphs for a terminal font of `pixel_size` with cells `cell_height` pixels tall.
+    fn set_size(&mut self, pixel_size: u32, cell_height: u32) {
+        self.size = (pixel_size, cell_height);
+    }
+    
+    /// Rasterizes `c` in `style` from the first font that has it, or `None` if none does.
+    pub fn rasterize(&mut self, c: char, style: FontStyle) -> Option<Glyph> {
+        let index = self.font_for(c)?;
+        let (pixel_size, cell_height) = self.size;
+        let (font, sized_for) = &mut self.fonts[index];
+        if *sized_for != pixel_size {
+            // The same em size, shrunk while its lines are taller than the terminal font's cells
+            let mut size = pixel_size;
+            loop {
+                font.set_pixel_size(size).ok()?;
+                let height = font.metrics().cell_height;
+                if height <= cell_height || size == 1 {
+                    break;
+                }
+                size = (size * cell_height / height).clamp(1, size - 1);
+            }
+            *sized_for = pixel_size;
+        }
+        font.synthesize(style);
+        font.rasterize(c)
+    }
+    
+    /// Index of the first font with a glyph for `c`, opening system fonts as needed.
+    fn font_for(&mut self, c: char) -> Option<usize> {
+        let block = c as u32 / FALLBACK_BLOCK;
+        if let Some(&index) = self.blocks.get(&block).filter(|&&index| self.fonts[index].0.has_glyph(c)) {
+            return Some(index);
+        }
+        let mut index = self.fonts.iter().position(|(font, _)| font.has_glyph(c));
+        while index.is_none() {
+            let Some((path, face_index)) = self.system.pop_front() else {
+                break;
+            };
+            match Font::from_file(&path, face_index) {
+                Ok(font) => {
+                    log::debug!("Opened fallback font {:?}", path);
+                    index = font.has_glyph(c).then_some(self.fonts.len());
+                    self.fonts.push((font, 0));
+                }
+                Err(e) => log::debug!("{:#}", e),
+            }
+        }
+        
+        match index {
+            Some(index) => {
+                self.blocks.insert(block, index);
+            }
+            None if self.missing.insert(block) => {
+                let start = block * FALLBACK_BLOCK;
+                log::warn!("No font has U+{:04X}, drawing characters from U+{:04X} to U+{:04X} that are missing as boxes", c as u32, start, start + FALLBACK_BLOCK - 1);
+            }
+            None => {}
+        }
+        index
+    }
+}
+
+/// The files and face indices of the system's fonts that fontconfig would use for characters its
+/// monospace font lacks, in the order it would try them.
+fn system_fonts() -> VecDeque<(PathBuf, i32)> {
+    let Some(fontconfig) = Fontconfig::new() else {
+        log::warn!("Failed to initialize fontconfig, so only configured fallback fonts are used");
+        return VecDeque::new();
+    };
+    let mut pattern = Pattern::new(&fontconfig);
+    pattern.add_string(FC_FAMILY, c"monospace");
+    // Matching also applies fontconfig's substitutions to the pattern, which sorting expects
+    pattern.font_match();
+    // Trimmed to the fonts covering characters that no font before them does
+    let sorted = sort_fonts(&pattern, true);
+    let fonts = sorted.iter()
+        .filter_map(|font| Some((PathBuf::from(font.filename()?), font.face_index().unwrap_or(0))))
+        .collect();
+    fonts
+}
+
+impl FontSet {
+    /// Loads all four styles of the configured families, as `Font::load` does, and the
+    /// fallback fonts.
+    pub fn load(font: &FontConfig) -> Result<Self> {
+        Ok(Self {
+            faces: [
+                Font::load(font, FontStyle::Regular)?,
+                Font::load(font, FontStyle::Bold)?,
+                Font::load(font, FontStyle::Italic)?,
+                Font::load(font, FontStyle::BoldItalic)?,
+            ],
+            fallback: Fallback::load(font),
+        })
+    }
+    
+    /// A font opened by `open`, with the styles synthesized from it and no fallback but
+    /// `fallback`.
+    pub fn synthesized(open: impl Fn() -> Result<Font>, fallback: Fallback) -> Result<Self> {
+        let synthesized = |style| -> Result<Font> {
+            let mut font = open()?;
+            font.synthesize(style);
+            Ok(font)
+        };
+        Ok(Self {
+            faces: [
+                open()?,
+                synthesized(FontStyle::Bold)?,
+                synthesized(FontStyle::Italic)?,
+                synthesized(FontStyle::BoldItalic)?,
+            ],
+            fallback,
+        })
+    }
+    
+    /// The embedded font, with the styles it lacks synthesized and no fallback fonts.
+    #[allow(dead_code)]
+    pub fn embedded() -> Result<Self> {
+        Self::synthesized(Font::embedded, Fallback::new(Vec::new(), false))
+    }
+    
+    /// Rasterizes `c` in `style`, from a fallback font if the face for `style` lacks it and
+    /// with that face's missing glyph box if every font does.
+    pub fn rasterize(&mut self, c: char, style: FontStyle) -> Option<Glyph> {
+        let face = &mut self.faces[style as usize];
+        if face.has_glyph(c) {
+            return face.rasterize(c);
+        }
+        self.fallback.rasterize(c, style).or_else(|| self.faces[style as usize].rasterize(c))
+    }
+    
+    /// Sets the pixel size of every face.
+    pub fn set_pixel_size(&mut self, size: u32) -> Result<()> {
+        self.faces.iter_mut().try_for_each(|face| face.set_pixel_size(size))?;
+        self.fallback.set_size(self.pixel_size(), self.metrics().cell_height);
+        Ok(())
+    }
+    
+    pub fn pixel_size(&self) -> u32 {
+        self.faces[0].pixel_size()
+    }
+    
+    /// Metrics of the regular face, which the styled faces are drawn in the cells of.
+    pub fn metrics(&self) -> Metrics {
+        self.faces[0].metrics()
+    }
+}
+
+impl Drop for Font {
+    fn drop(&mut self) {
+        // SAFETY: both handles were created in open and are released exactly once
+        unsafe {
+            ft::FT_Done_Face(self.face);
+            ft::FT_Done_FreeType(self.library);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,657 @@
+use anyhow::{anyhow, Context};
+use raw_window_handle::{RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle};
+use rgb::RGB8;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::time::Instant;
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::{Connection, Proxy};
+
+use crate::error::{MyTermError, Result};
+use crate::font::{FontStyle, Glyph};
+use crate::render::{self, Bitmap, Frame, GlyphCache, Painter, Rect, BYTES_PER_PIXEL};
+use crate::renderer::{Presented, Renderer, Target};
+
+/// Width and height in pixels of the textures glyphs and images are packed into.
+pub const ATLAS_SIZE: u32 = 2048;
+
+/// Images go into the atlas in tiles of at most this many pixels a side, only those in view,
+/// so an image larger than the atlas is drawn too.
+pub const IMAGE_TILE: u32 = ATLAS_SIZE / 4;
+
+/// Bytes per quad in the instance buffer: its rectangle, color, atlas offset and whether it
+/// is textured.
+const QUAD_BYTES: usize = 4 * 4 + 4 * 4 + 2 * 4 + 4;
+
+/// Format of offscreen targets, which reads back in the layout of a shared memory buffer.
+#[allow(dead_code)]
+const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8Unorm;
+
+/// Every shape is an instance of one quad. Glyph and image quads take their texel from an atlas,
+/// at the pixel under them plus their offset, so no filtering blurs the bitmap.
+const SHADER: &str = r#"
+struct Viewport {
+    size: vec2<f32>,
+    padding: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> viewport: Viewport;
+@group(0) @binding(1) var atlas: texture_2d<f32>;
+@group(0) @binding(2) var images: texture_2d<f32>;
+
+struct Quad {
+    @location(0) rect: vec4<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) offset: vec2<i32>,
+    @location(3) textured: u32,
+}
+
+struct Varyings {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) @interpolate(flat) offset: vec2<i32>,
+    @location(2) @interpolate(flat) textured: u32,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, quad: Quad) -> Varyings {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 0.0), vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0), vec2<f32>(1.0, 0.0), vec2<f32>(1.0, 1.0),
+    );
+    let pixel = quad.rect.xy + corners[vertex] * quad.rect.zw;
+    var out: Varyings;
+    out.position = vec4<f32>(pixel / viewport.size * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.color = quad.color;
+    out.offset = quad.offset;
+    out.textured = quad.textured;
+    return out;
+}
+
+@fragment
+fn fs_main(in: Varyings) -> @location(0) vec4<f32> {
+    if in.textured == 0u {
+        return in.color;
+    }
+    let texel = vec2<i32>(floor(in.position.xy)) + in.offset;
+    if in.textured == 2u {
+        return textureLoad(images, texel, 0);
+    }
+    return in.color * textureLoad(atlas, texel, 0).r;
+}
+"#;
+
+/// Bitmaps packed in rows into one texture, until it's full and starts over.
+struct Atlas<K> {
+    texture: wgpu::Texture,
+    bytes_per_pixel: u32,
+    /// Top left corner of each bitmap.
+    entries: HashMap<K, (u32, u32)>,
+    /// Where the next bitmap goes in the current row, and the row's height so far.
+    cursor: (u32, u32),
+    row_height: u32,
+    /// A bitmap didn't fit since the last clear.
+    full: bool,
+}
+
+impl<K: Hash + Eq> Atlas<K> {
+    fn new(device: &wgpu::Device, label: &str, format: wgpu::TextureFormat, bytes_per_pixel: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: ATLAS_SIZE, height: ATLAS_SIZE, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        Self {
+            texture,
+            bytes_per_pixel,
+            entries: HashMap::new(),
+            cursor: (0, 0),
+            row_height: 0,
+            full: false,
+        }
+    }
+    
+    /// Forgets every bitmap, leaving the texture to be written over.
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.cursor = (0, 0);
+        self.row_height = 0;
+        self.full = false;
+    }
+    
+    /// Where the bitmap for `key` is, uploading its `pixels` on first use, or `None` if it
+    /// doesn't fit.
+    fn position<P: AsRef<[u8]>>(&mut self, queue: &wgpu::Queue, key: K, (width, height): (u32, u32), pixels: impl FnOnce() -> P) -> Option<(u32, u32)> {
+        if let Some(&position) = self.entries.get(&key) {
+            return Some(position);
+        }
+        if width > ATLAS_SIZE || height > ATLAS_SIZE {
+            log::warn!("Leaving out a {}x{} bitmap, larger than the {}x{} atlas", width, height, ATLAS_SIZE, ATLAS_SIZE);
+            return None;
+        }
+        if self.cursor.0 + width > ATLAS_SIZE {
+            self.cursor = (0, self.cursor.1 + self.row_height);
+            self.row_height = 0;
+        }
+        if self.cursor.1 + height > ATLAS_SIZE {
+            self.full = true;
+            return None;
+        }
+        
+        let position = self.cursor;
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: position.0, y: position.1, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels().as_ref(),
+            wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(width * self.bytes_per_pixel), rows_per_image: Some(height) },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        self.cursor.0 += width;
+        self.row_height = self.row_height.max(height);
+        self.entries.insert(key, position);
+        Some(position)
+    }
+}
+
+/// A tile of an image, the image keyed by identity and kept alive while the atlas holds its
+/// pixels so that its address isn't reused, and the tile by its top left corner.
+struct ImageKey(Arc<Bitmap>, (usize, usize));
+
+impl PartialEq for ImageKey {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) && self.1 == other.1
+    }
+}
+
+impl Eq for ImageKey {}
+
+impl Hash for ImageKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).hash(state);
+        self.1.hash(state);
+    }
+}
+
+/// The pixels of `bitmap` in `tile`, row after row.
+fn tile_pixels(bitmap: &Bitmap, tile: &Rect) -> Vec<u8> {
+    let stride = bitmap.width * BYTES_PER_PIXEL;
+    let mut pixels = Vec::with_capacity(tile.width * tile.height * BYTES_PER_PIXEL);
+    for y in tile.y..tile.y + tile.height {
+        let start = y * stride + tile.x * BYTES_PER_PIXEL;
+        pixels.extend_from_slice(&bitmap.pixels[start..start + tile.width * BYTES_PER_PIXEL]);
+    }
+    pixels
+}
+
+/// Collects a frame's shapes as quads for the instance buffer.
+struct QuadPainter<'a> {
+    quads: &'a mut Vec<u8>,
+    glyphs: &'a mut Atlas<(char, FontStyle)>,
+    images: &'a mut Atlas<ImageKey>,
+    queue: &'a wgpu::Queue,
+}
+
+impl QuadPainter<'_> {
+    /// Adds a quad, textured from nothing (0), the glyph atlas (1) or the image atlas (2).
+    fn push(&mut self, rect: &Rect, color: [f32; 4], offset: (i32, i32), textured: u32) {
+        let floats = [rect.x as f32, rect.y as f32, rect.width as f32, rect.height as f32];
+        for value in floats.into_iter().chain(color) {
+            self.quads.extend_from_slice(&value.to_ne_bytes());
+        }
+        self.quads.extend_from_slice(&offset.0.to_ne_bytes());
+        self.quads.extend_from_slice(&offset.1.to_ne_bytes());
+        self.quads.extend_from_slice(&textured.to_ne_bytes());
+    }
+}
+
+/// A premultiplied pixel as in a shared memory buffer, as premultiplied RGBA.
+fn pixel_color([b, g, r, a]: [u8; 4]) -> [f32; 4] {
+    [r, g, b, a].map(|channel| channel as f32 / 255.0)
+}
+
+impl Painter for QuadPainter<'_> {
+    fn fill(&mut self, rect: &Rect, pixel: [u8; 4]) {
+        self.push(rect, pixel_color(pixel), (0, 0), 0);
+    }
+    
+    fn blend(&mut self, rect: &Rect, pixel: [u8; 4]) {
+        self.push(rect, pixel_color(pixel), (0, 0), 0);
+    }
+    
+    fn glyph(&mut self, clip: &Rect, (x, y): (i32, i32), key: (char, FontStyle), glyph: &Glyph, color: RGB8) {
+        if glyph.width == 0 || glyph.coverage.is_empty() {
+            return;
+        }
+        let height = glyph.coverage.len() / glyph.width;
+        let Some(rect) = Rect::clipped(clip, (x, y), (glyph.width, height)) else {
+            return;
+        };
+        let size = (glyph.width as u32, height as u32);
+        let Some((atlas_x, atlas_y)) = self.glyphs.position(self.queue, key, size, || &glyph.coverage) else {
+            return;
+        };
+        let color = [color.r, color.g, color.b].map(|channel| channel as f32 / 255.0);
+        let offset = (atlas_x as i32 - x, atlas_y as i32 - y);
+        self.push(&rect, [color[0], color[1], color[2], 1.0], offset, 1);
+    }
+    
+    fn image(&mut self, clip: &Rect, (x, y): (i32, i32), bitmap: &Arc<Bitmap>) {
+        let side = IMAGE_TILE as usize;
+        for tile_y in (0..bitmap.height).step_by(side) {
+            for tile_x in (0..bitmap.width).step_by(side) {
+                let tile = Rect { x: tile_x, y: tile_y, width: side.min(bitmap.width - tile_x), height: side.min(bitmap.height - tile_y) };
+                let origin = (x + tile_x as i32, y + tile_y as i32);
+                let Some(rect) = Rect::clipped(clip, origin, (tile.width, tile.height)) else {
+                    continue;
+                };
+                let key = ImageKey(bitmap.clone(), (tile_x, tile_y));
+                let size = (tile.width as u32, tile.height as u32);
+                let Some((atlas_x, atlas_y)) = self.images.position(self.queue, key, size, || tile_pixels(bitmap, &tile)) else {
+                    continue;
+                };
+                self.push(&rect, [0.0; 4], (atlas_x as i32 - origin.0, atlas_y as i32 - origin.1), 2);
+            }
+        }
+    }
+}
+
+/// Draws every frame in full on the GPU, as instanced quads over the frame's background.
+pub struct GpuRenderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    /// The window's surface and how it's configured, or `None` when drawing offscreen only.
+    surface: Option<(wgpu::Surface<'static>, wgpu::SurfaceConfiguration)>,
+    pipeline: wgpu::RenderPipeline,
+    /// The viewport uniform and the atlases.
+    bind_group: wgpu::BindGroup,
+    viewport: wgpu::Buffer,
+    /// Glyph coverage, for the glyph cache generation it was rasterized for.
+    glyphs: Atlas<(char, FontStyle)>,
+    glyph_generation: u64,
+    /// Premultiplied image pixels.
+    images: Atlas<ImageKey>,
+    /// Quads of the frame being drawn, and the buffer they go to, grown as needed.
+    quads: Vec<u8>,
+    instances: wgpu::Buffer,
+    /// Offscreen target of the last `render_offscreen`.
+    #[allow(dead_code)]
+    offscreen: Option<wgpu::Texture>,
+}
+
+impl GpuRenderer {
+    /// A renderer for the window `surface` on the display of `connection`.
+    pub async fn new(connection: &Connection, surface: &WlSurface) -> Result<Self> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let display = NonNull::new(connection.backend().display_ptr().cast()).context("No Wayland display pointer");
+        let window = NonNull::new(surface.id().as_ptr().cast()).context("No Wayland surface pointer");
+        let target = wgpu::SurfaceTargetUnsafe::RawHandle {
+            raw_display_handle: RawDisplayHandle::Wayland(WaylandDisplayHandle::new(display.map_err(MyTermError::gpu)?)),
+            raw_window_handle: RawWindowHandle::Wayland(WaylandWindowHandle::new(window.map_err(MyTermError::gpu)?)),
+        };
+        // SAFETY: `WaylandDisplay` keeps the connection and the window until after it drops its
+        // renderer
+        let wgpu_surface = unsafe { instance.create_surface_unsafe(target) }
+            .context("Failed to create GPU surface").map_err(MyTermError::gpu)?;
+        
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions { compatible_surface: Some(&wgpu_surface), ..Default::default() })
+            .await
+            .ok_or_else(|| MyTermError::gpu(anyhow!("No GPU adapter can draw to the window")))?;
+        let capabilities = wgpu_surface.get_capabilities(&adapter);
+        // Colors are blended as the software renderer blends them, without sRGB conversion
+        let format = capabilities.formats.iter().copied().find(|format| !format.is_srgb())
+            .ok_or_else(|| MyTermError::gpu(anyhow!("The GPU surface has no format without sRGB conversion")))?;
+        let alpha_mode = if capabilities.alpha_modes.contains(&wgpu::CompositeAlphaMode::PreMultiplied) {
+            wgpu::CompositeAlphaMode::PreMultiplied
+        } else {
+            log::warn!("The GPU surface can't be translucent, so display.opacity has no effect");
+            capabilities.alpha_modes[0]
+        };
+        log::info!("Rendering on {:?} in {:?}", adapter.get_info(), format);
+        
+        let mut renderer = Self::with_adapter(&adapter, format).await?;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: 0,
+            height: 0,
+            // Frame callbacks pace drawing, so presenting never waits
+            present_mode: wgpu::PresentMode::AutoNoVsync,
+            desired_maximum_frame_latency: 2,
+            alpha_mode,
+            view_formats: Vec::new(),
+        };
+        renderer.surface = Some((wgpu_surface, config));
+        Ok(renderer)
+    }
+    
+    /// A renderer with no window, which only draws with `render_offscreen`.
+    #[allow(dead_code)]
+    pub async fn headless() -> Result<Self> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await
+            .ok_or_else(|| MyTermError::gpu(anyhow!("No GPU adapter found")))?;
+        Self::with_adapter(&adapter, OFFSCREEN_FORMAT).await
+    }
+    
+    async fn with_adapter(adapter: &wgpu::Adapter, format: wgpu::TextureFormat) -> Result<Self> {
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("myterm"),
+                    required_features: wgpu::Features::empty(),
+                    required_limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+                    memory_hints: wgpu::MemoryHints::MemoryUsage,
+                },
+                None,
+            )
+            .await
+            .context("Failed to open GPU device").map_err(MyTermError::gpu)?;
+        
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("quads"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("quads"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("quads"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("quads"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: QUAD_BYTES as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x4,
+                        1 => Float32x4,
+                        2 => Sint32x2,
+                        3 => Uint32,
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        
+        let viewport = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("viewport"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let glyphs = Atlas::new(&device, "glyphs", wgpu::TextureFormat::R8Unorm, 1);
+        let glyphs_view = glyphs.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Bitmaps are in the shared memory buffer's byte order
+        let images = Atlas::new(&device, "images", wgpu::TextureFormat::Bgra8Unorm, BYTES_PER_PIXEL as u32);
+        let images_view = images.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("quads"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: viewport.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&glyphs_view) },
+                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&images_view) },
+            ],
+        });
+        let instances = Self::instance_buffer(&device, 0);
+        
+        Ok(Self {
+            device,
+            queue,
+            surface: None,
+            pipeline,
+            bind_group,
+            viewport,
+            glyphs,
+            glyph_generation: 0,
+            images,
+            quads: Vec::new(),
+            instances,
+            offscreen: None,
+        })
+    }
+    
+    fn instance_buffer(device: &wgpu::Device, quads: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("quads"),
+            size: (quads.max(1024).next_power_of_two() * QUAD_BYTES) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+    
+    /// Collects the quads of `frame`, uploading glyphs and images the atlases don't have yet.
+    fn paint(&mut self, frame: &Frame, glyphs: &mut GlyphCache) {
+        if glyphs.generation() != self.glyph_generation {
+            self.glyphs.clear();
+            self.glyph_generation = glyphs.generation();
+        }
+        // A frame whose bitmaps don't all fit starts over with the full atlas emptied, and
+        // anything that still doesn't fit is left out
+        for attempt in 0..2 {
+            self.quads.clear();
+            let mut painter = QuadPainter { quads: &mut self.quads, glyphs: &mut self.glyphs, images: &mut self.images, queue: &self.queue };
+            render::paint_frame(&mut painter, frame, glyphs);
+            if !self.glyphs.full && !self.images.full {
+                break;
+            }
+            if attempt == 0 {
+                log::debug!("Atlas full, starting over (glyphs {}, images {})", self.glyphs.full, self.images.full);
+            } else {
+                log::warn!("Bitmaps of a frame don't fit in a {}x{} atlas", ATLAS_SIZE, ATLAS_SIZE);
+            }
+            if self.glyphs.full {
+                self.glyphs.clear();
+            }
+            if self.images.full {
+                self.images.clear();
+            }
+        }
+    }
+    
+    /// Draws the collected quads into `view`, cleared to `background`.
+    fn submit(&mut self, view: &wgpu::TextureView, size: (usize, usize), background: [u8; 4]) {
+        let count = self.quads.len() / QUAD_BYTES;
+        if (self.instances.size() as usize) < self.quads.len() {
+            self.instances = Self::instance_buffer(&self.device, count);
+        }
+        self.queue.write_buffer(&self.instances, 0, &self.quads);
+        let viewport: Vec<u8> = [size.0 as f32, size.1 as f32, 0.0, 0.0].iter().flat_map(|value| value.to_ne_bytes()).collect();
+        self.queue.write_buffer(&self.viewport, 0, &viewport);
+        
+        let [r, g, b, a] = pixel_color(background).map(f64::from);
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("frame") });
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("frame"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color { r, g, b, a }), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            if count > 0 {
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &self.bind_group, &[]);
+                pass.set_vertex_buffer(0, self.instances.slice(..self.quads.len() as u64));
+                pass.draw(0..6, 0..count as u32);
+            }
+        }
+        self.queue.submit([encoder.finish()]);
+    }
+    
+    /// Draws `frame` into an offscreen texture and waits for the GPU to finish, for tests and
+    /// benchmarks.
+    #[allow(dead_code)]
+    pub fn render_offscreen(&mut self, frame: &Frame, glyphs: &mut GlyphCache) -> Result<()> {
+        let (width, height) = (frame.size.0 as u32, frame.size.1 as u32);
+        if self.offscreen.as_ref().is_none_or(|texture| (texture.width(), texture.height()) != (width, height)) {
+            self.offscreen = Some(self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("offscreen"),
+                size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: OFFSCREEN_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            }));
+        }
+        let view = self.offscreen.as_ref().expect("texture was created above").create_view(&wgpu::TextureViewDescriptor::default());
+        self.paint(frame, glyphs);
+        self.submit(&view, frame.size, frame.background);
+        self.device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+        Ok(())
+    }
+    
+    /// The pixels of the last `render_offscreen`, laid out as a shared memory buffer of its size.
+    #[allow(dead_code)]
+    pub fn read_pixels(&self) -> Result<Vec<u8>> {
+        let texture = self.offscreen.as_ref().context("Nothing was rendered offscreen").map_err(MyTermError::gpu)?;
+        let (width, height) = (texture.width(), texture.height());
+        let stride = width as usize * BYTES_PER_PIXEL;
+        let padded = stride.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: (padded * height as usize) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("readback") });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(padded as u32), rows_per_image: Some(height) },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        self.queue.submit([encoder.finish()]);
+        
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+        receiver.recv().context("Readback was dropped").map_err(MyTermError::gpu)?
+            .context("Failed to map readback buffer").map_err(MyTermError::gpu)?;
+        let mapped = buffer.slice(..).get_mapped_range();
+        Ok(mapped.chunks_exact(padded).flat_map(|row| &row[..stride]).copied().collect())
+    }
+}
+
+impl Renderer for GpuRenderer {
+    fn name(&self) -> &'static str {
+        "gpu"
+    }
+    
+    fn present(&mut self, frame: Frame, glyphs: &mut GlyphCache, target: &Target) -> Result<Option<Presented>> {
+        let (width, height) = (frame.size.0 as u32, frame.size.1 as u32);
+        let Some((surface, config)) = self.surface.as_mut() else {
+            return Err(MyTermError::gpu(anyhow!("The GPU renderer has no window to present to")));
+        };
+        if (config.width, config.height) != (width, height) {
+            config.width = width;
+            config.height = height;
+            surface.configure(&self.device, config);
+        }
+        let texture = match surface.get_current_texture() {
+            Ok(texture) => texture,
+            // Drawn again on the next frame callback or timeout
+            Err(wgpu::SurfaceError::Timeout) => return Ok(None),
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                surface.configure(&self.device, config);
+                return Ok(None);
+            }
+            Err(e) => return Err(MyTermError::gpu(anyhow::Error::new(e).context("Failed to get the next GPU frame"))),
+        };
+        
+        let started = Instant::now();
+        self.paint(&frame, glyphs);
+        let painted = Instant::now();
+        let view = texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.submit(&view, frame.size, frame.background);
+        let submitted = Instant::now();
+        
+        target.surface.frame(target.qh, target.surface.clone());
+        texture.present();
+        Ok(Some(Presented {
+            damage: vec![Rect { x: 0, y: 0, width: frame.size.0, height: frame.size.1 }],
+            raster: painted - started,
+            blit: submitted - painted,
+        }))
+    }
+    
+    /// Every frame is drawn in full, and glyphs follow the glyph cache's generation.
+    fn invalidate(&mut self) {}
+}
\ No newline at end of file
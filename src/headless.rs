@@ -0,0 +1,289 @@
+use anyhow::anyhow;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio::sync::mpsc;
+
+use crate::config::{self, Config, FontConfig, Padding};
+use crate::display::{Display, Event};
+use crate::error::{MyTermError, Result};
+use crate::font::FontSet;
+use crate::input::{self, Key};
+use crate::render::{self, GlyphCache};
+use crate::screenshot;
+use crate::terminal::{self, CellFlags, GridGeometry, Terminal};
+
+/// How `--dump` and a script's `dump` write the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// The characters alone.
+    Text,
+    /// The characters with SGR sequences for their colors and attributes.
+    Styled,
+}
+
+/// A line of a headless script.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptCommand {
+    /// `type <text>`: bytes written to the PTY as typed, with escapes as in a binding's `send`.
+    Type(Vec<u8>),
+    /// `key <key>`: a key press, written as in a binding, going through the bindings.
+    Key(Key),
+    /// `wait <ms>`: a pause before the next line.
+    Wait(Duration),
+    /// `resize <cols>x<rows>`: the window resized to hold that many cells.
+    Resize(u16, u16),
+    /// `dump [text|styled]`: the screen written to standard output.
+    Dump(DumpFormat),
+    /// `close`: the window closed.
+    Close,
+}
+
+/// A display without a window, for tests and scripting: the terminal is sized from the
+/// command line and driven by a script, or by standard input written straight to the PTY.
+pub struct HeadlessDisplay {
+    glyphs: GlyphCache,
+    font_size: f32,
+    cell_size: (u32, u32),
+    padding: Padding,
+    dynamic_padding: bool,
+    opacity: f32,
+    /// Content size the grid is fitted to.
+    size: (u32, u32),
+    /// Events to hand out before the script's, starting with the initial size.
+    pending: VecDeque<Event>,
+    /// Commands read by a task of their own, which also waits out the script's pauses.
+    commands: mpsc::UnboundedReceiver<Result<ScriptCommand>>,
+}
+
+impl DumpFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "styled" => Ok(Self::Styled),
+            _ => Err(MyTermError::parse(anyhow!("Unknown dump format {:?}, expected text or styled", s))),
+        }
+    }
+}
+
+impl ScriptCommand {
+    /// Parses a script line, returning `None` for blank lines and `#` comments.
+    pub fn parse(line: &str) -> Result<Option<Self>> {
+        let line = line.trim_start();
+        if line.trim().is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let (command, argument) = line.split_once(' ').unwrap_or((line.trim_end(), ""));
+        let invalid = || MyTermError::parse(anyhow!("Invalid script line {:?}", line));
+        let command = match command {
+            "type" => Self::Type(input::parse_escapes(argument)?.into_bytes()),
+            "key" => Self::Key(input::parse_key_binding(argument.trim())?),
+            "wait" => Self::Wait(Duration::from_millis(argument.trim().parse().map_err(|_| invalid())?)),
+            "resize" => {
+                let (cols, rows) = parse_size(argument.trim()).ok_or_else(invalid)?;
+                Self::Resize(cols, rows)
+            }
+            "dump" => Self::Dump(match argument.trim() {
+                "" => DumpFormat::Text,
+                format => DumpFormat::parse(format)?,
+            }),
+            "close" => Self::Close,
+            _ => return Err(invalid()),
+        };
+        Ok(Some(command))
+    }
+}
+
+/// Parses a size in cells written as `<cols>x<rows>`, e.g. `80x24`.
+pub fn parse_size(s: &str) -> Option<(u16, u16)> {
+    let (cols, rows) = s.split_once('x')?;
+    let (cols, rows) = (cols.parse().ok()?, rows.parse().ok()?);
+    (cols > 0 && rows > 0).then_some((cols, rows))
+}
+
+/// The screen in view as `format` has it, a line per row without trailing blanks, and
+/// without the blank rows below the last one with anything on it.
+pub fn dump(terminal: &Terminal, format: DumpFormat) -> String {
+    let background = terminal.screen_palette().background;
+    let mut lines: Vec<String> = terminal.styled_snapshot().iter()
+        .map(|row| {
+            let cells: Vec<_> = row.iter().filter(|cell| !cell.flags.contains(CellFlags::WIDE_SPACER)).collect();
+            let blank = |cell: &&terminal::Cell| cell.c == ' ' && (format == DumpFormat::Text || (cell.bg == background && (cell.flags - CellFlags::EXPLICIT_FG - CellFlags::EXPLICIT_BG).is_empty()));
+            let end = cells.iter().rposition(|cell| !blank(cell)).map_or(0, |last| last + 1);
+            match format {
+                DumpFormat::Text => cells[..end].iter().map(|cell| cell.c).collect(),
+                DumpFormat::Styled => styled_line(&cells[..end]),
+            }
+        })
+        .collect();
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// A row's cells with an SGR sequence wherever the style changes, reset at the end.
+fn styled_line(cells: &[&terminal::Cell]) -> String {
+    const ATTRIBUTES: [(CellFlags, u8); 5] = [
+        (CellFlags::BOLD, 1),
+        (CellFlags::DIM, 2),
+        (CellFlags::ITALIC, 3),
+        (CellFlags::UNDERLINE, 4),
+        (CellFlags::STRIKETHROUGH, 9),
+    ];
+    let mut line = String::new();
+    let mut style = None;
+    for cell in cells {
+        let flags = cell.flags & ATTRIBUTES.iter().fold(CellFlags::empty(), |all, &(flag, _)| all | flag);
+        if style != Some((cell.fg, cell.bg, flags)) {
+            style = Some((cell.fg, cell.bg, flags));
+            line.push_str("\x1b[0");
+            for (flag, sgr) in ATTRIBUTES {
+                if flags.contains(flag) {
+                    let _ = write!(line, ";{}", sgr);
+                }
+            }
+            let _ = write!(line, ";38;2;{};{};{};48;2;{};{};{}m", cell.fg.r, cell.fg.g, cell.fg.b, cell.bg.r, cell.bg.g, cell.bg.b);
+        }
+        line.push(cell.c);
+    }
+    if style.is_some() {
+        line.push_str("\x1b[0m");
+    }
+    line
+}
+
+impl HeadlessDisplay {
+    /// Sizes the grid to `size` cells, or as the config sizes the window if `None`, and starts
+    /// reading `script`, `-` for standard input, or else standard input as typed text.
+    pub fn new(config: &Config, size: Option<(u16, u16)>, script: Option<PathBuf>) -> Result<Self> {
+        let mut glyphs = GlyphCache::new(FontSet::load(&config.font)?, render::GLYPH_CACHE_BYTES);
+        glyphs.set_pixel_size(config::points_to_pixels(config.font.size, 1.0).round() as u32)?;
+        let metrics = glyphs.metrics();
+        let cell_size = (metrics.cell_width, metrics.cell_height);
+        let size = match size {
+            Some((cols, rows)) => terminal::window_size_for(cols as u32, rows as u32, cell_size, config.display.padding),
+            None => config.display.window_size(cell_size),
+        };
+        
+        let (sender, commands) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let result = match script {
+                Some(path) if path == Path::new("-") => read_script(tokio::io::stdin(), &sender).await,
+                Some(path) => match tokio::fs::File::open(&path).await {
+                    Ok(file) => read_script(file, &sender).await,
+                    Err(e) => Err(MyTermError::parse(anyhow::Error::new(e).context(format!("Failed to open {}", path.display())))),
+                },
+                None => read_typed(&sender).await,
+            };
+            if let Err(e) = result {
+                let _ = sender.send(Err(e));
+            }
+        });
+        
+        Ok(Self {
+            glyphs,
+            font_size: config.font.size,
+            cell_size,
+            padding: config.display.padding,
+            dynamic_padding: config.display.dynamic_padding,
+            opacity: config.display.opacity,
+            size,
+            pending: VecDeque::from([Event::Resize(size.0, size.1)]),
+            commands,
+        })
+    }
+}
+
+/// Sends the commands in `script`, pausing where it waits.
+async fn read_script(script: impl AsyncRead + Unpin, sender: &mpsc::UnboundedSender<Result<ScriptCommand>>) -> Result<()> {
+    let mut lines = BufReader::new(script).lines();
+    while let Some(line) = lines.next_line().await.map_err(MyTermError::parse)? {
+        let Some(command) = ScriptCommand::parse(&line)? else {
+            continue;
+        };
+        if let ScriptCommand::Wait(pause) = command {
+            tokio::time::sleep(pause).await;
+        } else if sender.send(Ok(command)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Sends standard input as it's read, to be written to the PTY untouched.
+async fn read_typed(sender: &mpsc::UnboundedSender<Result<ScriptCommand>>) -> Result<()> {
+    let mut stdin = tokio::io::stdin();
+    let mut buf = vec![0u8; 4096];
+    loop {
+        let n = stdin.read(&mut buf).await.map_err(MyTermError::pty)?;
+        if n == 0 || sender.send(Ok(ScriptCommand::Type(buf[..n].to_vec()))).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+impl Display for HeadlessDisplay {
+    /// Once the script or standard input ends, no more events come; the app goes on until
+    /// the program exits.
+    async fn next_event(&mut self) -> Result<Event> {
+        if let Some(event) = self.pending.pop_front() {
+            return Ok(event);
+        }
+        let Some(command) = self.commands.recv().await else {
+            return std::future::pending().await;
+        };
+        Ok(match command? {
+            ScriptCommand::Type(bytes) => Event::Input(bytes),
+            ScriptCommand::Key(key) => Event::Key(key),
+            ScriptCommand::Resize(cols, rows) => {
+                self.size = terminal::window_size_for(cols as u32, rows as u32, self.cell_size, self.padding);
+                Event::Resize(self.size.0, self.size.1)
+            }
+            ScriptCommand::Dump(format) => Event::Dump(format),
+            ScriptCommand::Close => Event::Close,
+            ScriptCommand::Wait(_) => unreachable!("the script reader waits itself"),
+        })
+    }
+    
+    fn content_size(&self) -> (u32, u32) {
+        self.size
+    }
+    
+    fn windowed_size(&self) -> (u32, u32) {
+        self.size
+    }
+    
+    fn resize(&mut self, width: u32, height: u32) -> GridGeometry {
+        self.size = (width, height);
+        terminal::grid_geometry(self.size, self.cell_size, self.padding, self.dynamic_padding)
+    }
+    
+    fn set_font(&mut self, font: &FontConfig) -> Result<(u32, u32)> {
+        self.glyphs.set_fonts(FontSet::load(font)?)?;
+        self.set_font_size(self.font_size)
+    }
+    
+    fn set_font_size(&mut self, points: f32) -> Result<(u32, u32)> {
+        self.font_size = points;
+        self.glyphs.set_pixel_size(config::points_to_pixels(points, 1.0).round() as u32)?;
+        let metrics = self.glyphs.metrics();
+        self.cell_size = (metrics.cell_width, metrics.cell_height);
+        Ok(self.cell_size)
+    }
+    
+    fn set_padding(&mut self, padding: Padding, dynamic: bool) {
+        self.padding = padding;
+        self.dynamic_padding = dynamic;
+    }
+    
+    fn screenshot(&mut self, terminal: &Terminal, path: &Path) -> Result<()> {
+        screenshot::save(path, terminal, self.opacity, &mut self.glyphs)
+    }
+    
+    fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity;
+    }
+}
\ No newline at end of file
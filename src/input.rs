@@ -1,6 +1,9 @@
-use anyhow::Result;
+use crate::error::{MyTermError, Result};
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
+use crate::config::{BackspaceSends, KeyBinding, MouseBinding};
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Key {
     pub code: KeyCode,
@@ -34,6 +37,32 @@ pub enum KeyCode {
     Menu,
 }
 
+/// A mouse button, named as in the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    Back,
+    Forward,
+}
+
+/// A mouse button press with the modifiers held at the time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseInput {
+    pub button: MouseButton,
+    pub modifiers: Modifiers,
+}
+
+/// Touchpad scrolling distance, in surface pixels, that counts as one wheel step.
+pub const PIXELS_PER_SCROLL_STEP: f64 = 10.0;
+
+/// Turns wheel steps into whole lines, carrying fractions over to the next event.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScrollAccumulator {
+    remainder: f64,
+}
+
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct Modifiers: u8 {
@@ -44,6 +73,81 @@ bitflags::bitflags! {
     }
 }
 
+/// Something a keybinding can trigger instead of sending the key to the PTY.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Copy,
+    Paste,
+    /// Paste the primary selection.
+    PastePrimary,
+    Search,
+    NewTab,
+    CloseTab,
+    NextTab,
+    PrevTab,
+    ScrollLineUp,
+    ScrollLineDown,
+    ScrollPageUp,
+    ScrollPageDown,
+    ScrollToTop,
+    ScrollToBottom,
+    /// Scroll to the prompt before the top of the view, as marked by shell integration.
+    JumpToPrevPrompt,
+    /// Scroll to the prompt after the top of the view, or back to the live screen.
+    JumpToNextPrompt,
+    /// Pan the view half a screen left or right over lines printed past the right margin
+    /// while auto-wrap was off.
+    PanLeft,
+    PanRight,
+    /// Wipe the screen and the scrollback without telling the shell.
+    ClearAll,
+    /// Re-read the config file and apply what can change while running.
+    ReloadConfig,
+    /// Save the screen as a PNG in the pictures directory.
+    Screenshot,
+    /// Write the given text to the PTY.
+    SendText(String),
+    /// Removes a default binding for the same key and mode.
+    None,
+}
+
+bitflags::bitflags! {
+    /// Terminal state a binding can be restricted to.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct BindingMode: u8 {
+        const ALT_SCREEN = 0b00000001;
+        const SELECTION = 0b00000010;
+        /// DECCKM: cursor keys send SS3 sequences such as `ESC O A`.
+        const APP_CURSOR = 0b00000100;
+    }
+}
+
+/// Mode restriction of a binding: modes that must be active and modes that must not be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ModeGuard {
+    pub required: BindingMode,
+    pub excluded: BindingMode,
+}
+
+/// Input that can be bound to an action.
+pub trait Trigger: fmt::Display {
+    /// Whether `other` should fire a binding made for `self`.
+    fn matches(&self, other: &Self) -> bool;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding<T = Key> {
+    pub trigger: T,
+    pub action: Action,
+    pub guard: ModeGuard,
+}
+
+/// Resolved binding table used by the input dispatcher, for keys or mouse buttons.
+#[derive(Debug, Clone)]
+pub struct Bindings<T = Key> {
+    bindings: Vec<Binding<T>>,
+}
+
 impl Key {
     pub fn new(code: KeyCode, modifiers: Modifiers) -> Self {
         Self { code, modifiers }
@@ -69,7 +173,34 @@ impl Key {
         Self::new(KeyCode::Char(c), Modifiers::SHIFT)
     }
     
+    /// Bytes for the key with every terminal mode off.
+    #[allow(dead_code)]
     pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_in(BindingMode::empty())
+    }
+    
+    /// Bytes for the key given the terminal's current modes, with Backspace sending DEL.
+    pub fn to_bytes_in(&self, mode: BindingMode) -> Vec<u8> {
+        self.to_bytes_with(mode, BackspaceSends::Delete)
+    }
+    
+    /// Bytes for the key given the terminal's current modes and what Backspace sends.
+    pub fn to_bytes_with(&self, mode: BindingMode, backspace: BackspaceSends) -> Vec<u8> {
+        if mode.contains(BindingMode::APP_CURSOR) && self.modifiers.is_empty() {
+            let final_byte = match self.code {
+                KeyCode::Up => Some(b'A'),
+                KeyCode::Down => Some(b'B'),
+                KeyCode::Right => Some(b'C'),
+                KeyCode::Left => Some(b'D'),
+                KeyCode::Home => Some(b'H'),
+                KeyCode::End => Some(b'F'),
+                _ => None,
+            };
+            if let Some(final_byte) = final_byte {
+                return vec![0x1b, b'O', final_byte];
+            }
+        }
+        
         match (&self.code, &self.modifiers) {
             (KeyCode::Char(c), modifiers) => {
                 if modifiers.contains(Modifiers::CTRL) {
@@ -94,8 +225,14 @@ impl Key {
             }
             (KeyCode::Enter, _) => vec![b'\r'],
             (KeyCode::Tab, _) => vec![b'\t'],
-            (KeyCode::Backspace, _) => vec![127],
-            (KeyCode::Delete, _) => b"\x1b[3~".to_vec(),
+            (KeyCode::Backspace, _) => match backspace {
+                BackspaceSends::Delete => vec![0x7f],
+                BackspaceSends::Backspace => vec![0x08],
+            },
+            (KeyCode::Delete, _) => match backspace {
+                BackspaceSends::Delete => b"\x1b[3~".to_vec(),
+                BackspaceSends::Backspace => vec![0x7f],
+            },
             (KeyCode::Insert, _) => b"\x1b[2~".to_vec(),
             (KeyCode::Home, _) => {
                 if self.modifiers.contains(Modifiers::CTRL) {
@@ -163,6 +300,7 @@ impl Key {
                     10 => b"\x1b[21~".to_vec(),
                     11 => b"\x1b[23~".to_vec(),
                     12 => b"\x1b[24~".to_vec(),
+                    // F13 and up send nothing unless bound, as with `send`
                     _ => vec![],
                 }
             }
@@ -172,6 +310,20 @@ impl Key {
     }
 }
 
+impl Trigger for Key {
+    /// Compares keys the way bindings are matched, ignoring the case of characters.
+    fn matches(&self, other: &Key) -> bool {
+        if self.modifiers != other.modifiers {
+            return false;
+        }
+        
+        match (&self.code, &other.code) {
+            (KeyCode::Char(a), KeyCode::Char(b)) => a.to_lowercase().eq(b.to_lowercase()),
+            (a, b) => a == b,
+        }
+    }
+}
+
 impl fmt::Display for Key {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut parts = Vec::new();
@@ -219,18 +371,243 @@ impl fmt::Display for Key {
     }
 }
 
-#[allow(dead_code)]
+impl MouseButton {
+    /// Maps a Linux input event code (`BTN_*`) to a button.
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0x110 => Some(Self::Left),
+            0x111 => Some(Self::Right),
+            0x112 => Some(Self::Middle),
+            0x113 => Some(Self::Back),
+            0x114 => Some(Self::Forward),
+            _ => None,
+        }
+    }
+}
+
+impl MouseInput {
+    pub fn new(button: MouseButton, modifiers: Modifiers) -> Self {
+        Self { button, modifiers }
+    }
+}
+
+impl Trigger for MouseInput {
+    fn matches(&self, other: &MouseInput) -> bool {
+        self == other
+    }
+}
+
+impl fmt::Display for MouseInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (Modifiers::CTRL, "Ctrl+"),
+            (Modifiers::ALT, "Alt+"),
+            (Modifiers::SHIFT, "Shift+"),
+            (Modifiers::SUPER, "Super+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        write!(f, "{:?}", self.button)
+    }
+}
+
+impl ScrollAccumulator {
+    /// Returns the lines to scroll for `steps` wheel steps, positive towards older output.
+    pub fn lines(&mut self, steps: f64, multiplier: f32) -> isize {
+        self.remainder += steps * multiplier as f64;
+        let lines = self.remainder.trunc();
+        self.remainder -= lines;
+        lines as isize
+    }
+}
+
+impl ModeGuard {
+    /// Parses a guard such as "AltScreen", "~AltScreen" or "AltScreen|~Selection".
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut guard = Self::default();
+        
+        for part in s.split('|').map(str::trim).filter(|p| !p.is_empty()) {
+            let (negated, name) = match part.strip_prefix('~') {
+                Some(name) => (true, name),
+                None => (false, part),
+            };
+            
+            let mode = match name.to_lowercase().as_str() {
+                "altscreen" => BindingMode::ALT_SCREEN,
+                "selection" => BindingMode::SELECTION,
+                "appcursor" => BindingMode::APP_CURSOR,
+                _ => return Err(MyTermError::parse(anyhow::anyhow!("Unknown binding mode: {}", name))),
+            };
+            
+            if negated {
+                guard.excluded.insert(mode);
+            } else {
+                guard.required.insert(mode);
+            }
+        }
+        
+        Ok(guard)
+    }
+    
+    pub fn allows(&self, mode: BindingMode) -> bool {
+        mode.contains(self.required) && !mode.intersects(self.excluded)
+    }
+}
+
+impl<T> Binding<T> {
+    fn parse(trigger: T, action: &Action, mode: Option<&str>) -> Result<Self> {
+        let guard = match mode {
+            Some(mode) => ModeGuard::parse(mode)?,
+            None => ModeGuard::default(),
+        };
+        
+        Ok(Self {
+            trigger,
+            action: action.clone(),
+            guard,
+        })
+    }
+}
+
+impl Binding {
+    pub fn from_config(binding: &KeyBinding) -> Result<Self> {
+        Self::parse(parse_key_binding(&binding.key)?, &binding.action, binding.mode.as_deref())
+    }
+}
+
+impl Binding<MouseInput> {
+    pub fn from_mouse_config(binding: &MouseBinding) -> Result<Self> {
+        Self::parse(parse_mouse_binding(&binding.button)?, &binding.action, binding.mode.as_deref())
+    }
+}
+
+impl Bindings {
+    /// Builds the keybinding table from the defaults overridden by the user's entries.
+    ///
+    /// User entries replace defaults bound to the same key and mode and are matched
+    /// before the remaining defaults; `Action::None` removes the binding. Binding the
+    /// same key twice in the same mode is an error.
+    pub fn new(user: &[KeyBinding]) -> Result<Self> {
+        let defaults = KeyBinding::defaults().iter().map(Binding::from_config).collect::<Result<_>>()?;
+        let user = user.iter().map(Binding::from_config).collect::<Result<_>>()?;
+        Self::merge(user, defaults)
+    }
+}
+
+impl Bindings<MouseInput> {
+    /// Builds the mouse binding table, with the same override rules as `Bindings::new`.
+    pub fn for_mouse(user: &[MouseBinding]) -> Result<Self> {
+        let defaults = MouseBinding::defaults().iter().map(Binding::from_mouse_config).collect::<Result<_>>()?;
+        let user = user.iter().map(Binding::from_mouse_config).collect::<Result<_>>()?;
+        Self::merge(user, defaults)
+    }
+}
+
+impl<T: Trigger> Bindings<T> {
+    fn merge(user: Vec<Binding<T>>, mut defaults: Vec<Binding<T>>) -> Result<Self> {
+        let mut bindings: Vec<Binding<T>> = Vec::with_capacity(user.len() + defaults.len());
+        for binding in user {
+            if bindings
+                .iter()
+                .any(|b| b.trigger.matches(&binding.trigger) && b.guard == binding.guard)
+            {
+                return Err(MyTermError::parse(anyhow::anyhow!(
+                    "{} is bound more than once in the same mode",
+                    binding.trigger
+                )));
+            }
+            bindings.push(binding);
+        }
+        
+        // User entries come first so they take precedence during lookup
+        defaults.retain(|d| {
+            !bindings
+                .iter()
+                .any(|b| b.trigger.matches(&d.trigger) && b.guard == d.guard)
+        });
+        bindings.retain(|b| b.action != Action::None);
+        bindings.append(&mut defaults);
+        
+        Ok(Self { bindings })
+    }
+    
+    /// Finds the action bound to `trigger` in the given terminal mode.
+    pub fn lookup(&self, trigger: &T, mode: BindingMode) -> Option<&Action> {
+        self.bindings
+            .iter()
+            .find(|b| b.trigger.matches(trigger) && b.guard.allows(mode))
+            .map(|b| &b.action)
+    }
+}
+
+impl<T> Default for Bindings<T> {
+    fn default() -> Self {
+        Self { bindings: Vec::new() }
+    }
+}
+
+/// Bytes for text sent to the terminal as if pasted: newlines become carriage returns, and with
+/// bracketed paste the text is wrapped in `ESC [200~` ... `ESC [201~`.
+///
+/// An end marker inside the text is dropped so the text can't end the paste early.
+pub fn text_to_bytes(text: &str, bracketed_paste: bool) -> Vec<u8> {
+    let text = text.replace("\r\n", "\r").replace('\n', "\r");
+    if !bracketed_paste {
+        return text.into_bytes();
+    }
+    
+    let mut bytes = b"\x1b[200~".to_vec();
+    bytes.extend(text.replace("\x1b[201~", "").into_bytes());
+    bytes.extend(b"\x1b[201~");
+    bytes
+}
+
+/// Replaces the escapes in a binding's `send` text: `\e` for ESC, `\xNN` for an ASCII byte,
+/// `\n`, `\r`, `\t` and `\\`. Anything TOML already unescaped, as in double quoted strings,
+/// passes through.
+pub fn parse_escapes(s: &str) -> Result<String> {
+    let mut text = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some('e') => '\x1b',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('\\') => '\\',
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 && digits.chars().all(|c| c.is_ascii_hexdigit()) && byte.is_ascii() => char::from(byte),
+                    _ => return Err(MyTermError::parse(anyhow::anyhow!("Invalid escape \\x{} in: {}", digits, s))),
+                }
+            }
+            Some(other) => return Err(MyTermError::parse(anyhow::anyhow!("Unknown escape \\{} in: {}", other, s))),
+            None => return Err(MyTermError::parse(anyhow::anyhow!("Trailing backslash in: {}", s))),
+        };
+        text.push(escaped);
+    }
+    Ok(text)
+}
+
 pub fn parse_key_binding(s: &str) -> Result<Key> {
     let parts: Vec<&str> = s.split('+').collect();
     let mut modifiers = Modifiers::empty();
     let mut key_code = None;
     
     for part in parts {
+        if let Some(modifier) = parse_modifier(part) {
+            modifiers.insert(modifier);
+            continue;
+        }
+        
         match part.to_lowercase().as_str() {
-            "ctrl" => modifiers.insert(Modifiers::CTRL),
-            "alt" => modifiers.insert(Modifiers::ALT),
-            "shift" => modifiers.insert(Modifiers::SHIFT),
-            "super" | "cmd" => modifiers.insert(Modifiers::SUPER),
             "enter" => key_code = Some(KeyCode::Enter),
             "tab" => key_code = Some(KeyCode::Tab),
             "backspace" => key_code = Some(KeyCode::Backspace),
@@ -247,7 +624,7 @@ pub fn parse_key_binding(s: &str) -> Result<Key> {
             "escape" => key_code = Some(KeyCode::Escape),
             s if s.starts_with('f') && s.len() > 1 => {
                 if let Ok(n) = s[1..].parse::<u8>() {
-                    if (1..=12).contains(&n) {
+                    if (1..=24).contains(&n) {
                         key_code = Some(KeyCode::F(n));
                     }
                 }
@@ -257,10 +634,45 @@ pub fn parse_key_binding(s: &str) -> Result<Key> {
                     key_code = Some(KeyCode::Char(c));
                 }
             }
-            _ => return Err(anyhow::anyhow!("Unknown key: {}", part)),
+            _ => return Err(MyTermError::parse(anyhow::anyhow!("Unknown key: {}", part))),
         }
     }
     
-    let code = key_code.ok_or_else(|| anyhow::anyhow!("No key code found in: {}", s))?;
+    let code = key_code.ok_or_else(|| MyTermError::parse(anyhow::anyhow!("No key code found in: {}", s)))?;
     Ok(Key::new(code, modifiers))
+}
+
+/// Parses a mouse binding such as "Middle" or "Shift+Right".
+pub fn parse_mouse_binding(s: &str) -> Result<MouseInput> {
+    let mut modifiers = Modifiers::empty();
+    let mut button = None;
+    
+    for part in s.split('+') {
+        if let Some(modifier) = parse_modifier(part) {
+            modifiers.insert(modifier);
+            continue;
+        }
+        
+        button = Some(match part.to_lowercase().as_str() {
+            "left" => MouseButton::Left,
+            "middle" => MouseButton::Middle,
+            "right" => MouseButton::Right,
+            "back" => MouseButton::Back,
+            "forward" => MouseButton::Forward,
+            _ => return Err(MyTermError::parse(anyhow::anyhow!("Unknown mouse button: {}", part))),
+        });
+    }
+    
+    let button = button.ok_or_else(|| MyTermError::parse(anyhow::anyhow!("No mouse button found in: {}", s)))?;
+    Ok(MouseInput::new(button, modifiers))
+}
+
+fn parse_modifier(name: &str) -> Option<Modifiers> {
+    match name.to_lowercase().as_str() {
+        "ctrl" => Some(Modifiers::CTRL),
+        "alt" => Some(Modifiers::ALT),
+        "shift" => Some(Modifiers::SHIFT),
+        "super" | "cmd" => Some(Modifiers::SUPER),
+        _ => None,
+    }
 }
\ No newline at end of file
@@ -1,9 +1,32 @@
+pub mod bell;
+pub mod blink;
+pub mod cli;
+pub mod clipboard;
 pub mod config;
 pub mod display;
+pub mod error;
+pub mod font;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+pub mod headless;
 pub mod input;
+pub mod metrics;
+pub mod pacing;
+pub mod palette;
 pub mod pty;
+pub mod render;
+pub mod renderer;
+pub mod screenshot;
+pub mod scrollback;
+pub mod search;
+pub mod selection;
+pub mod shm;
+pub mod sixel;
+pub mod state;
 pub mod terminal;
+pub mod title;
 pub mod wayland;
 
 pub use config::Config;
+pub use error::MyTermError;
 pub use terminal::Terminal;
\ No newline at end of file
@@ -1,94 +1,726 @@
 use anyhow::Result;
 use log::{debug, info, warn};
+use std::time::Duration;
+use tokio::time::Instant;
 
+mod bell;
+mod blink;
+mod cli;
+mod clipboard;
 mod config;
 mod display;
+mod error;
+mod font;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod headless;
 mod input;
+mod metrics;
+mod pacing;
+mod palette;
 mod pty;
+mod render;
+mod renderer;
+mod screenshot;
+mod scrollback;
+mod search;
+mod selection;
+mod shm;
+mod sixel;
+mod state;
 mod terminal;
+mod title;
 mod wayland;
 
-use config::Config;
-use display::Display;
+use cli::Options;
+use config::{Config, ConfigDelta, ConfigPaths};
+use bell::{Bell, Flash};
+use blink::BlinkTimeout;
+use display::{Display, WaylandDisplay};
+use font::FontSet;
+use headless::HeadlessDisplay;
+use render::GlyphCache;
+use input::{Action, BindingMode, Bindings, Key, KeyCode, Modifiers, MouseInput, ScrollAccumulator};
+use pacing::{DamageWatch, Throttle};
+use search::Search;
+use selection::SelectionMode;
+use state::WindowState;
 use terminal::Terminal;
+use title::TitleParts;
+use error::MyTermError;
+use wayland::ConnectionError;
+
+const CURSOR_BLINK_INTERVAL: Duration = Duration::from_millis(500);
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest a synchronized update may hold back drawing, for programs that never end one.
+const SYNCHRONIZED_UPDATE_TIMEOUT: Duration = Duration::from_millis(150);
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
     
+    let options = match Options::from_env() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("myterm: {}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if options.help {
+        print!("{}", cli::USAGE);
+        return Ok(());
+    }
+    
     info!("Starting MyTerm - Modern terminal for Sway/Wayland");
     
-    let config = Config::load().unwrap_or_else(|e| {
-        warn!("Failed to load config: {}, using defaults", e);
+    let config_paths = ConfigPaths::from_env(options.config.as_deref())
+        .map_err(|e| warn!("Failed to locate config: {}, using defaults", e))
+        .ok();
+    let mut config = config_paths.as_ref().map_or_else(|| Ok(Config::default()), Config::load).unwrap_or_else(|e| {
+        warn!("Failed to load config: {:#}, using defaults", e);
         Config::default()
     });
+    options.apply(&mut config);
     
     debug!("Configuration loaded: {:?}", config);
     
-    let display = Display::new(&config).await?;
+    if let Some(path) = &options.screenshot {
+        return run_screenshot(&config, &options, path).await;
+    }
+    if options.headless {
+        let display = HeadlessDisplay::new(&config, options.size, options.script.clone())?;
+        return run(config, config_paths, options, display).await;
+    }
+    
+    let saved_size = if config.display.remember_size {
+        WindowState::load().map(|state| (state.width, state.height))
+    } else {
+        None
+    };
+    
+    let display = WaylandDisplay::new(&config, saved_size).await.map_err(|e| {
+        if matches!(e, MyTermError::Connection(ConnectionError::NoDisplay)) {
+            anyhow::Error::new(e).context("No Wayland compositor to open a window on; use --headless to run without one")
+        } else {
+            e.into()
+        }
+    })?;
+    run(config, config_paths, options, display).await
+}
+
+/// Starts the shell, or the command given with `-e`, and runs the app on `display` until it
+/// exits.
+async fn run<D: Display>(config: Config, config_paths: Option<ConfigPaths>, options: Options, mut display: D) -> Result<()> {
     let mut terminal = Terminal::new(&config)?;
+    terminal.set_cell_size(display.set_font_size(config.font.size)?);
     
-    terminal.start_shell(&config).await?;
+    terminal.start_command(&config, &options.command).await?;
     
-    let app = MyTermApp::new(config, display, terminal);
+    let app = MyTermApp::new(config, config_paths, options, display, terminal);
     app.run().await
 }
 
-struct MyTermApp {
-    #[allow(dead_code)]
+/// Runs the command without a window, on a grid the size the window would open at, and saves
+/// the screen to `path` once the command exits or `--after-ms` have passed.
+async fn run_screenshot(config: &Config, options: &Options, path: &std::path::Path) -> Result<()> {
+    let mut glyphs = GlyphCache::new(FontSet::load(&config.font)?, render::GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(config::points_to_pixels(config.font.size, 1.0).round() as u32)?;
+    let metrics = glyphs.metrics();
+    let cell_size = (metrics.cell_width, metrics.cell_height);
+    
+    let mut terminal = Terminal::new(config)?;
+    terminal.set_cell_size(cell_size);
+    let window = config.display.window_size(cell_size);
+    terminal.set_geometry(terminal::grid_geometry(window, cell_size, config.display.padding, config.display.dynamic_padding))?;
+    terminal.start_command(config, &options.command).await?;
+    
+    let deadline = options.after_ms.map(|ms| Instant::now() + Duration::from_millis(ms));
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => break,
+            terminal_output = terminal.next_output() => {
+                if terminal_output?.is_none() {
+                    break;
+                }
+            }
+        }
+    }
+    
+    screenshot::save(path, &terminal, config.display.opacity, &mut glyphs)?;
+    Ok(())
+}
+
+struct MyTermApp<D: Display> {
     config: Config,
-    display: Display,
+    /// Where the config was loaded from, if anywhere, for reloading.
+    config_paths: Option<ConfigPaths>,
+    /// Command-line overrides, applied again to reloaded configs.
+    options: Options,
+    display: D,
     terminal: Terminal,
+    bindings: Bindings,
+    mouse_bindings: Bindings<MouseInput>,
+    /// Fractional wheel lines not scrolled yet.
+    scroll: ScrollAccumulator,
+    bell: Bell,
+    /// The visual bell's flash over the window.
+    flash: Flash,
+    /// Asking whether to close with a program running, see `terminal.confirm_close`.
+    confirming_close: bool,
+    /// Search mode, from the search action until Escape.
+    search: Option<Search>,
+    /// When the terminal changed since the last frame requested for it.
+    damage: DamageWatch,
+    /// The program other than the shell running in the foreground, as last shown in the title.
+    foreground_command: Option<String>,
+    /// The foreground process group `foreground_command` was looked up for.
+    foreground_group: Option<nix::unistd::Pid>,
+    /// Holds back titles set faster than `display.title_interval_ms`.
+    title_throttle: Throttle<String>,
+    /// Stops the cursor blink after `terminal.cursor_blink_timeout_ms` without typing.
+    blink_timeout: BlinkTimeout,
+    /// When to draw anyway if the synchronized update in progress hasn't ended.
+    synchronized_until: Option<Instant>,
+    /// The output the window is on, which may override the font size.
+    output: Option<display::OutputInfo>,
+    /// Font size in points in effect on that output.
+    font_size: f32,
 }
 
-impl MyTermApp {
-    fn new(config: Config, display: Display, terminal: Terminal) -> Self {
+impl<D: Display> MyTermApp<D> {
+    fn new(config: Config, config_paths: Option<ConfigPaths>, options: Options, display: D, terminal: Terminal) -> Self {
+        let bindings = Bindings::new(&config.keybindings).unwrap_or_else(|e| {
+            warn!("Invalid keybindings: {}, using defaults", e);
+            Bindings::new(&[]).expect("Default keybindings must be valid")
+        });
+        let mouse_bindings = Bindings::for_mouse(&config.mouse.bindings).unwrap_or_else(|e| {
+            warn!("Invalid mouse bindings: {}, using defaults", e);
+            Bindings::for_mouse(&[]).expect("Default mouse bindings must be valid")
+        });
+        let bell = Bell::new(&config.bell);
+        let font_size = config.font.size;
+        let title_throttle = Throttle::new(Duration::from_millis(config.display.title_interval_ms));
+        let blink_timeout = BlinkTimeout::new(config.terminal.cursor_blink_timeout_ms, std::time::Instant::now());
+        
         Self {
             config,
+            config_paths,
+            options,
             display,
             terminal,
+            bindings,
+            mouse_bindings,
+            scroll: ScrollAccumulator::default(),
+            bell,
+            flash: Flash::default(),
+            confirming_close: false,
+            search: None,
+            damage: DamageWatch::new(),
+            foreground_command: None,
+            foreground_group: None,
+            title_throttle,
+            blink_timeout,
+            synchronized_until: None,
+            output: None,
+            font_size,
         }
     }
     
     async fn run(mut self) -> Result<()> {
         info!("MyTerm application started");
         
+        let mut blink_timer = tokio::time::interval(CURSOR_BLINK_INTERVAL);
+        let mut config_poll = tokio::time::interval(CONFIG_POLL_INTERVAL);
+        let mut config_mtime = self.config_mtimes();
+        
         loop {
+            // Past the timeout the cursor stays solid until the next key press, while blinking
+            // text goes on
+            let cursor_blinking = self.terminal.cursor().blink && self.blink_timeout.blinking(std::time::Instant::now());
+            if !cursor_blinking {
+                self.display.reset_blink();
+            }
+            
+            // Everything that changed since the last frame is drawn in one go. Key presses
+            // aren't drawn themselves, but their echo is, right after it's parsed
+            if self.damage.needs_frame(self.terminal.damage_generation()) {
+                self.display.request_redraw();
+            }
+            self.display.draw_if_due(&self.terminal).await?;
+            
             debug!("Waiting for events...");
+            let flash_until = self.flash.deadline().map(Instant::from_std);
+            let synchronized_until = self.synchronized_until;
+            let frame_deadline = self.display.frame_deadline();
+            let scrollbar_deadline = self.display.scrollbar_deadline();
+            let title_deadline = self.title_throttle.deadline().map(Instant::from_std);
+            let winsize_deadline = self.terminal.winsize_deadline().map(Instant::from_std);
+            
             tokio::select! {
                 display_event = self.display.next_event() => {
                     debug!("Got display event");
                     match display_event? {
                         display::Event::Resize(width, height) => {
                             debug!("Resize event: {}x{}", width, height);
-                            self.terminal.resize(width, height)?;
-                            self.display.render(&self.terminal).await?;
+                            let geometry = self.display.resize(width, height);
+                            self.terminal.set_geometry(geometry)?;
+                        }
+                        display::Event::ScaleChanged(scale) => {
+                            info!("Output scale {}, rasterizing fonts at {:.2}px",
+                                  scale, config::points_to_pixels(self.font_size, scale));
+                            self.display.request_redraw();
+                        }
+                        display::Event::OutputChanged(output) => {
+                            self.output = output;
+                            if self.update_font_size() {
+                                self.resize_to_window().await?;
+                            }
                         }
                         display::Event::Key(key) => {
                             debug!("Key event: {:?}", key);
-                            let bytes = key.to_bytes();
-                            if !bytes.is_empty() {
-                                self.terminal.write_to_pty(&bytes).await?;
+                            if self.confirming_close {
+                                if self.answer_close_prompt(&key) {
+                                    info!("Close confirmed, shutting down");
+                                    break;
+                                }
+                                continue;
+                            }
+                            if self.search.is_some() {
+                                self.search_key(&key);
+                                continue;
+                            }
+                            self.display.reset_blink();
+                            blink_timer.reset();
+                            self.blink_timeout.input(std::time::Instant::now());
+                            
+                            let mode = self.terminal.binding_mode();
+                            if let Some(action) = self.bindings.lookup(&key, mode).cloned() {
+                                self.perform_action(action).await?;
+                                continue;
+                            }
+                            
+                            if self.config.terminal.scroll_on_keystroke {
+                                self.terminal.scroll_view_to_bottom();
+                            }
+                            
+                            // Modifiers and keys that send nothing leave the pointer be
+                            if self.terminal.handle_key(&key).await? && self.config.mouse.hide_when_typing {
+                                self.display.hide_pointer();
+                            }
+                        }
+                        display::Event::MousePress(input) => {
+                            let mode = self.terminal.binding_mode();
+                            if let Some(action) = self.mouse_bindings.lookup(&input, mode).cloned() {
+                                self.perform_action(action).await?;
                             }
-                            self.display.render(&self.terminal).await?;
                         }
+                        display::Event::SelectionStart { position, rectangular } => {
+                            let mode = if rectangular { SelectionMode::Rectangular } else { SelectionMode::Normal };
+                            self.terminal.begin_selection(self.terminal.point_at(position), mode);
+                        }
+                        display::Event::SelectionUpdate(position) => {
+                            self.terminal.extend_selection(self.terminal.point_at(position));
+                        }
+                        display::Event::SelectionEnd => {
+                            self.terminal.end_selection();
+                            if let Some(text) = self.terminal.selected_text() {
+                                debug!("Selected {} characters", text.chars().count());
+                                self.display.set_primary_selection(text);
+                            }
+                        }
+                        display::Event::Frame | display::Event::Presented(_) => {}
+                        display::Event::Focus(focused) => {
+                            debug!("Focus {}", if focused { "gained" } else { "lost" });
+                            self.terminal.set_focused(focused);
+                            self.display.request_redraw();
+                        }
+                        display::Event::Scroll(steps) => {
+                            let lines = self.scroll.lines(steps, self.config.mouse.scroll_multiplier);
+                            if lines != 0 {
+                                self.scroll_lines(lines).await?;
+                            }
+                        }
+                        display::Event::Input(bytes) => self.terminal.write_to_pty(&bytes).await?,
+                        display::Event::Paste(text) => {
+                            if self.config.terminal.scroll_on_keystroke {
+                                self.terminal.scroll_view_to_bottom();
+                            }
+                            self.terminal.paste(&text);
+                        }
+                        display::Event::Dump(format) => print!("{}", headless::dump(&self.terminal, format)),
                         display::Event::Close => {
-                            info!("Received close event, shutting down");
-                            break;
+                            if self.close_allowed() {
+                                info!("Received close event, shutting down");
+                                break;
+                            }
                         }
                     }
                 }
                 terminal_output = self.terminal.next_output() => {
                     debug!("Got terminal output");
-                    if let Some(output) = terminal_output? {
-                        debug!("Terminal output: {} bytes", output.len());
-                        self.display.render(&self.terminal).await?;
+                    let Some(output) = terminal_output? else {
+                        info!("Shell exited, shutting down");
+                        break;
+                    };
+                    
+                    debug!("Terminal output: {} bytes", output.len());
+                    self.display.add_parse_time(self.terminal.take_parse_time());
+                    self.display.add_output(self.terminal.take_output_arrival());
+                    // While searching, the view stays on the match shown
+                    if let Some(search) = self.search.as_mut() {
+                        search.update(self.terminal.grid());
+                        self.display.set_search(Some(search));
+                    } else if self.config.terminal.scroll_on_output {
+                        self.terminal.scroll_view_to_bottom();
+                    }
+                    if self.terminal.take_bell() {
+                        self.ring_bell();
+                    }
+                    self.update_title(false);
+                    // A program may have switched to a steady cursor mid-blink
+                    if !self.terminal.cursor().blink {
+                        self.display.reset_blink();
+                    }
+                    // Rendering does nothing until the update ends, drawing it as one frame
+                    if self.terminal.modes().synchronized_output {
+                        self.synchronized_until.get_or_insert_with(|| Instant::now() + SYNCHRONIZED_UPDATE_TIMEOUT);
+                    } else {
+                        self.synchronized_until = None;
                     }
                 }
+                _ = config_poll.tick(), if self.config_paths.is_some() => {
+                    let mtime = self.config_mtimes();
+                    if mtime != config_mtime {
+                        info!("Config file changed, reloading");
+                        self.reload_config().await?;
+                        // The reload may have switched to another theme file
+                        config_mtime = self.config_mtimes();
+                    }
+                }
+                _ = blink_timer.tick(), if cursor_blinking || self.display.has_blinking_text() => {
+                    self.display.toggle_blink();
+                    self.display.request_redraw();
+                }
+                _ = tokio::time::sleep_until(flash_until.unwrap_or_else(Instant::now)),
+                    if flash_until.is_some() => {
+                    if self.flash.expire(std::time::Instant::now()) {
+                        self.display.set_bell_flash(None);
+                        self.display.request_redraw();
+                    }
+                }
+                _ = tokio::time::sleep_until(synchronized_until.unwrap_or_else(Instant::now)),
+                    if synchronized_until.is_some() => {
+                    warn!("Synchronized update not ended within {:?}, drawing it anyway", SYNCHRONIZED_UPDATE_TIMEOUT);
+                    self.synchronized_until = None;
+                    self.terminal.end_synchronized_output();
+                    self.display.request_redraw();
+                }
+                _ = tokio::time::sleep_until(frame_deadline.unwrap_or_else(Instant::now)),
+                    if frame_deadline.is_some() => {
+                    self.display.frame_timed_out();
+                }
+                _ = tokio::time::sleep_until(scrollbar_deadline.unwrap_or_else(Instant::now)),
+                    if scrollbar_deadline.is_some() => {
+                    self.display.request_redraw();
+                }
+                _ = tokio::time::sleep_until(title_deadline.unwrap_or_else(Instant::now)),
+                    if title_deadline.is_some() => {
+                    if let Some(title) = self.title_throttle.flush(std::time::Instant::now()) {
+                        self.display.set_title(&title);
+                    }
+                }
+                _ = tokio::time::sleep_until(winsize_deadline.unwrap_or_else(Instant::now)),
+                    if winsize_deadline.is_some() => {
+                    self.terminal.flush_winsize()?;
+                }
             }
         }
         
         info!("MyTerm application shutting down");
+        if let Some(format) = self.options.dump {
+            print!("{}", headless::dump(&self.terminal, format));
+        }
+        if self.config.display.remember_size && !self.options.headless {
+            let (width, height) = self.display.windowed_size();
+            if let Err(e) = WindowState::new(width, height).save() {
+                warn!("Failed to save window size: {:#}", e);
+            }
+        }
         Ok(())
     }
+    
+    /// Modification times of the system and user config files and the theme, to notice edits
+    /// to any of them.
+    fn config_mtimes(&self) -> Vec<Option<std::time::SystemTime>> {
+        self.config_paths.iter()
+            .flat_map(|paths| paths.system.iter().chain(std::iter::once(&paths.user)))
+            .chain(&self.config.colors.theme)
+            .map(|path| modified_time(path))
+            .collect()
+    }
+    
+    async fn reload_config(&mut self) -> Result<()> {
+        let Some(paths) = &self.config_paths else {
+            return Ok(());
+        };
+        let new_config = match Config::load_merged(paths) {
+            Ok(mut config) => {
+                self.options.apply(&mut config);
+                config
+            }
+            Err(e) => {
+                warn!("Not reloading config: {:#}", e);
+                return Ok(());
+            }
+        };
+        
+        let delta = Config::diff(&self.config, &new_config);
+        self.apply_delta(&delta, new_config).await
+    }
+    
+    async fn apply_delta(&mut self, delta: &ConfigDelta, mut new_config: Config) -> Result<()> {
+        if delta.is_empty() {
+            debug!("Config reloaded, nothing changed");
+            return Ok(());
+        }
+        
+        if !delta.live.is_empty() {
+            info!("Applying changed settings: {}", delta.live.join(", "));
+            match Bindings::new(&new_config.keybindings) {
+                Ok(bindings) => self.bindings = bindings,
+                Err(e) => warn!("Invalid keybindings: {}, keeping the current ones", e),
+            }
+            match Bindings::for_mouse(&new_config.mouse.bindings) {
+                Ok(bindings) => self.mouse_bindings = bindings,
+                Err(e) => warn!("Invalid mouse bindings: {}, keeping the current ones", e),
+            }
+            self.bell = Bell::new(&new_config.bell);
+            self.display.set_opacity(new_config.display.opacity);
+            self.display.set_allow_blink(new_config.terminal.allow_blink);
+            self.display.set_scrollback_indicator(new_config.display.scrollback_indicator);
+            self.display.set_padding(new_config.display.padding, new_config.display.dynamic_padding);
+            self.title_throttle.set_interval(Duration::from_millis(new_config.display.title_interval_ms));
+            self.blink_timeout.set_timeout(new_config.terminal.cursor_blink_timeout_ms);
+            self.terminal.apply_config(&new_config);
+        }
+        if !delta.resize.is_empty() {
+            info!("Recomputing cell metrics for: {}", delta.resize.join(", "));
+        }
+        // Any of the four families or the fallbacks, all but the regular family being live as
+        // they don't affect the cells
+        if delta.live.iter().chain(&delta.resize).any(|field| field.starts_with("font.") && (field.ends_with("family") || field.ends_with("fallback"))) {
+            match self.display.set_font(&new_config.font) {
+                Ok(cell_size) => self.terminal.set_cell_size(cell_size),
+                Err(e) => warn!("Keeping the current font: {:#}", e),
+            }
+        }
+        if !delta.restart.is_empty() {
+            warn!("Restart MyTerm to apply: {}", delta.restart.join(", "));
+            new_config.keep_restart_settings(&self.config);
+        }
+        
+        self.config = new_config;
+        self.update_font_size();
+        if delta.live.contains(&"display.title_format") {
+            self.update_title(true);
+        }
+        
+        // Padding and metrics changes both change how many cells fit
+        self.resize_to_window().await
+    }
+    
+    /// Picks the font size for the current output, returning whether it changed.
+    fn update_font_size(&mut self) -> bool {
+        let font_size = self.config.font_size_for(self.output.as_ref());
+        if font_size == self.font_size {
+            return false;
+        }
+        
+        info!("Using font size {}pt", font_size);
+        self.font_size = font_size;
+        match self.display.set_font_size(font_size) {
+            Ok(cell_size) => self.terminal.set_cell_size(cell_size),
+            Err(e) => warn!("Keeping the current font size: {:#}", e),
+        }
+        true
+    }
+    
+    /// Fits the grid to the window after the cell size or padding changed.
+    async fn resize_to_window(&mut self) -> Result<()> {
+        let (width, height) = self.display.content_size();
+        let geometry = self.display.resize(width, height);
+        self.terminal.set_geometry(geometry)?;
+        Ok(())
+    }
+    
+    /// Scrolls the history, or sends arrow keys to programs on the alternate screen.
+    async fn scroll_lines(&mut self, lines: isize) -> Result<()> {
+        let alt_screen = self.terminal.binding_mode().contains(BindingMode::ALT_SCREEN);
+        if alt_screen && self.config.mouse.alternate_scroll {
+            // Sent as the arrow keys would be, so SS3 in application cursor mode
+            let arrow = Key::new(if lines > 0 { KeyCode::Up } else { KeyCode::Down }, Modifiers::empty());
+            let bytes = arrow.to_bytes_in(self.terminal.binding_mode());
+            self.terminal.write_to_pty(&bytes.repeat(lines.unsigned_abs())).await?;
+            return Ok(());
+        }
+        
+        self.terminal.scroll_view_lines(lines);
+        Ok(())
+    }
+    
+    /// The program other than the shell running in the foreground.
+    fn foreground_command(&self) -> Option<String> {
+        self.terminal.has_foreground_job().then(|| self.terminal.foreground_process()).flatten()
+    }
+    
+    /// Shows the title programs set, or else the program running in the foreground, or what
+    /// `display.title_format` makes of them, after output that may have changed any of it.
+    /// `force` shows it even if nothing did, e.g. for a new format.
+    fn update_title(&mut self, force: bool) {
+        let title_changed = self.terminal.take_title_changed();
+        let format = self.config.display.title_format.as_deref();
+        // Only looked up while it would be shown
+        let shows_command = match format {
+            Some(format) => title::shows_command(format),
+            None => self.terminal.title().is_empty(),
+        };
+        let group = if shows_command { self.terminal.foreground_process_group() } else { None };
+        // /proc is only read again once another process group has the foreground
+        let command = if force || group != self.foreground_group {
+            self.foreground_group = group;
+            group.and_then(|_| self.foreground_command())
+        } else {
+            self.foreground_command.clone()
+        };
+        if !force && !title_changed && command == self.foreground_command {
+            return;
+        }
+        
+        self.foreground_command = command;
+        let default = self.config.display.title.as_deref().unwrap_or(wayland::DEFAULT_TITLE);
+        let command = self.foreground_command.as_deref();
+        let title = match format {
+            Some(format) => {
+                let parts = TitleParts { title: self.terminal.title(), cwd: self.terminal.cwd(), command };
+                title::format_title(format, &parts, default)
+            }
+            None => wayland::window_title(self.terminal.title(), command, default),
+        };
+        if let Some(title) = self.title_throttle.update(title, std::time::Instant::now()) {
+            self.display.set_title(&title);
+        }
+    }
+    
+    /// Whether the window may close, or else asks first because a program other than the
+    /// shell is running. Being asked to close again while the question shows closes anyway.
+    fn close_allowed(&mut self) -> bool {
+        if !self.config.terminal.confirm_close || self.confirming_close || !self.terminal.has_foreground_job() {
+            return true;
+        }
+        self.confirming_close = true;
+        let command = self.foreground_command().unwrap_or_else(|| "A program".to_string());
+        self.display.set_prompt(Some(&format!("{} is still running. Close anyway? [y/N]", command)));
+        self.display.request_redraw();
+        false
+    }
+    
+    /// Takes down the close prompt, returning whether `key` confirmed closing.
+    fn answer_close_prompt(&mut self, key: &Key) -> bool {
+        self.confirming_close = false;
+        self.display.set_prompt(None);
+        self.display.request_redraw();
+        matches!(key.code, KeyCode::Char('y' | 'Y') | KeyCode::Enter)
+    }
+    
+    /// Starts searching the scrollback and screen, with an empty query.
+    fn start_search(&mut self) {
+        let search = Search::new();
+        self.display.set_search(Some(&search));
+        self.search = Some(search);
+    }
+    
+    /// Edits the search query or moves between matches, keeping the match shown in view.
+    /// Escape ends the search, leaving the view where it is.
+    fn search_key(&mut self, key: &Key) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        let grid = self.terminal.grid();
+        match key.code {
+            KeyCode::Escape => self.search = None,
+            KeyCode::Enter if key.modifiers.contains(Modifiers::SHIFT) => search.focus_newer(),
+            KeyCode::Enter | KeyCode::Up => search.focus_older(),
+            KeyCode::Down => search.focus_newer(),
+            KeyCode::Backspace => search.pop(grid),
+            KeyCode::Char(c) if !key.modifiers.intersects(Modifiers::CTRL | Modifiers::ALT | Modifiers::SUPER) => search.push(c, grid),
+            _ => {}
+        }
+        if let Some(line) = self.search.as_ref().and_then(Search::focused).map(|focused| focused.line) {
+            self.terminal.scroll_view_to_line(line);
+        }
+        self.display.set_search(self.search.as_ref());
+    }
+    
+    fn ring_bell(&mut self) {
+        let now = std::time::Instant::now();
+        let response = self.bell.ring(now, self.display.is_focused());
+        
+        if let Some(duration) = response.visual {
+            if self.flash.trigger(now, duration) {
+                self.display.set_bell_flash(Some(self.bell.visual_color()));
+                self.display.request_redraw();
+            }
+        }
+        if response.urgent {
+            self.display.request_urgency();
+        }
+        if response.run_command {
+            if let Err(e) = self.bell.spawn_command() {
+                warn!("{:#}", e);
+            }
+        }
+    }
+    
+    async fn perform_action(&mut self, action: Action) -> Result<()> {
+        debug!("Performing action: {:?}", action);
+        
+        match action {
+            Action::ScrollLineUp => self.terminal.scroll_view_lines(1),
+            Action::ScrollLineDown => self.terminal.scroll_view_lines(-1),
+            Action::ScrollPageUp => self.terminal.scroll_view_page_up(),
+            Action::ScrollPageDown => self.terminal.scroll_view_page_down(),
+            Action::ScrollToTop => self.terminal.scroll_view_to_top(),
+            Action::ScrollToBottom => self.terminal.scroll_view_to_bottom(),
+            Action::JumpToPrevPrompt => self.terminal.jump_to_prompt(true),
+            Action::JumpToNextPrompt => self.terminal.jump_to_prompt(false),
+            Action::PanLeft => self.terminal.pan_view(false),
+            Action::PanRight => self.terminal.pan_view(true),
+            Action::ClearAll => self.terminal.clear_all(),
+            Action::ReloadConfig => self.reload_config().await?,
+            Action::Screenshot => {
+                if let Err(e) = self.display.screenshot(&self.terminal, &screenshot::default_path()) {
+                    warn!("Failed to save screenshot: {:#}", e);
+                }
+            }
+            Action::Search => self.start_search(),
+            Action::SendText(text) => self.terminal.write_to_pty(text.as_bytes()).await?,
+            Action::Copy => match self.terminal.selected_text() {
+                Some(text) => self.display.set_clipboard(text),
+                None => debug!("Nothing selected to copy"),
+            },
+            Action::Paste => self.display.request_paste(),
+            Action::PastePrimary => self.display.request_primary_paste(),
+            Action::NewTab
+            | Action::CloseTab
+            | Action::NextTab
+            | Action::PrevTab => debug!("Action {:?} is not implemented yet", action),
+            Action::None => {}
+        }
+        
+        Ok(())
+    }
+}
+
+fn modified_time(path: &std::path::Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
 }
\ No newline at end of file
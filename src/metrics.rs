@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::render::{GlyphCacheStats, Rect};
+
+/// Environment variable that, set to 1, shows the render metrics in a corner of the window.
+pub const DEBUG_HUD_VAR: &str = "MYTERM_DEBUG_HUD";
+
+/// Frames the averages are taken over.
+pub const AVERAGED_FRAMES: usize = 60;
+
+/// Frames the latency percentiles are taken over, more than the averages so the 99th
+/// percentile means something.
+pub const LATENCY_FRAMES: usize = 600;
+
+/// How often the metrics are logged at debug level.
+pub const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Mean of the last samples pushed, up to a fixed number of them.
+#[derive(Debug, Clone)]
+pub struct RollingAverage {
+    samples: VecDeque<f64>,
+    capacity: usize,
+}
+
+/// Where the time for one frame went.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameTimings {
+    /// Parsing program output since the last frame.
+    pub parse: Duration,
+    /// Drawing the frame, rasterizing glyphs not cached yet.
+    pub raster: Duration,
+    /// Copying the canvas into the shared memory buffer, or submitting the GPU's commands.
+    pub blit: Duration,
+    /// Attaching, damaging and committing the buffer, or presenting the GPU's.
+    pub commit: Duration,
+}
+
+/// Frame timings, frame rate and damage averaged over the last frames, with the glyph cache's
+/// hit rate over the last log interval.
+#[derive(Debug, Clone)]
+pub struct RenderMetrics {
+    parse: RollingAverage,
+    raster: RollingAverage,
+    blit: RollingAverage,
+    commit: RollingAverage,
+    coverage: RollingAverage,
+    /// Seconds between frames.
+    interval: RollingAverage,
+    /// Milliseconds from output arriving to the frame showing it being presented.
+    latency: RollingAverage,
+    last_frame: Option<Instant>,
+    last_log: Instant,
+    /// Glyph cache hits and misses as of the last log.
+    logged_glyphs: (u64, u64),
+    hit_rate: Option<f64>,
+}
+
+impl RollingAverage {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+    
+    /// Adds `sample`, dropping the oldest one once full.
+    pub fn push(&mut self, sample: f64) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+    
+    pub fn average(&self) -> Option<f64> {
+        (!self.samples.is_empty()).then(|| self.samples.iter().sum::<f64>() / self.samples.len() as f64)
+    }
+    
+    /// The smallest sample at least `fraction` of the samples are no greater than, e.g. the
+    /// median for 0.5.
+    pub fn percentile(&self, fraction: f64) -> Option<f64> {
+        let mut sorted: Vec<f64> = self.samples.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let rank = (fraction * sorted.len() as f64).ceil() as usize;
+        sorted.get(rank.clamp(1, sorted.len().max(1)) - 1).copied()
+    }
+}
+
+/// Share of a canvas of `size` covered by `damage`, from 0 to 1. Overlapping rectangles count
+/// twice, up to the whole canvas.
+pub fn damage_coverage(damage: &[Rect], (width, height): (usize, usize)) -> f64 {
+    let area = width * height;
+    if area == 0 {
+        return 0.0;
+    }
+    let damaged: usize = damage.iter().map(|rect| rect.width * rect.height).sum();
+    (damaged as f64 / area as f64).min(1.0)
+}
+
+impl RenderMetrics {
+    pub fn new(now: Instant) -> Self {
+        let average = || RollingAverage::new(AVERAGED_FRAMES);
+        Self {
+            parse: average(),
+            raster: average(),
+            blit: average(),
+            commit: average(),
+            coverage: average(),
+            interval: average(),
+            latency: RollingAverage::new(LATENCY_FRAMES),
+            last_frame: None,
+            last_log: now,
+            logged_glyphs: (0, 0),
+            hit_rate: None,
+        }
+    }
+    
+    /// Counts a frame committed at `now`.
+    pub fn record(&mut self, now: Instant, timings: FrameTimings, coverage: f64) {
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        self.parse.push(millis(timings.parse));
+        self.raster.push(millis(timings.raster));
+        self.blit.push(millis(timings.blit));
+        self.commit.push(millis(timings.commit));
+        self.coverage.push(coverage);
+        if let Some(last) = self.last_frame {
+            self.interval.push(now.saturating_duration_since(last).as_secs_f64());
+        }
+        self.last_frame = Some(now);
+    }
+    
+    /// Counts the time from output arriving to the frame showing it being presented.
+    pub fn record_latency(&mut self, latency: Duration) {
+        self.latency.push(latency.as_secs_f64() * 1000.0);
+    }
+    
+    /// Median and 99th percentile of the latency in milliseconds, once the compositor has
+    /// reported presenting any frame with output in it.
+    pub fn latency(&self) -> Option<(f64, f64)> {
+        Some((self.latency.percentile(0.5)?, self.latency.percentile(0.99)?))
+    }
+    
+    /// Frames per second over the last frames, which is low while little changes on screen.
+    pub fn fps(&self) -> Option<f64> {
+        self.interval.average().filter(|&interval| interval > 0.0).map(|interval| 1.0 / interval)
+    }
+    
+    /// Share of glyph lookups found in the cache over the last log interval.
+    #[allow(dead_code)]
+    pub fn hit_rate(&self) -> Option<f64> {
+        self.hit_rate
+    }
+    
+    /// Takes the glyph cache's hit rate since the last log and returns whether it's time to
+    /// log again, every `LOG_INTERVAL`.
+    pub fn log_due(&mut self, now: Instant, glyphs: GlyphCacheStats) -> bool {
+        if now.saturating_duration_since(self.last_log) < LOG_INTERVAL {
+            return false;
+        }
+        let (hits, misses) = (glyphs.hits - self.logged_glyphs.0, glyphs.misses - self.logged_glyphs.1);
+        if hits + misses > 0 {
+            self.hit_rate = Some(hits as f64 / (hits + misses) as f64);
+        }
+        self.logged_glyphs = (glyphs.hits, glyphs.misses);
+        self.last_log = now;
+        true
+    }
+    
+    /// One line with every average, for the log and the HUD, and the latency where known.
+    pub fn summary(&self) -> String {
+        let millis = |average: &RollingAverage| average.average().unwrap_or(0.0);
+        let latency = self.latency()
+            .map(|(p50, p99)| format!(" latency p50 {:.1} p99 {:.1}ms", p50, p99))
+            .unwrap_or_default();
+        format!(
+            "{:.0}fps parse {:.1} raster {:.1} blit {:.1} commit {:.1}ms damage {:.0}% hits {:.0}%{}",
+            self.fps().unwrap_or(0.0),
+            millis(&self.parse),
+            millis(&self.raster),
+            millis(&self.blit),
+            millis(&self.commit),
+            self.coverage.average().unwrap_or(0.0) * 100.0,
+            self.hit_rate.unwrap_or(1.0) * 100.0,
+            latency,
+        )
+    }
+}
\ No newline at end of file
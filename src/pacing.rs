@@ -0,0 +1,155 @@
+use std::time::{Duration, Instant};
+
+/// What the compositor reported about a frame through presentation-time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Presentation {
+    /// When the frame turned to light, or `None` if it was discarded unseen.
+    pub at: Option<Instant>,
+    /// The output's refresh period, `None` when unknown or variable.
+    pub refresh: Option<Duration>,
+    /// When the oldest output the frame shows arrived from the PTY, if it shows any.
+    pub output_since: Option<Instant>,
+}
+
+/// Keeps frames to one per refresh of the output while programs flood it with output.
+///
+/// Frame callbacks alone let frames through as fast as the compositor takes them, which can be
+/// several per refresh when parsing outpaces the display. With presentation feedback, a frame
+/// drawn while the last one still waits for the screen is held until the next refresh, and the
+/// output arriving meanwhile is drawn with it instead of in frames nobody would see.
+#[derive(Debug, Clone, Default)]
+pub struct FramePacer {
+    /// Frames committed with a feedback request that hasn't been answered yet.
+    in_flight: usize,
+    /// When the last frame was presented, and the refresh period then.
+    last: Option<(Instant, Duration)>,
+}
+
+/// Tells the event loop when to ask for a frame: whenever the terminal's damage generation
+/// moved since it last did, so everything that changed in between is drawn in one go.
+#[derive(Debug, Clone, Default)]
+pub struct DamageWatch {
+    /// The damage generation the last frame was asked for at.
+    drawn_generation: u64,
+}
+
+impl DamageWatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Whether a frame is needed for the terminal at `generation`, as `damage_generation`
+    /// returns it, taking it as drawn if so.
+    pub fn needs_frame(&mut self, generation: u64) -> bool {
+        if generation == self.drawn_generation {
+            return false;
+        }
+        self.drawn_generation = generation;
+        true
+    }
+}
+
+/// Turns a presentation timestamp, `timestamp` on the compositor's clock, into an `Instant`,
+/// given that clock read `clock_now` at `now`.
+pub fn presented_at(timestamp: Duration, clock_now: Duration, now: Instant) -> Instant {
+    now.checked_sub(clock_now.saturating_sub(timestamp)).unwrap_or(now)
+}
+
+impl FramePacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// A frame with a feedback request is about to be committed.
+    pub fn committing(&mut self) {
+        self.in_flight += 1;
+    }
+    
+    /// Takes the compositor's feedback on a frame.
+    pub fn presented(&mut self, presentation: &Presentation) {
+        self.in_flight = self.in_flight.saturating_sub(1);
+        if let (Some(at), Some(refresh)) = (presentation.at, presentation.refresh) {
+            self.last = Some((at, refresh));
+        }
+    }
+    
+    /// When a frame due at `now` may be drawn, or `None` to draw it right away.
+    ///
+    /// Frames are only held while one is still waiting for the screen and the output is
+    /// refreshing steadily, i.e. the last presentation was no more than two refreshes ago.
+    /// Without feedback, as on compositors lacking presentation-time, nothing is held.
+    pub fn hold_until(&self, now: Instant) -> Option<Instant> {
+        let (last, refresh) = self.last?;
+        if self.in_flight == 0 || refresh.is_zero() {
+            return None;
+        }
+        let since = now.saturating_duration_since(last);
+        if since > refresh * 2 {
+            return None;
+        }
+        let refreshes = since.as_nanos() / refresh.as_nanos() + 1;
+        Some(last + refresh * refreshes as u32)
+    }
+}
+
+/// How often the PTY's window size follows the window while it's being resized.
+pub const WINSIZE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Holds back values, such as the window title or the PTY's window size, coming faster than
+/// an interval, keeping only the latest until it's due. The first value after a pause goes
+/// through at once, so a shell gets a SIGWINCH or two per interval of an interactive resize
+/// rather than one per configure.
+#[derive(Debug, Clone)]
+pub struct Throttle<T> {
+    interval: Duration,
+    /// When the value in effect was let through.
+    last: Option<Instant>,
+    sent: Option<T>,
+    pending: Option<T>,
+}
+
+impl<T: Clone + PartialEq> Throttle<T> {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, last: None, sent: None, pending: None }
+    }
+    
+    /// Changes the interval, keeping any value held back, which is then due by the new one.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+    
+    /// Asks to put `value` in effect at `now`, returning it if that should happen right away.
+    /// Otherwise it's held back until `deadline`, replacing any value held back before.
+    pub fn update(&mut self, value: T, now: Instant) -> Option<T> {
+        if self.sent.as_ref() == Some(&value) {
+            self.pending = None;
+            return None;
+        }
+        if self.last.is_some_and(|last| now.saturating_duration_since(last) < self.interval) {
+            self.pending = Some(value);
+            return None;
+        }
+        self.send(value, now)
+    }
+    
+    /// When the value held back is due.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.as_ref().and(self.last).map(|last| last + self.interval)
+    }
+    
+    /// Returns the value held back if it's due at `now`.
+    pub fn flush(&mut self, now: Instant) -> Option<T> {
+        if self.deadline().is_none_or(|deadline| now < deadline) {
+            return None;
+        }
+        let value = self.pending.take()?;
+        self.send(value, now)
+    }
+    
+    fn send(&mut self, value: T, now: Instant) -> Option<T> {
+        self.pending = None;
+        self.last = Some(now);
+        self.sent = Some(value.clone());
+        Some(value)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,134 @@
+use crate::error::Result;
+use rgb::RGB8;
+
+use crate::config::{parse_color, parse_color_with_alpha, ColorConfig};
+
+/// Colors resolved from `ColorConfig`, used when interpreting SGR and drawing cells.
+#[derive(Debug, Clone, PartialEq)]
+#[allow(dead_code)]
+pub struct Palette {
+    pub foreground: RGB8,
+    pub background: RGB8,
+    /// Alpha from a `#rrggbbaa` background, combined with `display.opacity` when drawing.
+    pub background_alpha: u8,
+    pub cursor: RGB8,
+    /// Text under a block cursor, which otherwise takes the cell's background color.
+    pub cursor_text: Option<RGB8>,
+    pub normal: [RGB8; 8],
+    pub bright: [RGB8; 8],
+    pub dim: [RGB8; 8],
+    pub selection_foreground: Option<SelectionColor>,
+    pub selection_background: Option<SelectionColor>,
+    /// How far resolved colors are blended toward the background, from 0 to 1, as
+    /// `display.unfocused_dim` has them while the window is unfocused.
+    pub fade: f32,
+}
+
+/// A selection color, either fixed or taken from the selected cell itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionColor {
+    CellForeground,
+    CellBackground,
+    Rgb(RGB8),
+}
+
+impl SelectionColor {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "CellForeground" => Ok(Self::CellForeground),
+            "CellBackground" => Ok(Self::CellBackground),
+            _ => Ok(Self::Rgb(parse_color(s)?)),
+        }
+    }
+    
+    fn resolve(self, fg: RGB8, bg: RGB8) -> RGB8 {
+        match self {
+            Self::CellForeground => fg,
+            Self::CellBackground => bg,
+            Self::Rgb(color) => color,
+        }
+    }
+}
+
+impl Palette {
+    pub fn from_config(colors: &ColorConfig) -> Self {
+        let defaults = ColorConfig::default();
+        
+        let color = |value: &str, fallback: &str| {
+            parse_color(value).unwrap_or_else(|e| {
+                log::warn!("{}, using {}", e, fallback);
+                parse_color(fallback).unwrap_or_default()
+            })
+        };
+        let colors8 = |values: &[String; 8], fallbacks: &[String; 8]| {
+            std::array::from_fn(|i| color(&values[i], &fallbacks[i]))
+        };
+        let selection = |value: &Option<String>| {
+            value.as_deref().and_then(|s| match SelectionColor::parse(s) {
+                Ok(color) => Some(color),
+                Err(e) => {
+                    log::warn!("Invalid selection color: {}", e);
+                    None
+                }
+            })
+        };
+        
+        let background = parse_color_with_alpha(&colors.background).unwrap_or_else(|e| {
+            log::warn!("{}, using {}", e, defaults.background);
+            parse_color_with_alpha(&defaults.background).unwrap_or_default()
+        });
+        
+        Self {
+            foreground: color(&colors.foreground, &defaults.foreground),
+            background: background.rgb(),
+            background_alpha: background.a,
+            cursor: color(&colors.cursor, &defaults.cursor),
+            cursor_text: colors.cursor_text.as_deref().and_then(|s| match parse_color(s) {
+                Ok(color) => Some(color),
+                Err(e) => {
+                    log::warn!("Invalid cursor text color: {}", e);
+                    None
+                }
+            }),
+            normal: colors8(&colors.normal, &defaults.normal),
+            bright: colors8(&colors.bright, &defaults.bright),
+            dim: colors8(&colors.dim, &defaults.dim),
+            selection_foreground: selection(&colors.selection_foreground),
+            selection_background: selection(&colors.selection_background),
+            fade: 0.0,
+        }
+    }
+    
+    /// `color` blended toward the background by `fade`.
+    pub fn faded(&self, color: RGB8) -> RGB8 {
+        if self.fade <= 0.0 {
+            return color;
+        }
+        let fade = self.fade.min(1.0);
+        let mix = |c: u8, bg: u8| (c as f32 + (bg as f32 - c as f32) * fade).round() as u8;
+        RGB8::new(mix(color.r, self.background.r), mix(color.g, self.background.g), mix(color.b, self.background.b))
+    }
+    
+    /// The configured dim variant of `color` if it is one of the eight normal colors.
+    ///
+    /// Cells keep their colors as RGB, so this goes by value, like turning colors back into SGR
+    /// parameters does. Callers only ask about colors set with SGR, `CellFlags::EXPLICIT_FG`
+    /// telling them apart from a default that happens to equal a normal color, as the default
+    /// black background does.
+    pub fn dim_color(&self, color: RGB8) -> Option<RGB8> {
+        self.normal.iter().position(|&normal| normal == color).map(|index| self.dim[index])
+    }
+    
+    /// Colors of a selected cell whose own colors are `fg` on `bg`.
+    ///
+    /// With neither selection color configured the cell is shown inverted.
+    pub fn selection_colors(&self, fg: RGB8, bg: RGB8) -> (RGB8, RGB8) {
+        match (self.selection_foreground, self.selection_background) {
+            (None, None) => (bg, fg),
+            (sel_fg, sel_bg) => (
+                sel_fg.map_or(fg, |c| c.resolve(fg, bg)),
+                sel_bg.map_or(bg, |c| c.resolve(fg, bg)),
+            ),
+        }
+    }
+}
\ No newline at end of file
@@ -1,18 +1,52 @@
-use anyhow::{Context, Result};
+use anyhow::Context;
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
 use nix::pty::{openpty, Winsize};
 use nix::sys::signal::{self, Signal};
-use nix::unistd::{close, dup2, execve, fork, setsid, ForkResult, Pid};
+use nix::unistd::{close, dup2, execvpe, fork, setsid, tcgetpgrp, ForkResult, Pid};
+use std::collections::VecDeque;
 use std::ffi::CString;
-use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
 use std::process;
-use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::unix::AsyncFd;
+
+use crate::error::{MyTermError, Result};
+
+/// Terminal type used when the configured one has no terminfo entry.
+pub const FALLBACK_TERM: &str = "xterm-256color";
+
+/// Variables describing the parent terminal that would mislead programs in the child.
+const INHERITED_TERMINAL_VARS: &[&str] = &[
+    "TERM",
+    "COLORTERM",
+    "TERM_PROGRAM",
+    "TERM_PROGRAM_VERSION",
+    "VTE_VERSION",
+];
 
 pub struct Pty {
     master_fd: RawFd,
     slave_fd: RawFd,
     child_pid: Option<Pid>,
-    master_file: Option<File>,
+    /// Non-blocking master side, so reads and writes wait on readiness and a cancelled read
+    /// loses no data.
+    master_file: Option<AsyncFd<File>>,
+    typescript: Option<Typescript>,
+    /// Input written as the program takes it while `read` waits, e.g. a large paste.
+    queued_input: VecDeque<u8>,
+}
+
+/// A record of the bytes that go through the PTY, as `terminal.typescript` asks for.
+///
+/// Output alone is written as it was read, so `cat` replays it like a `script(1)` typescript.
+/// With input too, each chunk is written after a line of `<` for output or `>` for input and
+/// its length in bytes, e.g. `< 12`, and a newline follows the chunk. Chunks go straight to the
+/// file, unbuffered, so a crash loses none of them.
+pub struct Typescript {
+    file: File,
+    input: bool,
 }
 
 impl Pty {
@@ -32,28 +66,58 @@ impl Pty {
             slave_fd,
             child_pid: None,
             master_file: None,
+            typescript: None,
+            queued_input: VecDeque::new(),
         })
     }
     
-    pub async fn spawn_shell(&mut self, shell: Option<&str>, working_dir: Option<&str>) -> Result<()> {
+    /// Records the bytes read from the PTY, and with `Typescript::input` those written to it,
+    /// from now on.
+    pub fn set_typescript(&mut self, typescript: Typescript) {
+        self.typescript = Some(typescript);
+    }
+    
+    pub async fn spawn_shell(&mut self, shell: Option<&str>, working_dir: Option<&str>, term: &str) -> Result<()> {
         let default_shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
-        let shell = shell.unwrap_or(&default_shell);
+        self.spawn(shell.unwrap_or(&default_shell), &[], working_dir, term).await
+    }
+    
+    /// Runs `program` with `args` on the PTY, looking it up in `PATH` unless it has a slash.
+    pub async fn spawn(&mut self, program: &str, args: &[String], working_dir: Option<&str>, term: &str) -> Result<()> {
+        let term = resolve_term(term, &terminfo_dirs());
+        let env_vars: Vec<CString> = child_environment(std::env::vars(), &term)
+            .into_iter()
+            .map(|(key, value)| CString::new(format!("{}={}", key, value)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(MyTermError::pty)?;
         
         match unsafe { fork() }? {
             ForkResult::Parent { child } => {
                 self.child_pid = Some(child);
                 close(self.slave_fd)?;
                 
+                let flags = OFlag::from_bits_truncate(fcntl(self.master_fd, FcntlArg::F_GETFL)?);
+                fcntl(self.master_fd, FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK))?;
+                
                 let master_file = unsafe {
                     File::from_raw_fd(self.master_fd)
                 };
-                self.master_file = Some(master_file);
+                self.master_file = Some(AsyncFd::new(master_file).context("Failed to register PTY").map_err(MyTermError::pty)?);
                 
                 Ok(())
             }
             ForkResult::Child => {
                 setsid()?;
                 
+                // The slave was opened before the new session, so it has to be made the
+                // controlling terminal for job control and signals from keys to work
+                use nix::ioctl_write_int_bad;
+                ioctl_write_int_bad!(tiocsctty, libc::TIOCSCTTY);
+                // SAFETY: the slave is open, and TIOCSCTTY takes an int rather than a pointer
+                unsafe {
+                    tiocsctty(self.slave_fd, 0)?;
+                }
+                
                 close(self.master_fd)?;
                 
                 dup2(self.slave_fd, 0)?; // stdin
@@ -66,16 +130,16 @@ impl Pty {
                 
                 if let Some(dir) = working_dir {
                     std::env::set_current_dir(dir)
-                        .context("Failed to set working directory")?;
+                        .context("Failed to set working directory")
+                        .map_err(MyTermError::pty)?;
                 }
                 
-                let shell_cstr = CString::new(shell)?;
-                let args = [&shell_cstr];
-                let env_vars: Vec<CString> = std::env::vars()
-                    .map(|(key, value)| CString::new(format!("{}={}", key, value)))
-                    .collect::<Result<Vec<_>, _>>()?;
+                let argv: Vec<CString> = std::iter::once(program).chain(args.iter().map(String::as_str))
+                    .map(CString::new)
+                    .collect::<Result<_, _>>()
+                    .map_err(MyTermError::pty)?;
                 
-                execve(&shell_cstr, &args, &env_vars)?;
+                execvpe(&argv[0], &argv, &env_vars)?;
                 
                 process::exit(1);
             }
@@ -83,20 +147,94 @@ impl Pty {
     }
     
     pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        if let Some(ref mut file) = self.master_file {
-            Ok(file.read(buf).await?)
-        } else {
-            Err(anyhow::anyhow!("PTY not initialized"))
+        loop {
+            let Some(ref file) = self.master_file else {
+                return Err(MyTermError::Pty(anyhow::anyhow!("PTY not initialized")));
+            };
+            // Queued input goes in as the program reads it, while its output keeps being read
+            // so that a program echoing what it reads can't fill the PTY both ways and hang
+            let mut ready = if self.queued_input.is_empty() {
+                file.readable().await.map_err(MyTermError::pty)?
+            } else {
+                tokio::select! {
+                    readable = file.readable() => readable.map_err(MyTermError::pty)?,
+                    writable = file.writable() => {
+                        let mut ready = writable.map_err(MyTermError::pty)?;
+                        let chunk = self.queued_input.as_slices().0;
+                        if let Ok(result) = ready.try_io(|file| file.get_ref().write(chunk)) {
+                            let n = result.map_err(MyTermError::pty)?;
+                            let written: Vec<u8> = self.queued_input.drain(..n).collect();
+                            self.record(b'>', &written);
+                        }
+                        continue;
+                    }
+                }
+            };
+            if let Ok(result) = ready.try_io(|file| file.get_ref().read(buf)) {
+                let n = result.map_err(MyTermError::pty)?;
+                self.record(b'<', &buf[..n]);
+                return Ok(n);
+            }
+        }
+    }
+    
+    /// Queues `data` to be written while `read` waits for output, as the program takes it,
+    /// rather than waiting for it all to go in.
+    pub fn queue_write(&mut self, data: &[u8]) {
+        self.queued_input.extend(data);
+    }
+    
+    /// Reads what is available without waiting, returning `None` if nothing is.
+    pub fn try_read(&mut self, buf: &mut [u8]) -> Result<Option<usize>> {
+        let Some(ref file) = self.master_file else {
+            return Err(MyTermError::Pty(anyhow::anyhow!("PTY not initialized")));
+        };
+        
+        // The fd is non-blocking; leaving its readiness set only costs `read` a wasted wakeup
+        match file.get_ref().read(buf) {
+            Ok(n) => {
+                self.record(b'<', &buf[..n]);
+                Ok(Some(n))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(MyTermError::pty(e)),
         }
     }
     
     pub async fn write(&mut self, data: &[u8]) -> Result<()> {
-        if let Some(ref mut file) = self.master_file {
-            file.write_all(data).await?;
-            file.flush().await?;
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("PTY not initialized"))
+        let Some(ref file) = self.master_file else {
+            return Err(MyTermError::Pty(anyhow::anyhow!("PTY not initialized")));
+        };
+        
+        // Typing during a paste goes in after it
+        if !self.queued_input.is_empty() {
+            self.queue_write(data);
+            return Ok(());
+        }
+        
+        let mut written = 0;
+        while written < data.len() {
+            let mut ready = file.writable().await.map_err(MyTermError::pty)?;
+            if let Ok(result) = ready.try_io(|file| file.get_ref().write(&data[written..])) {
+                written += result.map_err(MyTermError::pty)?;
+            }
+        }
+        self.record(b'>', data);
+        Ok(())
+    }
+    
+    /// Adds `bytes` going the way `direction` says to the typescript, if there is one. A
+    /// typescript that fails to write is dropped rather than failing the terminal.
+    fn record(&mut self, direction: u8, bytes: &[u8]) {
+        let Some(typescript) = &mut self.typescript else {
+            return;
+        };
+        if bytes.is_empty() {
+            return;
+        }
+        if let Err(e) = typescript.record(direction, bytes) {
+            log::warn!("Stopped writing the typescript: {}", e);
+            self.typescript = None;
         }
     }
     
@@ -119,6 +257,33 @@ impl Pty {
         Ok(())
     }
     
+    /// The terminal's foreground process group, the shell's own while it waits at its prompt.
+    pub fn foreground_process_group(&self) -> Option<Pid> {
+        // SAFETY: the master stays open for as long as self, which it is only borrowed for
+        let fd = unsafe { BorrowedFd::borrow_raw(self.master_fd) };
+        tcgetpgrp(fd).ok().filter(|group| group.as_raw() > 0)
+    }
+    
+    /// Whether a program other than the shell runs in the foreground, like a build started
+    /// from it.
+    pub fn has_foreground_job(&self) -> bool {
+        match (self.child_pid, self.foreground_process_group()) {
+            (Some(shell), Some(group)) => group != shell,
+            _ => false,
+        }
+    }
+    
+    /// Command name of the foreground process group's leader, the shell's own name while it
+    /// waits at its prompt. Only Linux has the `/proc` to look it up in.
+    pub fn foreground_process_name(&self) -> Option<String> {
+        let group = self.foreground_process_group()?;
+        if !cfg!(target_os = "linux") {
+            return None;
+        }
+        let comm = std::fs::read_to_string(format!("/proc/{}/comm", group)).ok()?;
+        Some(comm.trim_end_matches('\n').to_string()).filter(|name| !name.is_empty())
+    }
+    
     #[allow(dead_code)]
     pub fn child_pid(&self) -> Option<Pid> {
         self.child_pid
@@ -133,6 +298,87 @@ impl Pty {
     }
 }
 
+impl Typescript {
+    /// Creates or truncates `path`, to record output, and with `input` input as well.
+    pub fn create(path: &Path, input: bool) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create typescript {}", path.display()))
+            .map_err(MyTermError::Pty)?;
+        Ok(Self { file, input })
+    }
+    
+    fn record(&mut self, direction: u8, bytes: &[u8]) -> std::io::Result<()> {
+        if !self.input {
+            return if direction == b'<' { self.file.write_all(bytes) } else { Ok(()) };
+        }
+        let mut chunk = format!("{} {}\n", direction as char, bytes.len()).into_bytes();
+        chunk.extend_from_slice(bytes);
+        chunk.push(b'\n');
+        self.file.write_all(&chunk)
+    }
+}
+
+/// Builds the child environment from the parent's, advertising `term` as the terminal type
+/// and direct color support through COLORTERM.
+pub fn child_environment<I>(vars: I, term: &str) -> Vec<(String, String)>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut env: Vec<(String, String)> = vars
+        .into_iter()
+        .filter(|(key, _)| !INHERITED_TERMINAL_VARS.contains(&key.as_str()))
+        .collect();
+    
+    env.push(("TERM".to_string(), term.to_string()));
+    env.push(("COLORTERM".to_string(), "truecolor".to_string()));
+    env
+}
+
+/// Directories searched for compiled terminfo entries, in ncurses order.
+pub fn terminfo_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    
+    if let Ok(dir) = std::env::var("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(home) = dirs::home_dir() {
+        dirs.push(home.join(".terminfo"));
+    }
+    if let Ok(list) = std::env::var("TERMINFO_DIRS") {
+        dirs.extend(list.split(':').filter(|d| !d.is_empty()).map(PathBuf::from));
+    }
+    for dir in ["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo", "/usr/lib/terminfo"] {
+        dirs.push(PathBuf::from(dir));
+    }
+    
+    dirs
+}
+
+/// Checks whether a terminfo entry for `term` exists in any of `dirs`.
+pub fn terminfo_exists(term: &str, dirs: &[PathBuf]) -> bool {
+    let Some(first) = term.chars().next() else {
+        return false;
+    };
+    
+    // Entries live under their first letter, or its hex code on some systems
+    let subdirs = [first.to_string(), format!("{:x}", first as u32)];
+    dirs.iter().any(|dir| {
+        subdirs
+            .iter()
+            .any(|sub| Path::new(dir).join(sub).join(term).is_file())
+    })
+}
+
+/// Returns `term` if the system knows about it, otherwise the fallback terminal type.
+pub fn resolve_term(term: &str, dirs: &[PathBuf]) -> String {
+    if terminfo_exists(term, dirs) {
+        term.to_string()
+    } else {
+        log::warn!("No terminfo entry for '{}', falling back to {}", term, FALLBACK_TERM);
+        FALLBACK_TERM.to_string()
+    }
+}
+
 impl Drop for Pty {
     fn drop(&mut self) {
         if let Some(pid) = self.child_pid {
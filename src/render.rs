@@ -0,0 +1,1036 @@
+use rgb::RGB8;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::config::CursorShape;
+use crate::error::Result;
+use crate::font::{FontSet, FontStyle, Glyph, Metrics};
+use crate::palette::Palette;
+use crate::selection::Point;
+use crate::sixel::SixelImage;
+use crate::terminal::{Cell, CellFlags, UnderlineStyle};
+
+/// Bytes per pixel of the ARGB8888 buffers we draw into.
+pub const BYTES_PER_PIXEL: usize = 4;
+
+/// Width in buffer pixels of the beam and underline cursors and of a hollow block's outline.
+pub const CURSOR_THICKNESS: usize = 2;
+
+/// Memory the glyph cache may use before evicting, enough for thousands of glyphs at large sizes.
+pub const GLYPH_CACHE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Effective background alpha from the palette's `#rrggbbaa` alpha and `display.opacity`.
+pub fn background_alpha(palette_alpha: u8, opacity: f32) -> f32 {
+    (palette_alpha as f32 / 255.0) * opacity.clamp(0.0, 1.0)
+}
+
+/// A premultiplied ARGB8888 pixel in memory order (little endian: B, G, R, A).
+pub fn argb_pixel(color: RGB8, alpha: f32) -> [u8; 4] {
+    let alpha = alpha.clamp(0.0, 1.0);
+    let premultiply = |c: u8| (c as f32 * alpha).round() as u8;
+    [
+        premultiply(color.b),
+        premultiply(color.g),
+        premultiply(color.r),
+        (alpha * 255.0).round() as u8,
+    ]
+}
+
+/// Fills the whole canvas with one pixel value, padding included.
+pub fn fill(canvas: &mut [u8], pixel: [u8; 4]) {
+    for chunk in canvas.chunks_exact_mut(BYTES_PER_PIXEL) {
+        chunk.copy_from_slice(&pixel);
+    }
+}
+
+/// Fills whole pixel rows `rows` of a canvas with the given stride.
+pub fn fill_rows(canvas: &mut [u8], stride: usize, rows: Range<usize>, pixel: [u8; 4]) {
+    let start = (rows.start * stride).min(canvas.len());
+    let end = (rows.end * stride).min(canvas.len());
+    fill(&mut canvas[start..end], pixel);
+}
+
+/// Copies the pixels in `rects` from `src` to `dst`, canvases of the same stride.
+pub fn copy_rects(dst: &mut [u8], src: &[u8], stride: usize, rects: &[Rect]) {
+    for rect in rects {
+        let left = rect.x * BYTES_PER_PIXEL;
+        let right = left + rect.width * BYTES_PER_PIXEL;
+        for y in rect.y..rect.y + rect.height {
+            let row = y * stride;
+            dst[row + left..row + right].copy_from_slice(&src[row + left..row + right]);
+        }
+    }
+}
+
+/// Where the grid is drawn, in buffer pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridLayout {
+    /// Top-left corner of the first cell.
+    pub origin: (usize, usize),
+    pub cell_width: usize,
+    pub cell_height: usize,
+}
+
+/// The cursor as drawn: its cell in the viewport and its shape.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorSprite {
+    pub point: Point,
+    pub shape: CursorShape,
+    /// Drawn as an outlined block whatever the shape, as while the window is unfocused.
+    pub hollow: bool,
+}
+
+/// The colors a cell is drawn in, applying its attributes, the selection and a block cursor.
+///
+/// REVERSE swaps the cell's colors, the selection then recolors them as configured, and a block
+/// cursor shows the text in `colors.cursor_text`, or else the resolved background, on the cursor
+/// color. HIDDEN text takes the background color. DIM text set with SGR to one of the normal
+/// colors takes its configured dim color, and any other DIM text, that in a default color
+/// included, is blended halfway toward the background.
+/// Last, both colors fade toward the palette's background by its `fade`, the selection and
+/// cursor colors included.
+pub fn resolve_cell_colors(cell: &Cell, palette: &Palette, selected: bool, cursor_here: bool) -> (RGB8, RGB8) {
+    let (mut fg, mut bg) = (cell.fg, cell.bg);
+    // Whether each is a color the cell was given with SGR rather than a default
+    let (mut fg_set, mut bg_set) = (cell.flags.contains(CellFlags::EXPLICIT_FG), cell.flags.contains(CellFlags::EXPLICIT_BG));
+    if cell.flags.contains(CellFlags::REVERSE) {
+        (fg, bg) = (bg, fg);
+        (fg_set, bg_set) = (bg_set, fg_set);
+    }
+    if selected {
+        (fg, bg) = palette.selection_colors(fg, bg);
+        (fg_set, bg_set) = match (palette.selection_foreground, palette.selection_background) {
+            (None, None) => (bg_set, fg_set),
+            (sel_fg, sel_bg) => (fg_set && sel_fg.is_none(), bg_set && sel_bg.is_none()),
+        };
+    }
+    if cursor_here {
+        (fg, bg) = (palette.cursor_text.unwrap_or(bg), palette.cursor);
+        fg_set = palette.cursor_text.is_none() && bg_set;
+    }
+    if cell.flags.contains(CellFlags::HIDDEN) {
+        fg = bg;
+    } else if cell.flags.contains(CellFlags::DIM) {
+        fg = palette.dim_color(fg).filter(|_| fg_set).unwrap_or_else(|| dim(fg, bg));
+    }
+    (palette.faded(fg), palette.faded(bg))
+}
+
+/// Draws cell backgrounds, glyphs and the cursor onto a canvas already filled with the default
+/// background.
+///
+/// The rows come from `TerminalPerformer::styled_snapshot`, with their colors resolved apart
+/// from the cursor. Cells in the default background color are left as they are, so they keep
+/// the window's translucency. The glyphs must already be sized for the layout's cells.
+#[allow(dead_code)]
+pub fn draw_grid(canvas: &mut [u8], stride: usize, layout: &GridLayout, rows: &[Vec<Cell>], palette: &Palette, cursor: Option<&CursorSprite>, glyphs: &mut GlyphCache) {
+    draw_rows(canvas, stride, layout, rows, 0..rows.len(), palette, cursor, &[], glyphs);
+}
+
+/// Draws the grid rows `band` as `draw_grid` does, with the parts of `images` over them,
+/// touching no pixels outside of them.
+///
+/// The band must already be filled with the default background. Glyphs of the rows around it
+/// that reach into the band are drawn too, so the result matches drawing the whole grid.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_rows(canvas: &mut [u8], stride: usize, layout: &GridLayout, rows: &[Vec<Cell>], band: Range<usize>, palette: &Palette, cursor: Option<&CursorSprite>, images: &[FrameImage], glyphs: &mut GlyphCache) {
+    paint_rows(&mut CanvasPainter { canvas, stride }, layout, rows, band, palette, cursor, images, glyphs);
+}
+
+/// What a frame is drawn with, so that every renderer puts the same shapes in the same places.
+///
+/// Shapes are painted in order, each over the ones before it.
+pub trait Painter {
+    /// Fills `rect` with an opaque pixel.
+    fn fill(&mut self, rect: &Rect, pixel: [u8; 4]);
+    /// Composites a premultiplied pixel over `rect`.
+    fn blend(&mut self, rect: &Rect, pixel: [u8; 4]);
+    /// Composites `glyph`, the one cached for `key`, in `color`, with the top left corner of
+    /// its bitmap at `origin` and only inside `clip`.
+    fn glyph(&mut self, clip: &Rect, origin: (i32, i32), key: (char, FontStyle), glyph: &Glyph, color: RGB8);
+    /// Composites `bitmap` with its top left corner at `origin`, only inside `clip`.
+    fn image(&mut self, clip: &Rect, origin: (i32, i32), bitmap: &Arc<Bitmap>);
+}
+
+/// Paints into a canvas laid out like a shared memory buffer.
+pub struct CanvasPainter<'a> {
+    pub canvas: &'a mut [u8],
+    pub stride: usize,
+}
+
+impl Painter for CanvasPainter<'_> {
+    fn fill(&mut self, rect: &Rect, pixel: [u8; 4]) {
+        fill_rect(self.canvas, self.stride, rect, pixel);
+    }
+    
+    fn blend(&mut self, rect: &Rect, pixel: [u8; 4]) {
+        blend_rect(self.canvas, self.stride, rect, pixel);
+    }
+    
+    fn glyph(&mut self, clip: &Rect, origin: (i32, i32), _key: (char, FontStyle), glyph: &Glyph, color: RGB8) {
+        blend_coverage(self.canvas, self.stride, clip, origin, glyph.width, &glyph.coverage, color);
+    }
+    
+    fn image(&mut self, clip: &Rect, (x, y): (i32, i32), bitmap: &Arc<Bitmap>) {
+        let Some(rect) = Rect::clipped(clip, (x, y), (bitmap.width, bitmap.height)) else {
+            return;
+        };
+        let bitmap_stride = bitmap.width * BYTES_PER_PIXEL;
+        for py in rect.y..rect.y + rect.height {
+            let src_start = (py as i32 - y) as usize * bitmap_stride + (rect.x as i32 - x) as usize * BYTES_PER_PIXEL;
+            let src = &bitmap.pixels[src_start..src_start + rect.width * BYTES_PER_PIXEL];
+            let start = py * self.stride + rect.x * BYTES_PER_PIXEL;
+            let Some(dst) = self.canvas.get_mut(start..start + rect.width * BYTES_PER_PIXEL) else {
+                break;
+            };
+            for (dst, src) in dst.chunks_exact_mut(BYTES_PER_PIXEL).zip(src.chunks_exact(BYTES_PER_PIXEL)) {
+                let inverse = 255 - src[3] as u32;
+                for (d, &s) in dst.iter_mut().zip(src) {
+                    *d = (s as u32 + (*d as u32 * inverse + 127) / 255).min(255) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Premultiplied pixels laid out like a canvas, `width` pixels to a row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+/// `image` scaled to `width`x`height` pixels, taking the nearest pixel so its edges stay sharp.
+pub fn scale_image(image: &SixelImage, width: usize, height: usize) -> Bitmap {
+    let mut pixels = Vec::with_capacity(width * height * BYTES_PER_PIXEL);
+    for y in 0..height {
+        let row = y * image.height / height.max(1);
+        for x in 0..width {
+            let pixel = image.pixels[row * image.width + x * image.width / width.max(1)];
+            let alpha = pixel.a as f32 / 255.0;
+            let [b, g, r, _] = argb_pixel(pixel.rgb(), alpha);
+            pixels.extend_from_slice(&[b, g, r, pixel.a]);
+        }
+    }
+    Bitmap { width, height, pixels }
+}
+
+/// A sixel image at its place in a frame, scaled to buffer pixels.
+#[derive(Debug, Clone)]
+pub struct FrameImage {
+    /// Top left corner, above the grid for an image partly scrolled out of view.
+    pub origin: (i32, i32),
+    pub bitmap: Arc<Bitmap>,
+}
+
+/// The same image at the same place; images aren't compared pixel by pixel.
+impl PartialEq for FrameImage {
+    fn eq(&self, other: &Self) -> bool {
+        self.origin == other.origin && Arc::ptr_eq(&self.bitmap, &other.bitmap)
+    }
+}
+
+/// Paints all of `frame` over its background, which the painter's target is already filled
+/// with.
+pub fn paint_frame(painter: &mut impl Painter, frame: &Frame, glyphs: &mut GlyphCache) {
+    let window = Rect { x: 0, y: 0, width: frame.size.0, height: frame.size.1 };
+    if let Some(titlebar) = &frame.titlebar {
+        if let Some(bar) = window.intersect(&Rect { height: titlebar.height, ..window }) {
+            painter.fill(&bar, titlebar.pixel);
+        }
+        for (layout, cells) in &titlebar.text {
+            paint_rows(painter, layout, std::slice::from_ref(cells), 0..1, &frame.palette, None, &[], glyphs);
+        }
+    }
+    paint_rows(painter, &frame.layout, &frame.rows, 0..frame.rows.len(), &frame.palette, frame.cursor.as_ref(), &frame.images, glyphs);
+    if let Some(scrollbar) = frame.scrollbar {
+        painter.blend(&scrollbar.thumb, scrollbar.pixel);
+    }
+    if let Some(pixel) = frame.flash {
+        painter.blend(&window, pixel);
+    }
+}
+
+/// Draws all of `frame` into `canvas`, any memory laid out like a buffer of the frame's size,
+/// background first.
+pub fn raster_frame(canvas: &mut [u8], frame: &Frame, glyphs: &mut GlyphCache) {
+    fill(canvas, frame.background);
+    paint_frame(&mut CanvasPainter { canvas, stride: frame.size.0 * BYTES_PER_PIXEL }, frame, glyphs);
+}
+
+/// Paints the grid rows `band` as `draw_rows` draws them.
+#[allow(clippy::too_many_arguments)]
+pub fn paint_rows(painter: &mut impl Painter, layout: &GridLayout, rows: &[Vec<Cell>], band: Range<usize>, palette: &Palette, cursor: Option<&CursorSprite>, images: &[FrameImage], glyphs: &mut GlyphCache) {
+    let (cell_width, cell_height) = (layout.cell_width, layout.cell_height);
+    let cols = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let band = band.start.min(rows.len())..band.end.min(rows.len());
+    let clip = Rect {
+        x: layout.origin.0,
+        y: layout.origin.1 + band.start * cell_height,
+        width: cols * cell_width,
+        height: band.len() * cell_height,
+    };
+    
+    // A cursor on a double-width character covers both of its cells
+    let cursor_cells = cursor.map(|cursor| {
+        let Point { row, col } = cursor.point;
+        let wide = rows.get(row).and_then(|cells| cells.get(col)).is_some_and(|cell| cell.flags.contains(CellFlags::WIDE));
+        (cursor, if wide && col + 1 < cols { 2 } else { 1 })
+    });
+    let block = cursor_cells.filter(|(cursor, _)| cursor.shape == CursorShape::Block && !cursor.hollow);
+    // The rows are faded already, which only the cursor drawn over them still needs
+    let resolved = Palette { fade: 0.0, ..palette.clone() };
+    let colors = |row: usize, col: usize, cell: &Cell| {
+        let cursor_here = block.is_some_and(|(cursor, span)| {
+            row == cursor.point.row && (cursor.point.col..cursor.point.col + span).contains(&col)
+        });
+        resolve_cell_colors(cell, if cursor_here { palette } else { &resolved }, false, cursor_here)
+    };
+    
+    // Backgrounds first, so glyphs reaching into a neighboring cell aren't painted over. The
+    // halves of a double-width character each have their own
+    for (row, cells) in rows.iter().enumerate().take(band.end).skip(band.start) {
+        for (col, cell) in cells.iter().enumerate() {
+            let (_, bg) = colors(row, col, cell);
+            if bg != palette.background {
+                painter.fill(&cell_rect(layout, row, col), argb_pixel(bg, 1.0));
+            }
+        }
+    }
+    
+    // Images go over the cell backgrounds and under any text written over them since
+    for image in images {
+        painter.image(&clip, image.origin, &image.bitmap);
+    }
+    
+    // Center the font's line height in the cell
+    let metrics = glyphs.metrics();
+    let baseline = (cell_height as i32 - metrics.cell_height as i32) / 2 + metrics.ascent;
+    let reaching = band.start.saturating_sub(1)..(band.end + 1).min(rows.len());
+    for (row, cells) in rows.iter().enumerate().take(reaching.end).skip(reaching.start) {
+        for (col, cell) in cells.iter().enumerate() {
+            if cell.c == ' ' || cell.flags.intersects(CellFlags::HIDDEN | CellFlags::WIDE_SPACER) {
+                continue;
+            }
+            let style = FontStyle::from_flags(cell.flags.contains(CellFlags::BOLD), cell.flags.contains(CellFlags::ITALIC));
+            let key = (cell.c, style);
+            let Some(glyph) = glyphs.get(cell.c, style) else {
+                continue;
+            };
+            let (fg, _) = colors(row, col, cell);
+            let cell = cell_rect(layout, row, col);
+            let x = cell.x as i32 + glyph.left;
+            let y = cell.y as i32 + baseline - glyph.top;
+            // Glyphs wider than two cells, as from proportional fallback fonts, are cut off
+            // rather than drawn over the characters after them
+            let span = Rect { width: 2 * cell_width, y: clip.y, height: clip.height, ..cell };
+            let Some(clip) = clip.intersect(&span) else {
+                continue;
+            };
+            painter.glyph(&clip, (x, y), key, glyph, fg);
+        }
+    }
+    
+    // Decorations span whole cells so neighbors join up, but never leave their cell
+    for (row, cells) in rows.iter().enumerate().take(band.end).skip(band.start) {
+        for (col, cell) in cells.iter().enumerate() {
+            if !cell.flags.intersects(CellFlags::UNDERLINE | CellFlags::STRIKETHROUGH) || cell.flags.contains(CellFlags::HIDDEN) {
+                continue;
+            }
+            let (fg, _) = colors(row, col, cell);
+            let rect = cell_rect(layout, row, col);
+            let cell_baseline = rect.y as i32 + baseline;
+            if cell.flags.contains(CellFlags::UNDERLINE) {
+                let pixel = argb_pixel(cell.underline_color.unwrap_or(fg), 1.0);
+                draw_underline(painter, layout, &rect, cell_baseline, &metrics, cell.underline, pixel);
+            }
+            if cell.flags.contains(CellFlags::STRIKETHROUGH) {
+                let thickness = metrics.underline_thickness;
+                let y = cell_baseline - metrics.strikeout_position - thickness as i32 / 2;
+                fill_clipped(painter, &rect, (rect.x as i32, y), (rect.width, thickness as usize), argb_pixel(fg, 1.0));
+            }
+        }
+    }
+    
+    if let Some((cursor, span)) = cursor_cells.filter(|(cursor, _)| band.contains(&cursor.point.row)) {
+        let cell = cell_rect(layout, cursor.point.row, cursor.point.col);
+        let block = Rect { width: span * cell_width, ..cell };
+        let pixel = argb_pixel(palette.faded(palette.cursor), 1.0);
+        let thickness = CURSOR_THICKNESS;
+        let x = cell.x as i32;
+        if cursor.hollow {
+            let bottom = (block.y + block.height) as i32 - thickness as i32;
+            let right = (block.x + block.width) as i32 - thickness as i32;
+            fill_clipped(painter, &block, (x, block.y as i32), (block.width, thickness), pixel);
+            fill_clipped(painter, &block, (x, bottom), (block.width, thickness), pixel);
+            fill_clipped(painter, &block, (x, block.y as i32), (thickness, block.height), pixel);
+            fill_clipped(painter, &block, (right, block.y as i32), (thickness, block.height), pixel);
+        } else {
+            match cursor.shape {
+                CursorShape::Block => {}
+                CursorShape::Underline => {
+                    // Where an underline would be, raised to fit in short cells
+                    let bottom = (cell.y + cell.height) as i32 - thickness as i32;
+                    let top = (cell.y as i32 + baseline + metrics.underline_position).min(bottom);
+                    fill_clipped(painter, &cell, (x, top), (cell.width, thickness), pixel);
+                }
+                CursorShape::Beam => fill_clipped(painter, &cell, (x, cell.y as i32), (thickness, cell.height), pixel),
+            }
+        }
+    }
+}
+
+/// A rectangle of buffer pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// The part of this rectangle inside `other`, if any.
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        (x < right && y < bottom).then(|| Rect { x, y, width: right - x, height: bottom - y })
+    }
+    
+    /// The part inside `clip` of a rectangle of `size` at a possibly negative position.
+    pub fn clipped(clip: &Rect, (x, y): (i32, i32), (width, height): (usize, usize)) -> Option<Rect> {
+        let left = x.max(clip.x as i32);
+        let top = y.max(clip.y as i32);
+        let right = (x + width as i32).min((clip.x + clip.width) as i32);
+        let bottom = (y + height as i32).min((clip.y + clip.height) as i32);
+        (left < right && top < bottom)
+            .then(|| Rect { x: left as usize, y: top as usize, width: (right - left) as usize, height: (bottom - top) as usize })
+    }
+}
+
+fn cell_rect(layout: &GridLayout, row: usize, col: usize) -> Rect {
+    Rect {
+        x: layout.origin.0 + col * layout.cell_width,
+        y: layout.origin.1 + row * layout.cell_height,
+        width: layout.cell_width,
+        height: layout.cell_height,
+    }
+}
+
+/// Draws an underline of `style` across `cell`, whose baseline is at `baseline`.
+#[allow(clippy::too_many_arguments)]
+fn draw_underline(painter: &mut impl Painter, layout: &GridLayout, cell: &Rect, baseline: i32, metrics: &Metrics, style: UnderlineStyle, pixel: [u8; 4]) {
+    let thickness = metrics.underline_thickness as usize;
+    let t = thickness as i32;
+    let bottom = (cell.y + cell.height) as i32;
+    // Raised to fit in cells shorter than the font
+    let top = (baseline + metrics.underline_position).min(bottom - t);
+    let x = cell.x as i32;
+    // Patterns run from the grid's left edge, so they continue from one cell into the next
+    let grid_x = |dx: usize| cell.x - layout.origin.0 + dx;
+    
+    match style {
+        UnderlineStyle::Single => fill_clipped(painter, cell, (x, top), (cell.width, thickness), pixel),
+        UnderlineStyle::Double => {
+            let top = top.min(bottom - 3 * t);
+            fill_clipped(painter, cell, (x, top), (cell.width, thickness), pixel);
+            fill_clipped(painter, cell, (x, top + 2 * t), (cell.width, thickness), pixel);
+        }
+        UnderlineStyle::Curly => {
+            // One period of a wave per cell, starting and ending at its crest
+            let depth = (2 * t).max(2);
+            let top = top.min(bottom - depth - t);
+            let offset = |dx: usize| {
+                let phase = dx as f64 / cell.width as f64 * std::f64::consts::TAU;
+                ((1.0 - phase.cos()) / 2.0 * depth as f64).round() as i32
+            };
+            for dx in 0..cell.width {
+                // Reach the next column's height so steep slopes have no gaps
+                let (from, to) = (offset(dx), offset(dx + 1));
+                let y = top + from.min(to);
+                let height = (from - to).unsigned_abs() as usize + thickness;
+                fill_clipped(painter, cell, (x + dx as i32, y), (1, height), pixel);
+            }
+        }
+        UnderlineStyle::Dotted | UnderlineStyle::Dashed => {
+            let (period, on) = if style == UnderlineStyle::Dotted {
+                (2 * thickness, thickness)
+            } else {
+                let dash = (layout.cell_width / 2).max(2);
+                (dash, (dash * 2).div_ceil(3))
+            };
+            for dx in (0..cell.width).filter(|&dx| grid_x(dx) % period < on) {
+                fill_clipped(painter, cell, (x + dx as i32, top), (1, thickness), pixel);
+            }
+        }
+    }
+}
+
+/// Fills a rectangle at a possibly negative position, clipped to `clip`.
+fn fill_clipped(painter: &mut impl Painter, clip: &Rect, origin: (i32, i32), size: (usize, usize), pixel: [u8; 4]) {
+    if let Some(rect) = Rect::clipped(clip, origin, size) {
+        painter.fill(&rect, pixel);
+    }
+}
+
+/// Fills a rectangle, clipped to the canvas.
+fn fill_rect(canvas: &mut [u8], stride: usize, rect: &Rect, pixel: [u8; 4]) {
+    let canvas_width = stride / BYTES_PER_PIXEL;
+    let x_end = (rect.x + rect.width).min(canvas_width);
+    if rect.x >= x_end {
+        return;
+    }
+    
+    for y in rect.y..rect.y + rect.height {
+        let start = y * stride + rect.x * BYTES_PER_PIXEL;
+        let end = y * stride + x_end * BYTES_PER_PIXEL;
+        if end > canvas.len() {
+            break;
+        }
+        fill(&mut canvas[start..end], pixel);
+    }
+}
+
+/// Composites a premultiplied pixel over a rectangle of the canvas.
+fn blend_rect(canvas: &mut [u8], stride: usize, rect: &Rect, pixel: [u8; 4]) {
+    let inverse = 255 - pixel[3] as u32;
+    for y in rect.y..rect.y + rect.height {
+        let start = y * stride + rect.x * BYTES_PER_PIXEL;
+        let Some(row) = canvas.get_mut(start..start + rect.width * BYTES_PER_PIXEL) else {
+            break;
+        };
+        for dst in row.chunks_exact_mut(BYTES_PER_PIXEL) {
+            for (d, &s) in dst.iter_mut().zip(&pixel) {
+                *d = (s as u32 + (*d as u32 * inverse + 127) / 255).min(255) as u8;
+            }
+        }
+    }
+}
+
+/// Composites `color` at the given per-pixel coverage over the canvas, inside `clip`.
+fn blend_coverage(canvas: &mut [u8], stride: usize, clip: &Rect, (x, y): (i32, i32), width: usize, coverage: &[u8], color: RGB8) {
+    if width == 0 {
+        return;
+    }
+    let src = argb_pixel(color, 1.0);
+    
+    for (dy, row) in coverage.chunks_exact(width).enumerate() {
+        let py = y + dy as i32;
+        if py < clip.y as i32 || py >= (clip.y + clip.height) as i32 {
+            continue;
+        }
+        for (dx, &cov) in row.iter().enumerate() {
+            let px = x + dx as i32;
+            if cov == 0 || px < clip.x as i32 || px >= (clip.x + clip.width) as i32 {
+                continue;
+            }
+            
+            let offset = py as usize * stride + px as usize * BYTES_PER_PIXEL;
+            let Some(dst) = canvas.get_mut(offset..offset + BYTES_PER_PIXEL) else {
+                continue;
+            };
+            // Premultiplied "over": src * coverage + dst * (1 - coverage)
+            for (d, &s) in dst.iter_mut().zip(&src) {
+                *d = ((s as u32 * cov as u32 + *d as u32 * (255 - cov as u32) + 127) / 255) as u8;
+            }
+        }
+    }
+}
+
+/// Everything a frame shows, compared against the last frame to find what needs drawing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// Buffer size in pixels.
+    pub size: (usize, usize),
+    pub layout: GridLayout,
+    /// The default background pixel, which also fills the padding.
+    pub background: [u8; 4],
+    /// A titlebar we draw ourselves, when the compositor doesn't decorate the window.
+    pub titlebar: Option<Titlebar>,
+    pub palette: Palette,
+    /// Rows as in `draw_grid`.
+    pub rows: Vec<Vec<Cell>>,
+    pub cursor: Option<CursorSprite>,
+    /// Sixel images over the grid, in the order they were placed.
+    pub images: Vec<FrameImage>,
+    pub scrollbar: Option<Scrollbar>,
+    /// Premultiplied tint over the whole window while the visual bell flashes.
+    pub flash: Option<[u8; 4]>,
+}
+
+/// A client-side titlebar across the top of a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Titlebar {
+    /// Height in pixels.
+    pub height: usize,
+    pub pixel: [u8; 4],
+    /// The title and the close button, each drawn as a row of cells in its own layout. Their
+    /// background is the palette's, leaving the bar's pixel showing.
+    pub text: Vec<(GridLayout, Vec<Cell>)>,
+}
+
+/// Character drawn as the titlebar's close button.
+pub const CLOSE_BUTTON: char = '×';
+
+/// A titlebar `height` pixels high across a frame `width` pixels wide, in `pixel`: `title`
+/// from the left and a close button in the square at the right end, in `fg` on `bg`, with
+/// characters `cell_width` pixels wide. A title too long for the space is cut short with an
+/// ellipsis.
+pub fn titlebar(width: usize, height: usize, cell_width: usize, title: &str, pixel: [u8; 4], fg: RGB8, bg: RGB8) -> Titlebar {
+    let cell = |c: char| Cell { c, fg, bg, ..Cell::default() };
+    let cell_width = cell_width.max(1);
+    let margin = cell_width;
+    let cols = width.saturating_sub(height + 2 * margin) / cell_width;
+    let mut cells: Vec<Cell> = title.chars().map(cell).collect();
+    if cells.len() > cols {
+        cells.truncate(cols.saturating_sub(1));
+        cells.push(cell('…'));
+        cells.truncate(cols);
+    }
+    
+    let button = width.saturating_sub(height) + height.saturating_sub(cell_width) / 2;
+    Titlebar {
+        height,
+        pixel,
+        text: vec![
+            (GridLayout { origin: (margin, 0), cell_width, cell_height: height }, cells),
+            (GridLayout { origin: (button, 0), cell_width, cell_height: height }, vec![cell(CLOSE_BUTTON)]),
+        ],
+    }
+}
+
+/// Opacity of the visual bell's tint.
+pub const FLASH_ALPHA: f32 = 0.3;
+
+/// Width in buffer pixels of the scrollbar at scale 1.
+pub const SCROLLBAR_WIDTH: usize = 4;
+
+/// Height in buffer pixels below which a scrollbar thumb doesn't shrink, so that it stays
+/// visible over a long history.
+pub const MIN_THUMB_HEIGHT: usize = 8;
+
+/// A scrollbar thumb blended over the right edge of the window, within the grid's rows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scrollbar {
+    pub thumb: Rect,
+    /// Premultiplied, so translucent while the thumb fades out.
+    pub pixel: [u8; 4],
+}
+
+/// The pixel rows of a scrollbar thumb on a track `track` pixels high, for a view of `rows`
+/// lines scrolled `display_offset` lines up into `history` lines of scrollback.
+///
+/// The thumb is as much of the track as the view is of all lines, and is at the bottom on the
+/// live screen and at the top at the oldest line. There is no thumb without history.
+pub fn scrollbar_thumb(track: usize, rows: usize, history: usize, display_offset: usize) -> Option<Range<usize>> {
+    if history == 0 || track == 0 {
+        return None;
+    }
+    let height = (track * rows / (rows + history)).max(MIN_THUMB_HEIGHT).min(track);
+    let travel = track - height;
+    let top = travel * (history - display_offset.min(history)) / history;
+    Some(top..top + height)
+}
+
+/// A canvas kept from one frame to the next, so that a frame only draws the rows that changed.
+///
+/// Rows that scrolled are moved rather than drawn again. Changed rows are drawn together with
+/// the rows next to them, which glyphs may reach into.
+#[derive(Default)]
+pub struct RetainedCanvas {
+    pixels: Vec<u8>,
+    /// The last frame and the font metrics it was drawn with.
+    last: Option<(Frame, Metrics)>,
+}
+
+impl RetainedCanvas {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// The last frame drawn, in the layout of a buffer of its size.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+    
+    /// Makes the next frame draw in full, e.g. after changing the font.
+    pub fn invalidate(&mut self) {
+        self.last = None;
+    }
+    
+    /// Draws `frame` over the last one, returning the rectangles whose pixels changed.
+    pub fn draw(&mut self, frame: Frame, glyphs: &mut GlyphCache) -> Vec<Rect> {
+        let metrics = glyphs.metrics();
+        let stride = frame.size.0 * BYTES_PER_PIXEL;
+        // The visual bell tints everything, so frames with it are drawn whole
+        let last = self.last.take().filter(|(last, last_metrics)| {
+            (last.size, last.layout, last.background, &last.titlebar, &last.palette, last.rows.len(), *last_metrics, last.flash)
+                == (frame.size, frame.layout, frame.background, &frame.titlebar, &frame.palette, frame.rows.len(), metrics, None)
+                && frame.flash.is_none()
+        });
+        
+        let damage = match last {
+            Some((last, _)) => self.draw_changes(&last, &frame, stride, glyphs),
+            None => {
+                self.pixels.clear();
+                self.pixels.resize(stride * frame.size.1, 0);
+                raster_frame(&mut self.pixels, &frame, glyphs);
+                vec![Rect { x: 0, y: 0, width: frame.size.0, height: frame.size.1 }]
+            }
+        };
+        self.last = Some((frame, metrics));
+        damage
+    }
+    
+    fn draw_changes(&mut self, last: &Frame, frame: &Frame, stride: usize, glyphs: &mut GlyphCache) -> Vec<Rect> {
+        let layout = &frame.layout;
+        let count = frame.rows.len();
+        let cols = frame.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let grid_top = layout.origin.1;
+        let band_rect = |rows: Range<usize>| Rect {
+            x: layout.origin.0,
+            y: grid_top + rows.start * layout.cell_height,
+            width: cols * layout.cell_width,
+            height: rows.len() * layout.cell_height,
+        };
+        
+        // What the canvas shows on each row, after moving scrolled rows into place
+        let shift = scroll_shift(&last.rows, &frame.rows);
+        let source = |row: usize| row.checked_add_signed(shift).filter(|&row| row < count);
+        let mut damage = Vec::new();
+        if shift != 0 {
+            let moved = count - shift.unsigned_abs();
+            let (from, to) = if shift > 0 { (shift.unsigned_abs(), 0) } else { (0, shift.unsigned_abs()) };
+            let row_bytes = layout.cell_height * stride;
+            let start = grid_top * stride;
+            self.pixels.copy_within(start + from * row_bytes..start + (from + moved) * row_bytes, start + to * row_bytes);
+            damage.push(band_rect(0..count));
+        }
+        let shown_cursor = last.cursor.as_ref().and_then(|cursor| {
+            let row = cursor.point.row.checked_add_signed(-shift).filter(|&row| row < count)?;
+            Some(CursorSprite { point: Point::new(row, cursor.point.col), ..cursor.clone() })
+        });
+        
+        let mut changed: Vec<bool> = (0..count)
+            .map(|row| source(row).is_none_or(|source| last.rows[source] != frame.rows[row]))
+            .collect();
+        if shown_cursor != frame.cursor {
+            for cursor in [&shown_cursor, &frame.cursor].into_iter().flatten() {
+                changed[cursor.point.row] = true;
+            }
+        }
+        // Images are drawn again where they appeared or went, other than by moving with the rows
+        let shown_images: Vec<FrameImage> = last.images.iter()
+            .map(|image| FrameImage { origin: (image.origin.0, image.origin.1 - (shift * layout.cell_height as isize) as i32), ..image.clone() })
+            .collect();
+        let gone = shown_images.iter().filter(|image| !frame.images.contains(image));
+        let appeared = frame.images.iter().filter(|image| !shown_images.contains(image));
+        for image in gone.chain(appeared) {
+            let top = image.origin.1 - grid_top as i32;
+            let start = top.max(0) as usize / layout.cell_height;
+            let end = (top + image.bitmap.height as i32).max(0) as usize;
+            changed[start.min(count)..end.div_ceil(layout.cell_height).min(count)].fill(true);
+        }
+        // Rows moved to the edge still show what reached into them from rows now gone
+        if shift > 0 {
+            changed[0] = true;
+        } else if shift < 0 {
+            changed[count - 1] = true;
+        }
+        // The thumb is drawn again over the rows below it, and the ones it left are cleared
+        let thumb_rows = |thumb: &Rect| {
+            let start = thumb.y.saturating_sub(grid_top) / layout.cell_height;
+            let end = (thumb.y + thumb.height).saturating_sub(grid_top).div_ceil(layout.cell_height);
+            start.min(count)..end.min(count)
+        };
+        let shown_thumb = last.scrollbar.and_then(|scrollbar| {
+            let thumb = scrollbar.thumb;
+            let top = thumb.y as isize - shift * layout.cell_height as isize;
+            let bottom = top + thumb.height as isize;
+            let (top, bottom) = (top.max(grid_top as isize), bottom.min((grid_top + count * layout.cell_height) as isize));
+            (top < bottom).then(|| Rect { y: top as usize, height: (bottom - top) as usize, ..thumb })
+        });
+        if shift != 0 || last.scrollbar != frame.scrollbar {
+            if let Some(thumb) = shown_thumb {
+                changed[thumb_rows(&thumb)].fill(true);
+                // Where it stays, the new thumb is damaged with the rows below it
+                if frame.scrollbar.is_none_or(|scrollbar| scrollbar.thumb != thumb) {
+                    damage.push(thumb);
+                }
+            }
+            if let Some(scrollbar) = frame.scrollbar {
+                changed[thumb_rows(&scrollbar.thumb)].fill(true);
+            }
+        }
+        
+        let dirty: Vec<bool> = (0..count)
+            .map(|row| changed[row.saturating_sub(1)..(row + 2).min(count)].contains(&true))
+            .collect();
+        let mut row = 0;
+        while row < count {
+            if !dirty[row] {
+                row += 1;
+                continue;
+            }
+            let start = row;
+            while row < count && dirty[row] {
+                row += 1;
+            }
+            let rect = band_rect(start..row);
+            fill_rows(&mut self.pixels, stride, rect.y..rect.y + rect.height, frame.background);
+            draw_rows(&mut self.pixels, stride, layout, &frame.rows, start..row, &frame.palette, frame.cursor.as_ref(), &frame.images, glyphs);
+            if shift == 0 {
+                damage.push(rect);
+            }
+            let band = Rect { x: 0, width: frame.size.0, ..rect };
+            let thumb = frame.scrollbar.and_then(|scrollbar| Some((scrollbar.thumb.intersect(&band)?, scrollbar.pixel)));
+            if let Some((thumb, pixel)) = thumb {
+                blend_rect(&mut self.pixels, stride, &thumb, pixel);
+                damage.push(thumb);
+            }
+        }
+        damage
+    }
+}
+
+/// How many rows the grid scrolled between `last` and `rows`: positive when the content moved
+/// up, negative when it moved down, or 0 if it didn't scroll.
+///
+/// A scroll is only recognized when the rows still on screen are all unchanged.
+fn scroll_shift(last: &[Vec<Cell>], rows: &[Vec<Cell>]) -> isize {
+    let count = rows.len();
+    // Leave a row or two of edits to plain redrawing
+    if (0..count).filter(|&row| last[row] != rows[row]).count() <= 2 {
+        return 0;
+    }
+    for shift in 1..count {
+        if rows[0] == last[shift] && rows[..count - shift] == last[shift..] {
+            return shift as isize;
+        }
+        if rows[shift] == last[0] && rows[shift..] == last[..count - shift] {
+            return -(shift as isize);
+        }
+    }
+    0
+}
+
+/// Shows the text of blinking cells in `rows`, already resolved by `styled_snapshot`, or hides it
+/// for the off phase of the blink. Returns whether any cell blinks.
+pub fn blink_text(rows: &mut [Vec<Cell>], visible: bool) -> bool {
+    let mut blinking = false;
+    for cell in rows.iter_mut().flatten().filter(|cell| cell.flags.contains(CellFlags::BLINK)) {
+        blinking = true;
+        if !visible {
+            cell.fg = cell.bg;
+            cell.underline_color = None;
+        }
+    }
+    blinking
+}
+
+/// Writes `text` in `fg` on `bg` over the right end of the top row, as a badge laid over the
+/// grid. Nothing is written if the row is too narrow for it.
+pub fn overlay_badge(rows: &mut [Vec<Cell>], text: &str, fg: RGB8, bg: RGB8) {
+    let Some(row) = rows.first_mut() else {
+        return;
+    };
+    let len = text.chars().count();
+    if len > row.len() {
+        return;
+    }
+    let start = row.len() - len;
+    for (cell, c) in row[start..].iter_mut().zip(text.chars()) {
+        *cell = Cell { c, fg, bg, ..Cell::default() };
+    }
+}
+
+/// Writes `text` in `fg` on `bg` over the right end of the bottom row, as the debug HUD, cutting
+/// it short if the row is too narrow.
+pub fn overlay_hud(rows: &mut [Vec<Cell>], text: &str, fg: RGB8, bg: RGB8) {
+    let Some(row) = rows.last_mut() else {
+        return;
+    };
+    let len = text.chars().count().min(row.len());
+    let start = row.len() - len;
+    for (cell, c) in row[start..].iter_mut().zip(text.chars()) {
+        *cell = Cell { c, fg, bg, ..Cell::default() };
+    }
+}
+
+/// Writes `text` in `fg` on `bg` across the bottom row, as a prompt laid over the grid, cutting
+/// it short if the row is too narrow.
+pub fn overlay_prompt(rows: &mut [Vec<Cell>], text: &str, fg: RGB8, bg: RGB8) {
+    let Some(row) = rows.last_mut() else {
+        return;
+    };
+    let mut text = text.chars();
+    for cell in row.iter_mut() {
+        *cell = Cell { c: text.next().unwrap_or(' '), fg, bg, ..Cell::default() };
+    }
+}
+
+/// Recolors cells in `fg` on `bg`, as search matches are shown over the grid. Each span is a
+/// row and its columns; whatever lies beyond the rows is left out.
+pub fn highlight_spans(rows: &mut [Vec<Cell>], spans: &[(usize, Range<usize>)], fg: RGB8, bg: RGB8) {
+    for (row, cols) in spans {
+        let Some(row) = rows.get_mut(*row) else {
+            continue;
+        };
+        let end = cols.end.min(row.len());
+        for cell in row.get_mut(cols.start.min(end)..end).into_iter().flatten() {
+            cell.fg = fg;
+            cell.bg = bg;
+        }
+    }
+}
+
+/// Moves a faint foreground color halfway toward the background it is drawn on.
+pub fn dim(fg: RGB8, bg: RGB8) -> RGB8 {
+    let mix = |fg: u8, bg: u8| ((fg as u16 + bg as u16).div_ceil(2)) as u8;
+    RGB8::new(mix(fg.r, bg.r), mix(fg.g, bg.g), mix(fg.b, bg.b))
+}
+
+/// Rasterized glyphs of a font at its current size, evicting the least recently used ones past a
+/// memory cap.
+///
+/// Cells start on whole pixels, so a character in a style always rasterizes the same way and
+/// the pair serves as the key. Changing the font or its size empties the cache.
+pub struct GlyphCache {
+    fonts: FontSet,
+    /// Glyphs by character and style, `None` for characters the font can't render.
+    glyphs: HashMap<(char, FontStyle), CachedGlyph>,
+    metrics: Metrics,
+    memory: usize,
+    memory_cap: usize,
+    /// Incremented on every lookup, to order entries by last use.
+    clock: u64,
+    stats: GlyphCacheStats,
+    /// Incremented whenever the cache empties, as glyphs may then rasterize differently.
+    generation: u64,
+}
+
+struct CachedGlyph {
+    glyph: Option<Glyph>,
+    last_used: u64,
+}
+
+/// Counters for judging how well the cache works.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GlyphCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Glyphs currently held.
+    pub entries: usize,
+    /// Approximate bytes held by those glyphs.
+    pub memory: usize,
+}
+
+impl CachedGlyph {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<((char, FontStyle), CachedGlyph)>() + self.glyph.as_ref().map_or(0, |glyph| glyph.coverage.len())
+    }
+}
+
+impl GlyphCache {
+    pub fn new(fonts: FontSet, memory_cap: usize) -> Self {
+        let metrics = fonts.metrics();
+        Self {
+            fonts,
+            glyphs: HashMap::new(),
+            metrics,
+            memory: 0,
+            memory_cap,
+            clock: 0,
+            stats: GlyphCacheStats::default(),
+            generation: 0,
+        }
+    }
+    
+    /// Switches to another font, dropping every cached glyph.
+    pub fn set_fonts(&mut self, mut fonts: FontSet) -> Result<()> {
+        fonts.set_pixel_size(self.fonts.pixel_size())?;
+        self.fonts = fonts;
+        self.clear();
+        Ok(())
+    }
+    
+    /// Sets the font's size in pixels, dropping the cached glyphs if it changed.
+    pub fn set_pixel_size(&mut self, size: u32) -> Result<()> {
+        if size.max(1) != self.fonts.pixel_size() {
+            self.fonts.set_pixel_size(size)?;
+            self.clear();
+        }
+        Ok(())
+    }
+    
+    /// Metrics of the regular face at its current size.
+    pub fn metrics(&self) -> Metrics {
+        self.metrics
+    }
+    
+    /// The glyph for `c` in `style`, rasterizing it on first use.
+    pub fn get(&mut self, c: char, style: FontStyle) -> Option<&Glyph> {
+        let key = (c, style);
+        self.clock += 1;
+        if self.glyphs.contains_key(&key) {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            let entry = CachedGlyph { glyph: self.fonts.rasterize(c, style), last_used: 0 };
+            let size = entry.memory_usage();
+            if self.memory + size > self.memory_cap {
+                self.evict(size);
+            }
+            self.memory += size;
+            self.glyphs.insert(key, entry);
+        }
+        
+        let entry = self.glyphs.get_mut(&key)?;
+        entry.last_used = self.clock;
+        entry.glyph.as_ref()
+    }
+    
+    pub fn stats(&self) -> GlyphCacheStats {
+        GlyphCacheStats {
+            entries: self.glyphs.len(),
+            memory: self.memory,
+            ..self.stats
+        }
+    }
+    
+    /// Changes whenever the font or its size does, so copies of glyphs kept elsewhere, such as
+    /// on the GPU, know to drop theirs.
+    #[allow(dead_code)]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    
+    /// Drops every cached glyph, keeping the counters.
+    pub fn clear(&mut self) {
+        self.generation += 1;
+        self.glyphs.clear();
+        self.memory = 0;
+        self.metrics = self.fonts.metrics();
+    }
+    
+    /// Drops the least recently used glyphs until `incoming` more bytes fit with a quarter of
+    /// the cap to spare, so eviction doesn't run on every miss.
+    fn evict(&mut self, incoming: usize) {
+        let target = (self.memory_cap / 4 * 3).saturating_sub(incoming);
+        let mut by_age: Vec<_> = self.glyphs.iter().map(|(&key, entry)| (entry.last_used, key)).collect();
+        by_age.sort_unstable_by_key(|&(last_used, _)| last_used);
+        
+        for (_, key) in by_age {
+            if self.memory <= target {
+                break;
+            }
+            if let Some(entry) = self.glyphs.remove(&key) {
+                self.memory -= entry.memory_usage();
+            }
+        }
+    }
+}
\ No newline at end of file
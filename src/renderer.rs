@@ -0,0 +1,134 @@
+use anyhow::Context;
+use smithay_client_toolkit::shm::Shm;
+use std::time::{Duration, Instant};
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::{Connection, Proxy, QueueHandle};
+
+use crate::config::RendererKind;
+use crate::error::{MyTermError, Result};
+use crate::render::{self, Frame, GlyphCache, Rect, RetainedCanvas, BYTES_PER_PIXEL};
+use crate::shm::{BufferPool, DamageHistory};
+use crate::wayland::WaylandState;
+
+/// The window surface a frame is presented on, with what presenting may need.
+pub struct Target<'a> {
+    pub surface: &'a WlSurface,
+    pub shm: &'a Shm,
+    /// For the frame callback requested with each commit.
+    pub qh: &'a QueueHandle<WaylandState>,
+}
+
+/// What presenting a frame changed and where the time went.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Presented {
+    /// The rectangles whose pixels changed, in buffer pixels.
+    pub damage: Vec<Rect>,
+    /// Drawing the frame, rasterizing glyphs not cached yet.
+    pub raster: Duration,
+    /// Handing the drawn pixels over for the compositor.
+    pub blit: Duration,
+}
+
+/// Draws frames and commits them to the window's surface.
+///
+/// Every renderer paints frames through `render::Painter`, so they all show the same pixels.
+pub trait Renderer {
+    /// Name for logs.
+    fn name(&self) -> &'static str;
+    
+    /// Draws `frame` and commits it with a frame callback requested, or returns `None` when
+    /// there's nothing free to draw into yet, leaving the frame for later.
+    fn present(&mut self, frame: Frame, glyphs: &mut GlyphCache, target: &Target) -> Result<Option<Presented>>;
+    
+    /// Makes the next frame draw in full, e.g. after changing the font.
+    fn invalidate(&mut self);
+}
+
+/// Draws on the CPU into shared memory buffers, redrawing only what changed.
+pub struct SoftwareRenderer {
+    /// Frame buffers, reused once the compositor releases them.
+    buffers: BufferPool,
+    /// The last frame, which the next one only redraws where it changed.
+    canvas: RetainedCanvas,
+    /// What each buffer missed since it was last drawn, the only pixels copied into it.
+    history: DamageHistory,
+    /// The size of the buffers `history` knows about.
+    size: (usize, usize),
+}
+
+impl SoftwareRenderer {
+    pub fn new() -> Self {
+        Self {
+            buffers: BufferPool::new(),
+            canvas: RetainedCanvas::new(),
+            history: DamageHistory::new(),
+            size: (0, 0),
+        }
+    }
+}
+
+impl Renderer for SoftwareRenderer {
+    fn name(&self) -> &'static str {
+        "software"
+    }
+    
+    fn present(&mut self, frame: Frame, glyphs: &mut GlyphCache, target: &Target) -> Result<Option<Presented>> {
+        let (width, height) = frame.size;
+        let Some((buffer, canvas)) = self.buffers.acquire(target.shm, width as u32, height as u32)? else {
+            log::debug!("No free frame buffer: {:?}", self.buffers.stats());
+            return Ok(None);
+        };
+        // Buffers of another size were dropped
+        if self.size != (width, height) {
+            self.size = (width, height);
+            self.history.clear();
+        }
+        let started = Instant::now();
+        let damage = self.canvas.draw(frame, glyphs);
+        let drawn = Instant::now();
+        match self.history.record(buffer.wl_buffer().id(), &damage) {
+            Some(stale) => render::copy_rects(canvas, self.canvas.pixels(), width * BYTES_PER_PIXEL, &stale),
+            None => canvas.copy_from_slice(self.canvas.pixels()),
+        }
+        let copied = Instant::now();
+        
+        buffer.attach_to(target.surface)
+            .context("Failed to attach buffer")
+            .map_err(MyTermError::wayland)?;
+        for rect in &damage {
+            target.surface.damage_buffer(rect.x as i32, rect.y as i32, rect.width as i32, rect.height as i32);
+        }
+        target.surface.frame(target.qh, target.surface.clone());
+        target.surface.commit();
+        Ok(Some(Presented { damage, raster: drawn - started, blit: copied - drawn }))
+    }
+    
+    fn invalidate(&mut self) {
+        self.canvas.invalidate();
+    }
+}
+
+impl Default for SoftwareRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The renderer `kind` names for drawing to `surface`, or the software one when the GPU can't
+/// be used, logging why.
+pub async fn create(kind: RendererKind, connection: &Connection, surface: &WlSurface) -> Box<dyn Renderer> {
+    match kind {
+        RendererKind::Software => {}
+        #[cfg(feature = "gpu")]
+        RendererKind::Gpu => match crate::gpu::GpuRenderer::new(connection, surface).await {
+            Ok(renderer) => return Box::new(renderer),
+            Err(e) => log::warn!("GPU rendering unavailable, using software rendering: {:#}", e),
+        },
+        #[cfg(not(feature = "gpu"))]
+        RendererKind::Gpu => {
+            let _ = (connection, surface);
+            log::warn!("Built without the gpu feature, using software rendering");
+        }
+    }
+    Box::new(SoftwareRenderer::new())
+}
\ No newline at end of file
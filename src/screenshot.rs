@@ -0,0 +1,103 @@
+use anyhow::Context;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{MyTermError, Result};
+use crate::render::{self, CursorSprite, Frame, FrameImage, GlyphCache, GridLayout, BYTES_PER_PIXEL};
+use crate::selection::Point;
+use crate::terminal::Terminal;
+
+/// The frame a screenshot shows: the grid in view and the padding around it, at one pixel per
+/// surface-local pixel, without a titlebar, scrollbar or anything else over the text.
+pub fn frame(terminal: &Terminal, opacity: f32) -> Frame {
+    let grid = terminal.grid();
+    let palette = terminal.screen_palette();
+    let (cell_width, cell_height) = terminal.cell_size();
+    let padding = terminal.grid_padding();
+    let layout = GridLayout {
+        origin: (padding.left as usize, padding.top as usize),
+        cell_width: cell_width as usize,
+        cell_height: cell_height as usize,
+    };
+    
+    let cursor = terminal.cursor();
+    let row = cursor.row + grid.display_offset;
+    let col = cursor.col.checked_sub(grid.col_offset);
+    let cursor = (terminal.modes().cursor_visible && row < grid.rows).then_some(col).flatten().map(|col| CursorSprite {
+        point: Point::new(row, col),
+        shape: cursor.shape.clone(),
+        hollow: false,
+    });
+    let images = terminal.images().iter()
+        .map(|placed| FrameImage {
+            origin: (
+                layout.origin.0 as i32 + (placed.col as i32 - grid.col_offset as i32) * layout.cell_width as i32,
+                layout.origin.1 as i32 + (placed.row + grid.display_offset as isize) as i32 * layout.cell_height as i32,
+            ),
+            bitmap: Arc::new(render::scale_image(&placed.image, placed.image.width, placed.image.height)),
+        })
+        .collect();
+    
+    Frame {
+        size: (
+            layout.origin.0 + grid.cols * layout.cell_width + padding.right as usize,
+            layout.origin.1 + grid.rows * layout.cell_height + padding.bottom as usize,
+        ),
+        layout,
+        background: render::argb_pixel(palette.background, render::background_alpha(palette.background_alpha, opacity)),
+        titlebar: None,
+        palette: palette.clone(),
+        rows: terminal.styled_snapshot(),
+        cursor,
+        images,
+        scrollbar: None,
+        flash: None,
+    }
+}
+
+/// Straight-alpha RGBA, as PNG stores it, from premultiplied pixels in memory order.
+pub fn to_rgba(canvas: &[u8]) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(canvas.len());
+    for pixel in canvas.chunks_exact(BYTES_PER_PIXEL) {
+        let [b, g, r, a] = [pixel[0], pixel[1], pixel[2], pixel[3]];
+        let straight = |c: u8| if a == 0 { 0 } else { ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8 };
+        rgba.extend_from_slice(&[straight(r), straight(g), straight(b), a]);
+    }
+    rgba
+}
+
+/// Draws `terminal` with the software rasterizer and writes it to `path` as a PNG.
+///
+/// The glyphs must already be sized for the terminal's cells.
+pub fn save(path: &Path, terminal: &Terminal, opacity: f32, glyphs: &mut GlyphCache) -> Result<()> {
+    let frame = frame(terminal, opacity);
+    let (width, height) = frame.size;
+    let mut canvas = vec![0; width * height * BYTES_PER_PIXEL];
+    render::raster_frame(&mut canvas, &frame, glyphs);
+    
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create {}", path.display()))
+        .map_err(MyTermError::screenshot)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(&to_rgba(&canvas)))
+        .with_context(|| format!("Failed to write {}", path.display()))
+        .map_err(MyTermError::screenshot)?;
+    log::info!("Saved a {}x{} screenshot to {}", width, height, path.display());
+    Ok(())
+}
+
+/// Where the screenshot action saves, named after the time: the pictures directory, or the
+/// home directory without one.
+pub fn default_path() -> PathBuf {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    dirs::picture_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_default()
+        .join(format!("myterm-{}.png", seconds))
+}
\ No newline at end of file
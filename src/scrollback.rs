@@ -0,0 +1,236 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use crate::config::ScrollbackStorage;
+use crate::terminal::{Cell, CellFlags, UnderlineStyle};
+
+/// Storage for lines that scrolled off the top of the screen, oldest first.
+pub trait Scrollback: Send {
+    fn len(&self) -> usize;
+    
+    #[allow(dead_code)]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    fn push_back(&mut self, line: Vec<Cell>);
+    
+    /// Drops the oldest line.
+    fn pop_front(&mut self);
+    
+    /// Removes the newest line, e.g. to scroll it back onto the screen.
+    fn pop_back(&mut self) -> Option<Vec<Cell>>;
+    
+    /// Drops every line.
+    fn clear(&mut self);
+    
+    /// Line `index`, counting from the oldest.
+    fn line(&self, index: usize) -> Cow<'_, [Cell]>;
+    
+    /// Approximate heap bytes held, for comparing backends.
+    #[allow(dead_code)]
+    fn memory_usage(&self) -> usize;
+    
+    /// Bytes held by the lines, counted as they come and go so checking it against the
+    /// scrollback's byte budget is cheap.
+    fn bytes(&self) -> usize;
+}
+
+/// Creates the backend selected by `terminal.scrollback_storage`.
+pub fn new_scrollback(storage: ScrollbackStorage) -> Box<dyn Scrollback> {
+    match storage {
+        ScrollbackStorage::Full => Box::<FullScrollback>::default(),
+        ScrollbackStorage::Compact => Box::<CompactScrollback>::default(),
+    }
+}
+
+/// Keeps every cell as it was on screen.
+#[derive(Default)]
+pub struct FullScrollback {
+    lines: VecDeque<Vec<Cell>>,
+    bytes: usize,
+}
+
+fn line_bytes(line: &Vec<Cell>) -> usize {
+    std::mem::size_of::<Vec<Cell>>() + line.capacity() * std::mem::size_of::<Cell>()
+}
+
+impl Scrollback for FullScrollback {
+    fn len(&self) -> usize {
+        self.lines.len()
+    }
+    
+    fn push_back(&mut self, line: Vec<Cell>) {
+        self.bytes += line_bytes(&line);
+        self.lines.push_back(line);
+    }
+    
+    fn pop_front(&mut self) {
+        if let Some(line) = self.lines.pop_front() {
+            self.bytes -= line_bytes(&line);
+        }
+    }
+    
+    fn pop_back(&mut self) -> Option<Vec<Cell>> {
+        let line = self.lines.pop_back()?;
+        self.bytes -= line_bytes(&line);
+        Some(line)
+    }
+    
+    fn clear(&mut self) {
+        self.lines.clear();
+        self.bytes = 0;
+    }
+    
+    fn line(&self, index: usize) -> Cow<'_, [Cell]> {
+        Cow::Borrowed(&self.lines[index])
+    }
+    
+    fn memory_usage(&self) -> usize {
+        self.lines.capacity() * std::mem::size_of::<Vec<Cell>>()
+            + self.lines.iter().map(|line| line.capacity() * std::mem::size_of::<Cell>()).sum::<usize>()
+    }
+    
+    fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+/// Stores lines as text plus runs of identical attributes, dropping trailing blank cells.
+///
+/// Lines come back at their original width, with the trimmed cells restored as blanks.
+#[derive(Default)]
+pub struct CompactScrollback {
+    lines: VecDeque<CompactLine>,
+    bytes: usize,
+}
+
+struct CompactLine {
+    text: Box<str>,
+    runs: Box<[Run]>,
+    width: u16,
+}
+
+/// Attributes shared by `len` consecutive characters.
+#[derive(Clone, Copy, PartialEq)]
+struct Run {
+    len: u16,
+    fg: rgb::RGB8,
+    bg: rgb::RGB8,
+    flags: CellFlags,
+    underline: UnderlineStyle,
+    underline_color: Option<rgb::RGB8>,
+}
+
+impl CompactLine {
+    fn encode(line: &[Cell]) -> Self {
+        let blank = Cell::default();
+        let used = line.iter().rposition(|cell| *cell != blank).map_or(0, |i| i + 1);
+        
+        let mut text = String::with_capacity(used);
+        let mut runs: Vec<Run> = Vec::new();
+        for cell in &line[..used] {
+            text.push(cell.c);
+            match runs.last_mut() {
+                Some(run) if run.len < u16::MAX && run.matches(cell) => {
+                    run.len += 1;
+                }
+                _ => runs.push(Run {
+                    len: 1,
+                    fg: cell.fg,
+                    bg: cell.bg,
+                    flags: cell.flags,
+                    underline: cell.underline,
+                    underline_color: cell.underline_color,
+                }),
+            }
+        }
+        
+        Self {
+            text: text.into_boxed_str(),
+            runs: runs.into_boxed_slice(),
+            width: line.len().min(u16::MAX as usize) as u16,
+        }
+    }
+    
+    fn decode(&self) -> Vec<Cell> {
+        let mut line = Vec::with_capacity(self.width as usize);
+        let mut chars = self.text.chars();
+        for run in self.runs.iter() {
+            for c in chars.by_ref().take(run.len as usize) {
+                line.push(Cell {
+                    c,
+                    fg: run.fg,
+                    bg: run.bg,
+                    flags: run.flags,
+                    underline: run.underline,
+                    underline_color: run.underline_color,
+                });
+            }
+        }
+        line.resize(self.width as usize, Cell::default());
+        line
+    }
+    
+    fn memory_usage(&self) -> usize {
+        self.text.len() + self.runs.len() * std::mem::size_of::<Run>()
+    }
+    
+    /// Bytes held, counting the line's own size as kept in the scrollback.
+    fn bytes(&self) -> usize {
+        std::mem::size_of::<CompactLine>() + self.memory_usage()
+    }
+}
+
+impl Run {
+    /// Whether `cell` has this run's attributes.
+    fn matches(&self, cell: &Cell) -> bool {
+        self.fg == cell.fg
+            && self.bg == cell.bg
+            && self.flags == cell.flags
+            && self.underline == cell.underline
+            && self.underline_color == cell.underline_color
+    }
+}
+
+impl Scrollback for CompactScrollback {
+    fn len(&self) -> usize {
+        self.lines.len()
+    }
+    
+    fn push_back(&mut self, line: Vec<Cell>) {
+        let line = CompactLine::encode(&line);
+        self.bytes += line.bytes();
+        self.lines.push_back(line);
+    }
+    
+    fn pop_front(&mut self) {
+        if let Some(line) = self.lines.pop_front() {
+            self.bytes -= line.bytes();
+        }
+    }
+    
+    fn pop_back(&mut self) -> Option<Vec<Cell>> {
+        let line = self.lines.pop_back()?;
+        self.bytes -= line.bytes();
+        Some(line.decode())
+    }
+    
+    fn clear(&mut self) {
+        self.lines.clear();
+        self.bytes = 0;
+    }
+    
+    fn line(&self, index: usize) -> Cow<'_, [Cell]> {
+        Cow::Owned(self.lines[index].decode())
+    }
+    
+    fn memory_usage(&self) -> usize {
+        self.lines.capacity() * std::mem::size_of::<CompactLine>()
+            + self.lines.iter().map(CompactLine::memory_usage).sum::<usize>()
+    }
+    
+    fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
\ No newline at end of file
@@ -0,0 +1,119 @@
+use crate::terminal::{CellFlags, Grid};
+
+/// Where the query was found: columns `start..end` of an absolute line, as numbered by
+/// `Grid::absolute_line`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Search mode: the query typed so far, what it matches and which match is shown.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Search {
+    query: String,
+    /// Oldest first.
+    matches: Vec<SearchMatch>,
+    /// Index in `matches` of the match shown.
+    focused: Option<usize>,
+}
+
+/// Every place `query` occurs in the lines `grid` still keeps, oldest first and without
+/// overlapping. Case is ignored unless the query has capitals, and a match never continues
+/// onto the next line.
+pub fn find_matches(grid: &Grid, query: &str) -> Vec<SearchMatch> {
+    let ignore_case = !query.chars().any(char::is_uppercase);
+    let fold = |c: char| if ignore_case { c.to_lowercase().next().unwrap_or(c) } else { c };
+    let needle: Vec<char> = query.chars().map(fold).collect();
+    let mut matches = Vec::new();
+    if needle.is_empty() {
+        return matches;
+    }
+    
+    let first = grid.first_line();
+    for line in first..first + grid.scrollback.len() + grid.rows {
+        let Some(cells) = grid.line(line) else {
+            continue;
+        };
+        // Each character with the column it starts at, leaving out the second half of wide ones
+        let chars: Vec<(usize, char)> = cells.iter().enumerate()
+            .filter(|(_, cell)| !cell.flags.contains(CellFlags::WIDE_SPACER))
+            .map(|(col, cell)| (col, fold(cell.c)))
+            .collect();
+        let mut i = 0;
+        while i + needle.len() <= chars.len() {
+            if !chars[i..i + needle.len()].iter().map(|&(_, c)| c).eq(needle.iter().copied()) {
+                i += 1;
+                continue;
+            }
+            let last = chars[i + needle.len() - 1].0;
+            let width = if cells[last].flags.contains(CellFlags::WIDE) { 2 } else { 1 };
+            matches.push(SearchMatch { line, start: chars[i].0, end: last + width });
+            i += needle.len();
+        }
+    }
+    matches
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    #[allow(dead_code)]
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+    
+    pub fn matches(&self) -> &[SearchMatch] {
+        &self.matches
+    }
+    
+    /// The match shown, which the view follows.
+    pub fn focused(&self) -> Option<&SearchMatch> {
+        self.matches.get(self.focused?)
+    }
+    
+    /// Adds `c` to the query and searches `grid` again.
+    pub fn push(&mut self, c: char, grid: &Grid) {
+        self.query.push(c);
+        self.update(grid);
+    }
+    
+    /// Takes the last character off the query and searches `grid` again.
+    pub fn pop(&mut self, grid: &Grid) {
+        self.query.pop();
+        self.update(grid);
+    }
+    
+    /// Searches `grid` again, e.g. after the query or the output changed. The focus stays on
+    /// the last match starting no later than the one shown, or goes to the newest match if
+    /// none was shown.
+    pub fn update(&mut self, grid: &Grid) {
+        let shown = self.focused().map(|shown| (shown.line, shown.start));
+        self.matches = find_matches(grid, &self.query);
+        self.focused = match shown {
+            _ if self.matches.is_empty() => None,
+            Some(shown) => Some(self.matches.iter().rposition(|m| (m.line, m.start) <= shown).unwrap_or(0)),
+            None => Some(self.matches.len() - 1),
+        };
+    }
+    
+    /// Focuses the match before the one shown, going round to the newest after the oldest.
+    pub fn focus_older(&mut self) {
+        let count = self.matches.len();
+        self.focused = self.focused.map(|focused| (focused + count - 1) % count);
+    }
+    
+    /// Focuses the match after the one shown, going round to the oldest after the newest.
+    pub fn focus_newer(&mut self) {
+        let count = self.matches.len();
+        self.focused = self.focused.map(|focused| (focused + 1) % count);
+    }
+    
+    /// The status line shown while searching, e.g. `search: foo [2/7]`.
+    pub fn status(&self) -> String {
+        format!("search: {} [{}/{}]", self.query, self.focused.map_or(0, |focused| focused + 1), self.matches.len())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,110 @@
+/// A cell position in the viewport, or in a `Selection` on an absolute line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Point {
+    pub row: usize,
+    pub col: usize,
+}
+
+/// Text selected between two points, whose rows are absolute lines so the selection stays
+/// with its text as it scrolls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    /// Where the selection started.
+    pub anchor: Point,
+    /// Where the selection currently ends; may be before the anchor.
+    pub head: Point,
+    pub mode: SelectionMode,
+}
+
+/// How the cells between the endpoints are selected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionMode {
+    /// Everything from one endpoint to the other in reading order, following line wrapping like
+    /// a text editor.
+    #[default]
+    Normal,
+    /// The block of cells with the endpoints at opposite corners, selected by dragging with Alt.
+    Rectangular,
+}
+
+impl Point {
+    pub fn new(row: usize, col: usize) -> Self {
+        Self { row, col }
+    }
+}
+
+impl Selection {
+    #[allow(dead_code)]
+    pub fn new(anchor: Point, head: Point) -> Self {
+        Self { anchor, head, mode: SelectionMode::Normal }
+    }
+    
+    #[allow(dead_code)]
+    pub fn rectangular(anchor: Point, head: Point) -> Self {
+        Self { anchor, head, mode: SelectionMode::Rectangular }
+    }
+    
+    /// The selection's endpoints in reading order.
+    pub fn ordered(&self) -> (Point, Point) {
+        if self.anchor <= self.head {
+            (self.anchor, self.head)
+        } else {
+            (self.head, self.anchor)
+        }
+    }
+    
+    pub fn contains(&self, point: Point) -> bool {
+        let (start, end) = self.ordered();
+        match self.mode {
+            SelectionMode::Normal => start <= point && point <= end,
+            SelectionMode::Rectangular => {
+                let (left, right) = self.column_span();
+                (start.row..=end.row).contains(&point.row) && (left..=right).contains(&point.col)
+            }
+        }
+    }
+    
+    /// The first and last selected column on `row` of a grid `cols` wide, or `None` if the row
+    /// isn't selected.
+    pub fn columns(&self, row: usize, cols: usize) -> Option<(usize, usize)> {
+        let (start, end) = self.ordered();
+        if row < start.row || row > end.row || cols == 0 {
+            return None;
+        }
+        let (first, last) = match self.mode {
+            SelectionMode::Normal => (
+                if row == start.row { start.col } else { 0 },
+                if row == end.row { end.col } else { cols - 1 },
+            ),
+            SelectionMode::Rectangular => self.column_span(),
+        };
+        Some((first.min(cols - 1), last.min(cols - 1)))
+    }
+    
+    /// The selection without its lines before `first_line`, or `None` if nothing is left. A
+    /// normal selection then starts at the beginning of `first_line`.
+    pub fn clamp_to(self, first_line: usize) -> Option<Self> {
+        let (start, end) = self.ordered();
+        if end.row < first_line {
+            return None;
+        }
+        if start.row >= first_line {
+            return Some(self);
+        }
+        let col = match self.mode {
+            SelectionMode::Normal => 0,
+            SelectionMode::Rectangular => start.col,
+        };
+        let clamped = Point::new(first_line, col);
+        Some(if self.anchor <= self.head {
+            Self { anchor: clamped, ..self }
+        } else {
+            Self { head: clamped, ..self }
+        })
+    }
+    
+    /// Leftmost and rightmost column of a rectangular selection.
+    fn column_span(&self) -> (usize, usize) {
+        (self.anchor.col.min(self.head.col), self.anchor.col.max(self.head.col))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,165 @@
+use anyhow::Context;
+use smithay_client_toolkit::shm::slot::{Buffer, SlotPool};
+use smithay_client_toolkit::shm::Shm;
+use std::collections::{HashMap, VecDeque};
+use wayland_client::backend::ObjectId;
+use wayland_client::protocol::wl_shm;
+
+use crate::error::{MyTermError, Result};
+use crate::render::{self, Rect};
+
+/// Buffers kept around so one can be drawn while the compositor reads the other.
+pub const MIN_BUFFERS: usize = 2;
+
+/// Buffers allowed at once, the third only while the compositor holds on to both others.
+pub const MAX_BUFFERS: usize = 3;
+
+/// Capacity for `buffers` buffers of `buffer_len` bytes, with a quarter extra so that growing
+/// the window a few pixels at a time doesn't recreate the pool on every frame.
+pub fn pool_capacity(buffer_len: usize, buffers: usize) -> usize {
+    let needed = buffer_len * buffers;
+    needed + needed / 4
+}
+
+/// Whether a pool of `capacity` bytes has to be recreated to hold `buffers` buffers of
+/// `buffer_len` bytes. Pools are never shrunk.
+pub fn needs_new_pool(capacity: usize, buffer_len: usize, buffers: usize) -> bool {
+    buffer_len * buffers > capacity
+}
+
+/// Pool counters, for debug logs.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PoolStats {
+    /// Bytes of shared memory in the pool.
+    pub capacity: usize,
+    pub buffers: usize,
+    /// Buffers the compositor hasn't released yet.
+    pub busy: usize,
+    /// Times the pool was created, counting the first.
+    pub pools_created: u64,
+}
+
+/// Frame buffers for one surface, reused once the compositor releases them.
+pub struct BufferPool {
+    pool: Option<SlotPool>,
+    /// All of the same size; buffers of an old size are dropped, and destroyed by sctk once
+    /// the compositor releases them.
+    buffers: Vec<Buffer>,
+    pools_created: u64,
+}
+
+impl BufferPool {
+    pub fn new() -> Self {
+        Self {
+            pool: None,
+            buffers: Vec::new(),
+            pools_created: 0,
+        }
+    }
+    
+    /// A buffer of `width`x`height` pixels that is free to draw into, with its pixels, or
+    /// `None` while the compositor holds every buffer allowed.
+    ///
+    /// The pixels are whatever that buffer last showed.
+    pub fn acquire(&mut self, shm: &Shm, width: u32, height: u32) -> Result<Option<(&Buffer, &mut [u8])>> {
+        let stride = width as usize * render::BYTES_PER_PIXEL;
+        let buffer_len = stride * height as usize;
+        self.buffers.retain(|buffer| buffer.height() == height as i32 && buffer.stride() == stride as i32);
+        
+        let capacity = self.pool.as_ref().map_or(0, SlotPool::len);
+        if self.buffers.is_empty() && needs_new_pool(capacity, buffer_len, MIN_BUFFERS) {
+            // Anything still in the old pool keeps its memory mapped until released
+            self.pool = Some(
+                SlotPool::new(pool_capacity(buffer_len, MIN_BUFFERS), shm)
+                    .context("Failed to create shm pool").map_err(MyTermError::wayland)?,
+            );
+            self.pools_created += 1;
+            log::debug!("Created shm pool for {}x{} buffers: {:?}", width, height, self.stats());
+        }
+        let Some(pool) = self.pool.as_mut() else {
+            return Ok(None);
+        };
+        
+        let free = self.buffers.iter().position(|buffer| buffer.canvas(pool).is_some());
+        let index = match free {
+            Some(index) => index,
+            None if self.buffers.len() < MAX_BUFFERS => {
+                let count = self.buffers.len() + 1;
+                if needs_new_pool(pool.len(), buffer_len, count) {
+                    pool.resize(pool_capacity(buffer_len, count))
+                        .context("Failed to grow shm pool").map_err(MyTermError::wayland)?;
+                }
+                let (buffer, _) = pool
+                    .create_buffer(width as i32, height as i32, stride as i32, wl_shm::Format::Argb8888)
+                    .context("Failed to create shm buffer").map_err(MyTermError::wayland)?;
+                self.buffers.push(buffer);
+                log::debug!("Added a frame buffer: {:?}", self.stats());
+                self.buffers.len() - 1
+            }
+            None => return Ok(None),
+        };
+        
+        let pool = self.pool.as_mut().expect("pool was created above");
+        let buffer = &self.buffers[index];
+        Ok(buffer.canvas(pool).map(|canvas| (buffer, canvas)))
+    }
+    
+    pub fn stats(&mut self) -> PoolStats {
+        let Some(pool) = self.pool.as_mut() else {
+            return PoolStats::default();
+        };
+        PoolStats {
+            capacity: pool.len(),
+            buffers: self.buffers.len(),
+            busy: self.buffers.iter().filter(|buffer| buffer.canvas(pool).is_none()).count(),
+            pools_created: self.pools_created,
+        }
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The damage of the latest frames, so a buffer that last showed an older frame is brought up
+/// to date by copying only what changed since.
+#[derive(Debug, Default)]
+pub struct DamageHistory {
+    /// Damage of the last `MAX_BUFFERS` frames, the latest last.
+    frames: VecDeque<Vec<Rect>>,
+    /// Frames recorded so far.
+    count: u64,
+    /// The frame each buffer last showed, by its wl_buffer.
+    shown: HashMap<ObjectId, u64>,
+}
+
+impl DamageHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Records a frame with `damage` going to `buffer`, returning the rectangles to copy into
+    /// it: the damage of every frame since it last showed one, or `None` for the whole buffer
+    /// when it's new or too far behind.
+    pub fn record(&mut self, buffer: ObjectId, damage: &[Rect]) -> Option<Vec<Rect>> {
+        self.count += 1;
+        if self.frames.len() == MAX_BUFFERS {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(damage.to_vec());
+        let behind = self.shown.insert(buffer, self.count).map(|shown| (self.count - shown) as usize);
+        match behind {
+            Some(behind) if behind <= self.frames.len() => {
+                Some(self.frames.iter().rev().take(behind).flatten().copied().collect())
+            }
+            _ => None,
+        }
+    }
+    
+    /// Forgets every buffer, as when they were all dropped for a new size.
+    pub fn clear(&mut self) {
+        self.shown.clear();
+    }
+}
\ No newline at end of file
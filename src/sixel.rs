@@ -0,0 +1,239 @@
+use rgb::{RGB8, RGBA8};
+
+/// Largest image width or height we decode; pixels beyond it are dropped.
+pub const MAX_DIMENSION: usize = 4096;
+
+/// Number of color registers programs can define.
+const COLOR_REGISTERS: usize = 256;
+
+/// The VT340 default color registers, as RGB percentages.
+const DEFAULT_COLORS: [(u32, u32, u32); 16] = [
+    (0, 0, 0),
+    (20, 20, 80),
+    (80, 13, 13),
+    (20, 80, 20),
+    (80, 20, 80),
+    (20, 80, 80),
+    (80, 80, 20),
+    (53, 53, 53),
+    (26, 26, 26),
+    (33, 33, 60),
+    (60, 26, 26),
+    (33, 60, 33),
+    (60, 33, 60),
+    (33, 60, 60),
+    (60, 60, 33),
+    (80, 80, 80),
+];
+
+/// A decoded sixel image, row-major.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SixelImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<RGBA8>,
+}
+
+impl SixelImage {
+    /// Bytes held by the pixels.
+    pub fn memory_usage(&self) -> usize {
+        self.pixels.len() * std::mem::size_of::<RGBA8>()
+    }
+}
+
+/// The control function whose numeric parameters are being read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    None,
+    Repeat,
+    Color,
+    Raster,
+}
+
+/// Incremental decoder for the data bytes of a sixel DCS sequence.
+pub struct SixelDecoder {
+    colors: Vec<RGBA8>,
+    color: usize,
+    /// Color of pixels no sixel touched.
+    background: RGBA8,
+    command: Command,
+    params: Vec<u32>,
+    repeat: usize,
+    x: usize,
+    /// Top pixel row of the current six-pixel band.
+    y: usize,
+    rows: Vec<Vec<RGBA8>>,
+}
+
+impl SixelDecoder {
+    /// Starts decoding a sequence with the DCS parameters `params`.
+    ///
+    /// The second parameter selects whether untouched pixels are transparent (1) or `background`.
+    /// The aspect ratio parameter is ignored: pixels are square, as in other modern terminals.
+    pub fn new(params: &[u16], background: RGB8) -> Self {
+        let background = match params.get(1) {
+            Some(1) => RGBA8::new(0, 0, 0, 0),
+            _ => RGBA8::new(background.r, background.g, background.b, 255),
+        };
+        
+        let mut colors = vec![RGBA8::new(0, 0, 0, 255); COLOR_REGISTERS];
+        for (register, &(r, g, b)) in DEFAULT_COLORS.iter().enumerate() {
+            colors[register] = rgb_percent(r, g, b);
+        }
+        
+        Self {
+            colors,
+            color: 0,
+            background,
+            command: Command::None,
+            params: Vec::new(),
+            repeat: 1,
+            x: 0,
+            y: 0,
+            rows: Vec::new(),
+        }
+    }
+    
+    pub fn put(&mut self, byte: u8) {
+        if self.command != Command::None {
+            match byte {
+                b'0'..=b'9' => {
+                    if self.params.is_empty() {
+                        self.params.push(0);
+                    }
+                    let param = self.params.last_mut().unwrap();
+                    *param = param.saturating_mul(10).saturating_add((byte - b'0') as u32);
+                    return;
+                }
+                b';' => {
+                    self.params.push(0);
+                    return;
+                }
+                _ => self.finish_command(),
+            }
+        }
+        
+        match byte {
+            b'!' => self.command = Command::Repeat,
+            b'#' => self.command = Command::Color,
+            b'"' => self.command = Command::Raster,
+            b'$' => { // Graphics carriage return
+                self.x = 0;
+            }
+            b'-' => { // Graphics new line
+                self.x = 0;
+                self.y += 6;
+            }
+            b'?'..=b'~' => {
+                self.draw_sixel(byte - b'?');
+            }
+            _ => {}
+        }
+    }
+    
+    /// Ends the sequence, returning the image if anything was drawn.
+    pub fn finish(mut self) -> Option<SixelImage> {
+        self.finish_command();
+        
+        let width = self.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let height = self.rows.len();
+        if width == 0 || height == 0 {
+            return None;
+        }
+        
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let row = self.rows.get(y).map(Vec::as_slice).unwrap_or(&[]);
+            pixels.extend((0..width).map(|x| row.get(x).copied().unwrap_or(self.background)));
+        }
+        
+        Some(SixelImage { width, height, pixels })
+    }
+    
+    fn finish_command(&mut self) {
+        let params = std::mem::take(&mut self.params);
+        let param = |i: usize| params.get(i).copied().unwrap_or(0);
+        
+        match std::mem::replace(&mut self.command, Command::None) {
+            Command::None => {}
+            Command::Repeat => {
+                self.repeat = (param(0) as usize).max(1);
+            }
+            Command::Color => {
+                let register = param(0) as usize % COLOR_REGISTERS;
+                if params.len() >= 5 {
+                    self.colors[register] = match param(1) {
+                        1 => hls_percent(param(2), param(3), param(4)),
+                        2 => rgb_percent(param(2), param(3), param(4)),
+                        space => {
+                            log::debug!("Ignoring sixel color in unknown space {}", space);
+                            self.colors[register]
+                        }
+                    };
+                }
+                self.color = register;
+            }
+            Command::Raster => {
+                // Pan;Pad;Ph;Pv: pixels are square, and the image only as large as what's
+                // drawn, so a size declared and never filled costs no memory
+            }
+        }
+    }
+    
+    fn draw_sixel(&mut self, bits: u8) {
+        let repeat = std::mem::replace(&mut self.repeat, 1);
+        let start = self.x;
+        self.x = self.x.saturating_add(repeat);
+        
+        let end = self.x.min(MAX_DIMENSION);
+        if bits == 0 || start >= end {
+            return;
+        }
+        
+        let color = self.colors[self.color];
+        for bit in 0..6 {
+            let y = self.y + bit;
+            if bits & (1 << bit) == 0 || y >= MAX_DIMENSION {
+                continue;
+            }
+            if self.rows.len() <= y {
+                self.rows.resize(y + 1, Vec::new());
+            }
+            let row = &mut self.rows[y];
+            if row.len() < end {
+                row.resize(end, self.background);
+            }
+            row[start..end].fill(color);
+        }
+    }
+}
+
+fn percent(value: u32) -> u8 {
+    (value.min(100) * 255 / 100) as u8
+}
+
+fn rgb_percent(r: u32, g: u32, b: u32) -> RGBA8 {
+    RGBA8::new(percent(r), percent(g), percent(b), 255)
+}
+
+/// Converts a sixel HLS color, where hue 0 is blue rather than red, to RGB.
+fn hls_percent(hue: u32, lightness: u32, saturation: u32) -> RGBA8 {
+    let hue = ((hue + 240) % 360) as f32;
+    let lightness = lightness.min(100) as f32 / 100.0;
+    let saturation = saturation.min(100) as f32 / 100.0;
+    
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 / 60 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let channel = |c: f32| ((c + m) * 255.0).round() as u8;
+    
+    RGBA8::new(channel(r), channel(g), channel(b), 255)
+}
\ No newline at end of file
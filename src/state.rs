@@ -0,0 +1,69 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Window geometry remembered between launches when `display.remember_size` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowState {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowState {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+    
+    /// Loads the state saved by the last run, if there is a usable one.
+    pub fn load() -> Option<Self> {
+        let path = Self::state_path().ok()?;
+        if !path.exists() {
+            return None;
+        }
+        
+        Self::load_from(&path)
+            .map_err(|e| log::warn!("Ignoring saved window state: {:#}", e))
+            .ok()
+    }
+    
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read state file: {:?}", path))?;
+        let state: Self = toml::from_str(&content)
+            .with_context(|| format!("Invalid state file: {:?}", path))?;
+        
+        if state.width == 0 || state.height == 0 {
+            return Err(anyhow::anyhow!("Empty window size in {:?}", path));
+        }
+        Ok(state)
+    }
+    
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&Self::state_path()?)
+    }
+    
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create state directory: {:?}", parent))?;
+        }
+        
+        let content = toml::to_string(self)
+            .with_context(|| "Failed to serialize window state")?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write state file: {:?}", path))?;
+        
+        Ok(())
+    }
+    
+    /// `$XDG_STATE_HOME/myterm/window.toml`, which is not meant to be edited by hand.
+    pub fn state_path() -> Result<PathBuf> {
+        let mut path = dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .ok_or_else(|| anyhow::anyhow!("Could not find state directory"))?;
+        path.push("myterm");
+        path.push("window.toml");
+        Ok(path)
+    }
+}
\ No newline at end of file
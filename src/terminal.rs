@@ -1,33 +1,77 @@
-use anyhow::Result;
+use crate::error::Result;
 use crossbeam_channel::{unbounded, Receiver, Sender};
-use std::collections::VecDeque;
+use std::borrow::Cow;
+use std::sync::Arc;
+use unicode_width::UnicodeWidthChar;
 use vte::{Perform, Parser};
 
-use crate::config::{Config, CursorShape};
-use crate::pty::Pty;
+use crate::config::{AmbiguousWidth, BackspaceSends, Config, CursorShape, Padding, ScrollbackStorage};
+use crate::input::{text_to_bytes, BindingMode, Key};
+use crate::pacing::{Throttle, WINSIZE_INTERVAL};
+use crate::palette::Palette;
+use crate::pty::{Pty, Typescript};
+use crate::render::resolve_cell_colors;
+use crate::scrollback::{new_scrollback, Scrollback};
+use crate::selection::{Point, Selection, SelectionMode};
+use crate::sixel::{SixelDecoder, SixelImage};
 
 pub struct Terminal {
     pty: Pty,
     parser: Parser,
     performer: TerminalPerformer,
+    padding: Padding,
+    /// From `display.dynamic_padding`.
+    dynamic_padding: bool,
+    /// `padding` with the centering for the current window size.
+    grid_padding: Padding,
+    /// Holds back the PTY's window size while the window is being resized.
+    winsize: Throttle<(u16, u16)>,
+    decoder: OutputDecoder,
+    /// Output bytes `next_output` parses at most in one call.
+    read_chunk_bytes: usize,
+    /// From `terminal.backspace_sends`.
+    backspace_sends: BackspaceSends,
+    /// Time spent parsing output since `take_parse_time` was last called.
+    parse_time: std::time::Duration,
+    /// When the oldest output not yet taken by `take_output_arrival` arrived.
+    output_arrival: Option<std::time::Instant>,
+    /// Where a selection being dragged out started, until the button is released.
+    selection_start: Option<(Point, SelectionMode)>,
+    /// `terminal.startup_command` as typed, until it's sent.
+    startup_command: Option<Vec<u8>>,
     #[allow(dead_code)]
     output_receiver: Receiver<Vec<u8>>,
     #[allow(dead_code)]
     input_sender: Sender<Vec<u8>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 #[allow(dead_code)]
 pub struct Cell {
     pub c: char,
     pub fg: rgb::RGB8,
     pub bg: rgb::RGB8,
     pub flags: CellFlags,
+    /// How the cell is underlined when it has `CellFlags::UNDERLINE`.
+    pub underline: UnderlineStyle,
+    /// Color of the underline, `None` to use the foreground.
+    pub underline_color: Option<rgb::RGB8>,
+}
+
+/// Underline shapes selected by SGR 4 subparameters (`4:1` to `4:5`) and SGR 21.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnderlineStyle {
+    #[default]
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
 }
 
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-    pub struct CellFlags: u8 {
+    pub struct CellFlags: u16 {
         const BOLD = 0b00000001;
         const DIM = 0b00000010;
         const ITALIC = 0b00000100;
@@ -36,42 +80,354 @@ bitflags::bitflags! {
         const REVERSE = 0b00100000;
         const BLINK = 0b01000000;
         const HIDDEN = 0b10000000;
+        /// Holds a double-width character, drawn across this cell and the next.
+        const WIDE = 0b1_00000000;
+        /// The blank second half of a `WIDE` cell, with colors of its own.
+        const WIDE_SPACER = 0b10_00000000;
+        /// Written while DECSCA protection was on; selective erases leave it alone.
+        const PROTECTED = 0b100_00000000;
+        /// The foreground was set with SGR rather than left at the default, even if it's
+        /// the same color.
+        const EXPLICIT_FG = 0b1000_00000000;
+        /// The background was set with SGR rather than left at the default.
+        const EXPLICIT_BG = 0b10000_00000000;
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 #[allow(dead_code)]
 pub struct Cursor {
     pub row: usize,
     pub col: usize,
     pub shape: CursorShape,
-    pub visible: bool,
+    /// Set from the config and changed by DECSCUSR.
+    pub blink: bool,
+}
+
+/// A mode programs switch on and off with `CSI h` and `CSI l`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// IRM (4): printed characters push the rest of the line right.
+    Insert,
+    /// DECAWM (?7): printing past the last column continues on the next line.
+    AutoWrap,
+    /// DECOM (?6): cursor positions count from the top of the scroll region.
+    Origin,
+    /// DECCKM (?1), changing what the cursor keys send.
+    ApplicationCursor,
+    /// ?2004: pasted text is wrapped in markers so programs can tell it from typing.
+    BracketedPaste,
+    /// ?1000, ?1002 or ?1003: the program asked for mouse reports, which aren't sent yet.
+    MouseReporting,
+    /// ?47, ?1047 or ?1049: the alternate screen, without scrollback, is shown.
+    AltScreen,
+    /// DECTCEM (?25).
+    CursorVisible,
+    /// ?2026: the program is updating the screen, which shouldn't be shown until it's done.
+    SynchronizedOutput,
+    /// DECSCNM (?5): the whole screen shows the default colors swapped, as a visual bell or a
+    /// light theme.
+    ReverseVideo,
+}
+
+impl Mode {
+    /// The ANSI mode numbered `n`, as in `CSI 4 h`.
+    pub fn from_ansi(n: u16) -> Option<Self> {
+        match n {
+            4 => Some(Self::Insert),
+            _ => None,
+        }
+    }
+    
+    /// The DEC private mode numbered `n`, as in `CSI ? 7 h`.
+    pub fn from_private(n: u16) -> Option<Self> {
+        match n {
+            1 => Some(Self::ApplicationCursor),
+            5 => Some(Self::ReverseVideo),
+            6 => Some(Self::Origin),
+            7 => Some(Self::AutoWrap),
+            25 => Some(Self::CursorVisible),
+            47 | 1047 | 1049 => Some(Self::AltScreen),
+            1000 | 1002 | 1003 => Some(Self::MouseReporting),
+            2004 => Some(Self::BracketedPaste),
+            2026 => Some(Self::SynchronizedOutput),
+            _ => None,
+        }
+    }
+}
+
+/// The state of every `Mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalModes {
+    pub insert: bool,
+    pub auto_wrap: bool,
+    pub origin: bool,
+    pub application_cursor: bool,
+    pub bracketed_paste: bool,
+    pub mouse_reporting: bool,
+    pub alt_screen: bool,
+    pub cursor_visible: bool,
+    pub synchronized_output: bool,
+    pub reverse_video: bool,
+}
+
+impl Default for TerminalModes {
+    fn default() -> Self {
+        Self {
+            insert: false,
+            auto_wrap: true,
+            origin: false,
+            application_cursor: false,
+            bracketed_paste: false,
+            mouse_reporting: false,
+            alt_screen: false,
+            cursor_visible: true,
+            synchronized_output: false,
+            reverse_video: false,
+        }
+    }
+}
+
+impl TerminalModes {
+    pub fn get(&self, mode: Mode) -> bool {
+        match mode {
+            Mode::Insert => self.insert,
+            Mode::AutoWrap => self.auto_wrap,
+            Mode::Origin => self.origin,
+            Mode::ApplicationCursor => self.application_cursor,
+            Mode::BracketedPaste => self.bracketed_paste,
+            Mode::MouseReporting => self.mouse_reporting,
+            Mode::AltScreen => self.alt_screen,
+            Mode::CursorVisible => self.cursor_visible,
+            Mode::SynchronizedOutput => self.synchronized_output,
+            Mode::ReverseVideo => self.reverse_video,
+        }
+    }
+    
+    fn flag(&mut self, mode: Mode) -> &mut bool {
+        match mode {
+            Mode::Insert => &mut self.insert,
+            Mode::AutoWrap => &mut self.auto_wrap,
+            Mode::Origin => &mut self.origin,
+            Mode::ApplicationCursor => &mut self.application_cursor,
+            Mode::BracketedPaste => &mut self.bracketed_paste,
+            Mode::MouseReporting => &mut self.mouse_reporting,
+            Mode::AltScreen => &mut self.alt_screen,
+            Mode::CursorVisible => &mut self.cursor_visible,
+            Mode::SynchronizedOutput => &mut self.synchronized_output,
+            Mode::ReverseVideo => &mut self.reverse_video,
+        }
+    }
 }
 
 pub struct Grid {
     pub cells: Vec<Vec<Cell>>,
     pub rows: usize,
     pub cols: usize,
-    pub scrollback: VecDeque<Vec<Cell>>,
-    pub scrollback_limit: usize,
+    pub scrollback: Box<dyn Scrollback>,
+    /// Maximum scrollback lines; `Some(0)` keeps none and `None` grows up to the byte budget.
+    pub scrollback_limit: Option<usize>,
+    /// Most bytes the scrollback holds whatever its limit, `SCROLLBACK_BUDGET_BYTES` unless
+    /// changed; the oldest lines go first past it.
+    pub scrollback_budget: usize,
+    /// Number of scrollback lines the view is scrolled up from the live screen.
+    pub display_offset: usize,
+    /// Number of columns the view is panned right, to show what was printed past the right
+    /// margin with auto-wrap off.
+    pub col_offset: usize,
+    /// What each live-screen row would hold past the right margin, kept while auto-wrap is off.
+    overflow: Vec<Option<Overflow>>,
+    /// Lines ever scrolled off the top of the live screen, including those since dropped from
+    /// the scrollback. Line numbers counted from the first line stay put as output scrolls.
+    lines_scrolled: usize,
+    /// Sixel images on the live screen and in the scrollback, drawn over the cells they cover.
+    pub images: Vec<PlacedImage>,
+    /// Shell integration marks, oldest first, including ones on lines since dropped.
+    marks: Vec<SemanticMark>,
+    /// Generation at which each cell of `cells` last changed.
+    stamps: Vec<Vec<u64>>,
+    /// Incremented on every change to the live screen.
+    generation: u64,
+    /// Generation of the last resize or screen switch; older tokens get a full frame.
+    full_frame_at: u64,
+    /// Generation at which the cells were last known to be blank.
+    blank_at: u64,
+}
+
+/// The end of a row as printed with auto-wrap off, where the last column only shows the latest
+/// character to reach the margin.
+#[derive(Debug, Clone, PartialEq)]
+struct Overflow {
+    /// The column the row's own cells stop being the line's from.
+    from: usize,
+    /// The line's cells from `from` on.
+    cells: Vec<Cell>,
+}
+
+/// A sixel image anchored to a cell of the live screen.
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct PlacedImage {
+    /// Row of the image's top edge; negative once that has scrolled off the screen, counting
+    /// back into the scrollback.
+    pub row: isize,
+    pub col: usize,
+    /// Cells covered, rounding partial cells up.
+    pub rows: usize,
+    pub cols: usize,
+    /// Shared with the frames that show it.
+    pub image: Arc<SixelImage>,
+}
+
+/// Where a shell said, with OSC 133, that part of a command line starts or ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticMark {
+    /// Absolute line, as numbered by `Grid::absolute_line`.
+    pub line: usize,
+    pub kind: MarkKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkKind {
+    /// A: the prompt starts.
+    Prompt,
+    /// B: the prompt ends and the command typed starts.
+    Command,
+    /// C: the command runs, its output starting.
+    Output,
+    /// D: the command finished, with its exit status if the shell gave one.
+    Finished(Option<i32>),
+}
+
+impl PlacedImage {
+    fn overlaps(&self, rows: &std::ops::Range<usize>) -> bool {
+        self.row < rows.end as isize && self.row + self.rows as isize > rows.start as isize
+    }
+}
+
+/// Opaque position in a grid's change history, see `Grid::diff_since`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DiffToken(u64);
+
+/// A live-screen cell and its new contents.
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct CellChange {
+    pub row: usize,
+    pub col: usize,
+    pub cell: Cell,
+}
+
+/// Changes to the live screen since a token.
+#[derive(Debug, Clone, Default)]
+#[allow(dead_code)]
+pub struct GridDiff {
+    /// Set to the grid size when the consumer must discard its copy; `changes` then covers every cell.
+    pub full_frame: Option<(usize, usize)>,
+    pub changes: Vec<CellChange>,
 }
 
 pub struct TerminalPerformer {
     pub grid: Grid,
+    /// The grid not on screen: the primary grid while the alternate screen is active.
+    pub inactive_grid: Grid,
     pub cursor: Cursor,
+    pub palette: Palette,
+    /// Active selection, its rows being absolute lines as numbered by `Grid::absolute_line`.
+    pub selection: Option<Selection>,
     pub default_fg: rgb::RGB8,
     pub default_bg: rgb::RGB8,
     pub current_fg: rgb::RGB8,
     pub current_bg: rgb::RGB8,
     pub current_flags: CellFlags,
-    #[allow(dead_code)]
+    pub current_underline: UnderlineStyle,
+    pub current_underline_color: Option<rgb::RGB8>,
     pub saved_cursor: Option<Cursor>,
     pub scroll_region: (usize, usize),
-    pub insert_mode: bool,
-    pub auto_wrap_mode: bool,
-    #[allow(dead_code)]
-    pub origin_mode: bool,
+    pub modes: TerminalModes,
     pub title: String,
+    /// Titles saved with CSI 22 t, most recent last.
+    pub title_stack: Vec<String>,
+    /// The working directory reported with OSC 7.
+    pub cwd: Option<String>,
+    /// Reply to ENQ, from `terminal.answerback`.
+    pub answerback: String,
+    /// From `terminal.trim_copy_trailing_whitespace`.
+    pub trim_copy_trailing_whitespace: bool,
+    /// From `terminal.keep_colored_trailing_whitespace`.
+    pub keep_colored_trailing_whitespace: bool,
+    /// From `terminal.ambiguous_width`.
+    pub ambiguous_width: AmbiguousWidth,
+    /// From `display.unfocused_dim`.
+    pub unfocused_dim: f32,
+    /// From `terminal.cursor_shape` and `terminal.cursor_blink`, so a reload that leaves them
+    /// alone keeps the style a program set with DECSCUSR.
+    pub config_cursor: (CursorShape, bool),
+    /// Whether the window has keyboard focus, without which the palette fades by
+    /// `unfocused_dim`.
+    pub focused: bool,
+    /// Most bytes of image pixels kept on both screens, `IMAGE_BUDGET_BYTES` unless changed.
+    pub image_budget: usize,
+    /// Where the cursor was held at the right margin by printing with auto-wrap off, so
+    /// printing there again continues the line past it.
+    past_margin: Option<(usize, usize)>,
+    pub cell_width: u32,
+    pub cell_height: u32,
+    /// Bytes queued for transmission back to the PTY (query replies).
+    pub responses: Vec<u8>,
+    /// Set when a BEL was received and not yet handled.
+    pub bell: bool,
+    /// Set when the title or working directory changed and the window doesn't show it yet.
+    pub title_changed: bool,
+    /// The DCS sequence being received, if any.
+    dcs: Option<Dcs>,
+    /// Counts changes to what a frame shows, see `damage_generation`.
+    damage_generation: u64,
+    /// What a frame showed as of the last count.
+    shown: Option<View>,
+}
+
+/// What a frame shows of the terminal, compared to tell whether anything in it changed.
+#[derive(Debug, Clone, PartialEq)]
+struct View {
+    cells: DiffToken,
+    cursor: Cursor,
+    display_offset: usize,
+    col_offset: usize,
+    selection: Option<Selection>,
+    modes: TerminalModes,
+    palette: Palette,
+    images: usize,
+}
+
+/// How long the startup command waits for the shell to print something before it's sent.
+pub const STARTUP_COMMAND_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Smallest grid the window may be resized to.
+pub const MIN_COLUMNS: u32 = 10;
+pub const MIN_ROWS: u32 = 3;
+
+/// Longest DECRQSS request we accept; real requests are one or two bytes.
+const MAX_STATUS_REQUEST: usize = 8;
+
+/// Most bytes of scrollback kept by default, so unlimited scrollback can't take all memory.
+pub const SCROLLBACK_BUDGET_BYTES: usize = 256 * 1024 * 1024;
+
+/// Longest line kept by printing past the right margin with auto-wrap off; characters printed
+/// beyond it take its last columns, as they do the margin's.
+pub const MAX_LINE_COLUMNS: usize = 4096;
+
+/// Most bytes of image pixels kept by default, room for several of the largest images.
+pub const IMAGE_BUDGET_BYTES: usize = 256 * 1024 * 1024;
+
+/// How many titles CSI 22 t keeps, as in xterm.
+const TITLE_STACK_DEPTH: usize = 10;
+
+/// A DCS sequence whose data is still arriving.
+enum Dcs {
+    Sixel(SixelDecoder),
+    /// DECRQSS, with the bytes naming the setting requested.
+    RequestStatus(Vec<u8>),
 }
 
 impl Default for Cell {
@@ -81,23 +437,46 @@ impl Default for Cell {
             fg: rgb::RGB8::new(255, 255, 255),
             bg: rgb::RGB8::new(0, 0, 0),
             flags: CellFlags::empty(),
+            underline: UnderlineStyle::Single,
+            underline_color: None,
         }
     }
 }
 
 impl Grid {
-    pub fn new(rows: usize, cols: usize, scrollback_limit: usize) -> Self {
+    pub fn new(rows: usize, cols: usize, scrollback_limit: Option<usize>) -> Self {
+        Self::with_storage(rows, cols, scrollback_limit, ScrollbackStorage::Full)
+    }
+    
+    pub fn with_storage(rows: usize, cols: usize, scrollback_limit: Option<usize>, storage: ScrollbackStorage) -> Self {
         let cells = vec![vec![Cell::default(); cols]; rows];
         Self {
             cells,
             rows,
             cols,
-            scrollback: VecDeque::with_capacity(scrollback_limit),
+            // Grows on demand so a large limit doesn't allocate up front
+            scrollback: new_scrollback(storage),
             scrollback_limit,
+            scrollback_budget: SCROLLBACK_BUDGET_BYTES,
+            display_offset: 0,
+            col_offset: 0,
+            overflow: vec![None; rows],
+            lines_scrolled: 0,
+            images: Vec::new(),
+            marks: Vec::new(),
+            stamps: vec![vec![0; cols]; rows],
+            // Past the default token, so a first diff is a full frame
+            generation: 1,
+            full_frame_at: 1,
+            blank_at: 1,
         }
     }
     
     pub fn resize(&mut self, new_rows: usize, new_cols: usize) {
+        if new_cols == self.cols && new_rows == self.rows {
+            return;
+        }
+        
         if new_cols != self.cols {
             for row in &mut self.cells {
                 row.resize(new_cols, Cell::default());
@@ -109,30 +488,381 @@ impl Grid {
             self.cells.resize(new_rows, vec![Cell::default(); new_cols]);
             self.rows = new_rows;
         }
+        
+        self.images.retain(|image| image.row < new_rows as isize);
+        self.stamps = vec![vec![0; new_cols]; new_rows];
+        self.overflow = vec![None; new_rows];
+        self.col_offset = 0;
+        self.force_full_frame();
+    }
+    
+    fn bump_generation(&mut self) -> u64 {
+        self.generation += 1;
+        self.generation
+    }
+    
+    fn damage_row(&mut self, row: usize) {
+        let generation = self.bump_generation();
+        if let Some(stamps) = self.stamps.get_mut(row) {
+            stamps.fill(generation);
+        }
+    }
+    
+    fn damage_all(&mut self) {
+        let generation = self.bump_generation();
+        for stamps in &mut self.stamps {
+            stamps.fill(generation);
+        }
+    }
+    
+    /// Makes every earlier token produce a full frame.
+    fn force_full_frame(&mut self) {
+        self.full_frame_at = self.bump_generation();
+    }
+    
+    /// Continues `other`'s generation count, for when this grid replaces it on screen.
+    fn take_over_from(&mut self, other: &Grid) {
+        self.generation = self.generation.max(other.generation);
+        self.force_full_frame();
+    }
+    
+    /// Blanks the grid and takes over from `other`, as on entering the alternate screen.
+    ///
+    /// Every change bumps the generation, so the cells are left alone if it hasn't moved since
+    /// they were last blanked here, and the full frame makes stamping them unnecessary.
+    fn clear_and_take_over_from(&mut self, other: &Grid) {
+        if self.generation != self.blank_at {
+            for row in &mut self.cells {
+                row.fill(Cell::default());
+            }
+        }
+        self.images.clear();
+        self.overflow.fill(None);
+        self.col_offset = 0;
+        self.take_over_from(other);
+        self.blank_at = self.generation;
+    }
+    
+    pub fn set_cell(&mut self, row: usize, col: usize, cell: Cell) {
+        self.cells[row][col] = cell;
+        self.stamps[row][col] = self.bump_generation();
+    }
+    
+    /// Inserts a cell, shifting the rest of the row right and dropping what falls off the end.
+    pub fn insert_cell(&mut self, row: usize, col: usize, cell: Cell) {
+        self.overflow[row] = None;
+        let cells = &mut self.cells[row];
+        cells.insert(col, cell);
+        cells.truncate(self.cols);
+        self.damage_row(row);
+    }
+    
+    /// Blanks the other halves of double-width characters cut in two by writing over `cols`
+    /// of `row`, so that no half is left on its own.
+    pub fn clear_wide_halves(&mut self, row: usize, cols: std::ops::Range<usize>) {
+        let end = cols.end.min(self.cols);
+        if cols.start >= end {
+            return;
+        }
+        if cols.start > 0 && self.cells[row][cols.start].flags.contains(CellFlags::WIDE_SPACER) {
+            self.blank_cells(row, cols.start - 1..cols.start);
+        }
+        if end < self.cols && self.cells[row][end - 1].flags.contains(CellFlags::WIDE) {
+            self.blank_cells(row, end..end + 1);
+        }
+    }
+    
+    /// Resets the cells in `cols` of `row` to blanks.
+    pub fn clear_cells(&mut self, row: usize, cols: std::ops::Range<usize>) {
+        self.drop_overflow(row, cols.clone());
+        self.blank_cells(row, cols);
+    }
+    
+    /// Resets cells as `clear_cells` does, leaving the line's overflow alone.
+    fn blank_cells(&mut self, row: usize, cols: std::ops::Range<usize>) {
+        let generation = self.bump_generation();
+        let end = cols.end.min(self.cols);
+        for col in cols.start.min(end)..end {
+            self.cells[row][col] = Cell::default();
+            self.stamps[row][col] = generation;
+        }
+    }
+    
+    /// Resets the cells in `cols` of `row` that aren't `PROTECTED` to blanks.
+    pub fn erase_unprotected(&mut self, row: usize, cols: std::ops::Range<usize>) {
+        self.drop_overflow(row, cols.clone());
+        let generation = self.bump_generation();
+        let end = cols.end.min(self.cols);
+        for col in cols.start.min(end)..end {
+            if !self.cells[row][col].flags.contains(CellFlags::PROTECTED) {
+                self.cells[row][col] = Cell::default();
+                self.stamps[row][col] = generation;
+            }
+        }
+    }
+    
+    /// Keeps the cells in `cols` of `row`, about to be written over by printing past the right
+    /// margin, as part of the line, continued by `cells`.
+    pub fn overflow_past_margin(&mut self, row: usize, cols: std::ops::Range<usize>, cells: &[Cell]) {
+        let start = cols.start.min(self.cols);
+        let kept = &self.cells[row];
+        let overflow = self.overflow[row].get_or_insert_with(|| Overflow { from: self.cols, cells: Vec::new() });
+        // The cells written over join the line ahead of what's printed, the first time and when
+        // a double-width character moved back from the last column reaches one further left
+        if start < overflow.from {
+            overflow.cells.splice(0..0, kept[start..overflow.from].iter().cloned());
+            overflow.from = start;
+        }
+        let limit = MAX_LINE_COLUMNS.max(self.cols) - overflow.from;
+        if overflow.cells.len() + cells.len() > limit {
+            overflow.cells.truncate(limit.saturating_sub(cells.len()));
+        }
+        overflow.cells.extend_from_slice(cells);
+    }
+    
+    /// Forgets what `row` held past the right margin if `cols` reach the cells standing in for
+    /// it, as writing there draws the row anew.
+    pub fn drop_overflow(&mut self, row: usize, cols: std::ops::Range<usize>) {
+        if self.overflow[row].as_ref().is_some_and(|overflow| cols.end > overflow.from && !cols.is_empty()) {
+            self.overflow[row] = None;
+        }
+    }
+    
+    /// Row `row` of the live screen as printed, including what went past the right margin.
+    pub fn full_row(&self, row: usize) -> Cow<'_, [Cell]> {
+        match &self.overflow[row] {
+            Some(overflow) => Cow::Owned([&self.cells[row][..overflow.from], &overflow.cells].concat()),
+            None => Cow::Borrowed(&self.cells[row]),
+        }
+    }
+    
+    /// Columns of the widest line on the live screen or in view from the scrollback, at least
+    /// the grid's.
+    pub fn full_width(&self) -> usize {
+        let widest = self.overflow.iter().flatten().map(|overflow| overflow.from + overflow.cells.len()).max();
+        let history = (0..self.display_offset.min(self.rows)).map(|row| self.visible_row(row).len()).max();
+        widest.unwrap_or(0).max(history.unwrap_or(0)).max(self.cols)
+    }
+    
+    /// Pans the view right, up to where the widest line ends at the right edge.
+    pub fn scroll_view_right(&mut self, cols: usize) {
+        self.col_offset = (self.col_offset + cols).min(self.full_width() - self.cols);
+    }
+    
+    pub fn scroll_view_left(&mut self, cols: usize) {
+        self.col_offset = self.col_offset.saturating_sub(cols);
+    }
+    
+    /// Drops the images covering any of `rows`.
+    pub fn evict_images(&mut self, rows: std::ops::Range<usize>) {
+        self.images.retain(|image| !image.overlaps(&rows));
+    }
+    
+    pub fn token(&self) -> DiffToken {
+        DiffToken(self.generation)
+    }
+    
+    /// Returns the live-screen cells changed since `token`, and a token for the current state.
+    ///
+    /// `DiffToken::default()` always yields a full frame.
+    pub fn diff_since(&self, token: DiffToken) -> (GridDiff, DiffToken) {
+        let full = token.0 < self.full_frame_at || token.0 > self.generation;
+        let mut diff = GridDiff {
+            full_frame: full.then_some((self.rows, self.cols)),
+            changes: Vec::new(),
+        };
+        
+        for (row, stamps) in self.stamps.iter().enumerate() {
+            for (col, &stamp) in stamps.iter().enumerate() {
+                if full || stamp > token.0 {
+                    diff.changes.push(CellChange { row, col, cell: self.cells[row][col].clone() });
+                }
+            }
+        }
+        
+        (diff, self.token())
     }
     
     pub fn scroll_up(&mut self, lines: usize) {
         for _ in 0..lines {
-            if self.scrollback.len() >= self.scrollback_limit {
-                self.scrollback.pop_front();
+            let mut first_row = self.cells.remove(0);
+            // What the line held past the margin goes into the scrollback with it
+            if let Some(overflow) = self.overflow.remove(0) {
+                first_row.truncate(overflow.from);
+                first_row.extend(overflow.cells);
+            }
+            match self.scrollback_limit {
+                Some(0) => {} // Scrollback disabled, drop the line
+                limit => {
+                    self.scrollback.push_back(first_row);
+                    let limit = limit.unwrap_or(usize::MAX);
+                    while self.scrollback.len() > limit || self.scrollback.bytes() > self.scrollback_budget {
+                        self.scrollback.pop_front();
+                    }
+                }
             }
-            
-            let first_row = self.cells.remove(0);
-            self.scrollback.push_back(first_row);
             self.cells.push(vec![Cell::default(); self.cols]);
+            self.overflow.push(None);
+            self.lines_scrolled += 1;
+            
+            // Keep a scrolled-up view anchored to the same content
+            if self.display_offset > 0 {
+                self.display_offset = (self.display_offset + 1).min(self.scrollback.len());
+            }
+        }
+        
+        if lines > 0 {
+            // Images move with the text and are dropped once no line they cover is kept
+            for image in &mut self.images {
+                image.row -= lines as isize;
+            }
+            let history = self.scrollback.len() as isize;
+            self.images.retain(|image| image.row + image.rows as isize > -history);
+            self.damage_all();
         }
     }
     
+    pub fn scroll_view_up(&mut self, lines: usize) {
+        self.display_offset = (self.display_offset + lines).min(self.scrollback.len());
+    }
+    
+    pub fn scroll_view_down(&mut self, lines: usize) {
+        self.display_offset = self.display_offset.saturating_sub(lines);
+    }
+    
+    pub fn scroll_view_to_bottom(&mut self) {
+        self.display_offset = 0;
+    }
+    
+    /// Drops every scrollback line, returning the view to the live screen. Line numbers of
+    /// the screen's lines stay as they were.
+    pub fn clear_scrollback(&mut self) {
+        self.scrollback.clear();
+        self.display_offset = 0;
+        self.images.retain(|image| image.row + image.rows as isize > 0);
+    }
+    
+    /// Line number of the row shown at `row` in the viewport, counting every line that ever
+    /// scrolled off the live screen.
+    pub fn absolute_line(&self, row: usize) -> usize {
+        self.lines_scrolled - self.display_offset + row
+    }
+    
+    /// Scrolls the view so absolute line `line` is at the top, or as close as the scrollback
+    /// and the live screen allow.
+    pub fn scroll_view_to_top_line(&mut self, line: usize) {
+        self.display_offset = self.lines_scrolled.saturating_sub(line).min(self.scrollback.len());
+    }
+    
+    /// Marks live-screen row `row` with `kind`, replacing a mark of the same kind on it, e.g.
+    /// when the shell draws its prompt again. Marks on lines no longer kept are dropped, and
+    /// so are those below `row`, which a shell only marks above after the screen was cleared.
+    pub fn add_mark(&mut self, row: usize, kind: MarkKind) {
+        let line = self.lines_scrolled + row;
+        let first = self.first_line();
+        self.marks.retain(|mark| (first..line).contains(&mark.line) || (mark.line == line && mark.kind != kind));
+        self.marks.push(SemanticMark { line, kind });
+    }
+    
+    /// The marks on lines still kept, oldest first.
+    pub fn marks(&self) -> impl Iterator<Item = &SemanticMark> {
+        let first = self.first_line();
+        self.marks.iter().filter(move |mark| mark.line >= first)
+    }
+    
+    /// Scrolls the view as little as needed to show absolute line `line`.
+    pub fn scroll_view_to_line(&mut self, line: usize) {
+        let top = self.absolute_line(0);
+        if line < top {
+            self.scroll_view_up(top - line);
+        } else if line >= top + self.rows {
+            self.scroll_view_down(line + 1 - top - self.rows);
+        }
+    }
+    
+    /// Line number of the oldest line still kept, in scrollback or on screen.
+    pub fn first_line(&self) -> usize {
+        self.lines_scrolled - self.scrollback.len()
+    }
+    
+    /// The cells of absolute line `line`, or `None` if it was dropped or is below the screen.
+    /// Lines on the live screen include what they held past the right margin.
+    pub fn line(&self, line: usize) -> Option<Cow<'_, [Cell]>> {
+        let index = line.checked_sub(self.first_line())?;
+        let history = self.scrollback.len();
+        if index < history {
+            Some(self.scrollback.line(index))
+        } else {
+            (index - history < self.rows).then(|| self.full_row(index - history))
+        }
+    }
+    
+    /// Returns the row shown at `row` in the viewport, taking the display offset into account.
+    #[allow(dead_code)]
+    pub fn visible_row(&self, row: usize) -> Cow<'_, [Cell]> {
+        if row < self.display_offset {
+            self.scrollback.line(self.scrollback.len() - self.display_offset + row)
+        } else {
+            Cow::Borrowed(&self.cells[row - self.display_offset])
+        }
+    }
+    
+    /// The row shown at `row` in the viewport panned `col_offset` columns right, a row of the
+    /// grid's width, with what lines held past the right margin.
+    pub fn panned_row(&self, row: usize) -> Cow<'_, [Cell]> {
+        if self.col_offset == 0 {
+            // Lines from the scrollback may go past the right edge
+            return match self.visible_row(row) {
+                Cow::Borrowed(cells) => Cow::Borrowed(&cells[..cells.len().min(self.cols)]),
+                Cow::Owned(mut cells) => {
+                    cells.truncate(self.cols);
+                    Cow::Owned(cells)
+                }
+            };
+        }
+        let line = match row.checked_sub(self.display_offset) {
+            Some(row) => self.full_row(row),
+            None => self.visible_row(row),
+        };
+        let mut cells: Vec<Cell> = line.iter().skip(self.col_offset).take(self.cols).cloned().collect();
+        // A double-width character cut in two at the left edge shows as a blank
+        if cells.first().is_some_and(|cell| cell.flags.contains(CellFlags::WIDE_SPACER)) {
+            cells[0] = Cell::default();
+        }
+        cells.resize(self.cols, Cell::default());
+        Cow::Owned(cells)
+    }
+    
     #[allow(dead_code)]
     pub fn scroll_down(&mut self, lines: usize) {
         for _ in 0..lines {
-            if let Some(row) = self.scrollback.pop_back() {
+            let mut overflow = None;
+            if let Some(mut row) = self.scrollback.pop_back() {
+                // A line kept past the margin comes back with the rest in its overflow
+                if row.len() > self.cols {
+                    let cells = row.split_off(self.cols - 1);
+                    // The last column shows the latest character to reach the margin
+                    row.push(cells[cells.len() - 1].clone());
+                    overflow = Some(Overflow { from: self.cols - 1, cells });
+                }
                 self.cells.insert(0, row);
                 self.cells.pop();
+                self.lines_scrolled -= 1;
             } else {
                 self.cells.insert(0, vec![Cell::default(); self.cols]);
                 self.cells.pop();
             }
+            self.overflow.insert(0, overflow);
+            self.overflow.pop();
+        }
+        
+        if lines > 0 {
+            for image in &mut self.images {
+                image.row += lines as isize;
+            }
+            let rows = self.rows as isize;
+            self.images.retain(|image| image.row < rows);
+            self.damage_all();
         }
     }
     
@@ -142,6 +872,10 @@ impl Grid {
                 *cell = Cell::default();
             }
         }
+        self.images.clear();
+        self.overflow.fill(None);
+        self.col_offset = 0;
+        self.damage_all();
     }
     
     pub fn clear_line(&mut self, row: usize) {
@@ -149,34 +883,512 @@ impl Grid {
             for cell in &mut self.cells[row] {
                 *cell = Cell::default();
             }
+            self.overflow[row] = None;
+            self.damage_row(row);
         }
     }
 }
 
 impl TerminalPerformer {
     pub fn new(rows: usize, cols: usize, config: &Config) -> Self {
-        let default_fg = crate::config::parse_color(&config.colors.foreground).unwrap_or(rgb::RGB8::new(255, 255, 255));
-        let default_bg = crate::config::parse_color(&config.colors.background).unwrap_or(rgb::RGB8::new(0, 0, 0));
+        let palette = Palette::from_config(&config.colors);
+        let default_fg = palette.foreground;
+        let default_bg = palette.background;
         
         Self {
-            grid: Grid::new(rows, cols, config.terminal.scrollback_lines as usize),
+            grid: Grid::with_storage(rows, cols, config.terminal.scrollback_lines, config.terminal.scrollback_storage),
+            inactive_grid: Grid::new(rows, cols, Some(0)),
             cursor: Cursor {
                 row: 0,
                 col: 0,
                 shape: config.terminal.cursor_shape.clone(),
-                visible: true,
+                blink: config.terminal.cursor_blink,
             },
+            palette,
+            selection: None,
             default_fg,
             default_bg,
             current_fg: default_fg,
             current_bg: default_bg,
             current_flags: CellFlags::empty(),
+            current_underline: UnderlineStyle::Single,
+            current_underline_color: None,
             saved_cursor: None,
             scroll_region: (0, rows.saturating_sub(1)),
-            insert_mode: false,
-            auto_wrap_mode: true,
-            origin_mode: false,
+            modes: TerminalModes::default(),
             title: String::new(),
+            title_stack: Vec::new(),
+            cwd: None,
+            answerback: config.terminal.answerback.clone(),
+            trim_copy_trailing_whitespace: config.terminal.trim_copy_trailing_whitespace,
+            keep_colored_trailing_whitespace: config.terminal.keep_colored_trailing_whitespace,
+            ambiguous_width: config.terminal.ambiguous_width,
+            unfocused_dim: config.display.unfocused_dim,
+            config_cursor: (config.terminal.cursor_shape.clone(), config.terminal.cursor_blink),
+            focused: true,
+            image_budget: IMAGE_BUDGET_BYTES,
+            past_margin: None,
+            cell_width: cell_size_for(config.font.size).0,
+            cell_height: cell_size_for(config.font.size).1,
+            responses: Vec::new(),
+            bell: false,
+            title_changed: false,
+            dcs: None,
+            damage_generation: 0,
+            shown: None,
+        }
+    }
+    
+    /// Counts changes to what a frame shows: the cells, cursor, view position, selection, modes
+    /// and colors. Each call compares with the last one, so output that changes nothing visible,
+    /// such as a query, doesn't count.
+    pub fn damage_generation(&mut self) -> u64 {
+        let view = View {
+            cells: self.grid.token(),
+            cursor: self.cursor.clone(),
+            display_offset: self.grid.display_offset,
+            col_offset: self.grid.col_offset,
+            selection: self.selection,
+            modes: self.modes,
+            palette: self.palette.clone(),
+            images: self.grid.images.len(),
+        };
+        if self.shown.as_ref() != Some(&view) {
+            self.shown = Some(view);
+            self.damage_generation += 1;
+        }
+        self.damage_generation
+    }
+    
+    /// Fades the colors by `unfocused_dim` while unfocused. The palette changing counts as
+    /// damage, so the frame after is drawn whole, once.
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        self.palette.fade = if focused { 0.0 } else { self.unfocused_dim.clamp(0.0, 1.0) };
+    }
+    
+    /// Switches `mode` on or off, with the side effects of the sequence doing so.
+    pub fn set_mode(&mut self, mode: Mode, enable: bool) {
+        match mode {
+            Mode::AltScreen if enable => self.enter_alt_screen(false),
+            Mode::AltScreen => self.exit_alt_screen(false),
+            Mode::Origin => {
+                self.modes.origin = enable;
+                // DECOM homes the cursor, to the region's corner when set
+                self.cursor.row = if enable { self.scroll_region.0 } else { 0 };
+                self.cursor.col = 0;
+            }
+            _ => *self.modes.flag(mode) = enable,
+        }
+    }
+    
+    fn set_private_mode(&mut self, n: u16, enable: bool) {
+        match (n, Mode::from_private(n)) {
+            // Also saves the cursor on entering and restores it on leaving
+            (1049, _) if enable => self.enter_alt_screen(true),
+            (1049, _) => self.exit_alt_screen(true),
+            (_, Some(mode)) => self.set_mode(mode, enable),
+            (_, None) => log::debug!("Unhandled private mode {} ({})", n, enable),
+        }
+    }
+    
+    /// Answers DECRQM with whether mode `n` is set, so programs can detect support for modes
+    /// such as synchronized output before using them.
+    fn report_mode(&mut self, n: u16, private: bool) {
+        let mode = if private { Mode::from_private(n) } else { Mode::from_ansi(n) };
+        // 1 set, 2 reset, 0 not recognized
+        let value = mode.map_or(0, |mode| if self.modes.get(mode) { 1 } else { 2 });
+        let prefix = if private { "?" } else { "" };
+        self.responses.extend_from_slice(format!("\x1b[{}{};{}$y", prefix, n, value).as_bytes());
+    }
+    
+    /// Wipes the screen on show and every scrollback line, homing the cursor. Unlike the
+    /// program clearing the screen, nothing is written to the PTY; the program goes on as if
+    /// the lines were still there.
+    pub fn clear_all(&mut self) {
+        self.grid.clear_scrollback();
+        self.inactive_grid.clear_scrollback();
+        self.grid.clear();
+        self.grid.marks.clear();
+        self.selection = None;
+        self.cursor.row = 0;
+        self.cursor.col = 0;
+    }
+    
+    fn enter_alt_screen(&mut self, save_cursor: bool) {
+        if self.modes.alt_screen {
+            return;
+        }
+        
+        if save_cursor {
+            self.saved_cursor = Some(self.cursor.clone());
+        }
+        // Swapping moves the grids' handles only, leaving the primary grid's cells untouched
+        std::mem::swap(&mut self.grid, &mut self.inactive_grid);
+        self.grid.clear_and_take_over_from(&self.inactive_grid);
+        self.modes.alt_screen = true;
+        // Its lines are those of the other screen
+        self.selection = None;
+    }
+    
+    fn exit_alt_screen(&mut self, restore_cursor: bool) {
+        if !self.modes.alt_screen {
+            return;
+        }
+        
+        std::mem::swap(&mut self.grid, &mut self.inactive_grid);
+        self.grid.take_over_from(&self.inactive_grid);
+        self.modes.alt_screen = false;
+        self.selection = None;
+        if restore_cursor {
+            if let Some(cursor) = self.saved_cursor.take() {
+                self.cursor = cursor;
+            }
+        }
+    }
+    
+    /// Resizes both screens. A screen shorter than the cursor's row pushes the lines above it
+    /// into the scrollback, so the line being typed on stays in view.
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        let excess = (self.cursor.row + 1).saturating_sub(rows);
+        if excess > 0 {
+            self.scroll_up(excess);
+            self.cursor.row -= excess;
+        }
+        // Behind the alternate screen, the primary screen's cursor is the one saved by 1049
+        if let Some(saved) = self.saved_cursor.as_mut().filter(|_| self.modes.alt_screen) {
+            let excess = (saved.row + 1).saturating_sub(rows);
+            self.inactive_grid.scroll_up(excess);
+            saved.row -= excess;
+        }
+        
+        self.grid.resize(rows, cols);
+        self.inactive_grid.resize(rows, cols);
+        for cursor in std::iter::once(&mut self.cursor).chain(self.saved_cursor.as_mut()) {
+            cursor.row = cursor.row.min(rows.saturating_sub(1));
+            cursor.col = cursor.col.min(cols.saturating_sub(1));
+        }
+        self.scroll_region = (0, rows.saturating_sub(1));
+        self.past_margin = None;
+    }
+    
+    fn set_title(&mut self, title: String) {
+        if title != self.title {
+            self.title = title;
+            self.title_changed = true;
+        }
+    }
+    
+    fn report_window_size(&mut self, n: u16) {
+        let rows = self.grid.rows;
+        let cols = self.grid.cols;
+        let reply = match n {
+            14 => { // Text area size in pixels
+                let height = rows as u32 * self.cell_height;
+                let width = cols as u32 * self.cell_width;
+                format!("\x1b[4;{};{}t", height, width)
+            }
+            18 => format!("\x1b[8;{};{}t", rows, cols), // Text area size in characters
+            19 => format!("\x1b[9;{};{}t", rows, cols), // Screen size in characters
+            _ => {
+                // Requests that would move, resize or raise the window are ignored
+                log::debug!("Ignoring window manipulation request {}", n);
+                return;
+            }
+        };
+        self.responses.extend_from_slice(reply.as_bytes());
+    }
+    
+    /// Answers DSR `n`: 5 with "OK" and 6 with the cursor position, counted from the corner of
+    /// the scroll region in origin mode. The `private` form of 6 (DECXCPR) adds the page.
+    fn report_status_n(&mut self, n: u16, private: bool) {
+        let reply = match n {
+            5 if !private => "\x1b[0n".to_string(),
+            6 => {
+                let top = if self.modes.origin { self.scroll_region.0 } else { 0 };
+                let (row, col) = (self.cursor.row.saturating_sub(top) + 1, self.cursor.col + 1);
+                if private { format!("\x1b[?{};{};1R", row, col) } else { format!("\x1b[{};{}R", row, col) }
+            }
+            _ => {
+                log::debug!("Unhandled device status report {}", n);
+                return;
+            }
+        };
+        self.responses.extend_from_slice(reply.as_bytes());
+    }
+    
+    /// Answers a DECRQSS request with the current value of the setting it names.
+    fn report_status(&mut self, request: &[u8]) {
+        let setting = match request {
+            b"m" => format!("{}m", self.sgr_state()),
+            b"r" => format!("{};{}r", self.scroll_region.0 + 1, self.scroll_region.1 + 1),
+            b" q" => {
+                let style = match self.cursor.shape {
+                    CursorShape::Block => 1,
+                    CursorShape::Underline => 3,
+                    CursorShape::Beam => 5,
+                };
+                // Even values are the steady variants
+                format!("{} q", if self.cursor.blink { style } else { style + 1 })
+            }
+            b"\"q" => format!("{}\"q", self.current_flags.contains(CellFlags::PROTECTED) as u8),
+            _ => {
+                log::debug!("Unsupported DECRQSS request {:?}", String::from_utf8_lossy(request));
+                self.responses.extend_from_slice(b"\x1bP0$r\x1b\\");
+                return;
+            }
+        };
+        self.responses.extend_from_slice(format!("\x1bP1$r{}\x1b\\", setting).as_bytes());
+    }
+    
+    fn reset_sgr(&mut self) {
+        self.current_fg = self.default_fg;
+        self.current_bg = self.default_bg;
+        // Protection is set with DECSCA only
+        self.current_flags &= CellFlags::PROTECTED;
+        self.current_underline = UnderlineStyle::Single;
+        self.current_underline_color = None;
+    }
+    
+    /// Sets the foreground to `color`, or to the default with `None`.
+    fn set_fg(&mut self, color: Option<rgb::RGB8>) {
+        self.current_fg = color.unwrap_or(self.default_fg);
+        self.current_flags.set(CellFlags::EXPLICIT_FG, color.is_some());
+    }
+    
+    /// Sets the background to `color`, or to the default with `None`.
+    fn set_bg(&mut self, color: Option<rgb::RGB8>) {
+        self.current_bg = color.unwrap_or(self.default_bg);
+        self.current_flags.set(CellFlags::EXPLICIT_BG, color.is_some());
+    }
+    
+    /// The color of an extended color parameter's values after the 38, 48 or 58: `2` and an RGB
+    /// triple, optionally preceded by an empty color space id, or `5` and an index into the 256
+    /// color palette.
+    fn sgr_extended_color(&self, values: &[u16]) -> Option<rgb::RGB8> {
+        let channel = |value: u16| value.min(255) as u8;
+        match values {
+            [2, _, r, g, b] | [2, r, g, b] => Some(rgb::RGB8::new(channel(*r), channel(*g), channel(*b))),
+            [5, index] => Some(self.indexed_color(*index)),
+            _ => {
+                log::debug!("Malformed extended color {:?}", values);
+                None
+            }
+        }
+    }
+    
+    /// Entry `index` of the 256 color palette: the 16 configured colors, a 6x6x6 color cube and
+    /// a gray ramp.
+    fn indexed_color(&self, index: u16) -> rgb::RGB8 {
+        match index {
+            0..=7 => self.palette.normal[index as usize],
+            8..=15 => self.palette.bright[index as usize - 8],
+            16..=231 => {
+                let level = |value: u16| if value == 0 { 0 } else { (55 + value * 40) as u8 };
+                let index = index - 16;
+                rgb::RGB8::new(level(index / 36), level(index / 6 % 6), level(index % 6))
+            }
+            _ => {
+                let gray = (8 + (index.min(255) - 232) * 10) as u8;
+                rgb::RGB8::new(gray, gray, gray)
+            }
+        }
+    }
+    
+    /// SGR parameters that recreate the current attributes from a reset.
+    fn sgr_state(&self) -> String {
+        let mut params = vec!["0".to_string()];
+        
+        for (flag, param) in [
+            (CellFlags::BOLD, "1"),
+            (CellFlags::DIM, "2"),
+            (CellFlags::ITALIC, "3"),
+            (CellFlags::UNDERLINE, "4"),
+            (CellFlags::BLINK, "5"),
+            (CellFlags::REVERSE, "7"),
+            (CellFlags::HIDDEN, "8"),
+            (CellFlags::STRIKETHROUGH, "9"),
+        ] {
+            if self.current_flags.contains(flag) {
+                params.push(param.to_string());
+            }
+        }
+        let styled = match self.current_underline {
+            UnderlineStyle::Single => None,
+            UnderlineStyle::Double => Some("4:2"),
+            UnderlineStyle::Curly => Some("4:3"),
+            UnderlineStyle::Dotted => Some("4:4"),
+            UnderlineStyle::Dashed => Some("4:5"),
+        };
+        if let (true, Some(styled)) = (self.current_flags.contains(CellFlags::UNDERLINE), styled) {
+            if let Some(param) = params.iter_mut().find(|param| *param == "4") {
+                *param = styled.to_string();
+            }
+        }
+        if let Some(color) = self.current_underline_color {
+            params.push(format!("58:2::{}:{}:{}", color.r, color.g, color.b));
+        }
+        
+        if self.current_flags.contains(CellFlags::EXPLICIT_FG) {
+            params.push(self.sgr_color(self.current_fg, 30, 90, 38));
+        }
+        if self.current_flags.contains(CellFlags::EXPLICIT_BG) {
+            params.push(self.sgr_color(self.current_bg, 40, 100, 48));
+        }
+        
+        params.join(";")
+    }
+    
+    /// The SGR parameter selecting `color`, preferring palette entries over direct RGB.
+    fn sgr_color(&self, color: rgb::RGB8, normal: usize, bright: usize, direct: usize) -> String {
+        if let Some(i) = self.palette.normal.iter().position(|&c| c == color) {
+            (normal + i).to_string()
+        } else if let Some(i) = self.palette.bright.iter().position(|&c| c == color) {
+            (bright + i).to_string()
+        } else {
+            format!("{};2;{};{};{}", direct, color.r, color.g, color.b)
+        }
+    }
+    
+    /// The visible rows with the colors they should be drawn in, reverse video, DIM, HIDDEN and
+    /// selection included. REVERSE and DIM are cleared from the cells as they've been applied.
+    ///
+    /// Both halves of a double-width character are selected when either of them is. Panned
+    /// right, the rows start `Grid::col_offset` columns into each line.
+    pub fn styled_snapshot(&self) -> Vec<Vec<Cell>> {
+        let palette = self.screen_palette();
+        (0..self.grid.rows)
+            .map(|row| {
+                self.grid.panned_row(row)
+                    .iter()
+                    .enumerate()
+                    .map(|(col, cell)| {
+                        let col = col + self.grid.col_offset;
+                        let mut cell = cell.clone();
+                        let line = self.grid.absolute_line(row);
+                        let other_half = if cell.flags.contains(CellFlags::WIDE) {
+                            Some(col + 1)
+                        } else if cell.flags.contains(CellFlags::WIDE_SPACER) {
+                            col.checked_sub(1)
+                        } else {
+                            None
+                        };
+                        let selected = self.selection.is_some_and(|s| {
+                            s.contains(Point::new(line, col)) || other_half.is_some_and(|half| s.contains(Point::new(line, half)))
+                        });
+                        if self.modes.reverse_video {
+                            self.swap_default_colors(&mut cell);
+                        }
+                        (cell.fg, cell.bg) = resolve_cell_colors(&cell, &palette, selected, false);
+                        cell.flags.remove(CellFlags::REVERSE | CellFlags::DIM);
+                        cell
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+    
+    /// The palette the screen is drawn with: with reverse video on, the default foreground and
+    /// background trade places.
+    pub fn screen_palette(&self) -> Palette {
+        let mut palette = self.palette.clone();
+        if self.modes.reverse_video {
+            std::mem::swap(&mut palette.foreground, &mut palette.background);
+        }
+        palette
+    }
+    
+    /// Gives `cell` the colors reverse video shows it in: the default foreground and background
+    /// trade places, and colors set with SGR stay as they are, even one the same as a default.
+    fn swap_default_colors(&self, cell: &mut Cell) {
+        if !cell.flags.contains(CellFlags::EXPLICIT_FG) {
+            cell.fg = self.palette.background;
+        }
+        if !cell.flags.contains(CellFlags::EXPLICIT_BG) {
+            cell.bg = self.palette.foreground;
+        }
+    }
+    
+    /// The selected text, with lines separated by newlines. A normal selection drops the
+    /// trailing blanks of each line unless `trim_copy_trailing_whitespace` is off, keeping those
+    /// with a background color if `keep_colored_trailing_whitespace` is on; every line of a
+    /// rectangular one is as wide as the rectangle. A double-width character is copied once,
+    /// even when only its second half is selected.
+    pub fn selected_text(&self) -> Option<String> {
+        let selection = self.selection?;
+        let (start, end) = selection.ordered();
+        let width = self.grid.full_width();
+        let lines: Vec<String> = (start.row.max(self.grid.first_line())..=end.row)
+            .filter_map(|line| {
+                let cells = self.grid.line(line)?;
+                let (first, last) = selection.columns(line, width.max(cells.len()))?;
+                let spacer = |col: usize| cells.get(col).is_some_and(|cell| cell.flags.contains(CellFlags::WIDE_SPACER));
+                let first = if spacer(first) { first.saturating_sub(1) } else { first };
+                // Lines from history may be narrower than the grid after a resize
+                let text: String = (first..=last)
+                    .filter(|&col| !spacer(col))
+                    .map(|col| cells.get(col).map_or(' ', |cell| cell.c))
+                    .collect();
+                if selection.mode == SelectionMode::Rectangular || !self.trim_copy_trailing_whitespace {
+                    return Some(text);
+                }
+                // Up to the last cell that isn't blank, or that shows a color of its own
+                let colored = |col: usize| {
+                    self.keep_colored_trailing_whitespace
+                        && cells.get(col).is_some_and(|cell| resolve_cell_colors(cell, &self.palette, false, false).1 != self.palette.background)
+                };
+                let kept = (first..=last).rev().find(|&col| colored(col)).map_or(0, |col| {
+                    (first..=col).filter(|&col| !spacer(col)).count()
+                });
+                let trimmed = text.trim_end();
+                let kept = kept.max(trimmed.chars().count());
+                Some(text.chars().take(kept).collect())
+            })
+            .collect();
+        Some(lines.join("\n"))
+    }
+    
+    fn linefeed(&mut self) {
+        self.cursor.row += 1;
+        if self.cursor.row > self.scroll_region.1 {
+            self.scroll_up(1);
+            self.cursor.row = self.scroll_region.1;
+        }
+    }
+    
+    /// Scrolls the live screen up, dropping whatever part of the selection leaves the
+    /// scrollback with it.
+    fn scroll_up(&mut self, lines: usize) {
+        self.grid.scroll_up(lines);
+        if let Some(selection) = self.selection {
+            self.selection = selection.clamp_to(self.grid.first_line());
+        }
+    }
+    
+    fn place_image(&mut self, image: SixelImage) {
+        self.evict_images_for(image.memory_usage());
+        let rows = image.height.div_ceil(self.cell_height as usize);
+        let cols = image.width.div_ceil(self.cell_width as usize);
+        let col = self.cursor.col;
+        self.grid.images.push(PlacedImage { row: self.cursor.row as isize, col, rows, cols, image: Arc::new(image) });
+        
+        // Continue below the image, scrolling it up if it doesn't fit
+        for _ in 0..rows {
+            self.linefeed();
+        }
+        self.cursor.col = col;
+    }
+    
+    /// Drops the oldest images, those of the screen not shown first, until `incoming` more
+    /// bytes fit within `image_budget`. Scrollback that never ends would otherwise keep every
+    /// image ever shown.
+    fn evict_images_for(&mut self, incoming: usize) {
+        let held = |grid: &Grid| grid.images.iter().map(|image| image.image.memory_usage()).sum::<usize>();
+        let mut memory = held(&self.inactive_grid) + held(&self.grid);
+        for grid in [&mut self.inactive_grid, &mut self.grid] {
+            while memory + incoming > self.image_budget && !grid.images.is_empty() {
+                memory -= grid.images.remove(0).image.memory_usage();
+            }
         }
     }
     
@@ -185,51 +1397,104 @@ impl TerminalPerformer {
             return;
         }
         
+        let width = match self.ambiguous_width {
+            AmbiguousWidth::Narrow => c.width(),
+            AmbiguousWidth::Wide => c.width_cjk(),
+        };
+        let width = if width == Some(2) && self.grid.cols > 1 { 2 } else { 1 };
+        let past_margin = !self.modes.auto_wrap && self.past_margin.take() == Some((self.cursor.row, self.cursor.col));
+        // A double-width character that doesn't fit in the last column goes whole to the next line
+        if width == 2 && self.cursor.col + 1 == self.grid.cols {
+            if self.modes.auto_wrap {
+                self.grid.clear_wide_halves(self.cursor.row, self.cursor.col..self.grid.cols);
+                self.grid.clear_cells(self.cursor.row, self.cursor.col..self.grid.cols);
+                self.wrap_line();
+            } else {
+                self.cursor.col -= 1;
+            }
+        }
+        
         let cell = Cell {
             c,
             fg: self.current_fg,
             bg: self.current_bg,
             flags: self.current_flags,
+            underline: self.current_underline,
+            underline_color: self.current_underline_color,
+        };
+        let cells = if width == 2 {
+            // The spacer keeps the attributes, so backgrounds and decorations span both halves
+            let spacer = Cell { c: ' ', flags: cell.flags | CellFlags::WIDE_SPACER, ..cell.clone() };
+            vec![Cell { flags: cell.flags | CellFlags::WIDE, ..cell }, spacer]
+        } else {
+            vec![cell]
         };
         
-        if self.insert_mode {
-            self.grid.cells[self.cursor.row].insert(self.cursor.col, cell);
-            if self.grid.cells[self.cursor.row].len() > self.grid.cols {
-                self.grid.cells[self.cursor.row].truncate(self.grid.cols);
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        if past_margin {
+            self.grid.overflow_past_margin(row, col..col + width, &cells);
+        } else {
+            self.grid.drop_overflow(row, col..col + width);
+        }
+        if self.modes.insert {
+            // Inserting between the halves of a double-width character splits it
+            if self.grid.cells[row][col].flags.contains(CellFlags::WIDE_SPACER) {
+                self.grid.clear_cells(row, col.saturating_sub(1)..col + 1);
+            }
+            for (i, cell) in cells.into_iter().enumerate() {
+                self.grid.insert_cell(row, col + i, cell);
             }
         } else {
-            self.grid.cells[self.cursor.row][self.cursor.col] = cell;
+            self.grid.clear_wide_halves(row, col..col + width);
+            for (i, cell) in cells.into_iter().enumerate() {
+                self.grid.set_cell(row, col + i, cell);
+            }
         }
         
-        self.cursor.col += 1;
+        self.cursor.col += width;
         
         if self.cursor.col >= self.grid.cols {
-            if self.auto_wrap_mode {
-                self.cursor.col = 0;
-                self.cursor.row += 1;
-                
-                if self.cursor.row > self.scroll_region.1 {
-                    self.grid.scroll_up(1);
-                    self.cursor.row = self.scroll_region.1;
-                }
+            if self.modes.auto_wrap {
+                self.wrap_line();
             } else {
                 self.cursor.col = self.grid.cols - 1;
+                self.past_margin = Some((self.cursor.row, self.cursor.col));
             }
         }
     }
+    
+    /// Moves the cursor to the start of the next line, scrolling at the bottom of the region.
+    fn wrap_line(&mut self) {
+        self.cursor.col = 0;
+        self.cursor.row += 1;
+        
+        if self.cursor.row > self.scroll_region.1 {
+            self.scroll_up(1);
+            self.cursor.row = self.scroll_region.1;
+        }
+    }
 }
 
 impl Perform for TerminalPerformer {
     fn print(&mut self, c: char) {
+        // The parser passes DEL on as printable, but it's padding to be ignored as NUL is
+        if c == '\x7f' {
+            return;
+        }
         self.put_char(c);
     }
     
     fn execute(&mut self, byte: u8) {
         match byte {
-            0x08 => { // Backspace
-                if self.cursor.col > 0 {
-                    self.cursor.col -= 1;
-                }
+            0x00 => {} // Null, padding that takes no cell
+            0x05 => { // Enquiry
+                self.responses.extend_from_slice(self.answerback.as_bytes());
+            }
+            0x07 => { // Bell
+                self.bell = true;
+            }
+            0x08 if self.cursor.col > 0 => { // Backspace
+                self.cursor.col -= 1;
             }
             0x09 => { // Tab
                 self.cursor.col = ((self.cursor.col / 8) + 1) * 8;
@@ -238,11 +1503,7 @@ impl Perform for TerminalPerformer {
                 }
             }
             0x0A => { // Line Feed
-                self.cursor.row += 1;
-                if self.cursor.row > self.scroll_region.1 {
-                    self.grid.scroll_up(1);
-                    self.cursor.row = self.scroll_region.1;
-                }
+                self.linefeed();
             }
             0x0D => { // Carriage Return
                 self.cursor.col = 0;
@@ -251,25 +1512,93 @@ impl Perform for TerminalPerformer {
         }
     }
     
-    fn hook(&mut self, _params: &vte::Params, _intermediates: &[u8], _ignore: bool, _c: char) {
+    fn hook(&mut self, params: &vte::Params, intermediates: &[u8], _ignore: bool, c: char) {
+        self.dcs = match (intermediates, c) {
+            ([], 'q') => { // Sixel graphics
+                let params: Vec<u16> = params.iter().map(|param| param[0]).collect();
+                Some(Dcs::Sixel(SixelDecoder::new(&params, self.palette.background)))
+            }
+            ([b'$'], 'q') => Some(Dcs::RequestStatus(Vec::new())), // DECRQSS
+            _ => None,
+        };
     }
     
-    fn put(&mut self, _byte: u8) {
+    fn put(&mut self, byte: u8) {
+        match &mut self.dcs {
+            Some(Dcs::Sixel(sixel)) => sixel.put(byte),
+            Some(Dcs::RequestStatus(request)) if request.len() < MAX_STATUS_REQUEST => request.push(byte),
+            _ => {}
+        }
     }
     
     fn unhook(&mut self) {
+        match self.dcs.take() {
+            Some(Dcs::Sixel(sixel)) => {
+                if let Some(image) = sixel.finish() {
+                    self.place_image(image);
+                }
+            }
+            Some(Dcs::RequestStatus(request)) => self.report_status(&request),
+            None => {}
+        }
     }
     
     fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
-        if params.len() >= 2 && params[0] == b"0" {
-            if let Ok(title) = std::str::from_utf8(params[1]) {
-                self.title = title.to_string();
+        if params.len() < 2 {
+            return;
+        }
+        // The parser splits the text at semicolons, and invalid UTF-8 shouldn't cost the
+        // whole title
+        let text = params[1..].join(&b';');
+        let text = String::from_utf8_lossy(&text).into_owned();
+        match params[0] {
+            b"0" | b"2" => self.set_title(text), // Icon name and title, or just the title
+            b"133" if !self.modes.alt_screen => { // Shell integration marks
+                let kind = match params[1] {
+                    b"A" => MarkKind::Prompt,
+                    b"B" => MarkKind::Command,
+                    b"C" => MarkKind::Output,
+                    b"D" => {
+                        let status = params.get(2).and_then(|status| std::str::from_utf8(status).ok()?.parse().ok());
+                        MarkKind::Finished(status)
+                    }
+                    _ => return,
+                };
+                self.grid.add_mark(self.cursor.row, kind);
+            }
+            b"7" => { // Working directory, as file://host/path
+                let cwd = cwd_from_url(&text);
+                if cwd.is_none() {
+                    log::debug!("Ignoring working directory {:?}", text);
+                } else if cwd != self.cwd {
+                    self.cwd = cwd;
+                    self.title_changed = true;
+                }
             }
+            _ => {}
         }
     }
     
-    fn csi_dispatch(&mut self, params: &vte::Params, _intermediates: &[u8], _ignore: bool, c: char) {
+    fn csi_dispatch(&mut self, params: &vte::Params, intermediates: &[u8], _ignore: bool, c: char) {
         match c {
+            'h' | 'l' if intermediates.first() == Some(&b'?') => { // DEC private mode set/reset
+                for param in params.iter() {
+                    self.set_private_mode(param[0], c == 'h');
+                }
+            }
+            'h' | 'l' if intermediates.is_empty() => { // ANSI mode set/reset
+                for param in params.iter() {
+                    match Mode::from_ansi(param[0]) {
+                        Some(mode) => self.set_mode(mode, c == 'h'),
+                        None => log::debug!("Unhandled mode {} ({})", param[0], c == 'h'),
+                    }
+                }
+            }
+            'p' if intermediates == [b'?', b'$'] || intermediates == [b'$'] => { // DECRQM
+                if let Some(param) = params.iter().next() {
+                    self.report_mode(param[0], intermediates[0] == b'?');
+                }
+            }
             'A' => { // Cursor Up
                 let n = params.iter().next().unwrap_or(&[1])[0].max(1) as usize;
                 self.cursor.row = self.cursor.row.saturating_sub(n);
@@ -290,31 +1619,74 @@ impl Perform for TerminalPerformer {
                 let mut iter = params.iter();
                 let row = iter.next().unwrap_or(&[1])[0].max(1) as usize - 1;
                 let col = iter.next().unwrap_or(&[1])[0].max(1) as usize - 1;
-                self.cursor.row = row.min(self.grid.rows - 1);
+                self.cursor.row = if self.modes.origin {
+                    (self.scroll_region.0 + row).min(self.scroll_region.1)
+                } else {
+                    row.min(self.grid.rows - 1)
+                };
                 self.cursor.col = col.min(self.grid.cols - 1);
             }
+            'J' if intermediates == [b'?'] => { // Selective Erase in Display (DECSED)
+                let n = params.iter().next().unwrap_or(&[0])[0];
+                let (row, col) = (self.cursor.row, self.cursor.col);
+                let (cols, rows) = (self.grid.cols, self.grid.rows);
+                match n {
+                    0 => {
+                        self.grid.erase_unprotected(row, col..cols);
+                        for row in (row + 1)..rows {
+                            self.grid.erase_unprotected(row, 0..cols);
+                        }
+                    }
+                    1 => {
+                        for row in 0..row {
+                            self.grid.erase_unprotected(row, 0..cols);
+                        }
+                        self.grid.erase_unprotected(row, 0..col + 1);
+                    }
+                    2 => {
+                        for row in 0..rows {
+                            self.grid.erase_unprotected(row, 0..cols);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            'K' if intermediates == [b'?'] => { // Selective Erase in Line (DECSEL)
+                let n = params.iter().next().unwrap_or(&[0])[0];
+                let cols = match n {
+                    0 => self.cursor.col..self.grid.cols,
+                    1 => 0..self.cursor.col + 1,
+                    2 => 0..self.grid.cols,
+                    _ => return,
+                };
+                self.grid.erase_unprotected(self.cursor.row, cols);
+            }
             'J' => { // Erase in Display
                 let n = params.iter().next().unwrap_or(&[0])[0];
                 match n {
                     0 => { // Clear from cursor to end of screen
-                        for col in self.cursor.col..self.grid.cols {
-                            self.grid.cells[self.cursor.row][col] = Cell::default();
-                        }
+                        self.grid.evict_images(self.cursor.row..self.grid.rows);
+                        self.grid.clear_cells(self.cursor.row, self.cursor.col..self.grid.cols);
                         for row in (self.cursor.row + 1)..self.grid.rows {
                             self.grid.clear_line(row);
                         }
                     }
                     1 => { // Clear from beginning of screen to cursor
+                        self.grid.evict_images(0..self.cursor.row + 1);
                         for row in 0..self.cursor.row {
                             self.grid.clear_line(row);
                         }
-                        for col in 0..=self.cursor.col {
-                            self.grid.cells[self.cursor.row][col] = Cell::default();
-                        }
+                        self.grid.clear_cells(self.cursor.row, 0..self.cursor.col + 1);
                     }
                     2 => { // Clear entire screen
                         self.grid.clear();
                     }
+                    3 => { // Clear the scrollback, leaving the screen alone as xterm does
+                        self.grid.clear_scrollback();
+                        if let Some(selection) = self.selection {
+                            self.selection = selection.clamp_to(self.grid.first_line());
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -322,14 +1694,10 @@ impl Perform for TerminalPerformer {
                 let n = params.iter().next().unwrap_or(&[0])[0];
                 match n {
                     0 => { // Clear from cursor to end of line
-                        for col in self.cursor.col..self.grid.cols {
-                            self.grid.cells[self.cursor.row][col] = Cell::default();
-                        }
+                        self.grid.clear_cells(self.cursor.row, self.cursor.col..self.grid.cols);
                     }
                     1 => { // Clear from beginning of line to cursor
-                        for col in 0..=self.cursor.col {
-                            self.grid.cells[self.cursor.row][col] = Cell::default();
-                        }
+                        self.grid.clear_cells(self.cursor.row, 0..self.cursor.col + 1);
                     }
                     2 => { // Clear entire line
                         self.grid.clear_line(self.cursor.row);
@@ -338,36 +1706,120 @@ impl Perform for TerminalPerformer {
                 }
             }
             'm' => { // Set Graphics Rendition
-                for param in params.iter() {
-                    for &value in param {
-                        match value {
-                            0 => { // Reset
-                                self.current_fg = self.default_fg;
-                                self.current_bg = self.default_bg;
-                                self.current_flags = CellFlags::empty();
-                            }
-                            1 => self.current_flags.insert(CellFlags::BOLD),
-                            2 => self.current_flags.insert(CellFlags::DIM),
-                            3 => self.current_flags.insert(CellFlags::ITALIC),
-                            4 => self.current_flags.insert(CellFlags::UNDERLINE),
-                            7 => self.current_flags.insert(CellFlags::REVERSE),
-                            22 => self.current_flags.remove(CellFlags::BOLD | CellFlags::DIM),
-                            23 => self.current_flags.remove(CellFlags::ITALIC),
-                            24 => self.current_flags.remove(CellFlags::UNDERLINE),
-                            27 => self.current_flags.remove(CellFlags::REVERSE),
-                            30..=37 => {
-                                let _color_index = (value - 30) as usize;
-                                // Use default colors for now, proper color handling would go here
-                                self.current_fg = self.default_fg;
-                            }
-                            40..=47 => {
-                                let _color_index = (value - 40) as usize;
-                                // Use default colors for now, proper color handling would go here
-                                self.current_bg = self.default_bg;
+                // An empty sequence is a reset
+                if params.is_empty() {
+                    self.reset_sgr();
+                }
+                let mut params = params.iter().peekable();
+                while let Some(param) = params.next() {
+                    let value = param[0];
+                    match value {
+                        // Extended colors first, as their values would read as other attributes
+                        38 | 48 | 58 => { // As 38:2::r:g:b, 38;2;r;g;b, 38:5:n or 38;5;n
+                            let values: Vec<u16> = if param.len() > 1 {
+                                param[1..].to_vec()
+                            } else {
+                                // Separate parameters: take as many as the form needs
+                                let count = match params.peek().map(|p| p[0]) {
+                                    Some(2) => 4,
+                                    Some(5) => 2,
+                                    _ => 0,
+                                };
+                                params.by_ref().take(count).map(|p| p[0]).collect()
+                            };
+                            let Some(color) = self.sgr_extended_color(&values) else {
+                                continue;
+                            };
+                            match value {
+                                38 => self.set_fg(Some(color)),
+                                48 => self.set_bg(Some(color)),
+                                _ => self.current_underline_color = Some(color),
                             }
-                            _ => {}
+                        }
+                        0 => self.reset_sgr(),
+                        1 => self.current_flags.insert(CellFlags::BOLD),
+                        2 => self.current_flags.insert(CellFlags::DIM),
+                        3 => self.current_flags.insert(CellFlags::ITALIC),
+                        // Rapid blink is rare enough to share the slow rate
+                        5 | 6 => self.current_flags.insert(CellFlags::BLINK),
+                        4 => { // Underline, with the style as a subparameter
+                            let style = match param.get(1).copied().unwrap_or(1) {
+                                0 => None,
+                                2 => Some(UnderlineStyle::Double),
+                                3 => Some(UnderlineStyle::Curly),
+                                4 => Some(UnderlineStyle::Dotted),
+                                5 => Some(UnderlineStyle::Dashed),
+                                _ => Some(UnderlineStyle::Single),
+                            };
+                            self.current_flags.set(CellFlags::UNDERLINE, style.is_some());
+                            self.current_underline = style.unwrap_or_default();
+                        }
+                        7 => self.current_flags.insert(CellFlags::REVERSE),
+                        8 => self.current_flags.insert(CellFlags::HIDDEN),
+                        9 => self.current_flags.insert(CellFlags::STRIKETHROUGH),
+                        21 => { // Double underline
+                            self.current_flags.insert(CellFlags::UNDERLINE);
+                            self.current_underline = UnderlineStyle::Double;
+                        }
+                        22 => self.current_flags.remove(CellFlags::BOLD | CellFlags::DIM),
+                        23 => self.current_flags.remove(CellFlags::ITALIC),
+                        24 => self.current_flags.remove(CellFlags::UNDERLINE),
+                        25 => self.current_flags.remove(CellFlags::BLINK),
+                        27 => self.current_flags.remove(CellFlags::REVERSE),
+                        28 => self.current_flags.remove(CellFlags::HIDDEN),
+                        29 => self.current_flags.remove(CellFlags::STRIKETHROUGH),
+                        30..=37 => self.set_fg(Some(self.palette.normal[(value - 30) as usize])),
+                        39 => self.set_fg(None),
+                        40..=47 => self.set_bg(Some(self.palette.normal[(value - 40) as usize])),
+                        49 => self.set_bg(None),
+                        59 => self.current_underline_color = None,
+                        90..=97 => self.set_fg(Some(self.palette.bright[(value - 90) as usize])),
+                        100..=107 => self.set_bg(Some(self.palette.bright[(value - 100) as usize])),
+                        _ => {}
+                    }
+                }
+            }
+            // Primary Device Attributes (DA1): a VT220 with sixel graphics and ANSI color
+            'c' if intermediates.is_empty() && params.iter().next().is_none_or(|param| param[0] == 0) => {
+                self.responses.extend_from_slice(b"\x1b[?62;4;22c");
+            }
+            'n' if intermediates.is_empty() || intermediates == [b'?'] => { // Device status report (DSR)
+                let n = params.iter().next().unwrap_or(&[0])[0];
+                self.report_status_n(n, !intermediates.is_empty());
+            }
+            'q' if intermediates == [b'"'] => { // Select character protection attribute (DECSCA)
+                let n = params.iter().next().unwrap_or(&[0])[0];
+                self.current_flags.set(CellFlags::PROTECTED, n == 1);
+            }
+            'q' if intermediates == [b' '] => { // Set cursor style (DECSCUSR)
+                let n = params.iter().next().unwrap_or(&[0])[0];
+                let (shape, blink) = match n {
+                    0 | 1 => (CursorShape::Block, true),
+                    2 => (CursorShape::Block, false),
+                    3 => (CursorShape::Underline, true),
+                    4 => (CursorShape::Underline, false),
+                    5 => (CursorShape::Beam, true),
+                    6 => (CursorShape::Beam, false),
+                    _ => return,
+                };
+                self.cursor.shape = shape;
+                self.cursor.blink = blink;
+            }
+            't' => { // Window manipulation
+                let n = params.iter().next().unwrap_or(&[0])[0];
+                match n {
+                    22 => { // Save the title, dropping the oldest past xterm's limit
+                        if self.title_stack.len() == TITLE_STACK_DEPTH {
+                            self.title_stack.remove(0);
+                        }
+                        self.title_stack.push(self.title.clone());
+                    }
+                    23 => { // Restore the last title saved
+                        if let Some(title) = self.title_stack.pop() {
+                            self.set_title(title);
                         }
                     }
+                    _ => self.report_window_size(n),
                 }
             }
             _ => {}
@@ -380,7 +1832,10 @@ impl Perform for TerminalPerformer {
 
 impl Terminal {
     pub fn new(config: &Config) -> Result<Self> {
-        let pty = Pty::new()?;
+        let mut pty = Pty::new()?;
+        if let Some(path) = &config.terminal.typescript {
+            pty.set_typescript(Typescript::create(path, config.terminal.typescript_input)?);
+        }
         let parser = Parser::new();
         let performer = TerminalPerformer::new(24, 80, config); // Default size
         
@@ -391,6 +1846,17 @@ impl Terminal {
             pty,
             parser,
             performer,
+            padding: config.display.padding,
+            dynamic_padding: config.display.dynamic_padding,
+            grid_padding: config.display.padding,
+            winsize: Throttle::new(WINSIZE_INTERVAL),
+            decoder: OutputDecoder::new(config.terminal.eight_bit_controls),
+            read_chunk_bytes: config.terminal.read_chunk_bytes,
+            backspace_sends: config.terminal.backspace_sends,
+            parse_time: std::time::Duration::ZERO,
+            output_arrival: None,
+            selection_start: None,
+            startup_command: None,
             output_receiver,
             input_sender,
         })
@@ -400,73 +1866,640 @@ impl Terminal {
         let shell = config.terminal.shell.as_deref();
         let working_dir = config.terminal.working_directory.as_ref().and_then(|p| p.to_str());
         
-        self.pty.spawn_shell(shell, working_dir).await?;
+        self.pty.spawn_shell(shell, working_dir, &config.terminal.term).await?;
+        self.startup_command = config.terminal.startup_command.as_ref()
+            .map(|command| text_to_bytes(&format!("{}\n", command), false));
         Ok(())
     }
     
+    /// Runs `command`, a program and its arguments, in place of the shell.
+    pub async fn start_command(&mut self, config: &Config, command: &[String]) -> Result<()> {
+        let Some((program, args)) = command.split_first() else {
+            return self.start_shell(config).await;
+        };
+        let working_dir = config.terminal.working_directory.as_ref().and_then(|p| p.to_str());
+        self.pty.spawn(program, args, working_dir, &config.terminal.term).await
+    }
+    
+    /// Types `terminal.startup_command` into the shell, if it hasn't been yet.
+    async fn send_startup_command(&mut self) -> Result<()> {
+        let Some(command) = self.startup_command.take() else {
+            return Ok(());
+        };
+        log::debug!("Sending the startup command");
+        self.pty.write(&command).await
+    }
+    
     pub async fn write_to_pty(&mut self, data: &[u8]) -> Result<()> {
         self.pty.write(data).await
     }
     
+    /// Whether a program other than the shell runs in the foreground.
+    pub fn has_foreground_job(&self) -> bool {
+        self.pty.has_foreground_job()
+    }
+    
+    /// The terminal's foreground process group, the shell's own at its prompt.
+    pub fn foreground_process_group(&self) -> Option<nix::unistd::Pid> {
+        self.pty.foreground_process_group()
+    }
+    
+    /// Command name of the process in the foreground, which is the shell at its prompt.
+    pub fn foreground_process(&self) -> Option<String> {
+        self.pty.foreground_process_name()
+    }
+    
+    /// Fits the grid to a window content size of `width`x`height`, as `Display::resize` does.
+    #[allow(dead_code)]
     pub fn resize(&mut self, width: u32, height: u32) -> Result<()> {
-        let cols = (width / 8).max(1) as u16; // Rough estimation
-        let rows = (height / 16).max(1) as u16; // Rough estimation
-        
-        self.pty.resize(cols, rows)?;
-        self.performer.grid.resize(rows as usize, cols as usize);
+        self.set_geometry(grid_geometry((width, height), self.cell_size(), self.padding, self.dynamic_padding))
+    }
+    
+    /// Resizes the grid to `geometry`, and the PTY's window size too unless it was resized
+    /// moments ago, in which case it follows once `winsize_deadline` passes.
+    pub fn set_geometry(&mut self, geometry: GridGeometry) -> Result<()> {
+        let GridGeometry { cols, rows, padding } = geometry;
+        self.grid_padding = padding;
+        if let Some((cols, rows)) = self.winsize.update((cols, rows), std::time::Instant::now()) {
+            self.pty.resize(cols, rows)?;
+        }
+        self.performer.resize(rows as usize, cols as usize);
         
         Ok(())
     }
     
-    #[allow(dead_code)]
-    pub fn handle_key(&mut self, _key: crate::input::Key) -> Result<()> {
-        // Key handling implementation would go here
+    /// When the PTY's window size held back by `set_geometry` is due.
+    pub fn winsize_deadline(&self) -> Option<std::time::Instant> {
+        self.winsize.deadline()
+    }
+    
+    /// Gives the PTY the window size held back, if it's due.
+    pub fn flush_winsize(&mut self) -> Result<()> {
+        if let Some((cols, rows)) = self.winsize.flush(std::time::Instant::now()) {
+            self.pty.resize(cols, rows)?;
+        }
         Ok(())
     }
     
+    /// Estimates the cell size for a font of `points`, for when no font is loaded; callers
+    /// resize afterwards.
+    #[allow(dead_code)]
+    pub fn set_font_size(&mut self, points: f32) {
+        self.set_cell_size(cell_size_for(points));
+    }
+    
+    /// Sets the surface-local cell size measured from the font; callers resize afterwards.
+    pub fn set_cell_size(&mut self, (width, height): (u32, u32)) {
+        self.performer.cell_width = width.max(1);
+        self.performer.cell_height = height.max(1);
+    }
+    
+    pub fn cell_size(&self) -> (u32, u32) {
+        (self.performer.cell_width, self.performer.cell_height)
+    }
+    
+    /// The cell under a surface-local position measured from below any titlebar, clamped to
+    /// the grid.
+    pub fn point_at(&self, (x, y): (f64, f64)) -> Point {
+        let (cell_width, cell_height) = self.cell_size();
+        let cell = |offset: f64, padding: u32, size: u32, count: usize| {
+            let index = ((offset - padding as f64) / size as f64).max(0.0) as usize;
+            index.min(count.saturating_sub(1))
+        };
+        let grid = &self.performer.grid;
+        Point::new(
+            cell(y, self.grid_padding.top, cell_height, grid.rows),
+            cell(x, self.grid_padding.left, cell_width, grid.cols),
+        )
+    }
+    
+    /// Starts a selection at the viewport cell `point`, dropping the current one. Nothing is
+    /// selected until the pointer moves.
+    pub fn begin_selection(&mut self, point: Point, mode: SelectionMode) {
+        self.performer.selection = None;
+        let grid = &self.performer.grid;
+        let anchor = Point::new(grid.absolute_line(point.row), point.col + grid.col_offset);
+        self.selection_start = Some((anchor, mode));
+    }
+    
+    /// Extends the selection being dragged out to the viewport cell `point`.
+    pub fn extend_selection(&mut self, point: Point) {
+        if let Some((anchor, mode)) = self.selection_start {
+            let grid = &self.performer.grid;
+            let head = Point::new(grid.absolute_line(point.row), point.col + grid.col_offset);
+            self.performer.selection = Some(Selection { anchor, head, mode });
+        }
+    }
+    
+    /// Finishes dragging, keeping the selection.
+    pub fn end_selection(&mut self) {
+        self.selection_start = None;
+    }
+    
+    pub fn selected_text(&self) -> Option<String> {
+        self.performer.selected_text()
+    }
+    
+    /// Applies settings that can change while running: colors, cursor, answerback, padding and a
+    /// larger scrollback.
+    ///
+    /// Callers resize afterwards so padding changes take effect.
+    pub fn apply_config(&mut self, config: &Config) {
+        let performer = &mut self.performer;
+        let palette = Palette::from_config(&config.colors);
+        
+        // Keep text that was written in the default colors following the defaults
+        if !performer.current_flags.contains(CellFlags::EXPLICIT_FG) {
+            performer.current_fg = palette.foreground;
+        }
+        if !performer.current_flags.contains(CellFlags::EXPLICIT_BG) {
+            performer.current_bg = palette.background;
+        }
+        performer.default_fg = palette.foreground;
+        performer.default_bg = palette.background;
+        performer.palette = palette;
+        let config_cursor = (config.terminal.cursor_shape.clone(), config.terminal.cursor_blink);
+        if config_cursor != performer.config_cursor {
+            (performer.cursor.shape, performer.cursor.blink) = config_cursor.clone();
+            performer.config_cursor = config_cursor;
+        }
+        performer.answerback = config.terminal.answerback.clone();
+        performer.trim_copy_trailing_whitespace = config.terminal.trim_copy_trailing_whitespace;
+        performer.keep_colored_trailing_whitespace = config.terminal.keep_colored_trailing_whitespace;
+        performer.ambiguous_width = config.terminal.ambiguous_width;
+        performer.unfocused_dim = config.display.unfocused_dim;
+        performer.set_focused(performer.focused);
+        self.decoder.eight_bit_controls = config.terminal.eight_bit_controls;
+        self.read_chunk_bytes = config.terminal.read_chunk_bytes;
+        self.backspace_sends = config.terminal.backspace_sends;
+        
+        let limit = |l: Option<usize>| l.unwrap_or(usize::MAX);
+        let grid = if performer.modes.alt_screen { &mut performer.inactive_grid } else { &mut performer.grid };
+        if limit(config.terminal.scrollback_lines) > limit(grid.scrollback_limit) {
+            grid.scrollback_limit = config.terminal.scrollback_lines;
+        }
+        
+        self.padding = config.display.padding;
+        self.dynamic_padding = config.display.dynamic_padding;
+    }
+    
+    /// The modes programs have set, such as whether a synchronized update is in progress,
+    /// during which the screen isn't drawn.
+    pub fn modes(&self) -> TerminalModes {
+        self.performer.modes
+    }
+    
+    /// Switches a mode as the program's escape sequence for it would.
+    pub fn set_mode(&mut self, mode: Mode, enable: bool) {
+        self.performer.set_mode(mode, enable);
+    }
+    
+    /// Ends a synchronized update the program didn't end itself.
+    pub fn end_synchronized_output(&mut self) {
+        self.set_mode(Mode::SynchronizedOutput, false);
+    }
+    
+    /// Terminal state used to evaluate keybinding mode guards.
+    pub fn binding_mode(&self) -> BindingMode {
+        let mut mode = BindingMode::empty();
+        if self.performer.modes.alt_screen {
+            mode.insert(BindingMode::ALT_SCREEN);
+        }
+        if self.performer.selection.is_some() {
+            mode.insert(BindingMode::SELECTION);
+        }
+        if self.performer.modes.application_cursor {
+            mode.insert(BindingMode::APP_CURSOR);
+        }
+        mode
+    }
+    
+    pub fn scroll_view_page_up(&mut self) {
+        let rows = self.performer.grid.rows;
+        self.performer.grid.scroll_view_up(rows);
+    }
+    
+    pub fn scroll_view_page_down(&mut self) {
+        let rows = self.performer.grid.rows;
+        self.performer.grid.scroll_view_down(rows);
+    }
+    
+    pub fn scroll_view_to_bottom(&mut self) {
+        self.performer.grid.scroll_view_to_bottom();
+    }
+    
+    /// Pans the view half a screen right, or left, over lines printed past the right margin
+    /// with auto-wrap off.
+    pub fn pan_view(&mut self, right: bool) {
+        let grid = &mut self.performer.grid;
+        let half = (grid.cols / 2).max(1);
+        if right {
+            if grid.full_width() == grid.cols {
+                log::debug!("No line goes past the right margin to pan to");
+                return;
+            }
+            grid.scroll_view_right(half);
+        } else {
+            grid.scroll_view_left(half);
+        }
+    }
+    
+    pub fn scroll_view_lines(&mut self, lines: isize) {
+        if lines >= 0 {
+            self.performer.grid.scroll_view_up(lines as usize);
+        } else {
+            self.performer.grid.scroll_view_down(lines.unsigned_abs());
+        }
+    }
+    
+    pub fn scroll_view_to_top(&mut self) {
+        let grid = &mut self.performer.grid;
+        grid.scroll_view_up(grid.scrollback.len());
+    }
+    
+    pub fn scroll_view_to_line(&mut self, line: usize) {
+        self.performer.grid.scroll_view_to_line(line);
+    }
+    
+    /// Absolute lines where the shell marked a prompt with OSC 133, oldest first.
+    pub fn prompt_marks(&self) -> Vec<usize> {
+        self.performer.grid.marks()
+            .filter(|mark| mark.kind == MarkKind::Prompt)
+            .map(|mark| mark.line)
+            .collect()
+    }
+    
+    /// Clears the screen and the scrollback locally, for the clear-all action.
+    pub fn clear_all(&mut self) {
+        self.performer.clear_all();
+    }
+    
+    /// Scrolls the view to put the prompt above the top of the view at the top, or with
+    /// `older` false the one below it, going back to the live screen past the last prompt.
+    pub fn jump_to_prompt(&mut self, older: bool) {
+        let top = self.performer.grid.absolute_line(0);
+        let marks = self.prompt_marks();
+        if marks.is_empty() {
+            log::debug!("No prompts marked to jump to; the shell needs to send OSC 133");
+            return;
+        }
+        let target = if older {
+            marks.into_iter().rev().find(|&line| line < top)
+        } else {
+            marks.into_iter().find(|&line| line > top)
+        };
+        match target {
+            Some(line) => self.performer.grid.scroll_view_to_top_line(line),
+            None if !older => self.performer.grid.scroll_view_to_bottom(),
+            None => {}
+        }
+    }
+    
+    /// See `TerminalPerformer::set_focused`.
+    pub fn set_focused(&mut self, focused: bool) {
+        self.performer.set_focused(focused);
+    }
+    
+    /// See `TerminalPerformer::damage_generation`.
+    pub fn damage_generation(&mut self) -> u64 {
+        self.performer.damage_generation()
+    }
+    
+    /// Sends the bytes for a key press that is not bound to an action, returning whether it
+    /// sent any.
+    pub async fn handle_key(&mut self, key: &Key) -> Result<bool> {
+        let bytes = key.to_bytes_with(self.binding_mode(), self.backspace_sends);
+        if bytes.is_empty() {
+            return Ok(false);
+        }
+        self.pty.write(&bytes).await?;
+        Ok(true)
+    }
+    
+    /// Types `key` as if pressed, e.g. for scripting the program in the terminal.
+    ///
+    /// Cursor keys follow application cursor mode as the program set it. Returns once the bytes
+    /// have been written to the PTY.
+    #[allow(dead_code)]
+    pub async fn send_key(&mut self, key: Key) -> Result<()> {
+        self.handle_key(&key).await.map(|_| ())
+    }
+    
+    /// Pastes `text`, with newlines as carriage returns and in paste markers if the program
+    /// enabled bracketed paste. It goes in as the program reads it while output is read, so
+    /// a large paste doesn't hold up the terminal.
+    pub fn paste(&mut self, text: &str) {
+        self.pty.queue_write(&text_to_bytes(text, self.performer.modes.bracketed_paste));
+    }
+    
+    /// Sends `text` as if pasted, with newlines as carriage returns.
+    ///
+    /// When the program enabled bracketed paste the text is wrapped in paste markers, so shells
+    /// insert it rather than run it; follow with `send_key` of Enter to run a command line.
+    /// Returns once the bytes have been written to the PTY.
+    #[allow(dead_code)]
+    pub async fn send_text(&mut self, text: &str) -> Result<()> {
+        let bytes = text_to_bytes(text, self.performer.modes.bracketed_paste);
+        self.pty.write(&bytes).await
+    }
+    
+    /// Waits for program output and parses it along with whatever else is ready, up to
+    /// `terminal.read_chunk_bytes`, returning what was read or `None` once the program is gone.
     pub async fn next_output(&mut self) -> Result<Option<Vec<u8>>> {
-        let mut buf = vec![0u8; 4096];
+        let budget = self.read_chunk_bytes.max(1);
+        let mut buf = vec![0u8; budget.min(4096)];
         
-        // Use timeout to avoid blocking forever
-        match tokio::time::timeout(std::time::Duration::from_millis(100), self.pty.read(&mut buf)).await {
-            Ok(Ok(n)) if n > 0 => {
-                buf.truncate(n);
+        // The startup command waits for the shell's first output, normally its prompt, as a
+        // shell may drop input typed while it starts; a shell that stays quiet gets it anyway
+        let read = if self.startup_command.is_some() {
+            match tokio::time::timeout(STARTUP_COMMAND_DELAY, self.pty.read(&mut buf)).await {
+                Ok(read) => read,
+                Err(_) => {
+                    self.send_startup_command().await?;
+                    self.pty.read(&mut buf).await
+                }
+            }
+        } else {
+            self.pty.read(&mut buf).await
+        };
+        
+        match read {
+            Ok(n) if n > 0 => {
+                let mut output = buf[..n].to_vec();
+                // Take what's already waiting, leaving the end of the output for the next call
+                while output.len() < budget {
+                    let want = (budget - output.len()).min(buf.len());
+                    match self.pty.try_read(&mut buf[..want]) {
+                        Ok(Some(n)) if n > 0 => output.extend_from_slice(&buf[..n]),
+                        // The next call finds out whether the program has gone
+                        _ => break,
+                    }
+                }
                 
-                // Parse the output through VTE
-                for &byte in &buf {
-                    self.parser.advance(&mut self.performer, byte);
+                self.process(&output);
+                let responses = self.drain_responses();
+                if !responses.is_empty() {
+                    self.pty.write(&responses).await?;
                 }
+                self.send_startup_command().await?;
                 
-                log::debug!("Read {} bytes from PTY", n);
-                Ok(Some(buf))
+                log::debug!("Read {} bytes from PTY", output.len());
+                Ok(Some(output))
             }
-            Ok(Ok(_)) => {
-                // Read 0 bytes, PTY might be closed
+            Ok(_) => {
                 log::debug!("PTY read returned 0 bytes");
                 Ok(None)
             }
-            Ok(Err(e)) => {
+            Err(e) => {
+                // Linux reports EIO once the child side of the PTY is closed
                 log::debug!("PTY read error: {}", e);
                 Ok(None)
             }
-            Err(_) => {
-                // Timeout - no data available
-                Ok(None)
-            }
         }
     }
     
+    /// Parses `output` as if the program had written it, leaving any replies it asks for to
+    /// `drain_responses`.
+    pub fn process(&mut self, output: &[u8]) {
+        let started = std::time::Instant::now();
+        self.output_arrival.get_or_insert(started);
+        for &byte in output {
+            let (parser, performer) = (&mut self.parser, &mut self.performer);
+            self.decoder.push(byte, |byte| parser.advance(performer, byte));
+        }
+        self.parse_time += started.elapsed();
+    }
+    
+    /// Takes the replies to queries in the output processed so far, such as DSR and DA, which
+    /// `next_output` writes back to the program.
+    pub fn drain_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.performer.responses)
+    }
+    
+    /// Returns when the oldest output processed since the last call arrived, if any was.
+    pub fn take_output_arrival(&mut self) -> Option<std::time::Instant> {
+        self.output_arrival.take()
+    }
+    
+    /// Returns the time spent parsing output since the last call.
+    pub fn take_parse_time(&mut self) -> std::time::Duration {
+        std::mem::take(&mut self.parse_time)
+    }
+    
+    /// Returns whether a bell was rung since the last call.
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.performer.bell)
+    }
+    
+    /// Returns whether the title changed since the last call.
+    pub fn take_title_changed(&mut self) -> bool {
+        std::mem::take(&mut self.performer.title_changed)
+    }
+    
     pub fn grid(&self) -> &Grid {
         &self.performer.grid
     }
     
     #[allow(dead_code)]
+    pub fn palette(&self) -> &Palette {
+        &self.performer.palette
+    }
+    
+    /// See `TerminalPerformer::screen_palette`.
+    pub fn screen_palette(&self) -> Palette {
+        self.performer.screen_palette()
+    }
+    
     pub fn cursor(&self) -> &Cursor {
         &self.performer.cursor
     }
     
+    /// See `TerminalPerformer::styled_snapshot`.
+    pub fn styled_snapshot(&self) -> Vec<Vec<Cell>> {
+        self.performer.styled_snapshot()
+    }
+    
+    /// Padding around the grid as laid out in the window, centering included.
+    pub fn grid_padding(&self) -> Padding {
+        self.grid_padding
+    }
+    
     #[allow(dead_code)]
     pub fn title(&self) -> &str {
         &self.performer.title
     }
+    
+    /// The working directory the shell last reported.
+    pub fn cwd(&self) -> Option<&str> {
+        self.performer.cwd.as_deref()
+    }
+    
+    /// Sixel images on the screen shown and in its scrollback.
+    pub fn images(&self) -> &[PlacedImage] {
+        &self.performer.grid.images
+    }
+    
+    /// Returns the live-screen cells changed since `token`, for streaming the screen elsewhere.
+    #[allow(dead_code)]
+    pub fn diff_since(&self, token: DiffToken) -> (GridDiff, DiffToken) {
+        self.performer.grid.diff_since(token)
+    }
+}
+
+/// Cleans up program output for the parser, which drops invalid UTF-8 along with whatever
+/// byte follows it, even an `ESC`.
+///
+/// Invalid and truncated UTF-8 sequences become U+FFFD, with the byte that cut a sequence short
+/// then handled on its own. With `eight_bit_controls`, the bytes 0x80 to 0x9F outside of a
+/// UTF-8 sequence are C1 controls instead, rewritten to the 7-bit `ESC` forms the parser
+/// understands: 0x9B becomes `ESC [`, 0x9D `ESC ]` and so on.
+pub struct OutputDecoder {
+    pub eight_bit_controls: bool,
+    /// The UTF-8 sequence received so far.
+    pending: [u8; 4],
+    len: usize,
+    /// Length of the sequence in `pending`, from its first byte.
+    expected: usize,
+}
+
+const REPLACEMENT_CHARACTER: &[u8] = "\u{fffd}".as_bytes();
+
+impl OutputDecoder {
+    pub fn new(eight_bit_controls: bool) -> Self {
+        Self { eight_bit_controls, pending: [0; 4], len: 0, expected: 0 }
+    }
+    
+    /// Passes `byte` on to `emit`, or what replaces it; the bytes of a UTF-8 character are held
+    /// back until it is complete.
+    pub fn push(&mut self, byte: u8, mut emit: impl FnMut(u8)) {
+        if self.len > 0 {
+            // The second byte is restricted further, ruling out overlong forms, surrogates and
+            // code points past U+10FFFF
+            let continues = match (self.len, self.pending[0]) {
+                (1, 0xe0) => (0xa0..=0xbf).contains(&byte),
+                (1, 0xed) => (0x80..=0x9f).contains(&byte),
+                (1, 0xf0) => (0x90..=0xbf).contains(&byte),
+                (1, 0xf4) => (0x80..=0x8f).contains(&byte),
+                _ => (0x80..=0xbf).contains(&byte),
+            };
+            if continues {
+                self.pending[self.len] = byte;
+                self.len += 1;
+                if self.len == self.expected {
+                    self.pending[..self.len].iter().for_each(|&byte| emit(byte));
+                    self.len = 0;
+                }
+                return;
+            }
+            self.len = 0;
+            REPLACEMENT_CHARACTER.iter().for_each(|&byte| emit(byte));
+        }
+        
+        self.expected = match byte {
+            0x00..=0x7f => return emit(byte),
+            0x80..=0x9f if self.eight_bit_controls => {
+                emit(0x1b);
+                return emit(byte - 0x40);
+            }
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => return REPLACEMENT_CHARACTER.iter().for_each(|&byte| emit(byte)),
+        };
+        self.pending[0] = byte;
+        self.len = 1;
+    }
+}
+
+/// The path in an OSC 7 `file://host/path` URL, percent-decoded, or `None` if it isn't one.
+/// The host is left out, as shells report their own.
+pub fn cwd_from_url(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("file://")?;
+    let path = &rest[rest.find('/')?..];
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%').then(|| tail.get(..2)).flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Cell size in surface-local pixels for a font of `points`, estimated as a monospace cell half
+/// as wide as the line height.
+pub fn cell_size_for(points: f32) -> (u32, u32) {
+    let height = (crate::config::points_to_pixels(points, 1.0).round() as u32).max(2);
+    (height / 2, height)
+}
+
+/// How a grid fits in a window: its size in cells and the padding around it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridGeometry {
+    pub cols: u16,
+    pub rows: u16,
+    /// As from `grid_padding`, filling the window with the cells.
+    pub padding: Padding,
+}
+
+/// The grid of `cell_size` cells that fits in a `window` of content size inside `padding`,
+/// centered as `grid_padding` has it. Windows smaller than one cell, padding included, still
+/// get a 1x1 grid, which is then cut off.
+pub fn grid_geometry(window: (u32, u32), cell_size: (u32, u32), padding: Padding, centered: bool) -> GridGeometry {
+    let count = |size: u32, padding: u32, cell: u32| (size.saturating_sub(padding) / cell.max(1)).clamp(1, u16::MAX as u32) as u16;
+    GridGeometry {
+        cols: count(window.0, padding.horizontal(), cell_size.0),
+        rows: count(window.1, padding.vertical(), cell_size.1),
+        padding: grid_padding(window, cell_size, padding, centered),
+    }
+}
+
+/// Padding around a grid of `cell_size` cells in a `window` of content size, adding the strip
+/// too narrow for another cell to `padding`. With `centered` the strip is split between
+/// opposite sides, an odd pixel going right or down; otherwise it all goes there.
+pub fn grid_padding(window: (u32, u32), cell_size: (u32, u32), padding: Padding, centered: bool) -> Padding {
+    let leftover = |size: u32, padding: u32, cell: u32| {
+        let space = size.saturating_sub(padding);
+        let cell = cell.max(1);
+        space.saturating_sub((space / cell).max(1) * cell)
+    };
+    let width = leftover(window.0, padding.horizontal(), cell_size.0);
+    let height = leftover(window.1, padding.vertical(), cell_size.1);
+    let (left, top) = if centered { (width / 2, height / 2) } else { (0, 0) };
+    Padding {
+        top: padding.top + top,
+        bottom: padding.bottom + height - top,
+        left: padding.left + left,
+        right: padding.right + width - left,
+    }
+}
+
+/// The largest window content size up to `window` that holds whole cells inside `padding`,
+/// for resizing in steps of a cell. Sizes with no room for a cell are left as they are.
+pub fn snap_to_cells(window: (u32, u32), cell_size: (u32, u32), padding: Padding) -> (u32, u32) {
+    let snap = |size: u32, padding: u32, cell: u32| {
+        let cell = cell.max(1);
+        match size.checked_sub(padding) {
+            Some(space) if space >= cell => padding + space / cell * cell,
+            _ => size,
+        }
+    };
+    (snap(window.0, padding.horizontal(), cell_size.0), snap(window.1, padding.vertical(), cell_size.1))
+}
+
+/// Window content size, padding included, that holds exactly `cols` by `rows` cells.
+pub fn window_size_for(cols: u32, rows: u32, cell_size: (u32, u32), padding: Padding) -> (u32, u32) {
+    (
+        cols * cell_size.0 + padding.horizontal(),
+        rows * cell_size.1 + padding.vertical(),
+    )
 }
\ No newline at end of file
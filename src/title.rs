@@ -0,0 +1,46 @@
+/// What a `display.title_format` can show.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TitleParts<'a> {
+    /// The title set with OSC 0 or 2, empty if none was.
+    pub title: &'a str,
+    /// The working directory reported with OSC 7.
+    pub cwd: Option<&'a str>,
+    /// The program other than the shell running in the foreground.
+    pub command: Option<&'a str>,
+}
+
+/// Expands `{title}`, `{cwd}` and `{command}` in `format`, with whatever isn't known as
+/// nothing. Other text, unknown placeholders included, is kept as it is. A title that comes
+/// out blank is `default` instead, e.g. before the shell sets anything.
+pub fn format_title(format: &str, parts: &TitleParts, default: &str) -> String {
+    let mut title = String::new();
+    let mut rest = format;
+    while let Some(start) = rest.find('{') {
+        title.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = [("{title}", Some(parts.title)), ("{cwd}", parts.cwd), ("{command}", parts.command)]
+            .into_iter()
+            .find(|(placeholder, _)| rest.starts_with(placeholder));
+        match value {
+            Some((placeholder, value)) => {
+                title.push_str(value.unwrap_or(""));
+                rest = &rest[placeholder.len()..];
+            }
+            None => {
+                title.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    title.push_str(rest);
+    
+    if title.trim().is_empty() {
+        return default.to_string();
+    }
+    title
+}
+
+/// Whether `format` shows the foreground command, which is only worth looking up if so.
+pub fn shows_command(format: &str) -> bool {
+    format.contains("{command}")
+}
\ No newline at end of file
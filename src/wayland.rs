@@ -1,19 +1,33 @@
-use anyhow::{Context, Result};
+use anyhow::Context;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use smithay_client_toolkit::{
-    compositor::{CompositorHandler, CompositorState},
-    delegate_compositor, delegate_keyboard, delegate_output, delegate_pointer, delegate_registry,
-    delegate_seat, delegate_shm, delegate_xdg_shell, delegate_xdg_window,
+    activation::{ActivationHandler, ActivationState, RequestData},
+    compositor::{CompositorHandler, CompositorState, Region},
+    data_device_manager::{
+        data_device::{DataDevice, DataDeviceHandler},
+        data_offer::{DataOfferHandler, DragOffer},
+        data_source::{CopyPasteSource, DataSourceHandler},
+        DataDeviceManagerState, WritePipe,
+    },
+    delegate_activation, delegate_compositor, delegate_data_device, delegate_keyboard, delegate_output, delegate_pointer,
+    delegate_primary_selection, delegate_registry, delegate_seat, delegate_shm, delegate_xdg_shell, delegate_xdg_window,
     output::{OutputHandler, OutputState},
+    primary_selection::{
+        device::{PrimarySelectionDevice, PrimarySelectionDeviceHandler},
+        selection::{PrimarySelectionSource, PrimarySelectionSourceHandler},
+        PrimarySelectionManagerState,
+    },
     registry::{ProvidesRegistryState, RegistryState},
     registry_handlers,
     seat::{
         keyboard::{KeyEvent, KeyboardHandler, Modifiers as WaylandModifiers},
-        pointer::{PointerEvent, PointerEventKind, PointerHandler},
+        pointer::{CursorIcon, PointerEvent, PointerEventKind, PointerHandler, ThemeSpec, ThemedPointer},
         Capability, SeatHandler, SeatState,
     },
     shell::{
         xdg::{
-            window::{Window, WindowConfigure, WindowHandler},
+            window::{DecorationMode as XdgDecorationMode, Window, WindowConfigure, WindowDecorations, WindowHandler},
             XdgShell, XdgSurface,
         },
         WaylandSurface,
@@ -22,12 +36,166 @@ use smithay_client_toolkit::{
 };
 use wayland_client::{
     globals::registry_queue_init,
-    protocol::{wl_keyboard, wl_output, wl_pointer, wl_seat, wl_surface},
-    Connection, QueueHandle,
+    protocol::{wl_data_device::WlDataDevice, wl_data_device_manager::DndAction, wl_data_source::WlDataSource, wl_keyboard, wl_output, wl_pointer, wl_seat, wl_surface},
+    ConnectError, Connection, Dispatch, Proxy, QueueHandle,
+};
+use wayland_protocols::wp::fractional_scale::v1::client::{
+    wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+    wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+};
+use wayland_protocols::wp::primary_selection::zv1::client::{
+    zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1,
+    zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
 };
+use wayland_protocols::wp::presentation_time::client::{
+    wp_presentation::{self, WpPresentation},
+    wp_presentation_feedback::{self, WpPresentationFeedback},
+};
+use wayland_protocols::wp::viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter};
+
+use crate::clipboard;
+use crate::config::{Config, DecorationMode, Decorations, Padding, StartupMode};
+use crate::display::{self, Event, OutputInfo};
+use crate::error::{MyTermError, Result};
+use crate::input::{Key, KeyCode, Modifiers, MouseButton, MouseInput, PIXELS_PER_SCROLL_STEP};
+use crate::pacing::{self, Presentation};
+use crate::terminal::{snap_to_cells, window_size_for, MIN_COLUMNS, MIN_ROWS};
+
+/// Window title until the shell sets one, and whenever it sets an empty one, unless
+/// `display.title` is set.
+pub const DEFAULT_TITLE: &str = "MyTerm";
+
+/// The title a program set, or else `default` with any `command` running in front of it, as in
+/// "vim — MyTerm".
+pub fn window_title(title: &str, command: Option<&str>, default: &str) -> String {
+    match command {
+        _ if !title.is_empty() => title.to_string(),
+        Some(command) => format!("{} — {}", command, default),
+        None => default.to_string(),
+    }
+}
+
+/// The window's app_id unless `display.app_id` is set.
+pub const DEFAULT_APP_ID: &str = "myterm";
+
+/// The cursor over a surface-local `position`: an arrow over our titlebar, `titlebar_height`
+/// tall, and an I-beam over the text below.
+pub fn pointer_icon(position: (f64, f64), titlebar_height: u32) -> CursorIcon {
+    if position.1 < titlebar_height as f64 {
+        CursorIcon::Default
+    } else {
+        CursorIcon::Text
+    }
+}
+
+/// The decorations to ask the compositor for. With `Decorations::None` we ask to draw them
+/// ourselves and then draw none, as compositors such as Sway otherwise add their own.
+pub fn requested_decorations(decorations: Decorations, mode: DecorationMode) -> WindowDecorations {
+    match (decorations, mode) {
+        (Decorations::None, _) | (Decorations::Full, DecorationMode::Client) => WindowDecorations::RequestClient,
+        (Decorations::Full, DecorationMode::Server | DecorationMode::Auto) => WindowDecorations::RequestServer,
+    }
+}
+
+/// Whether we draw a titlebar once the compositor configured the window with `granted`
+/// decorations, which may not be the ones asked for. Compositors without xdg-decoration always
+/// leave them to the client.
+pub fn draws_titlebar(decorations: Decorations, mode: DecorationMode, granted: XdgDecorationMode) -> bool {
+    decorations == Decorations::Full && granted == XdgDecorationMode::Client && mode != DecorationMode::Server
+}
+
+/// Height of the minimal client-side titlebar, in surface-local pixels.
+pub const TITLEBAR_HEIGHT: u32 = 24;
+
+const BTN_LEFT: u32 = 0x110;
+
+/// Why no connection to a compositor could be made.
+#[derive(Debug, thiserror::Error)]
+pub enum ConnectionError {
+    /// There is no compositor to talk to, as under X11 or in CI.
+    #[error("no Wayland display available (WAYLAND_DISPLAY is unset or names no socket)")]
+    NoDisplay,
+    #[error("failed to connect to the Wayland display: {0}")]
+    Connect(#[source] ConnectError),
+}
+
+impl From<ConnectError> for ConnectionError {
+    fn from(error: ConnectError) -> Self {
+        match error {
+            ConnectError::NoCompositor => Self::NoDisplay,
+            error => Self::Connect(error),
+        }
+    }
+}
+
+/// A change to make to the pointer's cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorChange {
+    Show(CursorIcon),
+    Hide,
+}
+
+/// Which cursor the pointer shows over the window: the one for where it is, or none after
+/// typing until the pointer is used again.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PointerCursor {
+    /// Surface-local position of the pointer, `None` while it's outside the window.
+    position: Option<(f64, f64)>,
+    hidden: bool,
+    /// The cursor set since the pointer entered, if any.
+    shown: Option<CursorIcon>,
+}
 
-use crate::config::Config;
-use crate::input::{Key, KeyCode, Modifiers};
+impl PointerCursor {
+    /// The pointer entered at `position`, which always needs a cursor set.
+    pub fn enter(&mut self, position: (f64, f64), titlebar_height: u32) -> Option<CursorChange> {
+        *self = Self { position: Some(position), ..Self::default() };
+        self.show(titlebar_height)
+    }
+    
+    pub fn leave(&mut self) {
+        *self = Self::default();
+    }
+    
+    /// The pointer moved to `position`, bringing back a hidden cursor or changing the shape
+    /// for what it's over now.
+    pub fn motion(&mut self, position: (f64, f64), titlebar_height: u32) -> Option<CursorChange> {
+        self.position = Some(position);
+        self.show(titlebar_height)
+    }
+    
+    /// A button or the wheel was used where the pointer is.
+    pub fn used(&mut self, titlebar_height: u32) -> Option<CursorChange> {
+        self.show(titlebar_height)
+    }
+    
+    /// A key press typed something, which hides the cursor while the pointer is over the window.
+    pub fn typed(&mut self) -> Option<CursorChange> {
+        if self.position.is_none() || self.hidden {
+            return None;
+        }
+        self.hidden = true;
+        Some(CursorChange::Hide)
+    }
+    
+    /// Keyboard focus went elsewhere, where typing shouldn't keep the cursor hidden.
+    pub fn unfocused(&mut self, titlebar_height: u32) -> Option<CursorChange> {
+        if !self.hidden {
+            return None;
+        }
+        self.show(titlebar_height)
+    }
+    
+    fn show(&mut self, titlebar_height: u32) -> Option<CursorChange> {
+        let icon = pointer_icon(self.position?, titlebar_height);
+        if !self.hidden && self.shown == Some(icon) {
+            return None;
+        }
+        self.hidden = false;
+        self.shown = Some(icon);
+        Some(CursorChange::Show(icon))
+    }
+}
 
 pub struct WaylandState {
     registry_state: RegistryState,
@@ -36,45 +204,125 @@ pub struct WaylandState {
     compositor_state: CompositorState,
     shm: Shm,
     xdg_shell: XdgShell,
+    /// xdg-activation, used to request attention; not every compositor offers it.
+    activation: Option<ActivationState>,
+    fractional_scale_manager: Option<WpFractionalScaleManagerV1>,
+    viewporter: Option<WpViewporter>,
+    /// wl_data_device_manager, for the clipboard.
+    data_device_manager: Option<DataDeviceManagerState>,
+    data_device: Option<DataDevice>,
+    /// What we offer as the clipboard while it's ours.
+    copy_source: Option<(CopyPasteSource, Arc<str>)>,
+    /// zwp_primary_selection_device_manager_v1, for the selection middle-click pastes; not
+    /// every compositor offers it.
+    primary_selection_manager: Option<PrimarySelectionManagerState>,
+    primary_selection_device: Option<PrimarySelectionDevice>,
+    /// What we offer as the primary selection while it's ours.
+    primary_source: Option<(PrimarySelectionSource, Arc<str>)>,
+    /// presentation-time, for frame pacing and latency; not every compositor offers it.
+    presentation: Option<WpPresentation>,
+    /// The clock its timestamps are on, as the compositor announced it.
+    presentation_clock: nix::time::ClockId,
+    /// Per-surface objects, present when fractional scaling is in use.
+    fractional_scale: Option<WpFractionalScaleV1>,
+    viewport: Option<WpViewport>,
+    /// Ratio of buffer pixels to surface-local pixels.
+    scale: f64,
+    /// Integer buffer scale to set along with the first buffer drawn at it.
+    pending_buffer_scale: Option<i32>,
     
     pub window: Option<Window>,
+    decorations: Decorations,
+    decoration_mode: DecorationMode,
+    /// Whether we draw a titlebar ourselves because the compositor didn't.
+    client_titlebar: bool,
+    startup_mode: StartupMode,
+    /// Name of the output to start fullscreen on.
+    fullscreen_output: Option<String>,
+    app_id: String,
+    /// Title shown while the shell hasn't set one.
+    default_title: String,
+    /// The window's title, as also drawn in a client titlebar.
+    title: String,
+    /// Cell size and padding of the grid, for sizing floating windows in whole cells.
+    cell_grid: Option<((u32, u32), Padding)>,
+    /// Last size while neither maximized nor fullscreen, for remembering across launches.
+    windowed_size: (u32, u32),
+    seat: Option<wl_seat::WlSeat>,
     keyboard: Option<wl_keyboard::WlKeyboard>,
-    pointer: Option<wl_pointer::WlPointer>,
+    pointer: Option<ThemedPointer>,
+    pointer_cursor: PointerCursor,
+    /// Whether the left button went down over the grid and hasn't been released.
+    selecting: bool,
+    /// Serial of the last key or button press, which taking the clipboard must come with.
+    input_serial: u32,
+    /// Modifier state from the last `wl_keyboard.modifiers` event.
+    modifiers: WaylandModifiers,
+    focused: bool,
+    /// Outputs the surface is on, in the order it entered them.
+    entered_outputs: Vec<wl_output::WlOutput>,
+    /// Last output info sent, to only report changes.
+    current_output: Option<OutputInfo>,
+    /// Whether the first configure arrived; buffers must not be attached before it.
+    configured: bool,
     
-    exit: bool,
     width: u32,
     height: u32,
     
     event_sender: crossbeam_channel::Sender<Event>,
 }
 
-#[derive(Debug)]
-pub enum Event {
-    #[allow(dead_code)]
-    Resize(u32, u32),
-    #[allow(dead_code)]
-    Key(Key),
-    Close,
-}
-
 impl WaylandState {
-    pub fn new(config: &Config) -> Result<(Self, Connection, wayland_client::EventQueue<Self>)> {
-        let (event_sender, _) = crossbeam_channel::unbounded();
+    /// Connects to the compositor, returning the receiving end of the channel the handlers send `Event`s on.
+    pub fn new(
+        config: &Config,
+    ) -> Result<(Self, Connection, wayland_client::EventQueue<Self>, crossbeam_channel::Receiver<Event>)> {
+        let (event_sender, event_receiver) = crossbeam_channel::unbounded();
         
-        let conn = Connection::connect_to_env()
-            .context("Failed to connect to Wayland display")?;
+        let conn = Connection::connect_to_env().map_err(ConnectionError::from)?;
         
         let (globals, event_queue) = registry_queue_init(&conn)
-            .context("Failed to initialize registry")?;
+            .context("Failed to initialize registry")
+            .map_err(MyTermError::wayland)?;
             
         let qh = event_queue.handle();
         
         let compositor_state = CompositorState::bind(&globals, &qh)
-            .context("Failed to bind compositor")?;
+            .context("Failed to bind compositor")
+            .map_err(MyTermError::wayland)?;
         let xdg_shell = XdgShell::bind(&globals, &qh)
-            .context("Failed to bind XDG shell")?;
+            .context("Failed to bind XDG shell")
+            .map_err(MyTermError::wayland)?;
         let shm = Shm::bind(&globals, &qh)
-            .context("Failed to bind shared memory")?;
+            .context("Failed to bind shared memory")
+            .map_err(MyTermError::wayland)?;
+        
+        let activation = ActivationState::bind(&globals, &qh)
+            .map_err(|e| log::debug!("xdg-activation unavailable: {}", e))
+            .ok();
+        
+        let fractional_scale_manager = globals.bind(&qh, 1..=1, ())
+            .map_err(|e| log::debug!("wp_fractional_scale_manager_v1 unavailable: {}", e))
+            .ok();
+        let viewporter = globals.bind(&qh, 1..=1, ())
+            .map_err(|e| log::debug!("wp_viewporter unavailable: {}", e))
+            .ok();
+        let presentation = globals.bind(&qh, 1..=1, ())
+            .map_err(|e| log::debug!("wp_presentation unavailable: {}", e))
+            .ok();
+        let data_device_manager = DataDeviceManagerState::bind(&globals, &qh)
+            .map_err(|e| log::info!("wl_data_device_manager unavailable, no clipboard: {}", e))
+            .ok();
+        let primary_selection_manager = PrimarySelectionManagerState::bind(&globals, &qh)
+            .map_err(|e| log::info!("zwp_primary_selection_device_manager_v1 unavailable, no primary selection: {}", e))
+            .ok();
+        
+        // Without it nothing draws server-side decorations, leaving the client titlebar
+        let decoration_manager = globals.contents()
+            .with_list(|list| list.iter().any(|global| global.interface == "zxdg_decoration_manager_v1"));
+        if !decoration_manager && config.display.decorations == Decorations::Full {
+            log::info!("zxdg_decoration_manager_v1 unavailable, the compositor won't decorate the window");
+        }
         
         let registry_state = RegistryState::new(&globals);
         let seat_state = SeatState::new(&globals, &qh);
@@ -87,36 +335,83 @@ impl WaylandState {
             compositor_state,
             shm,
             xdg_shell,
+            activation,
+            fractional_scale_manager,
+            viewporter,
+            data_device_manager,
+            data_device: None,
+            copy_source: None,
+            primary_selection_manager,
+            primary_selection_device: None,
+            primary_source: None,
+            presentation,
+            presentation_clock: nix::time::ClockId::CLOCK_MONOTONIC,
+            fractional_scale: None,
+            viewport: None,
+            scale: 1.0,
+            pending_buffer_scale: None,
             window: None,
+            decorations: config.display.decorations,
+            decoration_mode: config.display.decoration_mode,
+            client_titlebar: false,
+            startup_mode: config.display.startup_mode.clone(),
+            fullscreen_output: config.display.fullscreen_output.clone(),
+            app_id: config.display.app_id.clone().unwrap_or_else(|| DEFAULT_APP_ID.to_string()),
+            default_title: config.display.title.clone().unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+            title: config.display.title.clone().unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+            cell_grid: None,
+            windowed_size: (config.display.width, config.display.height),
+            seat: None,
             keyboard: None,
             pointer: None,
-            exit: false,
+            pointer_cursor: PointerCursor::default(),
+            selecting: false,
+            input_serial: 0,
+            modifiers: WaylandModifiers::default(),
+            focused: false,
+            entered_outputs: Vec::new(),
+            current_output: None,
+            configured: false,
             width: config.display.width,
             height: config.display.height,
             event_sender,
         };
         
-        Ok((state, conn, event_queue))
+        Ok((state, conn, event_queue, event_receiver))
     }
     
-    pub fn create_window(&mut self, qh: &QueueHandle<Self>) -> Result<()> {
+    /// Creates the window, asking for `size` in surface-local pixels unless maximized or fullscreen.
+    pub fn create_window(&mut self, qh: &QueueHandle<Self>, size: (u32, u32)) -> Result<()> {
         log::debug!("Creating Wayland window");
         let surface = self.compositor_state.create_surface(qh);
         log::debug!("Created surface");
         
-        let window = self.xdg_shell.create_window(
-            surface, 
-            smithay_client_toolkit::shell::xdg::window::WindowDecorations::RequestServer, 
-            qh
-        );
+        let decorations = requested_decorations(self.decorations, self.decoration_mode);
+        log::debug!("Requesting {:?} decorations", decorations);
+        let window = self.xdg_shell.create_window(surface, decorations, qh);
         log::debug!("Created XDG window");
         
-        window.set_title("MyTerm");
-        window.set_app_id("myterm");
+        // Fractional scaling needs both protocols: the scale is only a hint, the
+        // viewport is what maps the larger buffer back onto the surface
+        if let (Some(manager), Some(viewporter)) = (&self.fractional_scale_manager, &self.viewporter) {
+            log::info!("Using fractional scaling via wp_fractional_scale_v1 and wp_viewporter");
+            self.fractional_scale = Some(manager.get_fractional_scale(window.wl_surface(), qh, ()));
+            self.viewport = Some(viewporter.get_viewport(window.wl_surface(), qh, ()));
+        } else {
+            log::info!("Fractional scaling unavailable, using integer wl_surface buffer scale");
+        }
         
-        // Set initial window size
-        window.set_min_size(Some((400, 300)));
-        window.set_max_size(Some((2000, 1500)));
+        window.set_title(&self.default_title);
+        window.set_app_id(&self.app_id);
+        
+        // The compositor may ignore the size, e.g. when tiling, and say so in the configure
+        (self.width, self.height) = size;
+        self.windowed_size = size;
+        match self.startup_mode {
+            StartupMode::Windowed => {}
+            StartupMode::Maximized => window.set_maximized(),
+            StartupMode::Fullscreen => window.set_fullscreen(self.fullscreen_output().as_ref()),
+        }
         
         log::debug!("Committing window configuration");
         window.commit();
@@ -126,8 +421,300 @@ impl WaylandState {
         Ok(())
     }
     
-    pub fn should_exit(&self) -> bool {
-        self.exit
+    /// The output `display.fullscreen_output` names, if it's connected.
+    fn fullscreen_output(&self) -> Option<wl_output::WlOutput> {
+        let name = self.fullscreen_output.as_deref()?;
+        let output = self.output_state.outputs()
+            .find(|output| self.output_state.info(output).is_some_and(|info| info.name.as_deref() == Some(name)));
+        if output.is_none() {
+            log::warn!("No output named {:?}, leaving the compositor to pick one to go fullscreen on", name);
+        }
+        output
+    }
+    
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+        if let Some(window) = &self.window {
+            window.set_title(&self.title);
+        }
+    }
+    
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+    
+    /// Sets the cells the grid is made of, which floating windows are sized in. The window
+    /// can't be made smaller than `MIN_COLUMNS` by `MIN_ROWS` of them, plus any titlebar.
+    pub fn set_cell_grid(&mut self, cell_size: (u32, u32), padding: Padding) {
+        self.cell_grid = Some((cell_size, padding));
+        self.apply_min_size();
+    }
+    
+    fn apply_min_size(&self) {
+        if let (Some(window), Some((cell_size, padding))) = (&self.window, self.cell_grid) {
+            let (width, height) = window_size_for(MIN_COLUMNS, MIN_ROWS, cell_size, padding);
+            let titlebar = if self.client_titlebar { TITLEBAR_HEIGHT } else { 0 };
+            window.set_min_size(Some((width, height + titlebar)));
+        }
+    }
+    
+    /// Shrinks the window to whole cells, leaving no strip along the edges too narrow for one.
+    fn snap_to_cells(&mut self) {
+        let Some((cell_size, padding)) = self.cell_grid else {
+            return;
+        };
+        let titlebar = self.titlebar_height();
+        let (width, height) = snap_to_cells(self.content_size(), cell_size, padding);
+        if (width, height + titlebar) != (self.width, self.height) {
+            log::debug!("Snapping window size {}x{} to {}x{}", self.width, self.height, width, height + titlebar);
+            (self.width, self.height) = (width, height + titlebar);
+        }
+    }
+    
+    /// The last window size that was neither maximized nor fullscreen.
+    pub fn windowed_size(&self) -> (u32, u32) {
+        self.windowed_size
+    }
+    
+    /// A surface-local pointer position relative to the area below any titlebar.
+    fn grid_position(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (x, y - self.titlebar_height() as f64)
+    }
+    
+    /// Height of our own titlebar in surface-local pixels, 0 when the compositor decorates.
+    pub fn titlebar_height(&self) -> u32 {
+        if self.client_titlebar {
+            TITLEBAR_HEIGHT.min(self.height)
+        } else {
+            0
+        }
+    }
+    
+    /// Size available to the terminal grid, below any client titlebar.
+    pub fn content_size(&self) -> (u32, u32) {
+        (self.width, self.height - self.titlebar_height())
+    }
+    
+    fn update_decorations(&mut self, granted: XdgDecorationMode) {
+        let client_titlebar = draws_titlebar(self.decorations, self.decoration_mode, granted);
+        
+        if client_titlebar != self.client_titlebar || !self.configured {
+            log::info!(
+                "Compositor granted {:?} decorations{}",
+                granted,
+                if client_titlebar { ", drawing a client titlebar" } else { "" }
+            );
+        }
+        if client_titlebar != self.client_titlebar {
+            self.client_titlebar = client_titlebar;
+            self.apply_min_size();
+        }
+    }
+    
+    pub fn is_configured(&self) -> bool {
+        self.configured
+    }
+    
+    pub fn shm(&self) -> &Shm {
+        &self.shm
+    }
+    
+    /// Marks the whole surface as opaque, or none of it, so the compositor can skip blending.
+    pub fn set_opaque(&self, opaque: bool) {
+        let Some(ref window) = self.window else {
+            return;
+        };
+        
+        if !opaque {
+            window.wl_surface().set_opaque_region(None);
+            return;
+        }
+        
+        match Region::new(&self.compositor_state) {
+            Ok(region) => {
+                region.add(0, 0, self.width as i32, self.height as i32);
+                window.wl_surface().set_opaque_region(Some(region.wl_region()));
+                region.wl_region().destroy();
+            }
+            Err(e) => log::warn!("Failed to create opaque region: {}", e),
+        }
+    }
+    
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+    
+    /// Size of the buffer that covers the surface at the current scale.
+    pub fn buffer_size(&self) -> (u32, u32) {
+        (
+            (self.width as f64 * self.scale).round() as u32,
+            (self.height as f64 * self.scale).round() as u32,
+        )
+    }
+    
+    /// Asks to hear when the next commit of `surface` is presented, as `Event::Presented` with
+    /// `output_since` passed along. Returns whether it was asked, which needs presentation-time.
+    pub fn request_presentation_feedback(&self, surface: &wl_surface::WlSurface, qh: &QueueHandle<Self>, output_since: Option<Instant>) -> bool {
+        let Some(presentation) = &self.presentation else {
+            return false;
+        };
+        presentation.feedback(surface, qh, FrameFeedback { output_since });
+        true
+    }
+    
+    /// Sets a changed integer buffer scale on `surface`, for the commit that attaches a buffer
+    /// drawn at that scale. Setting it any earlier would show the old buffer at the new scale.
+    pub fn apply_buffer_scale(&mut self, surface: &wl_surface::WlSurface) {
+        if let Some(scale) = self.pending_buffer_scale.take() {
+            surface.set_buffer_scale(scale);
+        }
+    }
+    
+    fn set_scale(&mut self, scale: f64) {
+        if scale != self.scale {
+            self.scale = scale;
+            self.send_event(Event::ScaleChanged(scale));
+        }
+    }
+    
+    fn update_viewport(&self) {
+        if let Some(ref viewport) = self.viewport {
+            viewport.set_destination(self.width as i32, self.height as i32);
+        }
+    }
+    
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+    
+    /// Hides the pointer's cursor after typing, until the pointer is used again.
+    pub fn hide_pointer(&mut self, conn: &Connection) {
+        let change = self.pointer_cursor.typed();
+        self.change_cursor(conn, change);
+    }
+    
+    /// Applies a change `pointer_cursor` asked for. A failure isn't retried before the next
+    /// change, rather than on every motion.
+    fn change_cursor(&self, conn: &Connection, change: Option<CursorChange>) {
+        let (Some(pointer), Some(change)) = (&self.pointer, change) else {
+            return;
+        };
+        // Through cursor-shape-v1 where the compositor has it, else from the XCURSOR_THEME
+        // theme at XCURSOR_SIZE, scaled for the output the cursor is on
+        let result = match change {
+            CursorChange::Show(icon) => pointer.set_cursor(conn, icon),
+            CursorChange::Hide => pointer.hide_cursor(),
+        };
+        if let Err(e) = result {
+            log::debug!("Failed to change the pointer cursor to {:?}: {}", change, e);
+        }
+    }
+    
+    /// Asks the compositor to draw attention to the window, e.g. an urgent hint in Sway.
+    pub fn request_urgency(&self, qh: &QueueHandle<Self>) {
+        let (Some(activation), Some(window)) = (&self.activation, &self.window) else {
+            log::debug!("Cannot request urgency without xdg-activation");
+            return;
+        };
+        
+        activation.request_token(qh, RequestData {
+            app_id: Some(self.app_id.clone()),
+            seat_and_serial: None,
+            surface: Some(window.wl_surface().clone()),
+        });
+    }
+    
+    /// Reports the output the window is sized for, as `display::effective_output` picks it
+    /// among those it overlaps, if it changed.
+    fn output_changed(&mut self) {
+        let entered: Vec<OutputInfo> = self.entered_outputs.iter()
+            .filter_map(|output| self.output_state.info(output))
+            .map(|info| OutputInfo {
+                name: info.name.clone(),
+                physical_size: info.physical_size,
+                mode_size: info.modes.iter().find(|mode| mode.current).map_or((0, 0), |mode| mode.dimensions),
+                scale_factor: info.scale_factor,
+            })
+            .collect();
+        let info = display::effective_output(&entered).cloned();
+        
+        if info != self.current_output {
+            log::info!("Window is on output {:?}", info.as_ref().and_then(|info| info.name.as_deref()));
+            self.current_output = info.clone();
+            self.send_event(Event::OutputChanged(info));
+        }
+    }
+    
+    /// Takes the clipboard, offering `text` to whoever pastes until another client takes it.
+    pub fn set_clipboard(&mut self, qh: &QueueHandle<Self>, text: String) {
+        let (Some(manager), Some(device)) = (&self.data_device_manager, &self.data_device) else {
+            log::warn!("No clipboard to copy to");
+            return;
+        };
+        let source = manager.create_copy_paste_source(qh, clipboard::TEXT_MIME_TYPES);
+        source.set_selection(device, self.input_serial);
+        self.copy_source = Some((source, text.into()));
+    }
+    
+    /// Asks for the clipboard's text, which arrives on `events` as an `Event::Paste` once it
+    /// has been read, however large it is.
+    pub fn request_paste(&self, events: &tokio::sync::mpsc::UnboundedSender<Event>) {
+        // Our own selection needn't go through a pipe
+        if let Some((_, text)) = &self.copy_source {
+            let _ = events.send(Event::Paste(text.to_string()));
+            return;
+        }
+        let Some(offer) = self.data_device.as_ref().and_then(|device| device.data().selection_offer()) else {
+            log::debug!("The clipboard is empty");
+            return;
+        };
+        let Some(mime) = offer.with_mime_types(clipboard::best_mime) else {
+            log::debug!("The clipboard holds no text");
+            return;
+        };
+        match offer.receive(mime.to_string()) {
+            Ok(pipe) => clipboard::receive_selection(pipe.into(), events.clone()),
+            Err(e) => log::warn!("Failed to paste: {}", e),
+        }
+    }
+    
+    /// Takes the primary selection, offering `text` to middle-click pastes until another client
+    /// takes it.
+    pub fn set_primary_selection(&mut self, qh: &QueueHandle<Self>, text: String) {
+        let (Some(manager), Some(device)) = (&self.primary_selection_manager, &self.primary_selection_device) else {
+            log::debug!("No primary selection to select into");
+            return;
+        };
+        let source = manager.create_selection_source(qh, clipboard::TEXT_MIME_TYPES);
+        source.set_selection(device, self.input_serial);
+        self.primary_source = Some((source, text.into()));
+    }
+    
+    /// Asks for the primary selection's text, which arrives on `events` as an `Event::Paste`
+    /// like the clipboard's.
+    pub fn request_primary_paste(&self, events: &tokio::sync::mpsc::UnboundedSender<Event>) {
+        if let Some((_, text)) = &self.primary_source {
+            let _ = events.send(Event::Paste(text.to_string()));
+            return;
+        }
+        let Some(offer) = self.primary_selection_device.as_ref().and_then(|device| device.data().selection_offer()) else {
+            log::debug!("The primary selection is empty");
+            return;
+        };
+        let Some(mime) = offer.with_mime_types(clipboard::best_mime) else {
+            log::debug!("The primary selection holds no text");
+            return;
+        };
+        match offer.receive(mime.to_string()) {
+            Ok(pipe) => clipboard::receive_selection(pipe.into(), events.clone()),
+            Err(e) => log::warn!("Failed to paste the primary selection: {}", e),
+        }
+    }
+    
+    fn send_event(&self, event: Event) {
+        if let Err(e) = self.event_sender.send(event) {
+            log::warn!("Dropping Wayland event, receiver is gone: {:?}", e.into_inner());
+        }
     }
     
     fn wayland_key_to_key(&self, event: &KeyEvent, modifiers: &WaylandModifiers) -> Option<Key> {
@@ -158,6 +745,8 @@ impl WaylandState {
             119 => Some(KeyCode::Delete),
             118 => Some(KeyCode::Insert),
             67..=76 => Some(KeyCode::F((event.raw_code - 66) as u8)),
+            95 | 96 => Some(KeyCode::F((event.raw_code - 84) as u8)),
+            191..=202 => Some(KeyCode::F((event.raw_code - 178) as u8)),
             _ => None,
         };
         
@@ -189,9 +778,21 @@ impl CompositorHandler for WaylandState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _new_factor: i32,
+        surface: &wl_surface::WlSurface,
+        new_factor: i32,
     ) {
+        // The cursor surface follows its own output, and the theme scales for it
+        if self.window.as_ref().map(|window| window.wl_surface()) != Some(surface) {
+            return;
+        }
+        // With fractional scaling the buffer scale stays 1 and the viewport does the mapping
+        if self.fractional_scale.is_some() {
+            return;
+        }
+        
+        log::info!("Using integer scale {}", new_factor);
+        self.pending_buffer_scale = Some(new_factor);
+        self.set_scale(new_factor as f64);
     }
 
     fn transform_changed(
@@ -210,6 +811,7 @@ impl CompositorHandler for WaylandState {
         _surface: &wl_surface::WlSurface,
         _time: u32,
     ) {
+        self.send_event(Event::Frame);
     }
 
     fn surface_enter(
@@ -217,8 +819,12 @@ impl CompositorHandler for WaylandState {
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
         _surface: &wl_surface::WlSurface,
-        _output: &wl_output::WlOutput,
+        output: &wl_output::WlOutput,
     ) {
+        if !self.entered_outputs.contains(output) {
+            self.entered_outputs.push(output.clone());
+        }
+        self.output_changed();
     }
 
     fn surface_leave(
@@ -226,8 +832,10 @@ impl CompositorHandler for WaylandState {
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
         _surface: &wl_surface::WlSurface,
-        _output: &wl_output::WlOutput,
+        output: &wl_output::WlOutput,
     ) {
+        self.entered_outputs.retain(|entered| entered != output);
+        self.output_changed();
     }
 }
 
@@ -248,46 +856,65 @@ impl OutputHandler for WaylandState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        output: wl_output::WlOutput,
     ) {
+        if self.entered_outputs.contains(&output) {
+            self.output_changed();
+        }
     }
 
     fn output_destroyed(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        output: wl_output::WlOutput,
     ) {
+        self.entered_outputs.retain(|entered| entered != &output);
+        self.output_changed();
     }
 }
 
 impl WindowHandler for WaylandState {
     fn request_close(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &Window) {
-        self.exit = true;
-        let _ = self.event_sender.send(Event::Close);
+        self.send_event(Event::Close);
     }
 
     fn configure(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _window: &Window,
+        window: &Window,
         configure: WindowConfigure,
         _serial: u32,
     ) {
         log::debug!("Window configure event: {:?}", configure);
+        self.update_decorations(configure.decoration_mode);
+        self.configured = true;
         
         if let (Some(width), Some(height)) = configure.new_size {
             self.width = width.get();
             self.height = height.get();
             log::debug!("New window size: {}x{}", self.width, self.height);
-            let _ = self.event_sender.send(Event::Resize(self.width, self.height));
+            // Floating windows move in whole cells, as in xterm; the size of tiled, maximized
+            // and fullscreen ones is up to the compositor, so they pad the grid instead
+            let tiled = configure.is_tiled_left() || configure.is_tiled_right() || configure.is_tiled_top() || configure.is_tiled_bottom();
+            if !tiled && !configure.is_maximized() && !configure.is_fullscreen() {
+                self.snap_to_cells();
+            }
         } else {
-            // Use default size if none specified  
-            self.width = 800;
-            self.height = 600;
-            log::debug!("Using default window size: {}x{}", self.width, self.height);
+            // The compositor leaves the size to us, keep the one we asked for
+            log::debug!("Keeping window size: {}x{}", self.width, self.height);
         }
+        if !configure.is_maximized() && !configure.is_fullscreen() {
+            self.windowed_size = (self.width, self.height);
+        }
+        
+        // The configure was acked as it arrived, which takes effect with the next commit: the
+        // frame drawn at this size once the grid is fitted to it
+        window.set_window_geometry(0, 0, self.width, self.height);
+        self.update_viewport();
+        let (width, height) = self.content_size();
+        self.send_event(Event::Resize(width, height));
         
         log::debug!("Window configured");
     }
@@ -307,6 +934,14 @@ impl SeatHandler for WaylandState {
         seat: wl_seat::WlSeat,
         capability: Capability,
     ) {
+        self.seat.get_or_insert_with(|| seat.clone());
+        if let (None, Some(manager)) = (&self.data_device, &self.data_device_manager) {
+            self.data_device = Some(manager.get_data_device(qh, &seat));
+        }
+        if let (None, Some(manager)) = (&self.primary_selection_device, &self.primary_selection_manager) {
+            self.primary_selection_device = Some(manager.get_selection_device(qh, &seat));
+        }
+        
         if capability == Capability::Keyboard && self.keyboard.is_none() {
             self.keyboard = Some(
                 self.seat_state
@@ -316,9 +951,10 @@ impl SeatHandler for WaylandState {
         }
 
         if capability == Capability::Pointer && self.pointer.is_none() {
+            let surface = self.compositor_state.create_surface(qh);
             self.pointer = Some(
                 self.seat_state
-                    .get_pointer(qh, &seat)
+                    .get_pointer_with_theme(qh, &seat, self.shm.wl_shm(), surface, ThemeSpec::System)
                     .expect("Failed to create pointer"),
             );
         }
@@ -336,7 +972,7 @@ impl SeatHandler for WaylandState {
         }
 
         if capability == Capability::Pointer && self.pointer.is_some() {
-            self.pointer.take().unwrap().release();
+            self.pointer.take().unwrap().pointer().release();
         }
     }
 
@@ -355,20 +991,24 @@ impl KeyboardHandler for WaylandState {
         _: &[smithay_client_toolkit::seat::keyboard::Keysym],
     ) {
         if Some(surface) == self.window.as_ref().map(|w| w.wl_surface()) {
-            // Window gained focus
+            self.focused = true;
+            self.send_event(Event::Focus(true));
         }
     }
 
     fn leave(
         &mut self,
-        _: &Connection,
+        conn: &Connection,
         _: &QueueHandle<Self>,
         _: &wl_keyboard::WlKeyboard,
         surface: &wl_surface::WlSurface,
         _: u32,
     ) {
         if Some(surface) == self.window.as_ref().map(|w| w.wl_surface()) {
-            // Window lost focus
+            self.focused = false;
+            self.send_event(Event::Focus(false));
+            let change = self.pointer_cursor.unfocused(self.titlebar_height());
+            self.change_cursor(conn, change);
         }
     }
 
@@ -377,14 +1017,12 @@ impl KeyboardHandler for WaylandState {
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
         _keyboard: &wl_keyboard::WlKeyboard,
-        _serial: u32,
+        serial: u32,
         event: KeyEvent,
     ) {
-        // We'll use empty modifiers for now - proper modifier tracking would require
-        // storing the current modifier state
-        let modifiers = WaylandModifiers::default();
-        if let Some(key) = self.wayland_key_to_key(&event, &modifiers) {
-            let _ = self.event_sender.send(Event::Key(key));
+        self.input_serial = serial;
+        if let Some(key) = self.wayland_key_to_key(&event, &self.modifiers) {
+            self.send_event(Event::Key(key));
         }
     }
 
@@ -404,41 +1042,263 @@ impl KeyboardHandler for WaylandState {
         _qh: &QueueHandle<Self>,
         _keyboard: &wl_keyboard::WlKeyboard,
         _serial: u32,
-        _modifiers: WaylandModifiers,
+        modifiers: WaylandModifiers,
         _layout: u32,
     ) {
+        self.modifiers = modifiers;
     }
 }
 
 impl PointerHandler for WaylandState {
     fn pointer_frame(
         &mut self,
-        _conn: &Connection,
+        conn: &Connection,
         _qh: &QueueHandle<Self>,
         _pointer: &wl_pointer::WlPointer,
         events: &[PointerEvent],
     ) {
         for event in events {
             match &event.kind {
-                PointerEventKind::Enter { .. } => {}
-                PointerEventKind::Leave { .. } => {}
-                PointerEventKind::Motion { .. } => {}
-                PointerEventKind::Press { button, .. } => {
-                    // Handle mouse button press
+                PointerEventKind::Enter { .. } => {
+                    let change = self.pointer_cursor.enter(event.position, self.titlebar_height());
+                    self.change_cursor(conn, change);
+                }
+                PointerEventKind::Leave { .. } => self.pointer_cursor.leave(),
+                PointerEventKind::Motion { .. } => {
+                    let change = self.pointer_cursor.motion(event.position, self.titlebar_height());
+                    self.change_cursor(conn, change);
+                    if self.selecting {
+                        self.send_event(Event::SelectionUpdate(self.grid_position(event.position)));
+                    }
+                }
+                PointerEventKind::Press { button, serial, .. } => {
                     log::debug!("Mouse button press: {}", button);
+                    self.input_serial = *serial;
+                    let change = self.pointer_cursor.used(self.titlebar_height());
+                    self.change_cursor(conn, change);
+                    
+                    // The close button is the square at the right end of the client titlebar,
+                    // and dragging the rest of it moves the window
+                    let titlebar = self.titlebar_height() as f64;
+                    if event.position.1 < titlebar {
+                        if *button == BTN_LEFT && event.position.0 >= self.width as f64 - titlebar {
+                            self.send_event(Event::Close);
+                        } else if *button == BTN_LEFT {
+                            if let (Some(window), Some(seat)) = (&self.window, &self.seat) {
+                                window.move_(seat, *serial);
+                            }
+                        }
+                        continue;
+                    }
+                    
+                    // Dragging with the left button selects, as a block while Alt is held
+                    if *button == BTN_LEFT {
+                        self.selecting = true;
+                        self.send_event(Event::SelectionStart {
+                            position: self.grid_position(event.position),
+                            rectangular: self.modifiers.alt,
+                        });
+                    }
+                    
+                    if let Some(button) = MouseButton::from_code(*button) {
+                        let modifiers = self.modifiers_to_key_modifiers(&self.modifiers);
+                        self.send_event(Event::MousePress(MouseInput::new(button, modifiers)));
+                    }
                 }
                 PointerEventKind::Release { button, .. } => {
-                    // Handle mouse button release  
                     log::debug!("Mouse button release: {}", button);
+                    if *button == BTN_LEFT && self.selecting {
+                        self.selecting = false;
+                        self.send_event(Event::SelectionEnd);
+                    }
                 }
-                PointerEventKind::Axis { .. } => {
-                    // Handle scroll wheel
+                PointerEventKind::Axis { vertical, .. } => {
+                    let change = self.pointer_cursor.used(self.titlebar_height());
+                    self.change_cursor(conn, change);
+                    // Wheels report whole steps, touchpads only a distance
+                    let steps = if vertical.discrete != 0 {
+                        vertical.discrete as f64
+                    } else {
+                        vertical.absolute / PIXELS_PER_SCROLL_STEP
+                    };
+                    // Positive axis values scroll down, towards newer output
+                    if steps != 0.0 {
+                        self.send_event(Event::Scroll(-steps));
+                    }
                 }
             }
         }
     }
 }
 
+impl ActivationHandler for WaylandState {
+    type RequestData = RequestData;
+    
+    fn new_token(&mut self, token: String, data: &RequestData) {
+        // Activating an unfocused window with a fresh token marks it urgent
+        if let (Some(activation), Some(surface)) = (&self.activation, &data.surface) {
+            activation.activate::<Self>(surface, token);
+        }
+    }
+}
+
+impl Dispatch<WpFractionalScaleV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _proxy: &WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+            // The scale is sent as a numerator over 120
+            let scale = scale as f64 / 120.0;
+            log::info!("Using fractional scale {}", scale);
+            state.set_scale(scale);
+        }
+    }
+}
+
+/// What `request_presentation_feedback` was given for a frame.
+pub struct FrameFeedback {
+    output_since: Option<Instant>,
+}
+
+impl Dispatch<WpPresentation, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _proxy: &WpPresentation,
+        event: wp_presentation::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wp_presentation::Event::ClockId { clk_id } = event {
+            state.presentation_clock = nix::time::ClockId::from_raw(clk_id as libc::clockid_t);
+        }
+    }
+}
+
+impl Dispatch<WpPresentationFeedback, FrameFeedback> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _proxy: &WpPresentationFeedback,
+        event: wp_presentation_feedback::Event,
+        data: &FrameFeedback,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let (at, refresh) = match event {
+            wp_presentation_feedback::Event::Presented { tv_sec_hi, tv_sec_lo, tv_nsec, refresh, .. } => {
+                let timestamp = Duration::new((tv_sec_hi as u64) << 32 | tv_sec_lo as u64, tv_nsec);
+                // Timestamps are on the compositor's clock, which an Instant can't be made from
+                let now = Instant::now();
+                let at = match state.presentation_clock.now() {
+                    Ok(clock_now) => pacing::presented_at(timestamp, Duration::from(clock_now), now),
+                    Err(e) => {
+                        log::debug!("Failed to read the presentation clock: {}", e);
+                        now
+                    }
+                };
+                (Some(at), (refresh > 0).then(|| Duration::from_nanos(refresh as u64)))
+            }
+            wp_presentation_feedback::Event::Discarded => (None, None),
+            _ => return,
+        };
+        state.send_event(Event::Presented(Presentation { at, refresh, output_since: data.output_since }));
+    }
+}
+
+impl DataDeviceHandler for WaylandState {
+    fn enter(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataDevice, _: f64, _: f64, _: &wl_surface::WlSurface) {}
+    
+    fn leave(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataDevice) {}
+    
+    fn motion(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataDevice, _: f64, _: f64) {}
+    
+    /// The offer is looked up when pasting, as the data device keeps the latest.
+    fn selection(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataDevice) {}
+    
+    fn drop_performed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataDevice) {}
+}
+
+impl DataOfferHandler for WaylandState {
+    fn source_actions(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &mut DragOffer, _: DndAction) {}
+    
+    fn selected_action(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &mut DragOffer, _: DndAction) {}
+}
+
+impl DataSourceHandler for WaylandState {
+    fn accept_mime(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataSource, _: Option<String>) {}
+    
+    fn send_request(&mut self, _: &Connection, _: &QueueHandle<Self>, source: &WlDataSource, mime: String, fd: WritePipe) {
+        match &self.copy_source {
+            Some((ours, text)) if ours.inner() == source && clipboard::TEXT_MIME_TYPES.contains(&mime.as_str()) => {
+                clipboard::send_selection(fd.into(), text.clone());
+            }
+            _ => log::debug!("Not sending the clipboard as {}", mime),
+        }
+    }
+    
+    /// Another client took the clipboard.
+    fn cancelled(&mut self, _: &Connection, _: &QueueHandle<Self>, source: &WlDataSource) {
+        if self.copy_source.as_ref().is_some_and(|(ours, _)| ours.inner() == source) {
+            self.copy_source = None;
+        }
+    }
+    
+    fn dnd_dropped(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataSource) {}
+    
+    fn dnd_finished(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataSource) {}
+    
+    fn action(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataSource, _: DndAction) {}
+}
+
+impl PrimarySelectionDeviceHandler for WaylandState {
+    /// The offer is looked up when pasting, as the device keeps the latest.
+    fn selection(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &ZwpPrimarySelectionDeviceV1) {}
+}
+
+impl PrimarySelectionSourceHandler for WaylandState {
+    fn send_request(&mut self, _: &Connection, _: &QueueHandle<Self>, source: &ZwpPrimarySelectionSourceV1, mime: String, fd: WritePipe) {
+        match &self.primary_source {
+            Some((ours, text)) if ours.inner() == source && clipboard::TEXT_MIME_TYPES.contains(&mime.as_str()) => {
+                clipboard::send_selection(fd.into(), text.clone());
+            }
+            _ => log::debug!("Not sending the primary selection as {}", mime),
+        }
+    }
+    
+    /// Another client took the primary selection.
+    fn cancelled(&mut self, _: &Connection, _: &QueueHandle<Self>, source: &ZwpPrimarySelectionSourceV1) {
+        if self.primary_source.as_ref().is_some_and(|(ours, _)| ours.inner() == source) {
+            self.primary_source = None;
+        }
+    }
+}
+
+/// Dispatch for protocol objects that have no events.
+macro_rules! ignore_events {
+    ($($iface:ty),*) => {
+        $(
+            impl Dispatch<$iface, ()> for WaylandState {
+                fn event(
+                    _: &mut Self,
+                    _: &$iface,
+                    _: <$iface as Proxy>::Event,
+                    _: &(),
+                    _: &Connection,
+                    _: &QueueHandle<Self>,
+                ) {
+                }
+            }
+        )*
+    };
+}
+
+ignore_events!(WpFractionalScaleManagerV1, WpViewporter, WpViewport);
+
 impl ShmHandler for WaylandState {
     fn shm_state(&mut self) -> &mut Shm {
         &mut self.shm
@@ -453,6 +1313,9 @@ impl ProvidesRegistryState for WaylandState {
     registry_handlers![OutputState, SeatState];
 }
 
+delegate_activation!(WaylandState);
+delegate_data_device!(WaylandState);
+delegate_primary_selection!(WaylandState);
 delegate_compositor!(WaylandState);
 delegate_output!(WaylandState);
 delegate_shm!(WaylandState);
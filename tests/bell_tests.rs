@@ -0,0 +1,93 @@
+use myterm::bell::{Bell, BellResponse, Flash, RateLimiter, BELL_COMMAND_INTERVAL};
+use myterm::config::{BellConfig, BellMode};
+use std::time::{Duration, Instant};
+
+fn bell(mode: BellMode, command: &[&str]) -> Bell {
+    Bell::new(&BellConfig {
+        mode,
+        visual_duration_ms: 100,
+        visual_color: "#ffffff".to_string(),
+        command: command.iter().map(|s| s.to_string()).collect(),
+    })
+}
+
+#[test]
+fn test_bell_modes() {
+    let now = Instant::now();
+    let flash = Some(Duration::from_millis(100));
+    
+    assert_eq!(bell(BellMode::None, &[]).ring(now, false), BellResponse::default());
+    assert_eq!(
+        bell(BellMode::Visual, &[]).ring(now, false),
+        BellResponse { visual: flash, urgent: false, run_command: false }
+    );
+    assert_eq!(
+        bell(BellMode::Urgent, &[]).ring(now, false),
+        BellResponse { visual: None, urgent: true, run_command: false }
+    );
+    assert_eq!(
+        bell(BellMode::Both, &[]).ring(now, false),
+        BellResponse { visual: flash, urgent: true, run_command: false }
+    );
+    
+    // Urgency is only requested while the window is unfocused
+    assert!(!bell(BellMode::Both, &[]).ring(now, true).urgent);
+    
+    // The command runs independently of the mode
+    assert!(bell(BellMode::None, &["true"]).ring(now, true).run_command);
+}
+
+#[test]
+fn test_rate_limiter() {
+    let start = Instant::now();
+    let mut limiter = RateLimiter::new(Duration::from_millis(100));
+    
+    assert!(limiter.allow(start));
+    assert!(!limiter.allow(start));
+    assert!(!limiter.allow(start + Duration::from_millis(99)));
+    assert!(limiter.allow(start + Duration::from_millis(100)));
+    
+    // Rejected calls don't extend the window
+    assert!(!limiter.allow(start + Duration::from_millis(150)));
+    assert!(limiter.allow(start + Duration::from_millis(200)));
+}
+
+#[test]
+fn test_bell_command_rate_limited() {
+    let start = Instant::now();
+    let mut bell = bell(BellMode::Visual, &["true"]);
+    
+    // A flood of BELs runs the command once per interval but flashes every time
+    let responses: Vec<_> = (0..10)
+        .map(|i| bell.ring(start + BELL_COMMAND_INTERVAL / 4 * i, true))
+        .collect();
+    
+    assert_eq!(responses.iter().filter(|r| r.run_command).count(), 3);
+    assert!(responses.iter().all(|r| r.visual.is_some()));
+}
+
+#[test]
+fn test_visual_bell_flash() {
+    let start = Instant::now();
+    let duration = Duration::from_millis(100);
+    let mut flash = Flash::default();
+    assert_eq!(flash.deadline(), None);
+    
+    assert!(flash.trigger(start, duration));
+    assert_eq!(flash.deadline(), Some(start + duration));
+    
+    // Bells during the flash merge into it without extending it
+    assert!(!flash.trigger(start + Duration::from_millis(50), duration));
+    assert_eq!(flash.deadline(), Some(start + duration));
+    
+    assert!(!flash.expire(start + Duration::from_millis(99)));
+    assert!(flash.expire(start + duration));
+    assert_eq!(flash.deadline(), None);
+    assert!(!flash.expire(start + duration));
+    
+    // ...and so do bells right after it, until it's been over for as long as it showed
+    assert!(!flash.trigger(start + Duration::from_millis(150), duration));
+    assert_eq!(flash.deadline(), None);
+    assert!(flash.trigger(start + Duration::from_millis(200), duration));
+    assert_eq!(flash.deadline(), Some(start + Duration::from_millis(300)));
+}
\ No newline at end of file
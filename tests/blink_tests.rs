@@ -0,0 +1,38 @@
+use myterm::blink::BlinkTimeout;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_blink_stops_when_idle() {
+    let start = Instant::now();
+    let mut blink = BlinkTimeout::new(5000, start);
+    assert!(blink.blinking(start));
+    assert!(blink.blinking(start + Duration::from_millis(4999)));
+    assert_eq!(blink.deadline(), Some(start + Duration::from_secs(5)));
+    
+    // Solid once idle, until the next key press
+    let idle = start + Duration::from_secs(5);
+    assert!(!blink.blinking(idle));
+    assert!(!blink.blinking(idle + Duration::from_secs(60)));
+    let typed = idle + Duration::from_secs(60);
+    blink.input(typed);
+    assert!(blink.blinking(typed));
+    assert!(!blink.blinking(typed + Duration::from_secs(5)));
+    
+    // A changed timeout counts from the last key press
+    blink.set_timeout(10_000);
+    assert!(blink.blinking(typed + Duration::from_secs(5)));
+    assert!(!blink.blinking(typed + Duration::from_secs(10)));
+}
+
+#[test]
+fn test_blink_timeout_disabled() {
+    let start = Instant::now();
+    let mut blink = BlinkTimeout::new(0, start);
+    assert_eq!(blink.deadline(), None);
+    assert!(blink.blinking(start + Duration::from_secs(3600)));
+    
+    blink.set_timeout(1000);
+    assert!(!blink.blinking(start + Duration::from_secs(1)));
+    blink.set_timeout(0);
+    assert!(blink.blinking(start + Duration::from_secs(1)));
+}
\ No newline at end of file
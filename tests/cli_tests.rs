@@ -0,0 +1,81 @@
+use myterm::cli::Options;
+use myterm::config::Config;
+use myterm::error::{MyTermError, Result};
+use myterm::headless::DumpFormat;
+use std::path::PathBuf;
+
+fn parse(args: &[&str]) -> Result<Options> {
+    Options::parse(args.iter().map(|arg| arg.to_string()))
+}
+
+#[test]
+fn test_cli_config_flag() {
+    assert_eq!(parse(&[]).unwrap(), Options::default());
+    
+    let expected = Some(PathBuf::from("/tmp/my.toml"));
+    assert_eq!(parse(&["--config", "/tmp/my.toml"]).unwrap().config, expected);
+    assert_eq!(parse(&["--config=/tmp/my.toml"]).unwrap().config, expected);
+    assert_eq!(parse(&["-c", "/tmp/my.toml"]).unwrap().config, expected);
+    assert!(parse(&["-h"]).unwrap().help);
+    assert!(parse(&["--headless", "-c", "/tmp/my.toml"]).unwrap().headless);
+}
+
+#[test]
+fn test_cli_class_flag() {
+    let options = parse(&["--class", "myterm-scratchpad"]).unwrap();
+    assert_eq!(options.class.as_deref(), Some("myterm-scratchpad"));
+    assert_eq!(parse(&["--class=myterm-scratchpad"]).unwrap(), options);
+    
+    // The flag wins over the config file, which is otherwise left alone
+    let mut config: Config = toml::from_str("[display]\napp_id = \"work\"\ntitle = \"Notes\"\n").unwrap();
+    Options::default().apply(&mut config);
+    assert_eq!(config.display.app_id.as_deref(), Some("work"));
+    options.apply(&mut config);
+    assert_eq!(config.display.app_id.as_deref(), Some("myterm-scratchpad"));
+    assert_eq!(config.display.title.as_deref(), Some("Notes"));
+}
+
+#[test]
+fn test_cli_screenshot_flags() {
+    let options = parse(&["--screenshot", "out.png", "--after-ms=500", "-e", "ls", "-l", "--color"]).unwrap();
+    assert_eq!(options.screenshot, Some(PathBuf::from("out.png")));
+    assert_eq!(options.after_ms, Some(500));
+    
+    // Everything after -e belongs to the command, even what looks like our own flags
+    assert_eq!(options.command, ["ls", "-l", "--color"]);
+    assert_eq!(parse(&["--command", "htop", "-h"]).unwrap().command, ["htop", "-h"]);
+    assert!(!parse(&["-e", "vim", "--help"]).unwrap().help);
+}
+
+#[test]
+fn test_cli_headless_flags() {
+    let options = parse(&["--headless", "--size", "100x30", "--script=-", "--dump", "styled"]).unwrap();
+    assert!(options.headless);
+    assert_eq!(options.size, Some((100, 30)));
+    assert_eq!(options.script, Some(PathBuf::from("-")));
+    assert_eq!(options.dump, Some(DumpFormat::Styled));
+}
+
+#[test]
+fn test_cli_log_pty_flag() {
+    let options = parse(&["--log-pty", "/tmp/pty.log"]).unwrap();
+    assert_eq!(options.log_pty, Some(PathBuf::from("/tmp/pty.log")));
+    
+    let mut config = Config::default();
+    options.apply(&mut config);
+    assert_eq!(config.terminal.typescript, Some(PathBuf::from("/tmp/pty.log")));
+    assert!(!config.terminal.typescript_input);
+}
+
+#[test]
+fn test_cli_errors() {
+    assert!(parse(&["--config"]).is_err());
+    assert!(parse(&["--class"]).is_err());
+    assert!(parse(&["--log-pty"]).is_err());
+    assert!(matches!(parse(&["--frobnicate"]), Err(MyTermError::Parse(_))));
+    assert!(parse(&["stray"]).is_err());
+    assert!(parse(&["--after-ms", "soon"]).is_err());
+    assert!(parse(&["-e"]).is_err());
+    assert!(parse(&["--size", "100"]).is_err());
+    assert!(parse(&["--dump", "html"]).is_err());
+}
\ No newline at end of file
@@ -0,0 +1,37 @@
+use myterm::clipboard::{best_mime, read_text, write_chunked};
+use tokio::net::unix::pipe;
+
+fn offer(mimes: &[&str]) -> Vec<String> {
+    mimes.iter().map(|mime| mime.to_string()).collect()
+}
+
+#[test]
+fn test_best_mime() {
+    assert_eq!(best_mime(&offer(&["text/html", "text/plain", "text/plain;charset=utf-8"])), Some("text/plain;charset=utf-8"));
+    assert_eq!(best_mime(&offer(&["TARGETS", "STRING", "UTF8_STRING"])), Some("UTF8_STRING"));
+    assert_eq!(best_mime(&offer(&["image/png", "text/plain"])), Some("text/plain"));
+    assert_eq!(best_mime(&offer(&["image/png", "text/uri-list"])), None);
+    assert_eq!(best_mime(&[]), None);
+}
+
+#[tokio::test]
+async fn test_chunked_write_through_pipe() {
+    // Several megabytes, far more than a pipe holds, so the writer waits on the reader
+    let text: String = (0..4 << 20).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+    let (sender, receiver) = pipe::pipe().unwrap();
+    let writer = tokio::spawn({
+        let text = text.clone();
+        async move { write_chunked(sender, text.as_bytes()).await }
+    });
+    
+    assert_eq!(read_text(receiver).await.unwrap(), text);
+    writer.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_read_text_replaces_invalid_utf8() {
+    let (mut sender, receiver) = pipe::pipe().unwrap();
+    tokio::io::AsyncWriteExt::write_all(&mut sender, b"caf\xc3\xa9 \xff").await.unwrap();
+    drop(sender);
+    assert_eq!(read_text(receiver).await.unwrap(), "café \u{fffd}");
+}
\ No newline at end of file
@@ -1,6 +1,16 @@
-use myterm::config::{Config, parse_color};
+use myterm::config::{AmbiguousWidth, BellMode, Config, ConfigDelta, ConfigPaths, CursorShape, DecorationMode, Decorations, KeyBinding, RendererKind, MouseBinding, OutputOverride, Padding, DOCUMENTED_TEMPLATE, SYSTEM_CONFIG_PATH, expand_vars, parse_color, parse_color_with_alpha};
+use myterm::display::OutputInfo;
+use myterm::error::MyTermError;
+use myterm::font::FontSet;
+use myterm::palette::Palette;
+use myterm::render::{GlyphCache, GLYPH_CACHE_BYTES};
+use myterm::input::Action;
 use tempfile::tempdir;
+use std::collections::HashMap;
 use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
 #[test]
 fn test_default_config() {
@@ -9,9 +19,221 @@ fn test_default_config() {
     assert_eq!(config.display.width, 800);
     assert_eq!(config.display.height, 600);
     assert_eq!(config.display.opacity, 1.0);
-    assert_eq!(config.terminal.scrollback_lines, 10000);
+    assert_eq!(config.terminal.scrollback_lines, Some(10000));
     assert_eq!(config.font.family, "monospace");
     assert_eq!(config.font.size, 12.0);
+    assert!(config.terminal.scroll_on_keystroke);
+    assert!(!config.terminal.scroll_on_output);
+}
+
+#[test]
+fn test_partial_config_uses_defaults() {
+    let config: Config = toml::from_str("[terminal]\nscroll_on_output = true\n")
+        .expect("Failed to deserialize partial config");
+    
+    assert!(config.terminal.scroll_on_output);
+    assert!(config.terminal.scroll_on_keystroke);
+    assert_eq!(config.display.width, 800);
+    assert!(config.keybindings.is_empty());
+    
+    let config: Config = toml::from_str("[terminal]\nallow_blinking = false\n").unwrap();
+    assert!(!config.terminal.allow_blink);
+}
+
+#[test]
+fn test_scrollback_lines_parsing() {
+    let config: Config = toml::from_str("[terminal]\nscrollback_lines = 0\n").unwrap();
+    assert_eq!(config.terminal.scrollback_lines, Some(0));
+    
+    let config: Config = toml::from_str("[terminal]\nscrollback_lines = \"unlimited\"\n").unwrap();
+    assert_eq!(config.terminal.scrollback_lines, None);
+    
+    // Unlimited survives a round trip
+    let serialized = toml::to_string(&config).expect("Failed to serialize config");
+    let reparsed: Config = toml::from_str(&serialized).expect("Failed to reparse config");
+    assert_eq!(reparsed.terminal.scrollback_lines, None);
+    
+    assert!(toml::from_str::<Config>("[terminal]\nscrollback_lines = \"lots\"\n").is_err());
+}
+
+#[test]
+fn test_font_pixel_size() {
+    let mut config = Config::default();
+    config.font.size = 12.0;
+    
+    assert_eq!(config.font.pixel_size(1.0), 16.0);
+    assert_eq!(config.font.pixel_size(1.5), 24.0);
+    assert_eq!(config.font.pixel_size(1.25), 20.0);
+}
+
+#[test]
+fn test_decoration_mode_parsing() {
+    assert_eq!(Config::default().display.decoration_mode, DecorationMode::Auto);
+    
+    let config: Config = toml::from_str("[display]\ndecoration_mode = \"client\"\n").unwrap();
+    assert_eq!(config.display.decoration_mode, DecorationMode::Client);
+    assert!(toml::from_str::<Config>("[display]\ndecoration_mode = \"fancy\"\n").is_err());
+}
+
+#[test]
+fn test_decorations_parsing() {
+    assert_eq!(Config::default().display.decorations, Decorations::Full);
+    
+    let config: Config = toml::from_str("[display]\ndecorations = \"none\"\n").unwrap();
+    assert_eq!(config.display.decorations, Decorations::None);
+    assert!(toml::from_str::<Config>("[display]\ndecorations = \"some\"\n").is_err());
+    
+    // Configs from before it had names still work
+    let config: Config = toml::from_str("[display]\ndecorations = false\n").unwrap();
+    assert_eq!(config.display.decorations, Decorations::None);
+    let config: Config = toml::from_str("[display]\ndecorations = true\n").unwrap();
+    assert_eq!(config.display.decorations, Decorations::Full);
+}
+
+#[test]
+fn test_padding_parsing() {
+    assert_eq!(Config::default().display.padding, Padding::default());
+    
+    let config: Config = toml::from_str("[display]\npadding = 6\n").unwrap();
+    assert_eq!(config.display.padding, Padding::uniform(6));
+    
+    let config: Config = toml::from_str("[display]\npadding = { left = 4, right = 2 }\n").unwrap();
+    assert_eq!(config.display.padding, Padding { top: 0, bottom: 0, left: 4, right: 2 });
+    assert_eq!(config.display.padding.horizontal(), 6);
+    
+    let serialized = toml::to_string(&config).expect("Failed to serialize config");
+    let reparsed: Config = toml::from_str(&serialized).expect("Failed to reparse config");
+    assert_eq!(reparsed.display.padding, config.display.padding);
+}
+
+#[test]
+fn test_keybindings_table_parsing() {
+    let toml_str = r#"
+[[keybindings]]
+key = "Ctrl+Shift+PageUp"
+action = "ScrollPageUp"
+mode = "~AltScreen"
+
+[[keybindings]]
+key = "F13"
+action = { SendText = "git status\n" }
+
+[[keybindings]]
+key = "Ctrl+Shift+T"
+action = "None"
+"#;
+    let config: Config = toml::from_str(toml_str).expect("Failed to parse keybindings");
+    
+    assert_eq!(config.keybindings.len(), 3);
+    assert_eq!(
+        config.keybindings[0],
+        KeyBinding::new("Ctrl+Shift+PageUp", Action::ScrollPageUp, Some("~AltScreen"))
+    );
+    assert_eq!(config.keybindings[1].action, Action::SendText("git status\n".to_string()));
+    assert_eq!(config.keybindings[1].mode, None);
+    assert_eq!(config.keybindings[2].action, Action::None);
+    
+    // Round-trips through serialization
+    let serialized = toml::to_string(&config).expect("Failed to serialize config");
+    let reparsed: Config = toml::from_str(&serialized).expect("Failed to reparse config");
+    assert_eq!(reparsed.keybindings, config.keybindings);
+}
+
+#[test]
+fn test_keybinding_send_parsing() {
+    let toml_str = r#"
+[[keybindings]]
+keys = "Ctrl+Up"
+send = '\e[1;5A'
+mode = "AltScreen"
+
+[[keybindings]]
+key = "F13"
+send = "git status\n"
+"#;
+    let config: Config = toml::from_str(toml_str).expect("Failed to parse send bindings");
+    assert_eq!(config.keybindings, vec![
+        KeyBinding::new("Ctrl+Up", Action::SendText("\x1b[1;5A".to_string()), Some("AltScreen")),
+        KeyBinding::new("F13", Action::SendText("git status\n".to_string()), None),
+    ]);
+    
+    // A binding does one thing, and its escapes must make sense
+    assert!(toml::from_str::<Config>("[[keybindings]]\nkey = \"F1\"\nsend = \"x\"\naction = \"Copy\"\n").is_err());
+    assert!(toml::from_str::<Config>("[[keybindings]]\nkey = \"F1\"\n").is_err());
+    assert!(toml::from_str::<Config>("[[keybindings]]\nkey = \"F1\"\nsend = '\\q'\n").is_err());
+}
+
+#[test]
+fn test_config_diff_classification() {
+    let old = Config::default();
+    assert!(Config::diff(&old, &old.clone()).is_empty());
+    
+    let mut new = old.clone();
+    new.colors.background = "#101010".to_string();
+    new.terminal.cursor_shape = CursorShape::Beam;
+    new.display.padding = Padding::uniform(4);
+    new.keybindings.push(KeyBinding::new("F5", Action::ReloadConfig, None));
+    new.font.size = 14.0;
+    new.terminal.shell = Some("/bin/zsh".to_string());
+    
+    assert_eq!(Config::diff(&old, &new), ConfigDelta {
+        live: vec!["display.padding", "terminal.cursor_shape", "colors", "keybindings"],
+        resize: vec!["font.size"],
+        restart: vec!["terminal.shell"],
+    });
+}
+
+#[test]
+fn test_config_diff_scrollback() {
+    let old = Config::default();
+    
+    // Growing the scrollback applies live, shrinking needs a restart
+    let mut new = old.clone();
+    new.terminal.scrollback_lines = None;
+    assert_eq!(Config::diff(&old, &new).live, vec!["terminal.scrollback_lines"]);
+    
+    new.terminal.scrollback_lines = Some(100);
+    let delta = Config::diff(&old, &new);
+    assert!(delta.live.is_empty());
+    assert_eq!(delta.restart, vec!["terminal.scrollback_lines"]);
+    
+    let mut new = old.clone();
+    new.font.family = "Fira Code".to_string();
+    new.display.decoration_mode = DecorationMode::Client;
+    let delta = Config::diff(&old, &new);
+    assert_eq!(delta.resize, vec!["font.family"]);
+    assert_eq!(delta.restart, vec!["display.decoration_mode"]);
+    
+    let new: Config = toml::from_str("[display]\nrenderer = \"gpu\"").unwrap();
+    assert_eq!(old.display.renderer, RendererKind::Software);
+    assert_eq!(new.display.renderer, RendererKind::Gpu);
+    assert_eq!(Config::diff(&old, &new).restart, vec!["display.renderer"]);
+    
+    let new: Config = toml::from_str("[terminal]\nambiguous_width = \"wide\"").unwrap();
+    assert_eq!(old.terminal.ambiguous_width, AmbiguousWidth::Narrow);
+    assert_eq!(new.terminal.ambiguous_width, AmbiguousWidth::Wide);
+    assert_eq!(Config::diff(&old, &new).live, vec!["terminal.ambiguous_width"]);
+}
+
+#[test]
+fn test_keep_restart_settings() {
+    let old = Config::default();
+    let mut new = old.clone();
+    new.terminal.shell = Some("/bin/zsh".to_string());
+    new.terminal.scrollback_lines = Some(100);
+    new.display.title = Some("Other".to_string());
+    new.terminal.cursor_shape = CursorShape::Beam;
+    
+    // Only the live setting is taken, the rest waits for a restart
+    new.keep_restart_settings(&old);
+    let delta = Config::diff(&old, &new);
+    assert_eq!(delta.live, vec!["terminal.cursor_shape"]);
+    assert!(delta.restart.is_empty());
+    
+    // A larger scrollback applies live
+    new.terminal.scrollback_lines = None;
+    new.keep_restart_settings(&old);
+    assert_eq!(new.terminal.scrollback_lines, None);
 }
 
 #[test]
@@ -47,8 +269,23 @@ fn test_color_parsing() {
     
     // Test invalid colors
     assert!(parse_color("invalid").is_err());
-    assert!(parse_color("#gg0000").is_err());
+    assert!(matches!(parse_color("#gg0000"), Err(MyTermError::Parse(_))));
     assert!(parse_color("#ff00").is_err());
+    assert!(parse_color("#ff000080").is_err());
+}
+
+#[test]
+fn test_color_with_alpha_parsing() {
+    assert_eq!(parse_color_with_alpha("#102030").unwrap(), rgb::RGBA8::new(0x10, 0x20, 0x30, 0xff));
+    assert_eq!(parse_color_with_alpha("#10203080").unwrap(), rgb::RGBA8::new(0x10, 0x20, 0x30, 0x80));
+    assert!(parse_color_with_alpha("#1020304").is_err());
+    assert!(parse_color_with_alpha("#ééé").is_err());
+    
+    let mut config = Config::default();
+    config.colors.background = "#1e1e1ecc".to_string();
+    let palette = Palette::from_config(&config.colors);
+    assert_eq!(palette.background, rgb::RGB8::new(0x1e, 0x1e, 0x1e));
+    assert_eq!(palette.background_alpha, 0xcc);
 }
 
 #[test]
@@ -74,4 +311,357 @@ fn test_config_save_and_load() {
     assert_eq!(loaded_config.display.width, 1024);
     assert_eq!(loaded_config.display.height, 768);
     assert_eq!(loaded_config.font.size, 14.0);
+}
+
+#[test]
+fn test_mouse_config_parsing() {
+    let config = Config::default();
+    assert!(!config.mouse.hide_when_typing);
+    assert_eq!(config.mouse.scroll_multiplier, 3.0);
+    assert!(config.mouse.alternate_scroll);
+    
+    let config: Config = toml::from_str(r#"
+        [mouse]
+        hide_when_typing = true
+        scroll_multiplier = 1.5
+        
+        [[mouse.bindings]]
+        button = "Shift+Right"
+        action = "PastePrimary"
+    "#).expect("Failed to parse mouse config");
+    assert!(config.mouse.hide_when_typing);
+    assert_eq!(config.mouse.scroll_multiplier, 1.5);
+    assert!(config.mouse.alternate_scroll);
+    assert_eq!(config.mouse.bindings, vec![MouseBinding::new("Shift+Right", Action::PastePrimary, None)]);
+}
+
+#[test]
+fn test_documented_template() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let config_path = temp_dir.path().join("myterm").join("config.toml");
+    
+    Config::write_documented_template(&config_path).expect("Failed to write template");
+    let config = Config::load_from(&config_path).expect("Template should parse");
+    assert_eq!(config, Config::default());
+    
+    // Every listed setting must show its real default
+    let uncommented: String = DOCUMENTED_TEMPLATE
+        .lines()
+        .map(|line| match line.strip_prefix('#') {
+            Some(setting) if !setting.is_empty() && !setting.starts_with(' ') => setting,
+            _ => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let config: Config = toml::from_str(&uncommented).expect("Uncommented template should parse");
+    assert_eq!(config, Config::default());
+    assert!(uncommented.contains("scrollback_lines = 10000"));
+}
+
+#[test]
+fn test_bell_config_parsing() {
+    let config = Config::default();
+    assert_eq!(config.bell.mode, BellMode::Visual);
+    assert!(config.bell.command.is_empty());
+    
+    let toml_str = r#"
+[bell]
+mode = "both"
+visual_duration_ms = 80
+command = ["paplay", "/usr/share/sounds/bell.oga"]
+"#;
+    let config: Config = toml::from_str(toml_str).expect("Failed to parse bell config");
+    assert_eq!(config.bell.mode, BellMode::Both);
+    assert_eq!(config.bell.visual_duration_ms, 80);
+    assert_eq!(config.bell.command, vec!["paplay", "/usr/share/sounds/bell.oga"]);
+    
+    assert!(toml::from_str::<Config>("[bell]\nmode = \"loud\"\n").is_err());
+}
+
+#[test]
+fn test_config_imports_precedence() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    fs::create_dir(temp_dir.path().join("themes")).unwrap();
+    
+    fs::write(temp_dir.path().join("themes/dark.toml"), r##"
+[colors]
+foreground = "#eeeeee"
+background = "#111111"
+"##).unwrap();
+    fs::write(temp_dir.path().join("local.toml"), r##"
+import = ["themes/dark.toml"]
+
+[colors]
+background = "#222222"
+
+[font]
+size = 15.0
+"##).unwrap();
+    fs::write(temp_dir.path().join("config.toml"), r##"
+import = ["./local.toml", "missing.toml"]
+
+[font]
+family = "Fira Code"
+"##).unwrap();
+
+    let config = Config::load_from(&temp_dir.path().join("config.toml")).expect("Failed to load config");
+    
+    // Later files override earlier ones and the main file overrides all imports
+    assert_eq!(config.colors.foreground, "#eeeeee");
+    assert_eq!(config.colors.background, "#222222");
+    assert_eq!(config.font.size, 15.0);
+    assert_eq!(config.font.family, "Fira Code");
+    
+    // Keys nobody sets keep their defaults
+    assert_eq!(config.display.width, 800);
+}
+
+#[test]
+fn test_config_import_cycle() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    fs::write(temp_dir.path().join("a.toml"), "import = [\"b.toml\"]\n").unwrap();
+    fs::write(temp_dir.path().join("b.toml"), "import = [\"a.toml\"]\n").unwrap();
+    
+    let err = Config::load_from(&temp_dir.path().join("a.toml")).unwrap_err();
+    assert!(format!("{:#}", err).contains("cycle"));
+}
+
+#[test]
+fn test_config_import_errors_name_the_file() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    fs::write(temp_dir.path().join("broken.toml"), "[font]\nsize = = 3\n").unwrap();
+    fs::write(temp_dir.path().join("config.toml"), "import = [\"broken.toml\"]\n").unwrap();
+    
+    let err = format!("{:#}", Config::load_from(&temp_dir.path().join("config.toml")).unwrap_err());
+    assert!(err.contains("broken.toml"));
+    assert!(err.contains("line 2"));
+}
+
+#[test]
+fn test_config_path_precedence() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let home = temp_dir.path().join("home");
+    let xdg = temp_dir.path().join("xdg");
+    let vars: HashMap<&str, PathBuf> = HashMap::from([
+        ("MYTERM_CONFIG", temp_dir.path().join("env.toml")),
+        ("MYTERM_CONFIG_HOME", temp_dir.path().join("config-home")),
+        ("XDG_CONFIG_HOME", xdg.clone()),
+    ]);
+    let resolve = |cli: Option<&Path>, names: &[&str]| {
+        let env = |name: &str| names.contains(&name).then(|| vars[name].clone().into_os_string());
+        ConfigPaths::resolve(cli, env, Some(home.clone())).unwrap()
+    };
+    let all = ["MYTERM_CONFIG", "MYTERM_CONFIG_HOME", "XDG_CONFIG_HOME"];
+    
+    // The flag beats every variable, and names a file that must exist
+    let paths = resolve(Some(Path::new("/tmp/flag.toml")), &all);
+    assert_eq!(paths.user, PathBuf::from("/tmp/flag.toml"));
+    assert!(paths.explicit);
+    assert_eq!(paths.system, Some(PathBuf::from(SYSTEM_CONFIG_PATH)));
+    
+    assert_eq!(resolve(None, &all).user, temp_dir.path().join("env.toml"));
+    let paths = resolve(None, &all[1..]);
+    assert_eq!(paths.user, temp_dir.path().join("config-home/config.toml"));
+    assert!(!paths.explicit);
+    
+    // Without any config file the XDG location is where the template goes
+    assert_eq!(resolve(None, &all[2..]).user, xdg.join("myterm/config.toml"));
+    assert_eq!(resolve(None, &[]).user, home.join(".config/myterm/config.toml"));
+    
+    // An existing ~/.config file is found when the XDG directory has none
+    let fallback = home.join(".config/myterm/config.toml");
+    fs::create_dir_all(fallback.parent().unwrap()).unwrap();
+    fs::write(&fallback, "").unwrap();
+    assert_eq!(resolve(None, &all[2..]).user, fallback);
+}
+
+#[test]
+fn test_config_path_from_environment() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    
+    // Each config's shell prints which config it came from
+    let config = |name: &str| {
+        let shell = temp_dir.path().join(format!("{}.sh", name));
+        fs::write(&shell, format!("#!/bin/sh\necho {}\n", name)).unwrap();
+        fs::set_permissions(&shell, fs::Permissions::from_mode(0o755)).unwrap();
+        let path = temp_dir.path().join(name).join("config.toml");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, format!("[terminal]\nshell = {:?}\n", shell)).unwrap();
+        path
+    };
+    let (from_env, from_home, from_flag) = (config("env"), config("home"), config("flag"));
+    
+    // The variables are set for the child only, so tests running alongside never see them
+    let shown = |args: &[&Path], vars: &[(&str, &Path)]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_myterm"))
+            .env_remove("MYTERM_CONFIG")
+            .env_remove("MYTERM_CONFIG_HOME")
+            .envs(vars.iter().copied())
+            .args(["--headless", "--size", "30x5", "--dump", "text"])
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap().lines().next().unwrap_or_default().to_string()
+    };
+    
+    let home = ("MYTERM_CONFIG_HOME", from_home.parent().unwrap());
+    assert_eq!(shown(&[], &[home]), "home");
+    assert_eq!(shown(&[], &[home, ("MYTERM_CONFIG", &from_env)]), "env");
+    assert_eq!(shown(&[Path::new("--config"), &from_flag], &[home, ("MYTERM_CONFIG", &from_env)]), "flag");
+}
+
+#[test]
+fn test_user_config_merges_over_system() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let system = temp_dir.path().join("system.toml");
+    let user = temp_dir.path().join("user/config.toml");
+    fs::write(&system, "[font]\nfamily = \"Fira Code\"\nsize = 14.0\n[colors]\nbackground = \"#101010\"\n").unwrap();
+    
+    let mut paths = ConfigPaths { system: Some(system), user: user.clone(), explicit: false };
+    
+    // First run writes the template, which leaves the system settings in place
+    let config = Config::load(&paths).unwrap();
+    assert!(user.exists());
+    assert_eq!(config.font.family, "Fira Code");
+    assert_eq!(config.font.size, 14.0);
+    
+    fs::write(&user, "[font]\nsize = 10.0\n").unwrap();
+    let config = Config::load(&paths).unwrap();
+    assert_eq!(config.font.family, "Fira Code");
+    assert_eq!(config.font.size, 10.0);
+    assert_eq!(config.colors.background, "#101010");
+    
+    // A config named on the command line has to exist
+    paths.user = temp_dir.path().join("missing.toml");
+    paths.explicit = true;
+    assert!(matches!(Config::load(&paths), Err(MyTermError::Config(_))));
+    assert!(!paths.user.exists());
+}
+
+fn output(name: &str, physical_size: (i32, i32), mode_size: (i32, i32), scale_factor: i32) -> OutputInfo {
+    OutputInfo { name: Some(name.to_string()), physical_size, mode_size, scale_factor }
+}
+
+#[test]
+fn test_output_overrides_parsing() {
+    let toml_str = r#"
+        [display]
+        scale_with_dpi = true
+        
+        [display.output_overrides]
+        "eDP-1" = { font_size = 13 }
+        "HDMI-A-1" = {}
+    "#;
+    let config: Config = toml::from_str(toml_str).unwrap();
+    
+    assert!(config.display.scale_with_dpi);
+    assert_eq!(config.display.output_overrides["eDP-1"].font_size, Some(13.0));
+    assert_eq!(config.display.output_overrides["HDMI-A-1"].font_size, None);
+}
+
+#[test]
+fn test_font_size_for_output() {
+    let mut config = Config::default();
+    config.font.size = 12.0;
+    
+    // A 4K 14" laptop panel at scale 2 is ~157 DPI per surface pixel, a 24" 1080p monitor ~92
+    let laptop = output("eDP-1", (310, 174), (3840, 2160), 2);
+    let external = output("HDMI-A-1", (531, 299), (1920, 1080), 1);
+    assert!((laptop.dpi().unwrap() - 157.4).abs() < 1.0);
+    assert!((external.dpi().unwrap() - 91.8).abs() < 1.0);
+    
+    // Without DPI scaling or overrides every output uses font.size
+    assert_eq!(config.font_size_for(None), 12.0);
+    assert_eq!(config.font_size_for(Some(&laptop)), 12.0);
+    
+    config.display.scale_with_dpi = true;
+    assert!((config.font_size_for(Some(&laptop)) - 19.7).abs() < 0.1);
+    assert!((config.font_size_for(Some(&external)) - 11.5).abs() < 0.1);
+    
+    // Outputs without a physical size, like projectors, are left alone
+    let projector = output("DP-2", (0, 0), (1920, 1080), 1);
+    assert_eq!(projector.dpi(), None);
+    assert_eq!(config.font_size_for(Some(&projector)), 12.0);
+    
+    // An override for the output by name beats DPI scaling
+    config.display.output_overrides.insert("eDP-1".to_string(), OutputOverride { font_size: Some(13.0) });
+    assert_eq!(config.font_size_for(Some(&laptop)), 13.0);
+    let unnamed = OutputInfo { name: None, ..laptop.clone() };
+    assert!((config.font_size_for(Some(&unnamed)) - 19.7).abs() < 0.1);
+}
+
+#[test]
+fn test_window_size_in_cells() {
+    let config: Config = toml::from_str("[display]\ncolumns = 120\nrows = 40\n").unwrap();
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let metrics = glyphs.metrics();
+    let cell_size = (metrics.cell_width, metrics.cell_height);
+    assert_eq!(config.display.window_size(cell_size), (120 * metrics.cell_width, 40 * metrics.cell_height));
+    
+    // Padding is added around the cells, and an unset count falls back to pixels
+    let config: Config = toml::from_str("[display]\ncolumns = 80\nheight = 500\npadding = 4\n").unwrap();
+    assert_eq!(config.display.window_size((9, 18)), (80 * 9 + 8, 500));
+    assert_eq!(Config::default().display.window_size((9, 18)), (800, 600));
+}
+
+#[test]
+fn test_color_theme_file() {
+    let temp_dir = tempdir().unwrap();
+    fs::create_dir(temp_dir.path().join("themes")).unwrap();
+    fs::write(temp_dir.path().join("themes/dark.toml"), "background = \"#101010\"\n").unwrap();
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "[colors]\nforeground = \"#eeeeee\"\nbackground = \"#ffffff\"\ntheme = \"themes/dark.toml\"\n",
+    ).unwrap();
+    
+    // The theme wins over the inline background and leaves everything else alone
+    let config = Config::load_from(&temp_dir.path().join("config.toml")).unwrap();
+    assert_eq!(config.colors.background, "#101010");
+    assert_eq!(config.colors.foreground, "#eeeeee");
+    assert_eq!(config.colors.normal, Config::default().colors.normal);
+    
+    // The path is kept as resolved, for the theme to be watched too
+    assert_eq!(config.colors.theme, Some(temp_dir.path().join("themes/dark.toml")));
+    
+    // Variables in the path are expanded, unset ones to nothing
+    let env = |name: &str| (name == "THEMES").then(|| "/usr/share/themes".to_string());
+    assert_eq!(expand_vars("${THEMES}/dark.toml", env), "/usr/share/themes/dark.toml");
+    assert_eq!(expand_vars("$THEMES/dark.toml", env), "/usr/share/themes/dark.toml");
+    assert_eq!(expand_vars("$UNSET/dark.toml", env), "/dark.toml");
+    assert_eq!(expand_vars("$/dark.toml", env), "$/dark.toml");
+    
+    // A broken or missing theme falls back to the inline colors
+    fs::write(temp_dir.path().join("themes/dark.toml"), "background = [\n").unwrap();
+    let config = Config::load_from(&temp_dir.path().join("config.toml")).unwrap();
+    assert_eq!(config.colors.background, "#ffffff");
+    fs::write(temp_dir.path().join("themes/dark.toml"), "background = 3\n").unwrap();
+    let config = Config::load_from(&temp_dir.path().join("config.toml")).unwrap();
+    assert_eq!(config.colors.background, "#ffffff");
+    fs::remove_file(temp_dir.path().join("themes/dark.toml")).unwrap();
+    let config = Config::load_from(&temp_dir.path().join("config.toml")).unwrap();
+    assert_eq!(config.colors.background, "#ffffff");
+}
+
+#[test]
+fn test_fullscreen_output_parsing() {
+    let config: Config = toml::from_str("[display]\nstartup_mode = \"Fullscreen\"\nfullscreen_output = \"HDMI-A-1\"\n").unwrap();
+    assert_eq!(config.display.fullscreen_output.as_deref(), Some("HDMI-A-1"));
+    assert_eq!(Config::default().display.fullscreen_output, None);
+    
+    // Empty, as in the template, leaves it to the compositor
+    let config: Config = toml::from_str("[display]\nfullscreen_output = \"\"\n").unwrap();
+    assert_eq!(config.display.fullscreen_output, None);
+}
+
+#[test]
+fn test_typescript_parsing() {
+    let config: Config = toml::from_str("[terminal]\ntypescript = \"/tmp/pty.log\"\n").unwrap();
+    assert_eq!(config.terminal.typescript, Some(PathBuf::from("/tmp/pty.log")));
+    
+    // Empty, as in the template, records nothing
+    let config: Config = toml::from_str("[terminal]\ntypescript = \"\"\n").unwrap();
+    assert_eq!(config.terminal.typescript, None);
 }
\ No newline at end of file
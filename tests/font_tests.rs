@@ -0,0 +1,157 @@
+use myterm::config::FontConfig;
+use myterm::font::{Fallback, Font, FontSet, FontStyle, Glyph, Metrics};
+use std::path::{Path, PathBuf};
+
+#[test]
+fn test_embedded_font_metrics() {
+    let mut font = Font::embedded().expect("Failed to open the embedded font");
+    
+    // DejaVu Sans Mono advances 1233 of 2048 units, rising 1901 and falling 483, with a
+    // 90 unit underline centered 85 below the baseline and a 1120 unit x-height
+    font.set_pixel_size(16).unwrap();
+    let metrics = Metrics {
+        cell_width: 10,
+        cell_height: 19,
+        ascent: 15,
+        descent: 4,
+        underline_position: 1,
+        underline_thickness: 1,
+        strikeout_position: 4,
+    };
+    assert_eq!(font.metrics(), metrics);
+    
+    // Hinting rounds the advance to whole pixels
+    font.set_pixel_size(32).unwrap();
+    let metrics = font.metrics();
+    assert_eq!((metrics.cell_width, metrics.cell_height), (19, 38));
+    
+    // The underline scales with the font, its top moving up as it thickens
+    font.set_pixel_size(64).unwrap();
+    let metrics = font.metrics();
+    assert_eq!((metrics.underline_position, metrics.underline_thickness), (2, 3));
+}
+
+#[test]
+fn test_rasterize() {
+    let mut font = Font::embedded().unwrap();
+    font.set_pixel_size(16).unwrap();
+    
+    let glyph = font.rasterize('M').expect("No glyph for M");
+    assert_eq!(glyph.coverage.len(), glyph.width * glyph.height);
+    assert!(glyph.width > 0 && glyph.width <= 10);
+    assert!(glyph.top > 0 && glyph.top <= 15);
+    assert!(glyph.coverage.contains(&255));
+    
+    // Spaces have an advance but nothing to draw
+    let space = font.rasterize(' ').expect("No glyph for space");
+    assert!(space.coverage.is_empty());
+}
+
+#[test]
+fn test_style_families() {
+    let mut config = FontConfig { family: "Fira Code".to_string(), ..FontConfig::default() };
+    config.bold_family = Some("Fira Code Bold".to_string());
+    
+    assert_eq!(FontStyle::Regular.family(&config), "Fira Code");
+    assert_eq!(FontStyle::Bold.family(&config), "Fira Code Bold");
+    assert_eq!(FontStyle::Italic.family(&config), "Fira Code");
+    assert_eq!(FontStyle::BoldItalic.family(&config), "Fira Code");
+}
+
+#[test]
+fn test_missing_family_is_named() {
+    let err = Font::find("No Such Family 1234", FontStyle::Regular).err().expect("Found a font that doesn't exist");
+    assert!(err.to_string().contains("No Such Family 1234"), "{}", err);
+}
+
+#[test]
+fn test_missing_family_falls_back() {
+    // Ends at the embedded font on systems without any monospace font
+    let config = FontConfig { family: "No Such Family 1234".to_string(), ..FontConfig::default() };
+    let mut font = Font::load(&config, FontStyle::Regular).expect("No fallback font");
+    
+    font.set_pixel_size(16).unwrap();
+    assert!(font.rasterize('a').is_some());
+}
+
+#[test]
+fn test_synthesized_styles() {
+    let mut regular = Font::embedded().unwrap();
+    regular.set_pixel_size(16).unwrap();
+    let upright = regular.rasterize('|').unwrap();
+    
+    let mut bold = Font::embedded().unwrap();
+    bold.synthesize(FontStyle::Bold);
+    bold.set_pixel_size(16).unwrap();
+    let heavy = bold.rasterize('|').unwrap();
+    let ink = |glyph: &Glyph| glyph.coverage.iter().map(|&c| c as u32).sum::<u32>();
+    assert!(ink(&heavy) > ink(&upright));
+    assert_eq!(heavy.height, upright.height);
+    
+    // Slanting moves the top of the stem right of its bottom
+    let mut italic = Font::embedded().unwrap();
+    italic.synthesize(FontStyle::Italic);
+    italic.set_pixel_size(16).unwrap();
+    let slanted = italic.rasterize('|').unwrap();
+    let center = |glyph: &Glyph, row: usize| {
+        let row = &glyph.coverage[row * glyph.width..][..glyph.width];
+        let total = row.iter().map(|&c| c as f32).sum::<f32>();
+        row.iter().enumerate().map(|(x, &c)| x as f32 * c as f32).sum::<f32>() / total
+    };
+    assert!(center(&slanted, 0) > center(&slanted, slanted.height - 1) + 1.0);
+    assert!((center(&upright, 0) - center(&upright, upright.height - 1)).abs() < 1.0);
+}
+
+#[test]
+fn test_style_from_flags() {
+    assert_eq!(FontStyle::from_flags(false, false), FontStyle::Regular);
+    assert_eq!(FontStyle::from_flags(true, false), FontStyle::Bold);
+    assert_eq!(FontStyle::from_flags(false, true), FontStyle::Italic);
+    assert_eq!(FontStyle::from_flags(true, true), FontStyle::BoldItalic);
+}
+
+/// Writes a bitmap font with solid 8x16 glyphs for printable ASCII and nothing else.
+fn ascii_only_font(dir: &Path) -> PathBuf {
+    let mut bdf = String::from(concat!(
+        "STARTFONT 2.1\nFONT -test-ascii-medium-r-normal--16-120-96-96-c-80-iso10646-1\n",
+        "SIZE 12 96 96\nFONTBOUNDINGBOX 8 16 0 -3\n",
+        "STARTPROPERTIES 6\nPIXEL_SIZE 16\nFONT_ASCENT 13\nFONT_DESCENT 3\nSPACING \"C\"\n",
+        "CHARSET_REGISTRY \"ISO10646\"\nCHARSET_ENCODING \"1\"\nENDPROPERTIES\nCHARS 95\n",
+    ));
+    for c in ' '..='~' {
+        bdf += &format!("STARTCHAR U+{0:04X}\nENCODING {0}\nSWIDTH 500 0\nDWIDTH 8 0\nBBX 8 16 0 -3\nBITMAP\n", c as u32);
+        bdf += &"FF\n".repeat(16);
+        bdf += "ENDCHAR\n";
+    }
+    bdf += "ENDFONT\n";
+    let path = dir.join("ascii.bdf");
+    std::fs::write(&path, bdf).unwrap();
+    path
+}
+
+#[test]
+fn test_fallback_fonts() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = ascii_only_font(dir.path());
+    let primary = Font::from_file(&path, 0).unwrap();
+    assert!(primary.has_glyph('A') && !primary.has_glyph('─') && !primary.has_glyph('Ж'));
+    
+    let fallback = Fallback::new(vec![Font::embedded().unwrap()], false);
+    let mut fonts = FontSet::synthesized(|| Font::from_file(&path, 0), fallback).unwrap();
+    fonts.set_pixel_size(16).unwrap();
+    let metrics = fonts.metrics();
+    assert_eq!((metrics.ascent, metrics.descent), (13, 3));
+    
+    // Latin comes from the primary font, and box drawing and Cyrillic from the fallback, shrunk
+    // from its 19 pixel lines to fit the primary's cells around the same baseline
+    for style in [FontStyle::Regular, FontStyle::Bold] {
+        for c in "Ab─│Жя".chars() {
+            let glyph = fonts.rasterize(c, style).unwrap();
+            let solid = glyph.coverage.iter().all(|&coverage| coverage == 255);
+            assert_eq!(solid, c.is_ascii(), "{:?}", c);
+            assert!(glyph.top <= metrics.ascent && glyph.height as i32 - glyph.top <= metrics.descent, "{:?}", c);
+        }
+    }
+    let bar = fonts.rasterize('│', FontStyle::Regular).unwrap();
+    assert!(bar.height >= 14);
+}
\ No newline at end of file
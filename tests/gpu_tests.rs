@@ -0,0 +1,134 @@
+#![cfg(feature = "gpu")]
+
+use myterm::config::{Config, CursorShape};
+use myterm::font::FontSet;
+use myterm::gpu::{GpuRenderer, ATLAS_SIZE};
+use myterm::render::{argb_pixel, scale_image, titlebar, CursorSprite, Frame, FrameImage, GlyphCache, GridLayout, Rect, RetainedCanvas, Scrollbar, BYTES_PER_PIXEL, GLYPH_CACHE_BYTES};
+use myterm::selection::Point;
+use myterm::sixel::SixelImage;
+use rgb::RGBA8;
+use std::sync::Arc;
+use myterm::terminal::TerminalPerformer;
+use vte::Parser;
+
+/// Channels may differ this much from the software renderer, which rounds differently.
+const TOLERANCE: u8 = 2;
+
+/// A headless renderer, or `None` where no adapter is available, as in most CI.
+fn gpu() -> Option<GpuRenderer> {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    match runtime.block_on(GpuRenderer::headless()) {
+        Ok(renderer) => Some(renderer),
+        Err(e) => {
+            eprintln!("Skipping GPU test: {}", e);
+            None
+        }
+    }
+}
+
+fn performer(text: &str) -> TerminalPerformer {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(6, 20, &config);
+    let mut parser = Parser::new();
+    for &byte in text.as_bytes() {
+        parser.advance(&mut performer, byte);
+    }
+    performer
+}
+
+/// A frame of a 20x6 grid of 10x19 cells, under a titlebar and 3 pixels in from the edges.
+fn frame(performer: &TerminalPerformer, cursor: CursorSprite) -> Frame {
+    Frame {
+        size: (206, 144),
+        layout: GridLayout { origin: (3, 24), cell_width: 10, cell_height: 19 },
+        background: argb_pixel(performer.palette.background, 0.8),
+        titlebar: Some(titlebar(206, 21, 10, "~/src: vim", argb_pixel(performer.palette.bright[0], 1.0), performer.palette.foreground, performer.palette.background)),
+        palette: performer.palette.clone(),
+        rows: performer.styled_snapshot(),
+        cursor: Some(cursor),
+        images: Vec::new(),
+        scrollbar: None,
+        flash: None,
+    }
+}
+
+/// Asserts that the GPU draws `frame` as the software renderer does.
+fn assert_matches_software(gpu: &mut GpuRenderer, frame: &Frame, glyphs: &mut GlyphCache) {
+    let mut canvas = RetainedCanvas::new();
+    canvas.draw(frame.clone(), glyphs);
+    gpu.render_offscreen(frame, glyphs).unwrap();
+    let pixels = gpu.read_pixels().unwrap();
+    
+    assert_eq!(pixels.len(), canvas.pixels().len());
+    let stride = frame.size.0 * BYTES_PER_PIXEL;
+    for (offset, (gpu_pixel, software_pixel)) in pixels.chunks_exact(4).zip(canvas.pixels().chunks_exact(4)).enumerate() {
+        let close = gpu_pixel.iter().zip(software_pixel).all(|(a, b)| a.abs_diff(*b) <= TOLERANCE);
+        let (x, y) = (offset * BYTES_PER_PIXEL % stride / BYTES_PER_PIXEL, offset * BYTES_PER_PIXEL / stride);
+        assert!(close, "GPU drew {:?} at ({}, {}) where software drew {:?}", gpu_pixel, x, y, software_pixel);
+    }
+}
+
+#[test]
+fn test_gpu_matches_software() {
+    let Some(mut gpu) = gpu() else {
+        return;
+    };
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    
+    // Colors, bold and italic faces, a double-width character, decorations and glyphs reaching
+    // below their cells
+    let performer = performer(concat!(
+        "\x1b[31mred\x1b[0m \x1b[1mbold\x1b[0m \x1b[3mitalic\x1b[0m\r\n",
+        "\x1b[42;30m black on green \x1b[0m 中\r\n",
+        "\x1b[4msingle\x1b[0m \x1b[4:3mcurly\x1b[0m \x1b[4:4mdots\x1b[0m\r\n",
+        "\x1b[21mdouble\x1b[0m \x1b[4:5mdashes\x1b[0m \x1b[9mstrike\x1b[0m\r\n",
+        "gjpqy \x1b[7mreverse\x1b[0m",
+    ));
+    let block = CursorSprite { point: Point::new(4, 2), shape: CursorShape::Block, hollow: false };
+    assert_matches_software(&mut gpu, &frame(&performer, block.clone()), &mut glyphs);
+    
+    for (shape, hollow) in [(CursorShape::Beam, false), (CursorShape::Underline, false), (CursorShape::Block, true)] {
+        let cursor = CursorSprite { point: Point::new(1, 17), shape, hollow };
+        assert_matches_software(&mut gpu, &frame(&performer, cursor), &mut glyphs);
+    }
+    
+    // Images over the cells, translucent pixels included, one reaching above the grid
+    let pixels = vec![RGBA8::new(255, 0, 0, 255), RGBA8::new(0, 0, 255, 128), RGBA8::new(0, 255, 0, 0), RGBA8::new(255, 255, 0, 255)];
+    let bitmap = Arc::new(scale_image(&SixelImage { width: 2, height: 2, pixels: pixels.clone() }, 30, 38));
+    let mut with_images = frame(&performer, block.clone());
+    with_images.images = vec![
+        FrameImage { origin: (13, 43), bitmap: bitmap.clone() },
+        FrameImage { origin: (153, 10), bitmap },
+    ];
+    assert_matches_software(&mut gpu, &with_images, &mut glyphs);
+    
+    // One larger than the atlas, drawn by the tiles in view
+    let width = ATLAS_SIZE as usize + 100;
+    let bitmap = Arc::new(scale_image(&SixelImage { width: 2, height: 2, pixels }, width, 60));
+    let mut with_large_image = frame(&performer, block.clone());
+    with_large_image.images = vec![FrameImage { origin: (100 - width as i32 / 2, 30), bitmap }];
+    assert_matches_software(&mut gpu, &with_large_image, &mut glyphs);
+    
+    // The translucent scrollbar and bell flash are blended over everything
+    let mut overlaid = frame(&performer, block);
+    overlaid.scrollbar = Some(Scrollbar { thumb: Rect { x: 202, y: 40, width: 4, height: 30 }, pixel: [60, 60, 60, 128] });
+    overlaid.flash = Some(argb_pixel(rgb::RGB8::new(255, 255, 255), 0.3));
+    assert_matches_software(&mut gpu, &overlaid, &mut glyphs);
+}
+
+#[test]
+fn test_gpu_glyphs_follow_font_size() {
+    let Some(mut gpu) = gpu() else {
+        return;
+    };
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    let performer = performer("The quick brown fox\r\njumps over the lazy dog");
+    let cursor = CursorSprite { point: Point::new(0, 0), shape: CursorShape::Beam, hollow: false };
+    
+    // Glyphs already in the atlas at one size aren't reused at another
+    for size in [16, 12, 16] {
+        glyphs.set_pixel_size(size).unwrap();
+        assert_matches_software(&mut gpu, &frame(&performer, cursor.clone()), &mut glyphs);
+    }
+}
\ No newline at end of file
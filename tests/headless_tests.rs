@@ -0,0 +1,140 @@
+use myterm::headless::{parse_size, DumpFormat, ScriptCommand};
+use myterm::input::Key;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Runs myterm headless on a 30x5 grid with `args`, feeding it `stdin`, and returns what it
+/// printed.
+fn myterm(args: &[&str], stdin: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_myterm"))
+        .args(["--config", "/dev/null", "--headless", "--size", "30x5"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_script_commands() {
+    assert_eq!(ScriptCommand::parse("type ls -l\\n").unwrap(), Some(ScriptCommand::Type(b"ls -l\n".to_vec())));
+    assert_eq!(ScriptCommand::parse("key Ctrl+C").unwrap(), Some(ScriptCommand::Key(Key::ctrl('c'))));
+    assert_eq!(ScriptCommand::parse("wait 250").unwrap(), Some(ScriptCommand::Wait(Duration::from_millis(250))));
+    assert_eq!(ScriptCommand::parse("resize 100x30").unwrap(), Some(ScriptCommand::Resize(100, 30)));
+    assert_eq!(ScriptCommand::parse("dump").unwrap(), Some(ScriptCommand::Dump(DumpFormat::Text)));
+    assert_eq!(ScriptCommand::parse("dump styled").unwrap(), Some(ScriptCommand::Dump(DumpFormat::Styled)));
+    assert_eq!(ScriptCommand::parse("close").unwrap(), Some(ScriptCommand::Close));
+    
+    // Blank lines and comments do nothing, and anything else is an error
+    assert_eq!(ScriptCommand::parse("  ").unwrap(), None);
+    assert_eq!(ScriptCommand::parse("# set up").unwrap(), None);
+    for line in ["wait soon", "resize 80", "dump html", "jump"] {
+        assert!(ScriptCommand::parse(line).is_err(), "{:?} parsed", line);
+    }
+    
+    assert_eq!(parse_size("80x24"), Some((80, 24)));
+    assert_eq!(parse_size("0x24"), None);
+    assert_eq!(parse_size("80"), None);
+}
+
+#[test]
+fn test_headless_dump_at_exit() {
+    let screen = myterm(&["--dump", "text", "-e", "sh", "-c", "printf 'one\\r\\n\\033[1mtwo\\033[0m  \\r\\n'"], "");
+    assert_eq!(screen, "one\ntwo\n");
+    
+    // Styled dumps carry the colors and attributes of each run of text
+    let screen = myterm(&["--dump", "styled", "-e", "sh", "-c", "printf '\\033[1;31mred\\033[0m ok'"], "");
+    assert_eq!(screen, "\x1b[0;1;38;2;128;0;0;48;2;0;0;0mred\x1b[0;38;2;255;255;255;48;2;0;0;0m ok\x1b[0m\n");
+}
+
+#[test]
+fn test_headless_script() {
+    // Typing into cat shows each line twice, once echoed and once from cat; the dumps come
+    // while it still runs, and closing ends it. Shrinking to two rows keeps the cursor's row,
+    // pushing the first line into the scrollback
+    let script = "type one\nkey Enter\nwait 300\ndump\nresize 3x2\nwait 100\ndump\nclose\n";
+    let screen = myterm(&["--script", "-", "-e", "cat"], script);
+    assert_eq!(screen, "one\none\none\n");
+}
+
+#[test]
+fn test_headless_stdin_goes_to_the_shell() {
+    let screen = myterm(&["--dump", "text", "-e", "sh", "-c", "read line; echo \"got $line\""], "hi\n");
+    assert_eq!(screen, "hi\ngot hi\n");
+}
+
+#[test]
+fn test_log_pty_records_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("pty.log");
+    myterm(&["--log-pty", log.to_str().unwrap(), "-e", "printf", "\\033[1mbold\\033[0m\\n"], "");
+    assert_eq!(std::fs::read(&log).unwrap(), b"\x1b[1mbold\x1b[0m\r\n");
+}
+
+#[test]
+fn test_typescript_tags_input_and_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("pty.log");
+    let config = dir.path().join("myterm.toml");
+    std::fs::write(&config, format!("[terminal]\ntypescript = {:?}\ntypescript_input = true\n", log)).unwrap();
+    
+    // The later --config wins over the /dev/null one
+    myterm(&["--config", config.to_str().unwrap(), "-e", "sh", "-c", "read line; echo \"got $line\""], "hi\n");
+    let typescript = std::fs::read(&log).unwrap();
+    
+    // Chunks of either kind, however the reads and writes happened to split them
+    let (mut input, mut output) = (Vec::new(), Vec::new());
+    let mut rest = typescript.as_slice();
+    while !rest.is_empty() {
+        let header_end = rest.iter().position(|&b| b == b'\n').unwrap();
+        let header = std::str::from_utf8(&rest[..header_end]).unwrap();
+        let (direction, len) = header.split_once(' ').unwrap();
+        let len: usize = len.parse().unwrap();
+        let chunk = &rest[header_end + 1..header_end + 1 + len];
+        match direction {
+            ">" => input.extend_from_slice(chunk),
+            "<" => output.extend_from_slice(chunk),
+            _ => panic!("Unknown direction in {:?}", header),
+        }
+        assert_eq!(rest[header_end + 1 + len], b'\n');
+        rest = &rest[header_end + 2 + len..];
+    }
+    assert_eq!(input, b"hi\n");
+    assert_eq!(output, b"hi\r\ngot hi\r\n");
+}
+
+/// User and system CPU time process `pid` has used, all its threads included.
+fn cpu_time(pid: u32) -> Duration {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap();
+    // The fields after the command name, which is in parentheses and may hold spaces
+    let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 2..].split(' ').collect();
+    let ticks: u64 = fields[11].parse::<u64>().unwrap() + fields[12].parse::<u64>().unwrap();
+    let per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as u64;
+    Duration::from_millis(ticks * 1000 / per_second)
+}
+
+#[test]
+fn test_idle_cpu() {
+    // The main loop with no input or output, left with its timers for the cursor blink and
+    // config polling
+    let mut child = Command::new(env!("CARGO_BIN_EXE_myterm"))
+        .args(["--config", "/dev/null", "--headless", "--size", "30x5", "-e", "sleep", "10"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    let before = cpu_time(child.id());
+    std::thread::sleep(Duration::from_secs(2));
+    let used = cpu_time(child.id()) - before;
+    child.kill().unwrap();
+    child.wait().unwrap();
+    
+    // A loop polling instead of waiting would use the whole two seconds
+    assert!(used < Duration::from_millis(100), "Used {:?} of CPU in 2s idle", used);
+}
\ No newline at end of file
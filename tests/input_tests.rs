@@ -1,4 +1,5 @@
-use myterm::input::{Key, KeyCode, Modifiers, parse_key_binding};
+use myterm::config::{BackspaceSends, Config, KeyBinding, MouseBinding};
+use myterm::input::{Action, BindingMode, Bindings, Key, KeyCode, ModeGuard, Modifiers, MouseButton, MouseInput, ScrollAccumulator, parse_escapes, parse_key_binding, parse_mouse_binding, text_to_bytes};
 
 #[test]
 fn test_key_creation() {
@@ -46,6 +47,49 @@ fn test_key_to_bytes() {
     assert_eq!(Key::alt('x').to_bytes(), b"\x1bx");
 }
 
+#[test]
+fn test_key_to_bytes_in_application_cursor_mode() {
+    let app_cursor = BindingMode::APP_CURSOR;
+    assert_eq!(Key::new(KeyCode::Up, Modifiers::empty()).to_bytes_in(app_cursor), b"\x1bOA");
+    assert_eq!(Key::new(KeyCode::Left, Modifiers::empty()).to_bytes_in(app_cursor), b"\x1bOD");
+    assert_eq!(Key::new(KeyCode::Home, Modifiers::empty()).to_bytes_in(app_cursor), b"\x1bOH");
+    
+    // Modified cursor keys and other keys are the same in either mode
+    assert_eq!(Key::new(KeyCode::Up, Modifiers::CTRL).to_bytes_in(app_cursor), b"\x1b[1;5A");
+    assert_eq!(Key::char('a').to_bytes_in(app_cursor), b"a");
+    assert_eq!(Key::new(KeyCode::Up, Modifiers::empty()).to_bytes_in(BindingMode::ALT_SCREEN), b"\x1b[A");
+}
+
+#[test]
+fn test_backspace_sends() {
+    let backspace = Key::new(KeyCode::Backspace, Modifiers::empty());
+    let delete = Key::new(KeyCode::Delete, Modifiers::empty());
+    
+    // DEL by default, leaving Delete its escape sequence
+    assert_eq!(Config::default().terminal.backspace_sends, BackspaceSends::Delete);
+    assert_eq!(backspace.to_bytes_with(BindingMode::empty(), BackspaceSends::Delete), vec![0x7f]);
+    assert_eq!(delete.to_bytes_with(BindingMode::empty(), BackspaceSends::Delete), b"\x1b[3~");
+    
+    // BS, with DEL going to Delete instead
+    assert_eq!(backspace.to_bytes_with(BindingMode::empty(), BackspaceSends::Backspace), vec![0x08]);
+    assert_eq!(delete.to_bytes_with(BindingMode::APP_CURSOR, BackspaceSends::Backspace), vec![0x7f]);
+    
+    let config: Config = toml::from_str("[terminal]\nbackspace_sends = \"backspace\"\n").unwrap();
+    assert_eq!(config.terminal.backspace_sends, BackspaceSends::Backspace);
+    assert!(toml::from_str::<Config>("[terminal]\nbackspace_sends = \"ctrl-h\"\n").is_err());
+}
+
+#[test]
+fn test_text_to_bytes() {
+    assert_eq!(text_to_bytes("ls -l\n", false), b"ls -l\r");
+    assert_eq!(text_to_bytes("a\r\nb\nc", false), b"a\rb\rc");
+    
+    assert_eq!(text_to_bytes("echo hi\n", true), b"\x1b[200~echo hi\r\x1b[201~");
+    
+    // Text can't close the paste itself
+    assert_eq!(text_to_bytes("a\x1b[201~b", true), b"\x1b[200~ab\x1b[201~");
+}
+
 #[test]
 fn test_key_display() {
     assert_eq!(Key::char('a').to_string(), "a");
@@ -84,4 +128,160 @@ fn test_parse_key_binding() {
     // Invalid keys should fail
     assert!(parse_key_binding("Invalid+Key").is_err());
     assert!(parse_key_binding("Ctrl+").is_err());
+}
+
+#[test]
+fn test_mode_guard() {
+    let any = ModeGuard::parse("").expect("Failed to parse empty guard");
+    assert!(any.allows(BindingMode::empty()));
+    assert!(any.allows(BindingMode::ALT_SCREEN));
+    
+    let primary = ModeGuard::parse("~AltScreen").expect("Failed to parse '~AltScreen'");
+    assert!(primary.allows(BindingMode::empty()));
+    assert!(!primary.allows(BindingMode::ALT_SCREEN));
+    
+    let combined = ModeGuard::parse("AltScreen|~Selection").expect("Failed to parse guard");
+    assert!(combined.allows(BindingMode::ALT_SCREEN));
+    assert!(!combined.allows(BindingMode::ALT_SCREEN | BindingMode::SELECTION));
+    assert!(!combined.allows(BindingMode::empty()));
+    
+    let app_cursor = ModeGuard::parse("AppCursor").expect("Failed to parse 'AppCursor'");
+    assert!(app_cursor.allows(BindingMode::APP_CURSOR));
+    
+    assert!(ModeGuard::parse("Fullscreen").is_err());
+}
+
+#[test]
+fn test_default_bindings_and_mode_dispatch() {
+    let bindings = Bindings::new(&[]).expect("Default bindings must be valid");
+    let page_up = Key::new(KeyCode::PageUp, Modifiers::SHIFT);
+    
+    assert_eq!(bindings.lookup(&page_up, BindingMode::empty()), Some(&Action::ScrollPageUp));
+    // Scrollback navigation is passed through to full-screen programs
+    assert_eq!(bindings.lookup(&page_up, BindingMode::ALT_SCREEN), None);
+    // Character keys match regardless of the reported case
+    let copy = Key::new(KeyCode::Char('C'), Modifiers::CTRL | Modifiers::SHIFT);
+    assert_eq!(bindings.lookup(&copy, BindingMode::empty()), Some(&Action::Copy));
+}
+
+#[test]
+fn test_user_bindings_override_and_unbind() {
+    let user = vec![
+        KeyBinding::new("Shift+PageUp", Action::ScrollLineUp, Some("~AltScreen")),
+        KeyBinding::new("Ctrl+Shift+T", Action::None, None),
+        KeyBinding::new("Shift+PageUp", Action::SendText("\x1b[5;2~".to_string()), Some("AltScreen")),
+    ];
+    let bindings = Bindings::new(&user).expect("Failed to build bindings");
+    
+    let page_up = Key::new(KeyCode::PageUp, Modifiers::SHIFT);
+    assert_eq!(bindings.lookup(&page_up, BindingMode::empty()), Some(&Action::ScrollLineUp));
+    assert_eq!(
+        bindings.lookup(&page_up, BindingMode::ALT_SCREEN),
+        Some(&Action::SendText("\x1b[5;2~".to_string()))
+    );
+    
+    let new_tab = Key::new(KeyCode::Char('t'), Modifiers::CTRL | Modifiers::SHIFT);
+    assert_eq!(bindings.lookup(&new_tab, BindingMode::empty()), None);
+}
+
+#[test]
+fn test_parse_escapes() {
+    assert_eq!(parse_escapes("\\e[A").unwrap(), "\x1b[A");
+    assert_eq!(parse_escapes("\\x1b[5;2~\\x7f").unwrap(), "\x1b[5;2~\x7f");
+    assert_eq!(parse_escapes("git status\\n\\r\\t\\\\").unwrap(), "git status\n\r\t\\");
+    // Text TOML already unescaped is left as it is
+    assert_eq!(parse_escapes("ls\n").unwrap(), "ls\n");
+    
+    for invalid in ["\\q", "trailing\\", "\\x1", "\\xzz", "\\x+1", "\\xff"] {
+        assert!(parse_escapes(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn test_send_binding_dispatch() {
+    let config: myterm::config::Config = toml::from_str("[[keybindings]]\nkeys = \"F13\"\nsend = 'git status\\n'\n").unwrap();
+    let bindings = Bindings::new(&config.keybindings).expect("Failed to build bindings");
+    
+    // The bound key sends the text instead of its own sequence
+    let f13 = parse_key_binding("F13").unwrap();
+    assert_eq!(f13, Key::new(KeyCode::F(13), Modifiers::empty()));
+    assert!(f13.to_bytes().is_empty());
+    assert_eq!(bindings.lookup(&f13, BindingMode::empty()), Some(&Action::SendText("git status\n".to_string())));
+}
+
+#[test]
+fn test_binding_conflicts() {
+    let conflicting = vec![
+        KeyBinding::new("Ctrl+Shift+X", Action::Copy, None),
+        KeyBinding::new("ctrl+shift+x", Action::Paste, None),
+    ];
+    assert!(Bindings::new(&conflicting).is_err());
+    
+    // The same key in different modes is not a conflict
+    let distinct_modes = vec![
+        KeyBinding::new("Ctrl+Shift+X", Action::Copy, Some("Selection")),
+        KeyBinding::new("Ctrl+Shift+X", Action::Paste, Some("~Selection")),
+    ];
+    let bindings = Bindings::new(&distinct_modes).expect("Failed to build bindings");
+    let key = parse_key_binding("Ctrl+Shift+X").unwrap();
+    assert_eq!(bindings.lookup(&key, BindingMode::SELECTION), Some(&Action::Copy));
+    assert_eq!(bindings.lookup(&key, BindingMode::empty()), Some(&Action::Paste));
+    
+    assert!(Bindings::new(&[KeyBinding::new("Ctrl+Nope", Action::Copy, None)]).is_err());
+}
+
+#[test]
+fn test_mouse_bindings() {
+    assert_eq!(
+        parse_mouse_binding("Shift+Right").unwrap(),
+        MouseInput::new(MouseButton::Right, Modifiers::SHIFT)
+    );
+    assert!(parse_mouse_binding("Shift").is_err());
+    assert!(parse_mouse_binding("Wheel").is_err());
+    
+    let middle = MouseInput::new(MouseButton::Middle, Modifiers::empty());
+    let right = MouseInput::new(MouseButton::Right, Modifiers::empty());
+    let defaults = Bindings::for_mouse(&[]).expect("Default mouse bindings must be valid");
+    assert_eq!(defaults.lookup(&middle, BindingMode::empty()), Some(&Action::PastePrimary));
+    assert_eq!(defaults.lookup(&right, BindingMode::empty()), None);
+    
+    let user = vec![
+        MouseBinding::new("Right", Action::PastePrimary, None),
+        MouseBinding::new("Middle", Action::None, None),
+        MouseBinding::new("Ctrl+Right", Action::Copy, Some("Selection")),
+    ];
+    let bindings = Bindings::for_mouse(&user).expect("Failed to build mouse bindings");
+    assert_eq!(bindings.lookup(&right, BindingMode::empty()), Some(&Action::PastePrimary));
+    assert_eq!(bindings.lookup(&middle, BindingMode::empty()), None);
+    
+    // Modifiers must match exactly
+    let ctrl_right = MouseInput::new(MouseButton::Right, Modifiers::CTRL);
+    assert_eq!(bindings.lookup(&ctrl_right, BindingMode::SELECTION), Some(&Action::Copy));
+    assert_eq!(bindings.lookup(&ctrl_right, BindingMode::empty()), None);
+    
+    let conflicting = vec![
+        MouseBinding::new("Right", Action::Copy, None),
+        MouseBinding::new("right", Action::Paste, None),
+    ];
+    assert!(Bindings::for_mouse(&conflicting).is_err());
+}
+
+#[test]
+fn test_scroll_multiplier() {
+    let mut scroll = ScrollAccumulator::default();
+    
+    // Wheel steps are scaled by the multiplier
+    assert_eq!(scroll.lines(1.0, 3.0), 3);
+    assert_eq!(scroll.lines(-2.0, 3.0), -6);
+    
+    // Fractions carry over so slow touchpad scrolling still moves
+    assert_eq!(scroll.lines(0.3, 1.0), 0);
+    assert_eq!(scroll.lines(0.3, 1.0), 0);
+    assert_eq!(scroll.lines(0.5, 1.0), 1);
+    assert_eq!(scroll.lines(1.0, 0.5), 0);
+    assert_eq!(scroll.lines(1.0, 0.5), 1);
+    
+    // Reversing direction cancels the carried fraction
+    assert_eq!(scroll.lines(0.5, 1.0), 0);
+    assert_eq!(scroll.lines(-1.0, 1.0), 0);
 }
\ No newline at end of file
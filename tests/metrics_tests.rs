@@ -0,0 +1,82 @@
+use myterm::metrics::{damage_coverage, FrameTimings, RenderMetrics, RollingAverage, AVERAGED_FRAMES, LOG_INTERVAL};
+use myterm::render::{GlyphCacheStats, Rect};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_rolling_average() {
+    let mut average = RollingAverage::new(3);
+    assert_eq!(average.average(), None);
+    
+    average.push(3.0);
+    assert_eq!(average.average(), Some(3.0));
+    average.push(6.0);
+    average.push(9.0);
+    assert_eq!(average.average(), Some(6.0));
+    
+    // Past its capacity the oldest samples drop out
+    average.push(12.0);
+    assert_eq!(average.average(), Some(9.0));
+    average.push(0.0);
+    average.push(0.0);
+    assert_eq!(average.average(), Some(4.0));
+}
+
+#[test]
+fn test_percentile() {
+    let mut samples = RollingAverage::new(100);
+    assert_eq!(samples.percentile(0.5), None);
+    
+    for sample in (1..=100).rev() {
+        samples.push(sample as f64);
+    }
+    assert_eq!(samples.percentile(0.5), Some(50.0));
+    assert_eq!(samples.percentile(0.99), Some(99.0));
+    assert_eq!(samples.percentile(1.0), Some(100.0));
+    assert_eq!(samples.percentile(0.0), Some(1.0));
+}
+
+#[test]
+fn test_damage_coverage() {
+    let rect = |width, height| Rect { x: 0, y: 0, width, height };
+    assert_eq!(damage_coverage(&[], (100, 50)), 0.0);
+    assert_eq!(damage_coverage(&[rect(100, 50)], (100, 50)), 1.0);
+    assert_eq!(damage_coverage(&[rect(100, 10), rect(50, 10)], (100, 50)), 0.3);
+    
+    // Overlaps never count for more than the canvas
+    assert_eq!(damage_coverage(&[rect(100, 50), rect(100, 50)], (100, 50)), 1.0);
+    assert_eq!(damage_coverage(&[rect(10, 10)], (0, 0)), 0.0);
+}
+
+#[test]
+fn test_render_metrics() {
+    let start = Instant::now();
+    let mut metrics = RenderMetrics::new(start);
+    let timings = |raster_ms| FrameTimings { raster: Duration::from_millis(raster_ms), ..FrameTimings::default() };
+    
+    // The rate comes from the time between frames, once there are two
+    metrics.record(start, timings(2), 1.0);
+    assert_eq!(metrics.fps(), None);
+    for frame in 1..=AVERAGED_FRAMES as u32 {
+        metrics.record(start + Duration::from_millis(20) * frame, timings(4), 0.1);
+    }
+    assert_eq!(metrics.fps().map(f64::round), Some(50.0));
+    assert_eq!(metrics.summary(), "50fps parse 0.0 raster 4.0 blit 0.0 commit 0.0ms damage 10% hits 100%");
+    
+    // The hit rate is taken over each log interval
+    let glyphs = |hits, misses| GlyphCacheStats { hits, misses, ..GlyphCacheStats::default() };
+    assert!(!metrics.log_due(start + LOG_INTERVAL / 2, glyphs(10, 10)));
+    assert_eq!(metrics.hit_rate(), None);
+    assert!(metrics.log_due(start + LOG_INTERVAL, glyphs(30, 10)));
+    assert_eq!(metrics.hit_rate(), Some(0.75));
+    assert!(!metrics.log_due(start + LOG_INTERVAL * 3 / 2, glyphs(100, 10)));
+    assert!(metrics.log_due(start + LOG_INTERVAL * 2, glyphs(120, 10)));
+    assert_eq!(metrics.hit_rate(), Some(1.0));
+    
+    // Latency shows once a frame with output in it was presented
+    assert_eq!(metrics.latency(), None);
+    for millis in [8, 9, 10, 30] {
+        metrics.record_latency(Duration::from_millis(millis));
+    }
+    assert_eq!(metrics.latency(), Some((9.0, 30.0)));
+    assert!(metrics.summary().ends_with("hits 100% latency p50 9.0 p99 30.0ms"));
+}
\ No newline at end of file
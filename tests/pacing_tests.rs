@@ -0,0 +1,108 @@
+use myterm::display::{queue_event, Event};
+use myterm::pacing::{presented_at, FramePacer, Presentation, Throttle, WINSIZE_INTERVAL};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const REFRESH: Duration = Duration::from_micros(16_667);
+
+fn presented(at: Instant) -> Presentation {
+    Presentation { at: Some(at), refresh: Some(REFRESH), output_since: None }
+}
+
+#[test]
+fn test_frame_pacing() {
+    let start = Instant::now();
+    let mut pacer = FramePacer::new();
+    
+    // Without feedback nothing is held, in flight or not
+    assert_eq!(pacer.hold_until(start), None);
+    pacer.committing();
+    assert_eq!(pacer.hold_until(start), None);
+    
+    // Once frames present steadily, a frame due while another waits for the screen is held
+    // until the next refresh, however many times output asks for it meanwhile
+    pacer.presented(&presented(start));
+    assert_eq!(pacer.hold_until(start + Duration::from_millis(1)), None);
+    pacer.committing();
+    let next = start + REFRESH;
+    for elapsed in [1, 5, 16] {
+        assert_eq!(pacer.hold_until(start + Duration::from_millis(elapsed)), Some(next));
+    }
+    // Refreshes that went by without a presentation don't count as the next one
+    assert_eq!(pacer.hold_until(start + Duration::from_millis(20)), Some(next + REFRESH));
+    
+    // The frame's feedback lets the next one through right away
+    pacer.presented(&presented(next));
+    assert_eq!(pacer.hold_until(next + Duration::from_millis(2)), None);
+    
+    // A discarded frame is no longer waited for
+    pacer.committing();
+    assert!(pacer.hold_until(next + Duration::from_millis(2)).is_some());
+    pacer.presented(&Presentation { at: None, refresh: None, output_since: None });
+    assert_eq!(pacer.hold_until(next + Duration::from_millis(2)), None);
+    
+    // Nor is a frame when the output last refreshed long ago, e.g. when output resumes after
+    // a pause, or one presented with an unknown refresh rate
+    pacer.committing();
+    assert_eq!(pacer.hold_until(next + REFRESH * 3), None);
+    let mut variable = FramePacer::new();
+    variable.presented(&Presentation { refresh: None, ..presented(start) });
+    variable.committing();
+    assert_eq!(variable.hold_until(start + Duration::from_millis(1)), None);
+}
+
+#[test]
+fn test_presented_at() {
+    let now = Instant::now();
+    let clock_now = Duration::from_secs(1000);
+    
+    // Timestamps are placed as far back from now as they are on the compositor's clock
+    let at = presented_at(clock_now - Duration::from_millis(3), clock_now, now);
+    assert_eq!(now - at, Duration::from_millis(3));
+    
+    // A timestamp ahead of the clock read, as it's read after the event came, counts as now
+    assert_eq!(presented_at(clock_now + Duration::from_millis(1), clock_now, now), now);
+}
+
+#[test]
+fn test_configures_coalesce() {
+    let mut queue = VecDeque::new();
+    queue_event(&mut queue, Event::Resize(800, 600));
+    queue_event(&mut queue, Event::Focus(true));
+    queue_event(&mut queue, Event::Resize(810, 600));
+    queue_event(&mut queue, Event::Frame);
+    queue_event(&mut queue, Event::Resize(820, 610));
+    
+    // Only the latest size is handed out, after what came before it
+    assert!(matches!(queue.pop_front(), Some(Event::Focus(true))));
+    assert!(matches!(queue.pop_front(), Some(Event::Frame)));
+    assert!(matches!(queue.pop_front(), Some(Event::Resize(820, 610))));
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn test_winsize_throttle() {
+    let start = Instant::now();
+    let mut throttle = Throttle::new(WINSIZE_INTERVAL);
+    assert_eq!(throttle.update((80, 24), start), Some((80, 24)));
+    assert_eq!(throttle.deadline(), None);
+    
+    // A drag's sizes within the interval are held back, keeping only the latest
+    for (step, cols) in (81..90).enumerate() {
+        assert_eq!(throttle.update((cols, 24), start + Duration::from_millis(step as u64 * 4)), None);
+    }
+    let due = start + WINSIZE_INTERVAL;
+    assert_eq!(throttle.deadline(), Some(due));
+    assert_eq!(throttle.flush(due - Duration::from_millis(1)), None);
+    assert_eq!(throttle.flush(due), Some((89, 24)));
+    assert_eq!(throttle.deadline(), None);
+    assert_eq!(throttle.flush(due + WINSIZE_INTERVAL), None);
+    
+    // Going back to the size the PTY has drops what was held back
+    assert_eq!(throttle.update((90, 24), due + Duration::from_millis(10)), None);
+    assert_eq!(throttle.update((89, 24), due + Duration::from_millis(20)), None);
+    assert_eq!(throttle.deadline(), None);
+    
+    // After a pause, a size goes through right away
+    assert_eq!(throttle.update((100, 30), due + WINSIZE_INTERVAL), Some((100, 30)));
+}
\ No newline at end of file
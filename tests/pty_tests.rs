@@ -0,0 +1,161 @@
+use myterm::config::Config;
+use myterm::pty::{child_environment, resolve_term, terminfo_exists, FALLBACK_TERM};
+use myterm::terminal::Terminal;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use tempfile::tempdir;
+
+fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+#[test]
+fn test_child_environment() {
+    let parent = vars(&[
+        ("HOME", "/home/user"),
+        ("TERM", "xterm-kitty"),
+        ("TERM_PROGRAM", "kitty"),
+        ("VTE_VERSION", "7600"),
+        ("COLORTERM", "24bit"),
+    ]);
+    
+    let env = child_environment(parent, "myterm-256color");
+    let get = |key: &str| {
+        env.iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .collect::<Vec<_>>()
+    };
+    
+    assert_eq!(get("HOME"), vec!["/home/user"]);
+    assert_eq!(get("TERM"), vec!["myterm-256color"]);
+    assert_eq!(get("COLORTERM"), vec!["truecolor"]);
+    assert!(get("TERM_PROGRAM").is_empty());
+    assert!(get("VTE_VERSION").is_empty());
+}
+
+#[test]
+fn test_terminfo_lookup_and_fallback() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let dirs = vec![temp_dir.path().to_path_buf()];
+    
+    fs::create_dir_all(temp_dir.path().join("f")).unwrap();
+    fs::write(temp_dir.path().join("f").join("foot"), b"").unwrap();
+    // Hex-named directory layout used on some systems
+    fs::create_dir_all(temp_dir.path().join("61")).unwrap();
+    fs::write(temp_dir.path().join("61").join("alacritty"), b"").unwrap();
+    
+    assert!(terminfo_exists("foot", &dirs));
+    assert!(terminfo_exists("alacritty", &dirs));
+    assert!(!terminfo_exists("missing-term", &dirs));
+    assert!(!terminfo_exists("", &dirs));
+    
+    assert_eq!(resolve_term("foot", &dirs), "foot");
+    assert_eq!(resolve_term("missing-term", &dirs), FALLBACK_TERM);
+}
+
+#[tokio::test]
+async fn test_output_flood_is_read_in_chunks() {
+    let temp_dir = tempdir().unwrap();
+    let script = temp_dir.path().join("flood");
+    fs::write(&script, "#!/bin/sh\nhead -c 1000000 /dev/zero | tr '\\0' x\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    
+    let mut config = Config::default();
+    config.terminal.shell = Some(script.to_str().unwrap().to_string());
+    config.terminal.read_chunk_bytes = 16 * 1024;
+    let mut terminal = Terminal::new(&config).unwrap();
+    terminal.start_shell(&config).await.unwrap();
+    
+    // Every byte arrives, never more than the budget at a time
+    let (mut total, mut reads) = (0, 0);
+    while let Some(output) = terminal.next_output().await.unwrap() {
+        assert!(output.len() <= 16 * 1024);
+        total += output.iter().filter(|&&byte| byte == b'x').count();
+        reads += 1;
+    }
+    assert_eq!(total, 1_000_000);
+    assert!(reads >= 1_000_000 / (16 * 1024));
+    assert!(terminal.grid().cells.iter().any(|row| row.iter().all(|cell| cell.c == 'x')));
+}
+
+#[tokio::test]
+async fn test_foreground_job_detection() {
+    let temp_dir = tempdir().unwrap();
+    let script = temp_dir.path().join("shell");
+    // Job control puts the sleep in a process group of its own, as an interactive shell would
+    fs::write(&script, "#!/bin/sh\nset -m\necho ready\nread line\nsleep 5\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    
+    let mut config = Config::default();
+    config.terminal.shell = Some(script.to_str().unwrap().to_string());
+    let mut terminal = Terminal::new(&config).unwrap();
+    terminal.start_shell(&config).await.unwrap();
+    
+    // Waiting for input, the shell is the foreground process itself
+    while !terminal.grid().cells[0].iter().any(|cell| cell.c == 'r') {
+        terminal.next_output().await.unwrap().expect("shell exited early");
+    }
+    assert!(!terminal.has_foreground_job());
+    assert_eq!(terminal.foreground_process().as_deref(), Some("shell"));
+    
+    terminal.write_to_pty(b"\n").await.unwrap();
+    let start = std::time::Instant::now();
+    while terminal.foreground_process().as_deref() != Some("sleep") {
+        assert!(start.elapsed() < std::time::Duration::from_secs(3), "sleep never took the foreground");
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert!(terminal.has_foreground_job());
+}
+
+#[tokio::test]
+async fn test_startup_command() {
+    let temp_dir = tempdir().unwrap();
+    // One shell prompts before reading, the other reads without a word
+    for (name, script) in [("prompting", "printf '$ '\nread line\necho \"got $line\"\n"), ("quiet", "read line\necho \"got $line\"\n")] {
+        let shell = temp_dir.path().join(name);
+        fs::write(&shell, format!("#!/bin/sh\n{}", script)).unwrap();
+        fs::set_permissions(&shell, fs::Permissions::from_mode(0o755)).unwrap();
+        
+        let mut config = Config::default();
+        config.terminal.shell = Some(shell.to_str().unwrap().to_string());
+        config.terminal.startup_command = Some("tmux attach".to_string());
+        let mut terminal = Terminal::new(&config).unwrap();
+        terminal.start_shell(&config).await.unwrap();
+        
+        // The shell reads the command as a typed line
+        let text = |terminal: &Terminal| terminal.grid().cells.iter()
+            .map(|row| row.iter().map(|cell| cell.c).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let read = async {
+            while terminal.next_output().await.unwrap().is_some() {}
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(5), read).await.expect("the shell never got the command");
+        assert!(text(&terminal).contains("got tmux attach"), "{}: {}", name, text(&terminal));
+    }
+}
+
+#[tokio::test]
+async fn test_large_paste_goes_in_while_output_is_read() {
+    let temp_dir = tempdir().unwrap();
+    let script = temp_dir.path().join("count");
+    fs::write(&script, "#!/bin/sh\nstty -icanon\nhead -c 1010000 | wc -c\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    
+    let mut config = Config::default();
+    config.terminal.shell = Some(script.to_str().unwrap().to_string());
+    let mut terminal = Terminal::new(&config).unwrap();
+    terminal.start_shell(&config).await.unwrap();
+    
+    // The echo of a megabyte fills the PTY long before the paste is in, so it only gets
+    // through if output keeps being read meanwhile
+    let text = format!("{}\n", "x".repeat(100)).repeat(10_000);
+    terminal.paste(&text);
+    let mut tail = Vec::new();
+    while let Some(output) = tokio::time::timeout(std::time::Duration::from_secs(10), terminal.next_output()).await.unwrap().unwrap() {
+        tail.extend(output);
+        tail.drain(..tail.len().saturating_sub(64));
+    }
+    assert!(String::from_utf8_lossy(&tail).contains("1010000"));
+}
\ No newline at end of file
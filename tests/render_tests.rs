@@ -0,0 +1,920 @@
+use myterm::config::{Config, CursorShape};
+use myterm::font::{FontSet, FontStyle};
+use myterm::render::{argb_pixel, background_alpha, blink_text, copy_rects, dim, draw_grid, fill, fill_rows, highlight_spans, overlay_badge, resolve_cell_colors, scale_image, scrollbar_thumb, titlebar, CursorSprite, Frame, FrameImage, GlyphCache, GridLayout, Rect, RetainedCanvas, Scrollbar, BYTES_PER_PIXEL, GLYPH_CACHE_BYTES, MIN_THUMB_HEIGHT};
+use myterm::selection::{Point, Selection};
+use myterm::sixel::SixelImage;
+use myterm::palette::Palette;
+use myterm::terminal::{Cell, CellFlags, TerminalPerformer};
+use vte::Parser;
+
+#[test]
+fn test_background_alpha() {
+    assert_eq!(background_alpha(255, 1.0), 1.0);
+    assert_eq!(background_alpha(255, 0.5), 0.5);
+    assert_eq!(background_alpha(0, 1.0), 0.0);
+    assert!((background_alpha(0x80, 0.5) - 0.251).abs() < 0.001);
+    
+    // Out of range opacity is clamped
+    assert_eq!(background_alpha(255, 1.5), 1.0);
+    assert_eq!(background_alpha(255, -1.0), 0.0);
+}
+
+#[test]
+fn test_argb_pixel_is_premultiplied() {
+    let color = rgb::RGB8::new(200, 100, 50);
+    
+    assert_eq!(argb_pixel(color, 1.0), [50, 100, 200, 255]);
+    assert_eq!(argb_pixel(color, 0.5), [25, 50, 100, 128]);
+    assert_eq!(argb_pixel(color, 0.0), [0, 0, 0, 0]);
+}
+
+#[test]
+fn test_fill() {
+    let mut canvas = vec![0u8; 3 * BYTES_PER_PIXEL];
+    fill(&mut canvas, [1, 2, 3, 4]);
+    
+    assert_eq!(canvas, [1, 2, 3, 4, 1, 2, 3, 4, 1, 2, 3, 4]);
+}
+
+#[test]
+fn test_fill_rows() {
+    // 2 pixels wide, 3 rows
+    let stride = 2 * BYTES_PER_PIXEL;
+    let mut canvas = vec![0u8; 3 * stride];
+    fill_rows(&mut canvas, stride, 0..1, [9, 9, 9, 9]);
+    
+    assert!(canvas[..stride].iter().all(|&b| b == 9));
+    assert!(canvas[stride..].iter().all(|&b| b == 0));
+    
+    // Rows past the end are ignored
+    fill_rows(&mut canvas, stride, 2..10, [7, 7, 7, 7]);
+    assert!(canvas[2 * stride..].iter().all(|&b| b == 7));
+}
+
+#[test]
+fn test_copy_rects() {
+    // 3 pixels wide, 3 rows
+    let stride = 3 * BYTES_PER_PIXEL;
+    let src = vec![9u8; 3 * stride];
+    let mut dst = vec![0u8; 3 * stride];
+    copy_rects(&mut dst, &src, stride, &[Rect { x: 1, y: 1, width: 2, height: 1 }, Rect { x: 0, y: 2, width: 1, height: 1 }]);
+    
+    let pixel = |x: usize, y: usize| dst[y * stride + x * BYTES_PER_PIXEL];
+    assert_eq!((0..3).map(|x| pixel(x, 0)).collect::<Vec<_>>(), [0, 0, 0]);
+    assert_eq!((0..3).map(|x| pixel(x, 1)).collect::<Vec<_>>(), [0, 9, 9]);
+    assert_eq!((0..3).map(|x| pixel(x, 2)).collect::<Vec<_>>(), [9, 0, 0]);
+}
+
+#[test]
+fn test_draw_grid() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(2, 3, &config);
+    let mut parser = Parser::new();
+    for &byte in b"\x1b[41mA\x1b[0m B" {
+        parser.advance(&mut performer, byte);
+    }
+    
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    
+    // 3x2 cells of 10x16 pixels, 2 pixels in from the top left corner
+    let layout = GridLayout { origin: (2, 2), cell_width: 10, cell_height: 16 };
+    let (width, height) = (2 + 3 * 10, 2 + 2 * 16);
+    let stride = width * BYTES_PER_PIXEL;
+    let mut canvas = vec![0u8; stride * height];
+    let palette = &performer.palette;
+    let background = argb_pixel(palette.background, 1.0);
+    fill(&mut canvas, background);
+    
+    let cursor = CursorSprite { point: Point::new(1, 1), shape: CursorShape::Underline, hollow: false };
+    draw_grid(&mut canvas, stride, &layout, &performer.styled_snapshot(), palette, Some(&cursor), &mut glyphs);
+    
+    let pixel = |x: usize, y: usize| -> [u8; 4] {
+        let offset = y * stride + x * BYTES_PER_PIXEL;
+        canvas[offset..offset + BYTES_PER_PIXEL].try_into().unwrap()
+    };
+    let cell_pixels = |row: usize, col: usize| {
+        let (x, y) = (2 + col * 10, 2 + row * 16);
+        (y..y + 16).flat_map(move |y| (x..x + 10).map(move |x| (x, y)))
+    };
+    
+    // The red cell is filled and has a glyph in it; the padding is untouched
+    let red = argb_pixel(palette.normal[1], 1.0);
+    assert_eq!(pixel(2, 2), red);
+    assert!(cell_pixels(0, 0).any(|(x, y)| pixel(x, y) != red));
+    assert_eq!(pixel(0, 0), background);
+    
+    // Blank cells keep the background, text cells don't
+    assert!(cell_pixels(0, 1).all(|(x, y)| pixel(x, y) == background));
+    assert!(cell_pixels(0, 2).any(|(x, y)| pixel(x, y) != background));
+    
+    // An underline cursor covers the bottom of its cell only
+    let cursor_color = argb_pixel(palette.cursor, 1.0);
+    assert!((12..22).all(|x| pixel(x, 2 + 2 * 16 - 1) == cursor_color));
+    assert_eq!(pixel(12, 2 + 16), background);
+}
+
+#[test]
+fn test_glyph_cache() {
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    
+    let first = glyphs.get('A', FontStyle::Regular).unwrap().coverage.clone();
+    assert_eq!(glyphs.get('A', FontStyle::Regular).unwrap().coverage, first);
+    glyphs.get('B', FontStyle::Regular);
+    let stats = glyphs.stats();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 2));
+    assert!(stats.memory >= first.len());
+    
+    // Resizing drops everything rasterized at the old size
+    glyphs.set_pixel_size(32).unwrap();
+    assert_eq!(glyphs.stats().entries, 0);
+    assert_eq!(glyphs.metrics().cell_height, 38);
+    assert!(glyphs.get('A', FontStyle::Regular).unwrap().coverage.len() > first.len());
+    
+    // The same size keeps the cache
+    glyphs.set_pixel_size(32).unwrap();
+    assert_eq!(glyphs.stats().entries, 1);
+}
+
+#[test]
+fn test_glyph_cache_eviction() {
+    let cap = 2 * 1024;
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), cap);
+    glyphs.set_pixel_size(16).unwrap();
+    
+    for c in 'A'..='Z' {
+        glyphs.get(c, FontStyle::Regular);
+        glyphs.get('0', FontStyle::Regular);
+        assert!(glyphs.stats().memory <= cap);
+    }
+    let stats = glyphs.stats();
+    assert!(stats.entries < 27);
+    
+    // '0' is used constantly, so it's never the least recently used glyph
+    let misses = stats.misses;
+    glyphs.get('0', FontStyle::Regular);
+    assert_eq!(glyphs.stats().misses, misses);
+    glyphs.get('A', FontStyle::Regular);
+    assert_eq!(glyphs.stats().misses, misses + 1);
+}
+
+#[test]
+fn test_styled_glyphs() {
+    let config = Config::default();
+    // A spare column so the line doesn't wrap and scroll away
+    let mut performer = TerminalPerformer::new(1, 5, &config);
+    let mut parser = Parser::new();
+    for &byte in b"l\x1b[1ml\x1b[22;3ml\x1b[23;2ml" {
+        parser.advance(&mut performer, byte);
+    }
+    
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let layout = GridLayout { origin: (0, 0), cell_width: 10, cell_height: 19 };
+    let stride = 5 * 10 * BYTES_PER_PIXEL;
+    let mut canvas = vec![0u8; stride * 19];
+    let palette = &performer.palette;
+    draw_grid(&mut canvas, stride, &layout, &performer.styled_snapshot(), palette, None, &mut glyphs);
+    
+    let cell_pixels = |col: usize| -> Vec<[u8; 4]> {
+        (0..19)
+            .flat_map(|y| (col * 10..col * 10 + 10).map(move |x| (y, x)))
+            .map(|(y, x)| canvas[y * stride + x * BYTES_PER_PIXEL..][..BYTES_PER_PIXEL].try_into().unwrap())
+            .collect()
+    };
+    let ink = |col: usize| cell_pixels(col).iter().map(|p| p[0] as u32).sum::<u32>();
+    let regular = cell_pixels(0);
+    
+    // Bold is heavier, italic is a different shape of the same weight, dim is fainter
+    assert!(ink(1) > ink(0) * 5 / 4);
+    assert_ne!(cell_pixels(2), regular);
+    assert!(ink(3) < ink(0));
+    assert_eq!(glyphs.stats().entries, 3);
+}
+
+#[test]
+fn test_dim() {
+    let white = rgb::RGB8::new(255, 255, 255);
+    let black = rgb::RGB8::new(0, 0, 0);
+    assert_eq!(dim(white, black), rgb::RGB8::new(128, 128, 128));
+    assert_eq!(dim(black, white), rgb::RGB8::new(128, 128, 128));
+    assert_eq!(dim(rgb::RGB8::new(200, 100, 0), black), rgb::RGB8::new(100, 50, 0));
+    assert_eq!(dim(white, white), white);
+}
+
+#[test]
+fn test_resolve_cell_colors() {
+    let mut config = Config::default();
+    config.colors.cursor = "#00ff00".to_string();
+    config.colors.selection_background = Some("#444444".to_string());
+    let palette = Palette::from_config(&config.colors);
+    
+    let red = rgb::RGB8::new(0xc0, 0, 0);
+    let blue = rgb::RGB8::new(0, 0, 0xc0);
+    let gray = rgb::RGB8::new(0x44, 0x44, 0x44);
+    let green = rgb::RGB8::new(0, 0xff, 0);
+    let dimmed = |fg: rgb::RGB8, bg: rgb::RGB8| dim(fg, bg);
+    
+    let cell = |flags: CellFlags| Cell { fg: red, bg: blue, flags, ..Cell::default() };
+    let resolve = |flags, selected, cursor_here| resolve_cell_colors(&cell(flags), &palette, selected, cursor_here);
+    let reverse = CellFlags::REVERSE;
+    let hidden = CellFlags::HIDDEN;
+    let faint = CellFlags::DIM;
+    
+    for (flags, selected, cursor_here, expected) in [
+        (CellFlags::empty(), false, false, (red, blue)),
+        (reverse, false, false, (blue, red)),
+        (hidden, false, false, (blue, blue)),
+        (faint, false, false, (dimmed(red, blue), blue)),
+        (reverse | faint, false, false, (dimmed(blue, red), red)),
+        (reverse | hidden, false, false, (red, red)),
+        // The selection background replaces whatever the attributes resolved to, keeping the
+        // foreground, which for reversed text is the cell's background
+        (CellFlags::empty(), true, false, (red, gray)),
+        (reverse, true, false, (blue, gray)),
+        (hidden, true, false, (gray, gray)),
+        (faint, true, false, (dimmed(red, gray), gray)),
+        // A block cursor draws the text in the resolved background on the cursor color
+        (CellFlags::empty(), false, true, (blue, green)),
+        (reverse, false, true, (red, green)),
+        (reverse, true, true, (gray, green)),
+        (hidden, false, true, (green, green)),
+        (faint, false, true, (dimmed(blue, green), green)),
+    ] {
+        assert_eq!(resolve(flags, selected, cursor_here), expected, "{:?} selected {} cursor {}", flags, selected, cursor_here);
+    }
+    
+    // Unless a cursor text color is set
+    config.colors.cursor_text = Some("#444444".to_string());
+    let palette = Palette::from_config(&config.colors);
+    assert_eq!(resolve_cell_colors(&cell(CellFlags::empty()), &palette, false, true), (gray, green));
+}
+
+#[test]
+fn test_dim_uses_dim_palette() {
+    let mut config = Config::default();
+    config.colors.dim[1] = "#550000".to_string();
+    let palette = Palette::from_config(&config.colors);
+    let dim_red = rgb::RGB8::new(0x55, 0, 0);
+    
+    // SGR 2;31 picks dim[1] rather than blending normal[1] with the background
+    let mut performer = TerminalPerformer::new(1, 4, &config);
+    let mut parser = Parser::new();
+    for &byte in b"\x1b[2;31mA\x1b[0;2;91mB" {
+        parser.advance(&mut performer, byte);
+    }
+    let row = &performer.styled_snapshot()[0];
+    assert_eq!(row[0].fg, dim_red);
+    assert_eq!(resolve_cell_colors(&performer.grid.visible_row(0)[0], &palette, false, false).0, dim_red);
+    
+    // Colors outside the normal eight are still blended
+    assert_eq!(row[1].fg, dim(palette.bright[1], palette.background));
+}
+
+#[test]
+fn test_dim_palette_skips_default_colors() {
+    let mut config = Config::default();
+    config.colors.dim[0] = "#111111".to_string();
+    let palette = Palette::from_config(&config.colors);
+    
+    // Black set with SGR 30 takes dim[0], while the default background, black too, is blended
+    // when reverse video makes it the text's color
+    let mut performer = TerminalPerformer::new(1, 4, &config);
+    let mut parser = Parser::new();
+    for &byte in b"\x1b[2;30mA\x1b[0;2;7mB" {
+        parser.advance(&mut performer, byte);
+    }
+    let row = &performer.styled_snapshot()[0];
+    assert_eq!(row[0].fg, rgb::RGB8::new(0x11, 0x11, 0x11));
+    assert_eq!(row[1].fg, dim(palette.background, palette.foreground));
+}
+
+#[test]
+fn test_unfocused_dim_fades_resolved_colors() {
+    let mut config = Config::default();
+    config.colors.background = "#646464".to_string();
+    config.colors.cursor = "#00ff00".to_string();
+    config.colors.selection_background = Some("#444444".to_string());
+    let mut palette = Palette::from_config(&config.colors);
+    
+    // Each channel moves that far toward the background, rounding, and nothing moves at 0
+    let color = rgb::RGB8::new(200, 0, 50);
+    assert_eq!(palette.faded(color), color);
+    palette.fade = 0.25;
+    assert_eq!(palette.faded(color), rgb::RGB8::new(175, 25, 63));
+    palette.fade = 1.0;
+    assert_eq!(palette.faded(color), palette.background);
+    
+    // Fading comes after everything else, so selection and cursor colors fade too, and the
+    // default background stays as it is
+    palette.fade = 0.5;
+    let cell = Cell { fg: color, bg: palette.background, ..Cell::default() };
+    let gray = rgb::RGB8::new(0x44, 0x44, 0x44);
+    assert_eq!(resolve_cell_colors(&cell, &palette, false, false), (rgb::RGB8::new(150, 50, 75), palette.background));
+    assert_eq!(resolve_cell_colors(&cell, &palette, true, false), (rgb::RGB8::new(150, 50, 75), rgb::RGB8::new(84, 84, 84)));
+    assert_eq!(resolve_cell_colors(&cell, &palette, false, true), (palette.background, rgb::RGB8::new(50, 178, 50)));
+    palette.fade = 0.0;
+    assert_eq!(resolve_cell_colors(&cell, &palette, true, false), (color, gray));
+}
+
+#[test]
+fn test_focus_change_repaints_once() {
+    let mut config = Config::default();
+    config.display.unfocused_dim = 0.5;
+    let mut performer = TerminalPerformer::new(5, 10, &config);
+    let mut parser = Parser::new();
+    for &byte in b"\x1b[31mred\x1b[0m\r\n\x1b[44mblue" {
+        parser.advance(&mut performer, byte);
+    }
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let mut canvas = RetainedCanvas::new();
+    canvas.draw(frame(&performer), &mut glyphs);
+    let generation = performer.damage_generation();
+    let whole = vec![Rect { x: 0, y: 0, width: 104, height: 99 }];
+    
+    // Losing focus fades the snapshot's colors and counts as one change, drawn whole once
+    performer.set_focused(false);
+    let row = performer.styled_snapshot();
+    assert_eq!(row[0][0].fg, performer.palette.faded(performer.palette.normal[1]));
+    assert_eq!(row[1][0].bg, dim(performer.palette.normal[4], performer.palette.background));
+    assert_eq!(performer.damage_generation(), generation + 1);
+    assert_eq!(canvas.draw(frame(&performer), &mut glyphs), whole);
+    let mut full = RetainedCanvas::new();
+    full.draw(frame(&performer), &mut glyphs);
+    assert!(canvas.pixels() == full.pixels());
+    
+    performer.set_focused(false);
+    assert_eq!(performer.damage_generation(), generation + 1);
+    assert!(canvas.draw(frame(&performer), &mut glyphs).is_empty());
+    
+    // Getting it back restores the colors, again in one full repaint
+    performer.set_focused(true);
+    assert_eq!(performer.styled_snapshot()[0][0].fg, performer.palette.normal[1]);
+    assert_eq!(performer.damage_generation(), generation + 2);
+    assert_eq!(canvas.draw(frame(&performer), &mut glyphs), whole);
+    assert!(canvas.draw(frame(&performer), &mut glyphs).is_empty());
+    
+    // Without unfocused_dim the colors stay as they are
+    let mut performer = TerminalPerformer::new(5, 10, &Config::default());
+    performer.set_focused(false);
+    assert_eq!(performer.palette.fade, 0.0);
+}
+
+/// Draws `bytes` into 3x2 cells 10x19 pixels large with a cursor, returning for each pixel row
+/// of the first grid row a map of its 30 columns: `#` for the cursor color, `t` for the cursor
+/// text color and `.` for anything else.
+fn cursor_spans(bytes: &[u8], cursor: CursorSprite) -> Vec<String> {
+    let mut config = Config::default();
+    config.colors.cursor = "#00ff00".to_string();
+    config.colors.cursor_text = Some("#ff0000".to_string());
+    let mut performer = TerminalPerformer::new(2, 3, &config);
+    let mut parser = Parser::new();
+    for &byte in bytes {
+        parser.advance(&mut performer, byte);
+    }
+    
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let layout = GridLayout { origin: (0, 0), cell_width: 10, cell_height: 19 };
+    let stride = 30 * BYTES_PER_PIXEL;
+    let mut canvas = vec![0u8; stride * 2 * 19];
+    let palette = &performer.palette;
+    draw_grid(&mut canvas, stride, &layout, &performer.styled_snapshot(), palette, Some(&cursor), &mut glyphs);
+    
+    let (cursor_color, text_color) = (argb_pixel(palette.cursor, 1.0), argb_pixel(rgb::RGB8::new(255, 0, 0), 1.0));
+    let pixel = |x: usize, y: usize| &canvas[y * stride + x * BYTES_PER_PIXEL..][..BYTES_PER_PIXEL];
+    (0..19)
+        .map(|y| (0..30).map(|x| match pixel(x, y) {
+            p if p == cursor_color => '#',
+            p if p == text_color => 't',
+            _ => '.',
+        }).collect())
+        .collect()
+}
+
+#[test]
+fn test_cursor_shapes() {
+    let at = |col: usize, shape: CursorShape, hollow: bool| CursorSprite { point: Point::new(0, col), shape, hollow };
+    let rows = |spans: &[String], range: std::ops::Range<usize>| -> Vec<usize> {
+        (0..19).filter(|&y| spans[y][range.clone()].contains('#')).collect()
+    };
+    
+    // A 2px beam along the left edge of the cell
+    let beam = cursor_spans(b"", at(1, CursorShape::Beam, false));
+    assert!(beam.iter().all(|span| span == &format!("{}##{}", ".".repeat(10), ".".repeat(18))));
+    
+    // A 2px bar where an underline would be, below the baseline at 15
+    let underline = cursor_spans(b"", at(0, CursorShape::Underline, false));
+    assert_eq!(rows(&underline, 0..30), vec![16, 17]);
+    assert_eq!(underline[16], format!("{}{}", "#".repeat(10), ".".repeat(20)));
+    
+    // A block fills the cell and draws its glyph in the cursor text color
+    let block = cursor_spans(b"A", at(0, CursorShape::Block, false));
+    let cell = format!("{}{}", "#".repeat(10), ".".repeat(20));
+    assert_eq!([&block[0], &block[18]], [&cell, &cell]);
+    assert!(block.iter().all(|span| !span[10..].contains(['#', 't'])));
+    assert!(block.iter().any(|span| span.contains('t')));
+    
+    // Unfocused, any shape is an outline of the cell
+    let hollow = cursor_spans(b"A", at(0, CursorShape::Beam, true));
+    let edge = format!("{}{}", "#".repeat(10), ".".repeat(20));
+    assert_eq!(&hollow[..2], [edge.clone(), edge.clone()]);
+    assert_eq!(&hollow[17..], [edge.clone(), edge]);
+    assert!(hollow[2..17].iter().all(|span| span.starts_with("##") && &span[8..12] == "##.." && !span.contains('t')));
+    
+    // On a double-width character the block and outline cover two cells
+    let wide = cursor_spans("中".as_bytes(), at(0, CursorShape::Block, false));
+    assert_eq!(wide[0], format!("{}{}", "#".repeat(20), ".".repeat(10)));
+    assert!(wide.iter().all(|span| span.ends_with(&".".repeat(10))));
+    let wide = cursor_spans("中".as_bytes(), at(0, CursorShape::Block, true));
+    assert_eq!(wide[0], format!("{}{}", "#".repeat(20), ".".repeat(10)));
+    assert!(wide[2..17].iter().all(|span| span.starts_with("##..") && &span[18..22] == "##.."));
+}
+
+/// Draws two cells of `bytes` into cells 10 pixels wide and `cell_height` tall, returning for
+/// each pixel row of the first grid row which of the 20 columns are `color`.
+fn decoration_spans(bytes: &[u8], cell_height: usize, color: rgb::RGB8) -> Vec<String> {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(2, 3, &config);
+    let mut parser = Parser::new();
+    for &byte in bytes {
+        parser.advance(&mut performer, byte);
+    }
+    
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let layout = GridLayout { origin: (0, 0), cell_width: 10, cell_height };
+    let stride = 30 * BYTES_PER_PIXEL;
+    let mut canvas = vec![0u8; stride * 2 * cell_height];
+    draw_grid(&mut canvas, stride, &layout, &performer.styled_snapshot(), &performer.palette, None, &mut glyphs);
+    
+    // Nothing may spill into the second, undecorated row
+    let pixel = argb_pixel(color, 1.0);
+    let is_color = |x: usize, y: usize| canvas[y * stride + x * BYTES_PER_PIXEL..][..BYTES_PER_PIXEL] == pixel;
+    assert!((cell_height..2 * cell_height).all(|y| (0..30).all(|x| !is_color(x, y))));
+    
+    (0..cell_height)
+        .map(|y| (0..20).map(|x| if is_color(x, y) { '#' } else { '.' }).collect())
+        .collect()
+}
+
+#[test]
+fn test_decorations() {
+    let red = rgb::RGB8::new(255, 0, 0);
+    let white = rgb::RGB8::new(255, 255, 255);
+    let lit = |spans: &[String]| -> Vec<(usize, String)> {
+        spans.iter().cloned().enumerate().filter(|(_, span)| span.contains('#')).collect()
+    };
+    let full = "#".repeat(20);
+    
+    // The embedded font at 16px has its baseline at 15 and 1px lines starting 1px below it,
+    // drawn in the underline color across both cells
+    let single = decoration_spans(b"\x1b[4;58:2::255:0:0m  ", 19, red);
+    assert_eq!(lit(&single), vec![(16, full.clone())]);
+    
+    let double = decoration_spans(b"\x1b[4:2;58:2::255:0:0m  ", 19, red);
+    assert_eq!(lit(&double), vec![(16, full.clone()), (18, full.clone())]);
+    
+    let dotted = decoration_spans(b"\x1b[4:4;58:2::255:0:0m  ", 19, red);
+    assert_eq!(lit(&dotted), vec![(16, "#.".repeat(10))]);
+    
+    let dashed = decoration_spans(b"\x1b[4:5;58:2::255:0:0m  ", 19, red);
+    assert_eq!(lit(&dashed), vec![(16, "####.".repeat(4))]);
+    
+    // A wave a period per cell, with every column drawn
+    let curly = decoration_spans(b"\x1b[4:3;58:2::255:0:0m  ", 19, red);
+    let rows: Vec<usize> = lit(&curly).iter().map(|(y, _)| *y).collect();
+    assert_eq!(rows, vec![16, 17, 18]);
+    assert!((0..20).all(|x| curly.iter().any(|span| span.as_bytes()[x] == b'#')));
+    assert_eq!(&curly[16][..10], &curly[16][10..]);
+    assert_ne!(curly[16], full);
+    
+    // Without an underline color the foreground is used; strikethrough is at half the x-height
+    let underline = decoration_spans(b"\x1b[4m  ", 19, white);
+    assert_eq!(lit(&underline), vec![(16, full.clone())]);
+    let strikethrough = decoration_spans(b"\x1b[9m  ", 19, white);
+    assert_eq!(lit(&strikethrough), vec![(11, full.clone())]);
+}
+
+#[test]
+fn test_decorations_clipped_to_small_cells() {
+    let red = rgb::RGB8::new(255, 0, 0);
+    // Cells shorter than the font's line move the lines up into the cell, which
+    // decoration_spans checks by looking for color in the row below
+    for sgr in ["4", "4:2", "4:3", "4:4", "4:5"] {
+        let bytes = format!("\x1b[{};58:2::255:0:0m  ", sgr);
+        for cell_height in [12, 17] {
+            let spans = decoration_spans(bytes.as_bytes(), cell_height, red);
+            assert!(spans.iter().any(|span| span.contains('#')), "{} at {}px", sgr, cell_height);
+        }
+    }
+}
+
+/// A frame of a 10x5 grid of 10x19 cells, 2 pixels in from the edges.
+fn frame(performer: &TerminalPerformer) -> Frame {
+    Frame {
+        size: (104, 99),
+        layout: GridLayout { origin: (2, 2), cell_width: 10, cell_height: 19 },
+        background: argb_pixel(performer.palette.background, 1.0),
+        titlebar: None,
+        palette: performer.palette.clone(),
+        rows: performer.styled_snapshot(),
+        cursor: Some(CursorSprite { point: Point::new(performer.cursor.row, performer.cursor.col), shape: CursorShape::Block, hollow: false }),
+        images: Vec::new(),
+        scrollbar: None,
+        flash: None,
+    }
+}
+
+#[test]
+fn test_retained_canvas() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(5, 10, &config);
+    let mut parser = Parser::new();
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let mut canvas = RetainedCanvas::new();
+    let band = |rows: std::ops::Range<usize>| Rect { x: 2, y: 2 + rows.start * 19, width: 100, height: rows.len() * 19 };
+    
+    // The first frame is drawn in full, and an unchanged one not at all
+    for &byte in "gjq\r\nAy\r\n\x1b[41m_\x1b[0m\r\npgÉ\x1b[2;3H".as_bytes() {
+        parser.advance(&mut performer, byte);
+    }
+    assert_eq!(canvas.draw(frame(&performer), &mut glyphs), vec![Rect { x: 0, y: 0, width: 104, height: 99 }]);
+    assert!(canvas.draw(frame(&performer), &mut glyphs).is_empty());
+    
+    // Every step must come out as drawing the frame from scratch would
+    let mut step = |change: &dyn Fn(&mut TerminalPerformer, &mut Parser), expected: Vec<Rect>| {
+        change(&mut performer, &mut parser);
+        let damage = canvas.draw(frame(&performer), &mut glyphs);
+        let mut full = RetainedCanvas::new();
+        full.draw(frame(&performer), &mut glyphs);
+        assert!(canvas.pixels() == full.pixels());
+        assert_eq!(damage, expected);
+    };
+    let write = |bytes: &'static [u8]| move |performer: &mut TerminalPerformer, parser: &mut Parser| {
+        for &byte in bytes {
+            parser.advance(performer, byte);
+        }
+    };
+    
+    // A change redraws its row with the rows around it, moving the cursor redraws both of its rows
+    step(&write(b"B"), vec![band(0..3)]);
+    step(&write(b"\x1b[4;5H"), vec![band(0..5)]);
+    step(&write(b"\x1b[5;1H"), vec![band(2..5)]);
+    
+    // Scrolling either way moves the rows, damaging the whole grid
+    step(&write(b"\r\nnext"), vec![band(0..5)]);
+    step(&write(b"\r\n\r\n"), vec![band(0..5)]);
+    step(&|performer, _| performer.grid.scroll_view_up(1), vec![band(0..5)]);
+    step(&|performer, _| performer.grid.scroll_view_down(1), vec![band(0..5)]);
+    
+    // Selecting redraws the rows that became selected, and moving or dropping the selection
+    // those that no longer are
+    let select = |row: usize| move |performer: &mut TerminalPerformer, _: &mut Parser| {
+        let line = performer.grid.absolute_line(row);
+        performer.selection = Some(Selection::new(Point::new(line, 0), Point::new(line, 2)));
+    };
+    step(&select(1), vec![band(0..3)]);
+    step(&select(3), vec![band(0..5)]);
+    step(&|performer, _| performer.selection = None, vec![band(2..5)]);
+}
+
+#[test]
+fn test_sixel_images() {
+    // Half opaque red, half transparent, scaled up to 2x1 cells
+    let red = rgb::RGBA8::new(255, 0, 0, 255);
+    let image = SixelImage { width: 2, height: 1, pixels: vec![red, rgb::RGBA8::new(0, 0, 255, 0)] };
+    let bitmap = std::sync::Arc::new(scale_image(&image, 20, 19));
+    assert_eq!((bitmap.width, bitmap.height, bitmap.pixels.len()), (20, 19, 20 * 19 * BYTES_PER_PIXEL));
+    assert_eq!(bitmap.pixels[..4], [0, 0, 255, 255]);
+    assert_eq!(bitmap.pixels[40..44], [0, 0, 0, 0]);
+    
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(5, 10, &config);
+    let mut parser = Parser::new();
+    for &byte in "a\r\nb\r\nc\r\nd\r\ne".as_bytes() {
+        parser.advance(&mut performer, byte);
+    }
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let mut canvas = RetainedCanvas::new();
+    let band = |rows: std::ops::Range<usize>| Rect { x: 2, y: 2 + rows.start * 19, width: 100, height: rows.len() * 19 };
+    let pixel = |canvas: &RetainedCanvas, x: usize, y: usize| canvas.pixels()[(y * 104 + x) * BYTES_PER_PIXEL..][..BYTES_PER_PIXEL].to_vec();
+    let background = argb_pixel(performer.palette.background, 1.0).to_vec();
+    let mut draw = |canvas: &mut RetainedCanvas, frame: Frame| {
+        let damage = canvas.draw(frame.clone(), &mut glyphs);
+        let mut full = RetainedCanvas::new();
+        full.draw(frame, &mut glyphs);
+        assert!(canvas.pixels() == full.pixels());
+        damage
+    };
+    let with_image = |performer: &TerminalPerformer, row: i32| Frame {
+        images: vec![FrameImage { origin: (12, 2 + row * 19), bitmap: bitmap.clone() }],
+        ..frame(performer)
+    };
+    draw(&mut canvas, frame(&performer));
+    
+    // An image appearing redraws the rows it covers, and shows the grid through its
+    // transparent pixels
+    assert_eq!(draw(&mut canvas, with_image(&performer, 1)), vec![band(0..3)]);
+    assert_eq!(pixel(&canvas, 12, 21), [0, 0, 255, 255]);
+    assert_eq!(pixel(&canvas, 21, 39), [0, 0, 255, 255]);
+    assert_eq!(pixel(&canvas, 22, 21), background);
+    assert!(draw(&mut canvas, with_image(&performer, 1)).is_empty());
+    
+    // It moves with the rows when they scroll, partly out of view at the top
+    for &byte in "\r\nf".as_bytes() {
+        parser.advance(&mut performer, byte);
+    }
+    draw(&mut canvas, with_image(&performer, 0));
+    assert_eq!(pixel(&canvas, 12, 2), [0, 0, 255, 255]);
+    parser.advance(&mut performer, b'\n');
+    draw(&mut canvas, with_image(&performer, -1));
+    assert_eq!(pixel(&canvas, 12, 2), background);
+    
+    // And going redraws the rows it covered
+    draw(&mut canvas, with_image(&performer, 2));
+    assert_eq!(draw(&mut canvas, frame(&performer)), vec![band(1..4)]);
+    assert_eq!(pixel(&canvas, 12, 40), background);
+}
+
+#[test]
+fn test_titlebar() {
+    let config = Config::default();
+    let performer = TerminalPerformer::new(5, 10, &config);
+    let palette = &performer.palette;
+    let bar = argb_pixel(palette.bright[0], 1.0);
+    let chars = |cells: &[Cell]| cells.iter().map(|cell| cell.c).collect::<String>();
+    
+    // The title starts a cell in, and the close button is centered in the square at the end
+    let bar_for = |title: &str| titlebar(104, 24, 10, title, bar, palette.foreground, palette.background);
+    let short = bar_for("vim");
+    assert_eq!(chars(&short.text[0].1), "vim");
+    assert_eq!(short.text[0].0, GridLayout { origin: (10, 0), cell_width: 10, cell_height: 24 });
+    assert_eq!((short.text[1].0.origin, chars(&short.text[1].1)), ((87, 0), "×".to_string()));
+    
+    // A title too long for the space left is cut short
+    assert_eq!(chars(&bar_for("a long title").text[0].1), "a lon…");
+    assert_eq!(chars(&titlebar(30, 24, 10, "vim", bar, palette.foreground, palette.background).text[0].1), "");
+    
+    // Over the grid, the text is drawn on the bar and a new title redraws the frame
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let mut frame = frame(&performer);
+    frame.size = (104, 123);
+    frame.layout.origin = (2, 26);
+    frame.titlebar = Some(short);
+    let mut canvas = RetainedCanvas::new();
+    canvas.draw(frame.clone(), &mut glyphs);
+    let stride = 104 * BYTES_PER_PIXEL;
+    let row = |y: usize, xs: std::ops::Range<usize>| canvas.pixels()[y * stride + xs.start * BYTES_PER_PIXEL..y * stride + xs.end * BYTES_PER_PIXEL].to_vec();
+    let drawn = |y: usize, xs: std::ops::Range<usize>| row(y, xs).chunks_exact(4).any(|pixel| pixel != bar);
+    assert!(row(0, 0..104).chunks_exact(4).all(|pixel| pixel == bar));
+    assert!(drawn(12, 10..40) && !drawn(12, 40..87) && drawn(12, 87..97) && !drawn(12, 97..104));
+    
+    frame.titlebar = Some(bar_for("less"));
+    assert_eq!(canvas.draw(frame, &mut glyphs), vec![Rect { x: 0, y: 0, width: 104, height: 123 }]);
+}
+
+#[test]
+fn test_scrollbar_thumb() {
+    // No history, no scrollbar
+    assert_eq!(scrollbar_thumb(480, 24, 0, 0), None);
+    
+    // The thumb is the view's share of all lines, at the bottom on the live screen and at the
+    // top at the oldest line
+    assert_eq!(scrollbar_thumb(480, 24, 24, 0), Some(240..480));
+    assert_eq!(scrollbar_thumb(480, 24, 24, 12), Some(120..360));
+    assert_eq!(scrollbar_thumb(480, 24, 24, 24), Some(0..240));
+    assert_eq!(scrollbar_thumb(480, 24, 72, 36), Some(180..300));
+    
+    // A long history doesn't shrink it out of sight, and it still spans the track
+    assert_eq!(scrollbar_thumb(480, 24, 100_000, 0), Some(480 - MIN_THUMB_HEIGHT..480));
+    assert_eq!(scrollbar_thumb(480, 24, 100_000, 100_000), Some(0..MIN_THUMB_HEIGHT));
+    assert_eq!(scrollbar_thumb(480, 24, 100_000, 50_000), Some(236..236 + MIN_THUMB_HEIGHT));
+    
+    // Nor can it outgrow a tiny track
+    assert_eq!(scrollbar_thumb(4, 1, 1000, 0), Some(0..4));
+}
+
+#[test]
+fn test_scrollbar_repaints() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(5, 10, &config);
+    let mut parser = Parser::new();
+    for line in 0..30 {
+        for &byte in format!("\r\nline {}", line).as_bytes() {
+            parser.advance(&mut performer, byte);
+        }
+    }
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let mut canvas = RetainedCanvas::new();
+    let with_bar = |performer: &TerminalPerformer, alpha: f32| {
+        let grid = &performer.grid;
+        let thumb = scrollbar_thumb(5 * 19, grid.rows, grid.scrollback.len(), grid.display_offset).unwrap();
+        Frame {
+            scrollbar: Some(Scrollbar {
+                thumb: Rect { x: 100, y: 2 + thumb.start, width: 4, height: thumb.len() },
+                pixel: argb_pixel(performer.palette.foreground, alpha),
+            }),
+            ..frame(performer)
+        }
+    };
+    let mut draw = |frame: Frame| {
+        let damage = canvas.draw(frame.clone(), &mut glyphs);
+        let mut full = RetainedCanvas::new();
+        full.draw(frame, &mut glyphs);
+        assert!(canvas.pixels() == full.pixels());
+        damage
+    };
+    draw(frame(&performer));
+    
+    // Scrolling up moves the rows and shows the thumb in the right padding, over the last rows
+    performer.grid.scroll_view_up(2);
+    let thumb = with_bar(&performer, 1.0).scrollbar.unwrap().thumb;
+    assert_eq!((thumb.y, thumb.height), (2 + 73, 15));
+    let damage = draw(with_bar(&performer, 1.0));
+    assert!(damage.contains(&thumb));
+    
+    // Fading only redraws the rows around the thumb
+    let damage = draw(with_bar(&performer, 0.5));
+    assert_eq!(damage, vec![Rect { x: 2, y: 2 + 2 * 19, width: 100, height: 3 * 19 }, thumb]);
+    assert!(draw(with_bar(&performer, 0.5)).is_empty());
+    
+    // The thumb follows the view, and going leaves only the rows around it to redraw
+    performer.grid.scroll_view_up(10);
+    draw(with_bar(&performer, 0.5));
+    let thumb = with_bar(&performer, 0.5).scrollbar.unwrap().thumb;
+    let damage = draw(frame(&performer));
+    assert_eq!(damage, vec![thumb, Rect { x: 2, y: 2 + 19, width: 100, height: 4 * 19 }]);
+}
+
+#[test]
+fn test_scrollback_badge() {
+    let (fg, bg) = (rgb::RGB8::new(0, 0, 0), rgb::RGB8::new(255, 255, 255));
+    let mut rows = vec![vec![Cell::default(); 10]; 2];
+    overlay_badge(&mut rows, "[3/120]", fg, bg);
+    let text: String = rows[0].iter().map(|cell| cell.c).collect();
+    assert_eq!(text, "   [3/120]");
+    assert_eq!((rows[0][3].fg, rows[0][3].bg), (fg, bg));
+    assert_eq!(rows[0][2], Cell::default());
+    assert!(rows[1].iter().all(|cell| *cell == Cell::default()));
+    
+    // A badge wider than the grid is left out
+    let mut rows = vec![vec![Cell::default(); 4]; 1];
+    overlay_badge(&mut rows, "[3/120]", fg, bg);
+    assert!(rows[0].iter().all(|cell| *cell == Cell::default()));
+}
+
+#[test]
+fn test_search_highlights() {
+    let (fg, bg) = (rgb::RGB8::new(0, 0, 0), rgb::RGB8::new(255, 255, 0));
+    let mut rows = vec![vec![Cell::default(); 6]; 2];
+    highlight_spans(&mut rows, &[(0, 1..3), (1, 4..9), (2, 0..6)], fg, bg);
+    let highlighted: Vec<String> = rows.iter()
+        .map(|row| row.iter().map(|cell| if (cell.fg, cell.bg) == (fg, bg) { '#' } else { '.' }).collect())
+        .collect();
+    
+    // Spans reaching past the grid are cut short, and rows below it left out
+    assert_eq!(highlighted, [".##...", "....##"]);
+}
+
+/// Draws `bytes` into 3x6 cells 10x19 pixels large with `selection`, returning for each row a
+/// map of its cells: `#` where the cell is drawn with the selection background, `.` elsewhere.
+fn selection_cells(bytes: &[u8], selection: Selection) -> Vec<String> {
+    let mut config = Config::default();
+    config.colors.selection_background = Some("#444444".to_string());
+    let mut performer = TerminalPerformer::new(3, 6, &config);
+    let mut parser = Parser::new();
+    for &byte in bytes {
+        parser.advance(&mut performer, byte);
+    }
+    performer.selection = Some(selection);
+    
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let layout = GridLayout { origin: (0, 0), cell_width: 10, cell_height: 19 };
+    let stride = 60 * BYTES_PER_PIXEL;
+    let mut canvas = vec![0u8; stride * 3 * 19];
+    let palette = &performer.palette;
+    fill(&mut canvas, argb_pixel(palette.background, 1.0));
+    draw_grid(&mut canvas, stride, &layout, &performer.styled_snapshot(), palette, None, &mut glyphs);
+    
+    // The top left pixel of each cell, which no glyph reaches
+    let selected = argb_pixel(rgb::RGB8::new(0x44, 0x44, 0x44), 1.0);
+    let pixel = |x: usize, y: usize| &canvas[y * stride + x * BYTES_PER_PIXEL..][..BYTES_PER_PIXEL];
+    (0..3)
+        .map(|row| (0..6).map(|col| if pixel(col * 10, row * 19) == selected { '#' } else { '.' }).collect())
+        .collect()
+}
+
+#[test]
+fn test_selection_rendering() {
+    // Within a line
+    let simple = selection_cells(b"hello", Selection::new(Point::new(0, 3), Point::new(0, 1)));
+    assert_eq!(simple, [".###..", "......", "......"]);
+    
+    // Across a wrapped line, running to the end of the first row and on from the start of the next
+    let wrapped = selection_cells(b"abcdefghij", Selection::new(Point::new(0, 4), Point::new(1, 1)));
+    assert_eq!(wrapped, ["....##", "##....", "......"]);
+    
+    // A block covers the same columns on every row
+    let block = selection_cells(b"abc\r\ndef\r\nghi", Selection::rectangular(Point::new(2, 2), Point::new(0, 1)));
+    assert_eq!(block, [".##...", ".##...", ".##..."]);
+}
+
+#[test]
+fn test_wide_character_rendering() {
+    let config = Config::default();
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let mut draw = |rows: &[Vec<Cell>], palette: &Palette, cell_width: usize| -> Vec<Vec<[u8; 4]>> {
+        let layout = GridLayout { origin: (0, 0), cell_width, cell_height: 19 };
+        let width = rows[0].len() * cell_width;
+        let stride = width * BYTES_PER_PIXEL;
+        let mut canvas = vec![0u8; stride * rows.len() * 19];
+        fill(&mut canvas, argb_pixel(palette.background, 1.0));
+        draw_grid(&mut canvas, stride, &layout, rows, palette, None, &mut glyphs);
+        canvas.chunks_exact(stride)
+            .map(|row| row.chunks_exact(BYTES_PER_PIXEL).map(|pixel| pixel.try_into().unwrap()).collect())
+            .collect()
+    };
+    
+    // After 40 double-width characters, text is drawn exactly where it is on its own
+    let mut cjk = TerminalPerformer::new(1, 84, &config);
+    let mut alone = TerminalPerformer::new(1, 84, &config);
+    let mut parser = Parser::new();
+    for &byte in format!("{}x", "中文".repeat(20)).as_bytes() {
+        parser.advance(&mut cjk, byte);
+    }
+    for &byte in b"\x1b[1;81Hx" {
+        parser.advance(&mut alone, byte);
+    }
+    assert_eq!(cjk.grid.cells[0][80].c, 'x');
+    let (cjk, alone) = (draw(&cjk.styled_snapshot(), &cjk.palette, 10), draw(&alone.styled_snapshot(), &alone.palette, 10));
+    assert!(cjk.iter().zip(&alone).all(|(cjk, alone)| cjk[800..] == alone[800..]));
+    assert!(alone.iter().any(|row| row[800..810].iter().any(|&pixel| pixel != row[0])));
+    
+    // The halves keep their own backgrounds
+    let palette = Palette::from_config(&config.colors);
+    let (red, blue) = (rgb::RGB8::new(255, 0, 0), rgb::RGB8::new(0, 0, 255));
+    let pair = vec![vec![
+        Cell { c: '中', bg: red, flags: CellFlags::WIDE, ..Cell::default() },
+        Cell { c: ' ', bg: blue, flags: CellFlags::WIDE_SPACER, ..Cell::default() },
+    ]];
+    let canvas = draw(&pair, &palette, 10);
+    assert_eq!((canvas[0][0], canvas[0][19]), (argb_pixel(red, 1.0), argb_pixel(blue, 1.0)));
+    
+    // A glyph wider than two cells is cut off at the end of the second
+    let narrow: Vec<Cell> = "W   ".chars().map(|c| Cell { c, ..Cell::default() }).collect();
+    let canvas = draw(&[narrow], &palette, 3);
+    let background = argb_pixel(palette.background, 1.0);
+    assert!(canvas.iter().any(|row| row[3..6].iter().any(|&pixel| pixel != background)));
+    assert!(canvas.iter().all(|row| row[6..].iter().all(|&pixel| pixel == background)));
+}
+
+#[test]
+fn test_blinking_text_damage() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(5, 10, &config);
+    let mut parser = Parser::new();
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let mut canvas = RetainedCanvas::new();
+    let band = |rows: std::ops::Range<usize>| Rect { x: 2, y: 2 + rows.start * 19, width: 100, height: rows.len() * 19 };
+    let blink_frame = |performer: &TerminalPerformer, visible: bool| {
+        let mut frame = frame(performer);
+        let blinking = blink_text(&mut frame.rows, visible);
+        (frame, blinking)
+    };
+    
+    // Steady text doesn't keep the blink going, and a phase flip changes nothing
+    for &byte in b"steady\x1b[5;1H" {
+        parser.advance(&mut performer, byte);
+    }
+    let (first, blinking) = blink_frame(&performer, true);
+    assert!(!blinking);
+    canvas.draw(first, &mut glyphs);
+    let (hidden, _) = blink_frame(&performer, false);
+    assert!(canvas.draw(hidden, &mut glyphs).is_empty());
+    
+    // Flipping the phase only redraws the rows with blinking text, and the rows around them
+    for &byte in b"\x1b[3;2H\x1b[5mblink\x1b[0m\x1b[5;1H" {
+        parser.advance(&mut performer, byte);
+    }
+    canvas.draw(blink_frame(&performer, true).0, &mut glyphs);
+    for visible in [false, true] {
+        let (frame, blinking) = blink_frame(&performer, visible);
+        assert!(blinking);
+        assert_eq!(canvas.draw(frame, &mut glyphs), vec![band(1..4)]);
+        let mut full = RetainedCanvas::new();
+        full.draw(blink_frame(&performer, visible).0, &mut glyphs);
+        assert!(canvas.pixels() == full.pixels());
+    }
+    let (shown, _) = blink_frame(&performer, true);
+    assert!(canvas.draw(shown, &mut glyphs).is_empty());
+}
\ No newline at end of file
@@ -0,0 +1,53 @@
+use myterm::config::{Config, Padding};
+use myterm::font::FontSet;
+use myterm::render::{GlyphCache, GLYPH_CACHE_BYTES};
+use myterm::screenshot::{self, to_rgba};
+use myterm::terminal::{GridGeometry, Terminal};
+
+/// Decodes a PNG into its size and RGBA pixels.
+fn decode(path: &std::path::Path) -> ((u32, u32), Vec<u8>) {
+    let decoder = png::Decoder::new(std::fs::File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(info.color_type, png::ColorType::Rgba);
+    ((info.width, info.height), pixels)
+}
+
+#[tokio::test]
+async fn test_screenshot_pixels() {
+    let config = Config::default();
+    let mut terminal = Terminal::new(&config).unwrap();
+    terminal.set_cell_size((8, 16));
+    terminal.set_geometry(GridGeometry { cols: 10, rows: 3, padding: Padding::uniform(2) }).unwrap();
+    // Red and reversed cells on the first row, a blue one on the second, then the cursor
+    terminal.process(b"\x1b[41m  \x1b[0m\x1b[7m \x1b[0m\r\n\x1b[44m \x1b[0m");
+    
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("shot.png");
+    screenshot::save(&path, &terminal, 1.0, &mut glyphs).unwrap();
+    
+    // The grid with its padding, and nothing else
+    let ((width, height), pixels) = decode(&path);
+    assert_eq!((width, height), (2 + 10 * 8 + 2, 2 + 3 * 16 + 2));
+    let pixel = |x: u32, y: u32| {
+        let start = ((y * width + x) * 4) as usize;
+        [pixels[start], pixels[start + 1], pixels[start + 2], pixels[start + 3]]
+    };
+    let cell = |row: u32, col: u32| pixel(2 + col * 8 + 4, 2 + row * 16 + 8);
+    assert_eq!(pixel(0, 0), [0, 0, 0, 255]);
+    assert_eq!(cell(0, 0), [128, 0, 0, 255]);
+    assert_eq!(cell(0, 1), [128, 0, 0, 255]);
+    assert_eq!(cell(0, 2), [255, 255, 255, 255]);
+    assert_eq!(cell(1, 0), [0, 0, 128, 255]);
+    assert_eq!(cell(1, 1), [255, 255, 255, 255]);
+    assert_eq!(cell(2, 0), [0, 0, 0, 255]);
+    
+    // A translucent background stays translucent, and isn't premultiplied in the file
+    screenshot::save(&path, &terminal, 0.5, &mut glyphs).unwrap();
+    let (_, pixels) = decode(&path);
+    assert_eq!(pixels[..4], [0, 0, 0, 128]);
+    assert_eq!(to_rgba(&[64, 0, 128, 128, 0, 0, 0, 0]), [255, 0, 128, 128, 0, 0, 0, 0]);
+}
\ No newline at end of file
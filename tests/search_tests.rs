@@ -0,0 +1,95 @@
+use myterm::config::Config;
+use myterm::search::{find_matches, Search, SearchMatch};
+use myterm::terminal::TerminalPerformer;
+use vte::Parser;
+
+fn performer(rows: usize, text: &str) -> TerminalPerformer {
+    let mut config = Config::default();
+    config.terminal.scrollback_lines = Some(100);
+    let mut performer = TerminalPerformer::new(rows, 20, &config);
+    let mut parser = Parser::new();
+    for &byte in text.as_bytes() {
+        parser.advance(&mut performer, byte);
+    }
+    performer
+}
+
+#[test]
+fn test_find_matches() {
+    let performer = performer(3, "Foo bar foo\r\nfoofoo\r\n中文 foo\r\nlast");
+    let grid = &performer.grid;
+    
+    // Lines scrolled into the scrollback are searched too, and matches don't overlap
+    let lines: Vec<usize> = find_matches(grid, "foo").iter().map(|m| m.line).collect();
+    assert_eq!(lines, [0, 0, 1, 1, 2]);
+    assert_eq!(find_matches(grid, "oo").len(), 5);
+    
+    // Lowercase queries ignore case, ones with capitals don't
+    assert_eq!(find_matches(grid, "Foo"), vec![SearchMatch { line: 0, start: 0, end: 3 }]);
+    assert_eq!(find_matches(grid, "fOO").len(), 0);
+    
+    // Columns count both halves of wide characters
+    assert_eq!(find_matches(grid, "文 f"), vec![SearchMatch { line: 2, start: 2, end: 6 }]);
+    assert_eq!(find_matches(grid, "中文"), vec![SearchMatch { line: 2, start: 0, end: 4 }]);
+    
+    // Nothing spans lines, and an empty query matches nothing
+    assert!(find_matches(grid, "foolast").is_empty());
+    assert!(find_matches(grid, "").is_empty());
+}
+
+#[test]
+fn test_search_focus() {
+    let performer = performer(4, "one\r\nan apple\r\ntwo apples\r\napricot");
+    let grid = &performer.grid;
+    let mut search = Search::new();
+    assert_eq!(search.status(), "search:  [0/0]");
+    
+    // Typing focuses the newest match, and narrowing keeps the focus where it was
+    search.push('a', grid);
+    assert_eq!(search.status(), "search: a [4/4]");
+    assert_eq!(search.focused(), Some(&SearchMatch { line: 3, start: 0, end: 1 }));
+    search.focus_older();
+    assert_eq!(search.focused().unwrap().line, 2);
+    search.push('p', grid);
+    assert_eq!(search.status(), "search: ap [2/3]");
+    assert_eq!(search.focused(), Some(&SearchMatch { line: 2, start: 4, end: 6 }));
+    search.push('p', grid);
+    assert_eq!(search.status(), "search: app [2/2]");
+    
+    // Moving between matches goes round at either end
+    search.focus_newer();
+    assert_eq!(search.status(), "search: app [1/2]");
+    search.focus_older();
+    assert_eq!(search.status(), "search: app [2/2]");
+    
+    // Without matches nothing is focused, and taking characters off finds them again
+    search.push('x', grid);
+    assert_eq!(search.status(), "search: appx [0/0]");
+    search.focus_older();
+    assert_eq!(search.focused(), None);
+    search.pop(grid);
+    assert_eq!(search.status(), "search: app [2/2]");
+}
+
+#[test]
+fn test_scroll_view_to_line() {
+    let mut text = String::new();
+    for line in 0..20 {
+        text.push_str(&format!("line {}\r\n", line));
+    }
+    let mut performer = performer(5, &text);
+    let grid = &mut performer.grid;
+    assert_eq!(grid.absolute_line(0), 16);
+    
+    // Lines above the view bring it up to them, lines in view leave it be
+    grid.scroll_view_to_line(3);
+    assert_eq!(grid.absolute_line(0), 3);
+    grid.scroll_view_to_line(7);
+    assert_eq!(grid.absolute_line(0), 3);
+    
+    // Lines below it bring it down just far enough
+    grid.scroll_view_to_line(10);
+    assert_eq!(grid.absolute_line(0), 6);
+    grid.scroll_view_to_line(20);
+    assert_eq!((grid.absolute_line(0), grid.display_offset), (16, 0));
+}
\ No newline at end of file
@@ -0,0 +1,355 @@
+use myterm::config::Config;
+use myterm::font::FontSet;
+use myterm::render::{argb_pixel, draw_grid, fill, GlyphCache, GridLayout, Rect, BYTES_PER_PIXEL, GLYPH_CACHE_BYTES};
+use myterm::shm::{needs_new_pool, pool_capacity, BufferPool, DamageHistory, MAX_BUFFERS, MIN_BUFFERS};
+use myterm::terminal::TerminalPerformer;
+use smithay_client_toolkit::delegate_shm;
+use smithay_client_toolkit::shm::{Shm, ShmHandler};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use vte::Parser;
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_compositor::WlCompositor, wl_registry::WlRegistry, wl_surface::WlSurface};
+use wayland_client::{Connection, Dispatch, EventQueue, Proxy, QueueHandle};
+
+/// A compositor offering only wl_shm and surfaces to attach buffers to, which holds every
+/// buffer attached until told to release them.
+mod compositor {
+    use std::sync::{Arc, Mutex};
+    use wayland_server::backend::ClientData;
+    use wayland_server::protocol::{wl_buffer, wl_compositor, wl_shm, wl_shm_pool, wl_surface};
+    use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+    
+    #[derive(Default)]
+    pub struct Buffers {
+        /// Buffers the client hasn't destroyed.
+        pub live: Vec<wl_buffer::WlBuffer>,
+        /// Buffers attached and not released yet.
+        pub held: Vec<wl_buffer::WlBuffer>,
+    }
+    
+    impl Buffers {
+        pub fn release_held(&mut self) {
+            for buffer in self.held.drain(..) {
+                buffer.release();
+            }
+        }
+    }
+    
+    pub struct State(pub Arc<Mutex<Buffers>>);
+    
+    pub struct NoClientData;
+    
+    impl ClientData for NoClientData {}
+    
+    impl GlobalDispatch<wl_shm::WlShm, ()> for State {
+        fn bind(_: &mut Self, _: &DisplayHandle, _: &Client, resource: New<wl_shm::WlShm>, _: &(), init: &mut DataInit<'_, Self>) {
+            let shm = init.init(resource, ());
+            shm.format(wl_shm::Format::Argb8888);
+        }
+    }
+    
+    impl Dispatch<wl_shm::WlShm, ()> for State {
+        fn request(_: &mut Self, _: &Client, _: &wl_shm::WlShm, request: wl_shm::Request, _: &(), _: &DisplayHandle, init: &mut DataInit<'_, Self>) {
+            if let wl_shm::Request::CreatePool { id, .. } = request {
+                init.init(id, ());
+            }
+        }
+    }
+    
+    impl Dispatch<wl_shm_pool::WlShmPool, ()> for State {
+        fn request(state: &mut Self, _: &Client, _: &wl_shm_pool::WlShmPool, request: wl_shm_pool::Request, _: &(), _: &DisplayHandle, init: &mut DataInit<'_, Self>) {
+            if let wl_shm_pool::Request::CreateBuffer { id, .. } = request {
+                let buffer = init.init(id, ());
+                state.0.lock().unwrap().live.push(buffer);
+            }
+        }
+    }
+    
+    impl Dispatch<wl_buffer::WlBuffer, ()> for State {
+        fn request(_: &mut Self, _: &Client, _: &wl_buffer::WlBuffer, _: wl_buffer::Request, _: &(), _: &DisplayHandle, _: &mut DataInit<'_, Self>) {}
+        
+        fn destroyed(state: &mut Self, _: wayland_server::backend::ClientId, resource: &wl_buffer::WlBuffer, _: &()) {
+            let mut buffers = state.0.lock().unwrap();
+            buffers.live.retain(|buffer| buffer.id() != resource.id());
+            buffers.held.retain(|buffer| buffer.id() != resource.id());
+        }
+    }
+    
+    impl GlobalDispatch<wl_compositor::WlCompositor, ()> for State {
+        fn bind(_: &mut Self, _: &DisplayHandle, _: &Client, resource: New<wl_compositor::WlCompositor>, _: &(), init: &mut DataInit<'_, Self>) {
+            init.init(resource, ());
+        }
+    }
+    
+    impl Dispatch<wl_compositor::WlCompositor, ()> for State {
+        fn request(_: &mut Self, _: &Client, _: &wl_compositor::WlCompositor, request: wl_compositor::Request, _: &(), _: &DisplayHandle, init: &mut DataInit<'_, Self>) {
+            if let wl_compositor::Request::CreateSurface { id } = request {
+                init.init(id, ());
+            }
+        }
+    }
+    
+    impl Dispatch<wl_surface::WlSurface, ()> for State {
+        fn request(state: &mut Self, _: &Client, _: &wl_surface::WlSurface, request: wl_surface::Request, _: &(), _: &DisplayHandle, _: &mut DataInit<'_, Self>) {
+            if let wl_surface::Request::Attach { buffer: Some(buffer), .. } = request {
+                state.0.lock().unwrap().held.push(buffer);
+            }
+        }
+    }
+}
+
+/// The client side: what `BufferPool` needs from the Wayland connection.
+struct ClientState {
+    shm: Shm,
+}
+
+impl ShmHandler for ClientState {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+delegate_shm!(ClientState);
+
+impl Dispatch<WlRegistry, GlobalListContents> for ClientState {
+    fn event(_: &mut Self, _: &WlRegistry, _: <WlRegistry as wayland_client::Proxy>::Event, _: &GlobalListContents, _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<WlCompositor, ()> for ClientState {
+    fn event(_: &mut Self, _: &WlCompositor, _: <WlCompositor as wayland_client::Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<WlSurface, ()> for ClientState {
+    fn event(_: &mut Self, _: &WlSurface, _: <WlSurface as wayland_client::Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+/// A client connected to a `compositor` running on a thread of its own.
+struct Session {
+    state: ClientState,
+    queue: EventQueue<ClientState>,
+    surface: WlSurface,
+    buffers: Arc<Mutex<compositor::Buffers>>,
+    stop: Arc<AtomicBool>,
+    server: Option<JoinHandle<()>>,
+}
+
+impl Session {
+    fn new() -> Self {
+        let (server_socket, client_socket) = UnixStream::pair().unwrap();
+        let buffers = Arc::new(Mutex::new(compositor::Buffers::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let server = std::thread::spawn({
+            let buffers = buffers.clone();
+            let stop = stop.clone();
+            move || {
+                let mut display = wayland_server::Display::<compositor::State>::new().unwrap();
+                let handle = display.handle();
+                handle.create_global::<compositor::State, wayland_server::protocol::wl_shm::WlShm, _>(1, ());
+                handle.create_global::<compositor::State, wayland_server::protocol::wl_compositor::WlCompositor, _>(1, ());
+                display.handle().insert_client(server_socket, Arc::new(compositor::NoClientData)).unwrap();
+                let mut state = compositor::State(buffers);
+                while !stop.load(Ordering::Relaxed) {
+                    display.dispatch_clients(&mut state).unwrap();
+                    display.flush_clients().unwrap();
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            }
+        });
+        
+        let conn = Connection::from_socket(client_socket).unwrap();
+        let (globals, mut queue) = registry_queue_init::<ClientState>(&conn).unwrap();
+        let qh = queue.handle();
+        let mut state = ClientState { shm: Shm::bind(&globals, &qh).unwrap() };
+        let surface = globals.bind::<WlCompositor, _, _>(&qh, 1..=1, ()).unwrap().create_surface(&qh, ());
+        queue.roundtrip(&mut state).unwrap();
+        Self { state, queue, surface, buffers, stop, server: Some(server) }
+    }
+    
+    /// Releases every buffer the client attached so far, and waits for it to hear of it.
+    fn release_held(&mut self) {
+        self.roundtrip();
+        self.buffers.lock().unwrap().release_held();
+        self.roundtrip();
+    }
+    
+    fn roundtrip(&mut self) {
+        self.queue.roundtrip(&mut self.state).unwrap();
+    }
+    
+    /// Buffers the client created and hasn't destroyed.
+    fn live_buffers(&mut self) -> usize {
+        self.roundtrip();
+        self.buffers.lock().unwrap().live.len()
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(server) = self.server.take() {
+            server.join().unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_pool_sizing_during_interactive_resize() {
+    let buffer_len = |width: usize, height: usize| width * height * 4;
+    
+    // Dragging the window corner outwards a couple of pixels per frame
+    let mut capacity = 0;
+    let mut pools = 0;
+    for step in 0..500 {
+        let len = buffer_len(800 + step * 2, 600 + step);
+        if needs_new_pool(capacity, len, MIN_BUFFERS) {
+            capacity = pool_capacity(len, MIN_BUFFERS);
+            pools += 1;
+        }
+        assert!(capacity >= len * MIN_BUFFERS);
+    }
+    assert!(pools < 20, "Pool recreated {} times", pools);
+    
+    // Shrinking back never needs a new pool, and what's held stays bounded by the largest size
+    let largest = capacity;
+    for step in (0..500).rev() {
+        assert!(!needs_new_pool(capacity, buffer_len(800 + step * 2, 600 + step), MIN_BUFFERS));
+    }
+    assert!(largest <= pool_capacity(buffer_len(1798, 1099), MIN_BUFFERS));
+    
+    // A third buffer only grows the pool when it doesn't fit in the headroom
+    let len = buffer_len(800, 600);
+    let capacity = pool_capacity(len, MIN_BUFFERS);
+    assert!(needs_new_pool(capacity, len, MAX_BUFFERS));
+    assert!(!needs_new_pool(pool_capacity(len, MAX_BUFFERS), len, MAX_BUFFERS));
+}
+
+#[test]
+fn test_acquire_reuses_released_buffers() {
+    let mut session = Session::new();
+    let mut pool = BufferPool::new();
+    let len = 80 * BYTES_PER_PIXEL * 60;
+    
+    // Two buffers to start with, the first keeping what it showed once released
+    let (buffer, canvas) = pool.acquire(&session.state.shm, 80, 60).unwrap().unwrap();
+    assert_eq!(canvas.len(), len);
+    canvas.fill(0x11);
+    buffer.attach_to(&session.surface).unwrap();
+    let (buffer, canvas) = pool.acquire(&session.state.shm, 80, 60).unwrap().unwrap();
+    canvas.fill(0x22);
+    buffer.attach_to(&session.surface).unwrap();
+    let stats = pool.stats();
+    assert_eq!((stats.buffers, stats.busy, stats.pools_created), (2, 2, 1));
+    assert_eq!(stats.capacity, pool_capacity(len, MIN_BUFFERS));
+    
+    // A third while the compositor holds both, and nothing once it holds all three
+    let (buffer, _) = pool.acquire(&session.state.shm, 80, 60).unwrap().unwrap();
+    buffer.attach_to(&session.surface).unwrap();
+    assert!(pool.acquire(&session.state.shm, 80, 60).unwrap().is_none());
+    assert_eq!(pool.stats().buffers, MAX_BUFFERS);
+    assert!(pool.stats().capacity >= len * MAX_BUFFERS);
+    
+    session.release_held();
+    let (_, canvas) = pool.acquire(&session.state.shm, 80, 60).unwrap().unwrap();
+    assert!(canvas.iter().all(|&byte| byte == 0x11));
+    assert_eq!(pool.stats().busy, 0);
+    assert_eq!(session.live_buffers(), MAX_BUFFERS);
+    
+    // A new size drops the old buffers, and a larger one than the pool holds makes a new pool
+    let (buffer, canvas) = pool.acquire(&session.state.shm, 200, 100).unwrap().unwrap();
+    assert_eq!(canvas.len(), 200 * BYTES_PER_PIXEL * 100);
+    buffer.attach_to(&session.surface).unwrap();
+    let stats = pool.stats();
+    assert_eq!((stats.buffers, stats.busy, stats.pools_created), (1, 1, 2));
+    assert_eq!(session.live_buffers(), 1);
+}
+
+#[test]
+fn test_damage_history() {
+    let session = Session::new();
+    let mut pool = BufferPool::new();
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let (buffer, _) = pool.acquire(&session.state.shm, 80, 60).unwrap().unwrap();
+        buffer.attach_to(&session.surface).unwrap();
+        ids.push(buffer.wl_buffer().id());
+    }
+    let row = |y| Rect { x: 0, y, width: 80, height: 1 };
+    let mut history = DamageHistory::new();
+    
+    // New buffers are copied whole
+    assert_eq!(history.record(ids[0].clone(), &[row(0)]), None);
+    assert_eq!(history.record(ids[1].clone(), &[row(1)]), None);
+    
+    // Then each gets what changed in the frames it didn't show too
+    assert_eq!(history.record(ids[0].clone(), &[row(2)]), Some(vec![row(2), row(1)]));
+    assert_eq!(history.record(ids[0].clone(), &[row(3)]), Some(vec![row(3)]));
+    assert_eq!(history.record(ids[1].clone(), &[]), Some(vec![row(3), row(2)]));
+    
+    // One further behind than the frames kept is copied whole again, as are all after a clear
+    for _ in 0..MAX_BUFFERS {
+        history.record(ids[0].clone(), &[]);
+    }
+    assert_eq!(history.record(ids[1].clone(), &[row(4)]), None);
+    history.clear();
+    assert_eq!(history.record(ids[0].clone(), &[]), None);
+}
+
+#[test]
+fn test_resize_while_output_floods_in() {
+    let mut session = Session::new();
+    let mut pool = BufferPool::new();
+    let config = Config::default();
+    let mut glyphs = GlyphCache::new(FontSet::embedded().unwrap(), GLYPH_CACHE_BYTES);
+    glyphs.set_pixel_size(16).unwrap();
+    let layout = GridLayout { origin: (0, 0), cell_width: 10, cell_height: 20 };
+    let mut performer = TerminalPerformer::new(24, 80, &config);
+    let mut parser = Parser::new();
+    let background = argb_pixel(performer.palette.background, 1.0);
+    let flood: Vec<u8> = (0..50).flat_map(|n| format!("\x1b[3{}mline {} of the flood\r\n", n % 8, n).into_bytes()).collect();
+    
+    // Dragging the window corner out and back in a few times, drawing every frame the
+    // compositor leaves a buffer free for, and releasing them a frame late now and then
+    let mut largest = 0;
+    let mut cycles = Vec::new();
+    for step in 0..300usize {
+        let offset = if step % 100 < 50 { step % 100 } else { 100 - step % 100 };
+        let (width, height) = (400 + offset * 12, 300 + offset * 8);
+        largest = largest.max(width * BYTES_PER_PIXEL * height);
+        performer.resize(height / layout.cell_height, width / layout.cell_width);
+        for &byte in &flood {
+            parser.advance(&mut performer, byte);
+        }
+        
+        if let Some((buffer, canvas)) = pool.acquire(&session.state.shm, width as u32, height as u32).unwrap() {
+            fill(canvas, background);
+            draw_grid(canvas, width * BYTES_PER_PIXEL, &layout, &performer.styled_snapshot(), &performer.palette, None, &mut glyphs);
+            buffer.attach_to(&session.surface).unwrap();
+            session.surface.commit();
+        }
+        if step % 3 != 0 {
+            session.release_held();
+        }
+        
+        let stats = pool.stats();
+        assert!(stats.buffers <= MAX_BUFFERS);
+        assert!(stats.capacity <= pool_capacity(largest, MAX_BUFFERS), "{:?}", stats);
+        if step % 100 == 99 {
+            cycles.push((stats.capacity, stats.pools_created));
+        }
+    }
+    
+    // Going through the same sizes again takes no more memory or pools
+    assert!(cycles.windows(2).all(|pair| pair[0] == pair[1]), "{:?}", cycles);
+    assert!(pool.stats().pools_created < 20, "{:?}", pool.stats());
+    
+    // Once the compositor lets go, buffers of sizes no longer drawn are all destroyed
+    session.release_held();
+    assert!(session.live_buffers() <= MAX_BUFFERS);
+    drop(pool);
+    session.release_held();
+    assert_eq!(session.live_buffers(), 0);
+}
\ No newline at end of file
@@ -0,0 +1,84 @@
+use myterm::sixel::{SixelDecoder, SixelImage, MAX_DIMENSION};
+use rgb::{RGB8, RGBA8};
+
+const BLACK: RGB8 = RGB8::new(0, 0, 0);
+
+fn decode(params: &[u16], data: &[u8]) -> Option<SixelImage> {
+    let mut decoder = SixelDecoder::new(params, BLACK);
+    for &byte in data {
+        decoder.put(byte);
+    }
+    decoder.finish()
+}
+
+#[test]
+fn test_sixel_single_column() {
+    // '~' sets all six pixels of the band in register 0, defined as red
+    let image = decode(&[0, 1], b"#0;2;100;0;0~").unwrap();
+    assert_eq!((image.width, image.height), (1, 6));
+    assert!(image.pixels.iter().all(|&p| p == RGBA8::new(255, 0, 0, 255)));
+}
+
+#[test]
+fn test_sixel_repeat_and_bands() {
+    // Three columns of the top pixel, then a second band with one column of the bottom pixel
+    let image = decode(&[0, 1], b"#1;2;0;100;0!3@-_").unwrap();
+    assert_eq!((image.width, image.height), (3, 12));
+    
+    let green = RGBA8::new(0, 255, 0, 255);
+    let clear = RGBA8::new(0, 0, 0, 0);
+    assert_eq!(&image.pixels[0..3], &[green; 3]);
+    assert_eq!(image.pixels[3], clear);
+    assert_eq!(image.pixels[11 * 3], green);
+    assert_eq!(image.pixels[11 * 3 + 1], clear);
+}
+
+#[test]
+fn test_sixel_carriage_return_overdraws() {
+    let image = decode(&[0, 1], b"#1;2;100;0;0@$#2;2;0;0;100@").unwrap();
+    assert_eq!((image.width, image.height), (1, 1));
+    assert_eq!(image.pixels[0], RGBA8::new(0, 0, 255, 255));
+}
+
+#[test]
+fn test_sixel_background() {
+    // P2 = 0 fills untouched pixels within what's drawn with the background
+    let background = RGB8::new(10, 20, 30);
+    let mut decoder = SixelDecoder::new(&[0, 0], background);
+    for &byte in b"#0@!2?A" {
+        decoder.put(byte);
+    }
+    let image = decoder.finish().unwrap();
+    
+    assert_eq!((image.width, image.height), (4, 2));
+    assert_eq!(image.pixels[0], RGBA8::new(0, 0, 0, 255));
+    assert_eq!(image.pixels[1], RGBA8::new(10, 20, 30, 255));
+    assert_eq!(image.pixels[4], RGBA8::new(10, 20, 30, 255));
+    assert_eq!(image.pixels[7], RGBA8::new(0, 0, 0, 255));
+}
+
+#[test]
+fn test_sixel_declared_size_not_allocated() {
+    // Raster attributes alone make no image, and don't pad a small one to their size
+    assert!(decode(&[0, 1], b"\"1;1;4096;4096").is_none());
+    let image = decode(&[0, 0], b"\"1;1;4096;4096#0@").unwrap();
+    assert_eq!((image.width, image.height), (1, 1));
+    assert_eq!(image.memory_usage(), 4);
+}
+
+#[test]
+fn test_sixel_hls_color() {
+    // Sixel hue 120 is red
+    let image = decode(&[0, 1], b"#3;1;120;50;100@").unwrap();
+    assert_eq!(image.pixels[0], RGBA8::new(255, 0, 0, 255));
+}
+
+#[test]
+fn test_sixel_empty_and_oversized() {
+    assert!(decode(&[], b"").is_none());
+    assert!(decode(&[], b"#1;2;0;0;0").is_none());
+    
+    // Huge repeat counts are clamped instead of allocating without bound
+    let image = decode(&[0, 1], b"!999999@").unwrap();
+    assert_eq!((image.width, image.height), (MAX_DIMENSION, 1));
+}
\ No newline at end of file
@@ -0,0 +1,31 @@
+use myterm::state::WindowState;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_window_state_round_trip() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let path = temp_dir.path().join("myterm").join("window.toml");
+    
+    let state = WindowState::new(1280, 720);
+    state.save_to(&path).expect("Failed to save window state");
+    assert_eq!(WindowState::load_from(&path).expect("Failed to load window state"), state);
+    
+    // Overwriting keeps only the latest size
+    WindowState::new(640, 480).save_to(&path).expect("Failed to save window state");
+    assert_eq!(WindowState::load_from(&path).unwrap(), WindowState::new(640, 480));
+}
+
+#[test]
+fn test_window_state_rejects_bad_files() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let path = temp_dir.path().join("window.toml");
+    
+    assert!(WindowState::load_from(&path).is_err());
+    
+    fs::write(&path, "width = 0\nheight = 600\n").unwrap();
+    assert!(WindowState::load_from(&path).is_err());
+    
+    fs::write(&path, "width = \"wide\"\n").unwrap();
+    assert!(WindowState::load_from(&path).is_err());
+}
\ No newline at end of file
@@ -1,5 +1,16 @@
-use myterm::config::Config;
-use myterm::terminal::{Cell, CellFlags, Grid};
+use myterm::config::{AmbiguousWidth, Config, CursorShape, ScrollbackStorage};
+use myterm::pacing::DamageWatch;
+use myterm::render::blink_text;
+use myterm::selection::{Point, Selection, SelectionMode};
+use myterm::terminal::{cwd_from_url, Cell, CellFlags, DiffToken, Grid, MarkKind, Mode, OutputDecoder, TerminalModes, TerminalPerformer, UnderlineStyle};
+use vte::Parser;
+
+fn feed(performer: &mut TerminalPerformer, bytes: &[u8]) {
+    let mut parser = Parser::new();
+    for &byte in bytes {
+        parser.advance(performer, byte);
+    }
+}
 
 #[test]
 fn test_cell_default() {
@@ -26,10 +37,10 @@ fn test_cell_flags() {
 
 #[test]
 fn test_grid_creation() {
-    let grid = Grid::new(24, 80, 1000);
+    let grid = Grid::new(24, 80, Some(1000));
     assert_eq!(grid.rows, 24);
     assert_eq!(grid.cols, 80);
-    assert_eq!(grid.scrollback_limit, 1000);
+    assert_eq!(grid.scrollback_limit, Some(1000));
     assert_eq!(grid.cells.len(), 24);
     assert_eq!(grid.cells[0].len(), 80);
     
@@ -44,7 +55,7 @@ fn test_grid_creation() {
 
 #[test]
 fn test_grid_resize() {
-    let mut grid = Grid::new(24, 80, 1000);
+    let mut grid = Grid::new(24, 80, Some(1000));
     
     // Resize to larger
     grid.resize(30, 120);
@@ -61,9 +72,61 @@ fn test_grid_resize() {
     assert_eq!(grid.cells[0].len(), 60);
 }
 
+#[test]
+fn test_shrink_keeps_cursor_line() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(24, 80, &config);
+    for line in 1..24 {
+        feed(&mut performer, format!("line {line}\r\n").as_bytes());
+    }
+    feed(&mut performer, b"$ ");
+    assert_eq!(performer.cursor.row, 23);
+    
+    // The lines above the prompt go to the scrollback, and erasing on it stays in bounds
+    performer.resize(20, 40);
+    assert_eq!((performer.cursor.row, performer.cursor.col), (19, 2));
+    assert_eq!(performer.grid.scrollback.len(), 4);
+    assert_eq!(performer.grid.cells[19][0].c, '$');
+    assert_eq!(performer.grid.cells[0][5].c, '5');
+    feed(&mut performer, b"\x1b[K\x1b[J");
+    
+    // Behind the alternate screen, the primary screen keeps the row saved on entering it
+    feed(&mut performer, b"\x1b[?1049h\x1b[20;1H");
+    performer.resize(10, 40);
+    assert_eq!(performer.cursor.row, 9);
+    assert_eq!(performer.saved_cursor.as_ref().map(|cursor| cursor.row), Some(9));
+    assert_eq!(performer.inactive_grid.cells[9][0].c, '$');
+    feed(&mut performer, b"\x1b[K\x1b[?1049l\x1b[K");
+    assert_eq!((performer.cursor.row, performer.cursor.col), (9, 2));
+    assert_eq!(performer.grid.scrollback.len(), 14);
+    assert_eq!(performer.grid.cells[9][0].c, '$');
+    
+    // A cursor past the new width is held at the last column
+    feed(&mut performer, b"\x1b[1;40H");
+    performer.resize(10, 20);
+    assert_eq!(performer.cursor.col, 19);
+    feed(&mut performer, b"\x1b[K");
+}
+
+#[test]
+fn test_grow_scrolls_at_new_bottom() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(24, 80, &config);
+    performer.resize(30, 80);
+    assert_eq!(performer.scroll_region, (0, 29));
+    
+    feed(&mut performer, b"\x1b[24;1Htwenty-four\r\n");
+    assert_eq!(performer.cursor.row, 24);
+    assert!(performer.grid.scrollback.is_empty());
+    feed(&mut performer, b"\x1b[30;1Hbottom\r\n");
+    assert_eq!(performer.cursor.row, 29);
+    assert_eq!(performer.grid.scrollback.len(), 1);
+    assert_eq!(performer.grid.cells[22][0].c, 't');
+}
+
 #[test]
 fn test_grid_scroll_up() {
-    let mut grid = Grid::new(3, 3, 10);
+    let mut grid = Grid::new(3, 3, Some(10));
     
     // Fill first row with 'A', second with 'B', third with 'C'
     for col in 0..3 {
@@ -84,13 +147,159 @@ fn test_grid_scroll_up() {
     // Scrollback should contain the original first row
     assert_eq!(grid.scrollback.len(), 1);
     for col in 0..3 {
-        assert_eq!(grid.scrollback[0][col].c, 'A');
+        assert_eq!(grid.scrollback.line(0)[col].c, 'A');
     }
 }
 
+#[test]
+fn test_grid_scrollback_disabled() {
+    let mut grid = Grid::new(2, 1, Some(0));
+    
+    for c in ['A', 'B', 'C'] {
+        grid.cells[1][0].c = c;
+        grid.scroll_up(1);
+    }
+    assert!(grid.scrollback.is_empty());
+    assert_eq!(grid.cells[0][0].c, 'C');
+    
+    // Nothing to scroll back to
+    grid.scroll_view_up(5);
+    assert_eq!(grid.display_offset, 0);
+    
+    grid.scroll_down(1);
+    assert_eq!(grid.cells[0][0].c, ' ');
+    assert_eq!(grid.cells[1][0].c, 'C');
+}
+
+#[test]
+fn test_grid_scrollback_unlimited() {
+    let mut grid = Grid::new(2, 1, None);
+    
+    for i in 0..20_000u32 {
+        grid.cells[1][0].c = char::from_digit(i % 10, 10).unwrap();
+        grid.scroll_up(1);
+    }
+    
+    // Nothing is evicted, the oldest line is still the initial blank row
+    assert_eq!(grid.scrollback.len(), 20_000);
+    assert_eq!(grid.scrollback.line(0)[0].c, ' ');
+    assert_eq!(grid.scrollback.line(1)[0].c, '0');
+    
+    grid.scroll_view_up(usize::MAX);
+    assert_eq!(grid.display_offset, 20_000);
+    assert_eq!(grid.visible_row(0)[0].c, ' ');
+}
+
+#[test]
+fn test_grid_scrollback_byte_budget() {
+    for storage in [ScrollbackStorage::Full, ScrollbackStorage::Compact] {
+        let mut grid = Grid::with_storage(2, 80, None, storage);
+        fill_scrollback(&mut grid, 200);
+        assert_eq!(grid.scrollback.len(), 200);
+        
+        // Unlimited scrollback drops its oldest lines once they take more than the budget,
+        // which here is what the first 200 took
+        grid.scrollback_budget = grid.scrollback.bytes();
+        fill_scrollback(&mut grid, 300);
+        assert!(grid.scrollback.bytes() <= grid.scrollback_budget);
+        assert!((190..=200).contains(&grid.scrollback.len()), "{} lines", grid.scrollback.len());
+        let oldest: String = grid.scrollback.line(0).iter().map(|cell| cell.c).collect();
+        assert!(oldest.starts_with("user@host:~/src$ ls -l file-"), "{}", oldest);
+        assert!(!oldest.starts_with("user@host:~/src$ ls -l file-0.txt"), "{}", oldest);
+        
+        // A line limit below the budget still applies
+        grid.scrollback_limit = Some(10);
+        fill_scrollback(&mut grid, 1);
+        assert_eq!(grid.scrollback.len(), 10);
+        grid.scrollback.clear();
+        assert_eq!(grid.scrollback.bytes(), 0);
+    }
+}
+
+#[test]
+fn test_grid_viewport_scrolling() {
+    let mut grid = Grid::new(2, 1, Some(10));
+    
+    for c in ['A', 'B', 'C', 'D'] {
+        grid.cells[1][0].c = c;
+        grid.scroll_up(1);
+    }
+    assert_eq!(grid.scrollback.len(), 4);
+    
+    // Cannot scroll past the oldest scrollback line
+    grid.scroll_view_up(10);
+    assert_eq!(grid.display_offset, 4);
+    
+    grid.scroll_view_down(3);
+    assert_eq!(grid.display_offset, 1);
+    assert_eq!(grid.visible_row(0)[0].c, 'C');
+    assert_eq!(grid.visible_row(1)[0].c, 'D');
+    
+    // New output keeps the scrolled-up view on the same content
+    grid.cells[1][0].c = 'E';
+    grid.scroll_up(1);
+    assert_eq!(grid.display_offset, 2);
+    assert_eq!(grid.visible_row(0)[0].c, 'C');
+    
+    grid.scroll_view_to_bottom();
+    assert_eq!(grid.display_offset, 0);
+    assert_eq!(grid.visible_row(0)[0].c, 'E');
+}
+
+/// Scrolls `lines` lines of shell-like output, a colored prompt followed by plain text, into history.
+fn fill_scrollback(grid: &mut Grid, lines: usize) {
+    let last = grid.rows - 1;
+    for i in 0..lines {
+        let text = format!("user@host:~/src$ ls -l file-{}.txt", i);
+        for (col, c) in text.chars().enumerate().take(grid.cols) {
+            grid.cells[last][col].c = c;
+            if col < 16 {
+                grid.cells[last][col].fg = rgb::RGB8::new(0, 205, 0);
+                grid.cells[last][col].flags = CellFlags::BOLD;
+            }
+        }
+        grid.scroll_up(1);
+    }
+}
+
+#[test]
+fn test_compact_scrollback_round_trip() {
+    let mut full = Grid::new(2, 80, None);
+    let mut compact = Grid::with_storage(2, 80, None, ScrollbackStorage::Compact);
+    fill_scrollback(&mut full, 10);
+    fill_scrollback(&mut compact, 10);
+    
+    // Text, colors and flags come back cell for cell, padded to the original width
+    assert_eq!(compact.scrollback.len(), full.scrollback.len());
+    for i in 0..full.scrollback.len() {
+        let (expected, actual) = (full.scrollback.line(i), compact.scrollback.line(i));
+        assert_eq!(actual.len(), 80);
+        for (a, b) in expected.iter().zip(actual.iter()) {
+            assert_eq!((a.c, a.fg, a.bg, a.flags), (b.c, b.fg, b.bg, b.flags));
+        }
+    }
+    
+    // Scrolling back down decodes the newest line onto the screen
+    compact.scroll_down(1);
+    assert_eq!(compact.cells[0][0].c, 'u');
+    assert_eq!(compact.cells[0][0].flags, CellFlags::BOLD);
+    assert_eq!(compact.scrollback.len(), 9);
+}
+
+#[test]
+fn test_scrollback_memory_usage() {
+    let mut full = Grid::new(2, 80, Some(1000));
+    let mut compact = Grid::with_storage(2, 80, Some(1000), ScrollbackStorage::Compact);
+    fill_scrollback(&mut full, 2000);
+    fill_scrollback(&mut compact, 2000);
+    assert_eq!(compact.scrollback.len(), 1000);
+    
+    assert!(compact.scrollback.memory_usage() * 4 < full.scrollback.memory_usage());
+}
+
 #[test]
 fn test_grid_clear() {
-    let mut grid = Grid::new(3, 3, 10);
+    let mut grid = Grid::new(3, 3, Some(10));
     
     // Fill with some data
     for row in 0..3 {
@@ -113,7 +322,7 @@ fn test_grid_clear() {
 
 #[test]
 fn test_grid_clear_line() {
-    let mut grid = Grid::new(3, 3, 10);
+    let mut grid = Grid::new(3, 3, Some(10));
     
     // Fill with some data
     for row in 0..3 {
@@ -132,6 +341,1100 @@ fn test_grid_clear_line() {
     }
 }
 
+#[test]
+fn test_erase_scrollback() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(3, 10, &config);
+    feed(&mut performer, b"one\r\ntwo\r\nthree\r\nfour\r\nfive");
+    assert_eq!(performer.grid.scrollback.len(), 2);
+    
+    // Scrolled up with a selection in the scrollback, as `clear -x` might find it
+    performer.grid.scroll_view_up(2);
+    let line = performer.grid.absolute_line(0);
+    performer.selection = Some(Selection::new(Point::new(line, 0), Point::new(line, 2)));
+    let screen = performer.grid.cells.clone();
+    
+    // ED 3 drops the scrollback and shows the live screen, which it leaves alone
+    feed(&mut performer, b"\x1b[3J");
+    assert_eq!(performer.grid.scrollback.len(), 0);
+    assert_eq!(performer.grid.display_offset, 0);
+    assert_eq!(performer.grid.cells, screen);
+    assert_eq!(performer.selection, None);
+    assert_eq!((performer.cursor.row, performer.cursor.col), (2, 4));
+    
+    // Output scrolls into a fresh scrollback
+    feed(&mut performer, b"\r\nsix");
+    assert_eq!(performer.grid.scrollback.len(), 1);
+    assert_eq!(performer.grid.line(performer.grid.first_line()).unwrap()[0].c, 't');
+}
+
+#[test]
+fn test_selective_erase() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(3, 10, &config);
+    let row = |performer: &TerminalPerformer, row: usize| {
+        performer.grid.cells[row].iter().map(|cell| cell.c).collect::<String>()
+    };
+    
+    // DECSCA protects what's written after it, through SGR resets
+    feed(&mut performer, b"ab\x1b[1\"qCD\x1b[0mE\x1b[0\"qfg\r\nhi\x1b[1\"qJ\x1b[2\"qk");
+    assert!(performer.grid.cells[0][4].flags.contains(CellFlags::PROTECTED));
+    assert!(!performer.grid.cells[0][5].flags.contains(CellFlags::PROTECTED));
+    
+    // DECSEL and DECSED skip protected cells
+    feed(&mut performer, b"\x1b[1;4H\x1b[?K");
+    assert_eq!(row(&performer, 0), "abCDE     ");
+    feed(&mut performer, b"\x1b[?1K");
+    assert_eq!(row(&performer, 0), "  CDE     ");
+    feed(&mut performer, b"\x1b[?2J");
+    assert_eq!(row(&performer, 0), "  CDE     ");
+    assert_eq!(row(&performer, 1), "  J       ");
+    
+    // ...while EL and ED erase them like any other
+    feed(&mut performer, b"\x1b[1;1H\x1b[K");
+    assert_eq!(row(&performer, 0), " ".repeat(10));
+    feed(&mut performer, b"\x1b[J");
+    assert_eq!(row(&performer, 1), " ".repeat(10));
+    
+    // DECRQSS reports the protection
+    feed(&mut performer, b"\x1b[1\"q\x1bP$q\"q\x1b\\");
+    assert_eq!(performer.responses, b"\x1bP1$r1\"q\x1b\\");
+}
+
+#[test]
+fn test_report_text_area_size() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(24, 80, &config);
+    
+    feed(&mut performer, b"\x1b[18t");
+    assert_eq!(performer.responses, b"\x1b[8;24;80t");
+    
+    performer.responses.clear();
+    performer.grid.resize(30, 100);
+    feed(&mut performer, b"\x1b[19t");
+    assert_eq!(performer.responses, b"\x1b[9;30;100t");
+}
+
+#[test]
+fn test_report_pixel_size() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(24, 80, &config);
+    performer.cell_width = 10;
+    performer.cell_height = 20;
+    
+    feed(&mut performer, b"\x1b[14t");
+    assert_eq!(performer.responses, b"\x1b[4;480;800t");
+    
+    // Window manipulation requests are ignored
+    performer.responses.clear();
+    feed(&mut performer, b"\x1b[8;50;200t\x1b[3;0;0t");
+    assert!(performer.responses.is_empty());
+}
+
+#[test]
+fn test_alternate_screen() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(3, 10, &config);
+    
+    feed(&mut performer, b"primary");
+    let cursor_col = performer.cursor.col;
+    
+    feed(&mut performer, b"\x1b[?1049h");
+    assert!(performer.modes.alt_screen);
+    assert_eq!(performer.grid.cells[0][0].c, ' ');
+    
+    feed(&mut performer, b"\x1b[Halt");
+    assert_eq!(performer.grid.cells[0][0].c, 'a');
+    
+    feed(&mut performer, b"\x1b[?1049l");
+    assert!(!performer.modes.alt_screen);
+    assert_eq!(performer.grid.cells[0][0].c, 'p');
+    assert_eq!(performer.cursor.col, cursor_col);
+    assert!(performer.grid.scrollback.is_empty());
+}
+
+#[test]
+fn test_alternate_screen_without_cursor_save() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(3, 10, &config);
+    feed(&mut performer, b"primary");
+    
+    // 47 and 1047 switch screens but leave the cursor where the program put it
+    for mode in [&b"47"[..], b"1047"] {
+        feed(&mut performer, &[b"\x1b[?", mode, b"h"].concat());
+        assert!(performer.modes.alt_screen);
+        feed(&mut performer, b"\x1b[2;3H");
+        feed(&mut performer, &[b"\x1b[?", mode, b"l"].concat());
+        assert!(!performer.modes.alt_screen);
+        assert_eq!((performer.cursor.row, performer.cursor.col), (1, 2));
+        assert_eq!(performer.grid.cells[0][0].c, 'p');
+    }
+    
+    // Scrolling the alternate screen keeps no history
+    feed(&mut performer, b"\x1b[?1049h\n\n\n\n");
+    assert!(performer.grid.scrollback.is_empty());
+}
+
+#[test]
+fn test_alternate_screen_switches() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(3, 10, &config);
+    feed(&mut performer, b"one\r\ntwo\r\nthree\r\nfour");
+    let primary = performer.grid.cells.clone();
+    let history = performer.grid.scrollback.len();
+    
+    // The primary screen comes back as it was, however the alternate one is used
+    for bytes in [&b""[..], b"\x1b[2;2Hedit", b"\x1b[Hx\r\n\r\n\r\n\r\n", b"\x1b[2J"] {
+        feed(&mut performer, b"\x1b[?1049h");
+        assert!(performer.grid.cells.iter().flatten().all(|cell| *cell == Cell::default()), "{:?}", bytes);
+        let token = performer.grid.token();
+        feed(&mut performer, bytes);
+        feed(&mut performer, b"\x1b[?1049l");
+        
+        assert_eq!(performer.grid.cells, primary);
+        assert_eq!(performer.grid.scrollback.len(), history);
+        // Whoever draws the screen starts over
+        assert!(performer.grid.diff_since(token).0.full_frame.is_some());
+    }
+}
+
+#[test]
+fn test_sgr_palette_colors() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(2, 10, &config);
+    let palette = performer.palette.clone();
+    
+    // Normal and bright colors, then back to the defaults with 39 and 49
+    feed(&mut performer, b"\x1b[31;44ma\x1b[93;105mb\x1b[39;49mc");
+    let row = &performer.grid.cells[0];
+    assert_eq!((row[0].fg, row[0].bg), (palette.normal[1], palette.normal[4]));
+    assert_eq!((row[1].fg, row[1].bg), (palette.bright[3], palette.bright[5]));
+    assert_eq!((row[2].fg, row[2].bg), (palette.foreground, palette.background));
+}
+
+#[test]
+fn test_sgr_extended_colors() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(1, 10, &config);
+    
+    // Direct and indexed colors in the colon and semicolon forms
+    feed(&mut performer, b"\x1b[38;2;1;2;3;48:2::4:5:6ma\x1b[38:5:196;48;5;9mb\x1b[39;49mc");
+    let cells = &performer.grid.cells[0];
+    assert_eq!((cells[0].fg, cells[0].bg), (rgb::RGB8::new(1, 2, 3), rgb::RGB8::new(4, 5, 6)));
+    assert_eq!((cells[1].fg, cells[1].bg), (rgb::RGB8::new(255, 0, 0), performer.palette.bright[1]));
+    assert!(cells[1].flags.contains(CellFlags::EXPLICIT_FG | CellFlags::EXPLICIT_BG));
+    assert_eq!((cells[2].fg, cells[2].bg), (performer.palette.foreground, performer.palette.background));
+    assert!(!cells[2].flags.intersects(CellFlags::EXPLICIT_FG | CellFlags::EXPLICIT_BG));
+    
+    // A malformed color leaves the current one alone
+    feed(&mut performer, b"\x1b[38;2;1;2;3m\x1b[38;7md");
+    assert_eq!(performer.grid.cells[0][3].fg, rgb::RGB8::new(1, 2, 3));
+}
+
+#[test]
+fn test_selection_colors() {
+    let red = rgb::RGB8::new(0x80, 0, 0);
+    let blue = rgb::RGB8::new(0, 0, 0x80);
+    let black = rgb::RGB8::new(0, 0, 0);
+    let white = rgb::RGB8::new(0xff, 0xff, 0xff);
+    
+    // Red on blue text followed by default-colored text
+    let mut config = Config::default();
+    let mut performer = TerminalPerformer::new(2, 10, &config);
+    feed(&mut performer, b"\x1b[31;44mab\x1b[0mcd");
+    performer.selection = Some(Selection::new(Point::new(0, 3), Point::new(0, 1)));
+    
+    // Without selection colors the selection is inverted
+    let snapshot = performer.styled_snapshot();
+    assert_eq!((snapshot[0][0].fg, snapshot[0][0].bg), (red, blue));
+    assert_eq!((snapshot[0][1].fg, snapshot[0][1].bg), (blue, red));
+    assert_eq!((snapshot[0][2].fg, snapshot[0][2].bg), (black, white));
+    assert_eq!((snapshot[0][4].fg, snapshot[0][4].bg), (white, black));
+    
+    // Fixed background, foreground kept from the cell
+    config.colors.selection_background = Some("#444444".to_string());
+    config.colors.selection_foreground = Some("CellForeground".to_string());
+    let mut performer = TerminalPerformer::new(2, 10, &config);
+    feed(&mut performer, b"\x1b[31;44mab\x1b[0mcd");
+    performer.selection = Some(Selection::new(Point::new(0, 1), Point::new(0, 2)));
+    
+    let gray = rgb::RGB8::new(0x44, 0x44, 0x44);
+    let snapshot = performer.styled_snapshot();
+    assert_eq!((snapshot[0][0].fg, snapshot[0][0].bg), (red, blue));
+    assert_eq!((snapshot[0][1].fg, snapshot[0][1].bg), (red, gray));
+    assert_eq!((snapshot[0][2].fg, snapshot[0][2].bg), (white, gray));
+    
+    // Background set to the cell's foreground gives a solid block of the text color
+    config.colors.selection_background = Some("CellForeground".to_string());
+    config.colors.selection_foreground = None;
+    let mut performer = TerminalPerformer::new(2, 10, &config);
+    feed(&mut performer, b"\x1b[31;44mab");
+    performer.selection = Some(Selection::new(Point::new(0, 0), Point::new(0, 0)));
+    
+    let snapshot = performer.styled_snapshot();
+    assert_eq!((snapshot[0][0].fg, snapshot[0][0].bg), (red, red));
+    assert_eq!((snapshot[0][1].fg, snapshot[0][1].bg), (red, blue));
+}
+
+#[test]
+fn test_selected_text() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(4, 10, &config);
+    feed(&mut performer, b"abcdefgh\r\nij\r\nklmnopq");
+    assert_eq!(performer.selected_text(), None);
+    
+    // Linewise runs to the end of each line and drops trailing blanks
+    performer.selection = Some(Selection::new(Point::new(2, 4), Point::new(0, 2)));
+    assert_eq!(performer.selected_text().as_deref(), Some("cdefgh\nij\nklmno"));
+    
+    // A block takes the same columns from every line, padding short ones
+    performer.selection = Some(Selection::rectangular(Point::new(2, 4), Point::new(0, 2)));
+    assert_eq!(performer.selected_text().as_deref(), Some("cde\n   \nmno"));
+    
+    // A single column
+    performer.selection = Some(Selection::rectangular(Point::new(0, 1), Point::new(2, 1)));
+    assert_eq!(performer.selected_text().as_deref(), Some("b\nj\nl"));
+}
+
+#[test]
+fn test_selected_text_trailing_whitespace() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(3, 10, &config);
+    // A line ending in a blue bar, and a plain one
+    feed(&mut performer, b"ab\x1b[44m   \x1b[0m\r\ncd");
+    performer.selection = Some(Selection::new(Point::new(0, 0), Point::new(1, 9)));
+    assert_eq!(performer.selected_text().as_deref(), Some("ab\ncd"));
+    
+    // The bar's blanks are kept, the default colored ones after it still trimmed
+    performer.keep_colored_trailing_whitespace = true;
+    assert_eq!(performer.selected_text().as_deref(), Some("ab   \ncd"));
+    
+    // Reverse video blanks show a color too
+    feed(&mut performer, b"\x1b[7m  \x1b[0m");
+    assert_eq!(performer.selected_text().as_deref(), Some("ab   \ncd  "));
+    
+    // Without trimming every line runs to the end of the selection
+    performer.trim_copy_trailing_whitespace = false;
+    assert_eq!(performer.selected_text().as_deref(), Some("ab        \ncd        "));
+    performer.keep_colored_trailing_whitespace = false;
+    assert_eq!(performer.selected_text().as_deref(), Some("ab        \ncd        "));
+}
+
+#[test]
+fn test_wide_characters() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(3, 5, &config);
+    let row = |performer: &TerminalPerformer, row: usize| -> Vec<(char, CellFlags)> {
+        performer.grid.cells[row].iter().map(|cell| (cell.c, cell.flags)).collect()
+    };
+    let (wide, spacer, blank) = (CellFlags::WIDE, CellFlags::WIDE_SPACER, CellFlags::empty());
+    
+    // Each takes two cells, and one that doesn't fit in the last column wraps whole
+    feed(&mut performer, "中a文字".as_bytes());
+    assert_eq!(row(&performer, 0), [('中', wide), (' ', spacer), ('a', blank), ('文', wide), (' ', spacer)]);
+    assert_eq!(row(&performer, 1)[..2], [('字', wide), (' ', spacer)]);
+    assert_eq!((performer.cursor.row, performer.cursor.col), (1, 2));
+    feed(&mut performer, "ab字".as_bytes());
+    assert_eq!(row(&performer, 1)[4], (' ', blank));
+    assert_eq!(row(&performer, 2)[..2], [('字', wide), (' ', spacer)]);
+    
+    // Writing over either half blanks the other
+    feed(&mut performer, b"\x1b[1;2Hx\x1b[1;4Hy");
+    assert_eq!(row(&performer, 0), [(' ', blank), ('x', blank), ('a', blank), ('y', blank), (' ', blank)]);
+    
+    // The spacer has the colors it was printed with
+    feed(&mut performer, "\x1b[3;1H\x1b[41m中".as_bytes());
+    assert_eq!(performer.grid.cells[2][1].bg, performer.grid.cells[2][0].bg);
+    assert_ne!(performer.grid.cells[2][1].bg, performer.palette.background);
+    
+    // Selecting either half selects the character, and copies it once
+    performer.selection = Some(Selection::new(Point::new(1, 1), Point::new(1, 3)));
+    assert_eq!(performer.selected_text().as_deref(), Some("字ab"));
+    let snapshot = performer.styled_snapshot();
+    assert_eq!(snapshot[1][0].bg, snapshot[1][1].bg);
+    assert_ne!(snapshot[1][0].bg, performer.palette.background);
+}
+
+#[test]
+fn test_selection_follows_scrolling() {
+    let mut config = Config::default();
+    config.terminal.scrollback_lines = Some(2);
+    config.colors.selection_background = Some("#444444".to_string());
+    let mut performer = TerminalPerformer::new(3, 10, &config);
+    let gray = rgb::RGB8::new(0x44, 0x44, 0x44);
+    let highlighted = |performer: &TerminalPerformer| -> Vec<usize> {
+        let snapshot = performer.styled_snapshot();
+        (0..3).filter(|&row| snapshot[row][0].bg == gray).collect()
+    };
+    
+    feed(&mut performer, b"one\r\ntwo\r\nthree");
+    let line = performer.grid.absolute_line(1);
+    performer.selection = Some(Selection::new(Point::new(line, 0), Point::new(line, 2)));
+    assert_eq!(highlighted(&performer), vec![1]);
+    
+    // Output scrolls the selection up with its text
+    feed(&mut performer, b"\r\nfour");
+    assert_eq!(highlighted(&performer), vec![0]);
+    assert_eq!(performer.selected_text().as_deref(), Some("two"));
+    
+    // And so does scrolling the view, which also brings it back from the scrollback
+    feed(&mut performer, b"\r\nfive");
+    assert_eq!(highlighted(&performer), Vec::<usize>::new());
+    performer.grid.scroll_view_up(1);
+    assert_eq!(highlighted(&performer), vec![0]);
+    assert_eq!(performer.selected_text().as_deref(), Some("two"));
+    performer.grid.scroll_view_to_bottom();
+    
+    // Once its text is dropped from the scrollback the selection goes too
+    feed(&mut performer, b"\r\nsix");
+    assert!(performer.selection.is_some());
+    feed(&mut performer, b"\r\nseven");
+    assert_eq!(performer.selection, None);
+    
+    // A selection partly dropped keeps what is left, from the start of the oldest line
+    let (first, last) = (performer.grid.first_line(), performer.grid.absolute_line(1));
+    performer.selection = Some(Selection::new(Point::new(last, 3), Point::new(first, 1)));
+    assert_eq!(performer.selected_text().as_deref(), Some("hree\nfour\nfive\nsix"));
+    feed(&mut performer, b"\r\n");
+    assert_eq!(performer.selection, Some(Selection::new(Point::new(last, 3), Point::new(first + 1, 0))));
+    assert_eq!(performer.selected_text().as_deref(), Some("four\nfive\nsix"));
+}
+
+#[test]
+fn test_rectangular_selection_highlight() {
+    let selection = Selection::rectangular(Point::new(3, 1), Point::new(1, 4));
+    assert_eq!(selection.mode, SelectionMode::Rectangular);
+    assert!(selection.contains(Point::new(2, 1)));
+    assert!(selection.contains(Point::new(1, 4)));
+    assert!(!selection.contains(Point::new(2, 5)));
+    assert!(!selection.contains(Point::new(2, 0)));
+    assert!(!selection.contains(Point::new(0, 2)));
+    assert_eq!(selection.columns(2, 10), Some((1, 4)));
+    assert_eq!(selection.columns(4, 10), None);
+    
+    // The same endpoints select whole middle lines linewise
+    let linewise = Selection::new(Point::new(3, 1), Point::new(1, 4));
+    assert!(linewise.contains(Point::new(2, 0)));
+    assert!(linewise.contains(Point::new(2, 9)));
+    assert!(!linewise.contains(Point::new(1, 3)));
+    assert_eq!(linewise.columns(1, 10), Some((4, 9)));
+    
+    // Only the block is drawn selected
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(4, 10, &config);
+    performer.selection = Some(selection);
+    let snapshot = performer.styled_snapshot();
+    let white = rgb::RGB8::new(0xff, 0xff, 0xff);
+    let highlighted: Vec<_> = (0..4)
+        .flat_map(|row| (0..10).map(move |col| (row, col)))
+        .filter(|&(row, col)| snapshot[row][col].bg == white)
+        .collect();
+    assert_eq!(highlighted.len(), 3 * 4);
+    assert!(highlighted.iter().all(|&(row, col)| (1..=3).contains(&row) && (1..=4).contains(&col)));
+}
+
+#[test]
+fn test_input_modes() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(2, 10, &config);
+    assert!(!performer.modes.application_cursor);
+    assert!(!performer.modes.bracketed_paste);
+    
+    feed(&mut performer, b"\x1b[?1h\x1b[?2004h");
+    assert!(performer.modes.application_cursor);
+    assert!(performer.modes.bracketed_paste);
+    
+    feed(&mut performer, b"\x1b[?1l\x1b[?2004l");
+    assert!(!performer.modes.application_cursor);
+    assert!(!performer.modes.bracketed_paste);
+}
+
+#[test]
+fn test_modes() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(4, 10, &config);
+    assert_eq!(performer.modes, TerminalModes::default());
+    
+    feed(&mut performer, b"\x1b[4h\x1b[?7;25l\x1b[?1002h\x1b[?1049h");
+    assert_eq!(performer.modes, TerminalModes {
+        insert: true,
+        auto_wrap: false,
+        cursor_visible: false,
+        mouse_reporting: true,
+        alt_screen: true,
+        ..TerminalModes::default()
+    });
+    
+    // DECRQM answers for ANSI modes as well as private ones
+    feed(&mut performer, b"\x1b[4$p\x1b[?7$p\x1b[20$p");
+    assert_eq!(performer.responses, b"\x1b[4;1$y\x1b[?7;2$y\x1b[20;0$y");
+    
+    feed(&mut performer, b"\x1b[4l\x1b[?7;25h\x1b[?1002l\x1b[?1049l");
+    assert_eq!(performer.modes, TerminalModes::default());
+    
+    // Setting a mode directly has the side effects of the sequence: origin mode homes the
+    // cursor to the scroll region, which positions then count from
+    performer.scroll_region = (1, 2);
+    performer.set_mode(Mode::Origin, true);
+    assert_eq!((performer.cursor.row, performer.cursor.col), (1, 0));
+    feed(&mut performer, b"\x1b[2;3H");
+    assert_eq!((performer.cursor.row, performer.cursor.col), (2, 2));
+    feed(&mut performer, b"\x1b[9;1H");
+    assert_eq!(performer.cursor.row, 2);
+    
+    performer.set_mode(Mode::AltScreen, true);
+    assert!(performer.modes.get(Mode::AltScreen));
+    assert_eq!(Mode::from_private(1047), Some(Mode::AltScreen));
+}
+
+#[test]
+fn test_synchronized_output() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(2, 10, &config);
+    
+    // Programs ask whether the mode is supported before using it
+    feed(&mut performer, b"\x1b[?2026$p");
+    assert_eq!(performer.responses, b"\x1b[?2026;2$y");
+    performer.responses.clear();
+    
+    // The grid keeps updating while drawing is held back
+    feed(&mut performer, b"\x1b[?2026hab");
+    assert!(performer.modes.synchronized_output);
+    assert_eq!(performer.grid.cells[0][1].c, 'b');
+    feed(&mut performer, b"\x1b[?2026$p\x1b[?9999$p");
+    assert_eq!(performer.responses, b"\x1b[?2026;1$y\x1b[?9999;0$y");
+    
+    feed(&mut performer, b"\x1b[?2026l");
+    assert!(!performer.modes.synchronized_output);
+}
+
+#[test]
+fn test_underline_styles() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(1, 10, &config);
+    
+    // Subparameters pick the style rather than being read as attributes of their own
+    feed(&mut performer, b"\x1b[4:3ma\x1b[21mb\x1b[4:0mc\x1b[4;9md\x1b[24;29me");
+    let cells = &performer.grid.cells[0];
+    assert_eq!((cells[0].flags, cells[0].underline), (CellFlags::UNDERLINE, UnderlineStyle::Curly));
+    assert_eq!((cells[1].flags, cells[1].underline), (CellFlags::UNDERLINE, UnderlineStyle::Double));
+    assert_eq!(cells[2].flags, CellFlags::empty());
+    assert_eq!((cells[3].flags, cells[3].underline), (CellFlags::UNDERLINE | CellFlags::STRIKETHROUGH, UnderlineStyle::Single));
+    assert_eq!(cells[4].flags, CellFlags::empty());
+}
+
+#[test]
+fn test_blink_attribute() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(1, 10, &config);
+    
+    feed(&mut performer, b"\x1b[5ma\x1b[25mb\x1b[1;6mc\x1b[0md");
+    let cells = &performer.grid.cells[0];
+    assert_eq!(cells[0].flags, CellFlags::BLINK);
+    assert_eq!(cells[1].flags, CellFlags::empty());
+    assert_eq!(cells[2].flags, CellFlags::BOLD | CellFlags::BLINK);
+    assert_eq!(cells[3].flags, CellFlags::empty());
+    
+    // The off phase hides blinking text and leaves the rest alone
+    let mut rows = performer.styled_snapshot();
+    assert!(blink_text(&mut rows, false));
+    assert_eq!(rows[0][0].fg, rows[0][0].bg);
+    assert_ne!(rows[0][1].fg, rows[0][1].bg);
+    let mut rows = performer.styled_snapshot();
+    assert!(blink_text(&mut rows, true));
+    assert_ne!(rows[0][0].fg, rows[0][0].bg);
+    
+    feed(&mut performer, b"\x1b[2J");
+    assert!(!blink_text(&mut performer.styled_snapshot(), false));
+    
+    // The 5 of an indexed color isn't a blink
+    feed(&mut performer, b"\x1b[H\x1b[38;5;196ma\x1b[48;5;5mb");
+    let cells = &performer.grid.cells[0];
+    assert!(!cells[0].flags.contains(CellFlags::BLINK));
+    assert!(!cells[1].flags.contains(CellFlags::BLINK));
+}
+
+#[test]
+fn test_underline_color() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(1, 10, &config);
+    
+    // Colon and semicolon forms, the parameters after a semicolon form still applying
+    feed(&mut performer, b"\x1b[58:2::1:2:3ma\x1b[58;2;4;5;6;1mb\x1b[58:5:196mc\x1b[58;5;9md\x1b[59me");
+    let cells = &performer.grid.cells[0];
+    assert_eq!(cells[0].underline_color, Some(rgb::RGB8::new(1, 2, 3)));
+    assert_eq!(cells[1].underline_color, Some(rgb::RGB8::new(4, 5, 6)));
+    assert!(cells[1].flags.contains(CellFlags::BOLD));
+    assert_eq!(cells[2].underline_color, Some(rgb::RGB8::new(255, 0, 0)));
+    assert_eq!(cells[3].underline_color, Some(performer.palette.bright[1]));
+    assert_eq!(cells[4].underline_color, None);
+    
+    // DECRQSS reports the style and color
+    feed(&mut performer, b"\x1b[0;4:3;58:2::1:2:3m\x1bP$qm\x1b\\");
+    assert_eq!(performer.responses, b"\x1bP1$r0;4:3;58:2::1:2:3m\x1b\\");
+}
+
+#[test]
+fn test_answerback() {
+    // Nothing is sent unless configured
+    let mut config = Config::default();
+    let mut performer = TerminalPerformer::new(1, 10, &config);
+    feed(&mut performer, b"\x05");
+    assert!(performer.responses.is_empty());
+    
+    config.terminal.answerback = "myterm".to_string();
+    let mut performer = TerminalPerformer::new(1, 10, &config);
+    feed(&mut performer, b"a\x05b");
+    assert_eq!(performer.responses, b"myterm");
+    assert_eq!(performer.grid.cells[0][1].c, 'b');
+}
+
+fn feed_decoded(performer: &mut TerminalPerformer, decoder: &mut OutputDecoder, bytes: &[u8]) {
+    let mut parser = Parser::new();
+    for &byte in bytes {
+        decoder.push(byte, |byte| parser.advance(performer, byte));
+    }
+}
+
+#[test]
+fn test_eight_bit_controls() {
+    let config = Config::default();
+    
+    // A raw 0x9B introduces a CSI
+    let mut performer = TerminalPerformer::new(1, 10, &config);
+    feed_decoded(&mut performer, &mut OutputDecoder::new(true), b"a\x9b5Cb");
+    assert_eq!(performer.grid.cells[0][0].c, 'a');
+    assert_eq!(performer.grid.cells[0][6].c, 'b');
+    
+    // The same byte continuing a UTF-8 character is left alone
+    let mut performer = TerminalPerformer::new(1, 10, &config);
+    feed_decoded(&mut performer, &mut OutputDecoder::new(true), "ě5C".as_bytes());
+    assert_eq!(performer.grid.cells[0][0].c, 'ě');
+    assert_eq!(performer.grid.cells[0][1].c, '5');
+    
+    // Disabled, C1 bytes are invalid UTF-8
+    let mut performer = TerminalPerformer::new(1, 10, &config);
+    feed_decoded(&mut performer, &mut OutputDecoder::new(false), b"a\x9b5Cb");
+    assert_eq!(text(&performer), "a\u{fffd}5Cb");
+}
+
+fn text(performer: &TerminalPerformer) -> String {
+    performer.grid.cells[0]
+        .iter()
+        .filter(|cell| !cell.flags.contains(CellFlags::WIDE_SPACER))
+        .map(|cell| cell.c)
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+#[test]
+fn test_invalid_utf8() {
+    let config = Config::default();
+    for (bytes, expected) in [
+        // Lone bytes that can't start a character
+        (&b"a\xffb"[..], "a\u{fffd}b"),
+        (b"a\x80b", "a\u{fffd}b"),
+        (b"a\xc0\xafb", "a\u{fffd}\u{fffd}b"),
+        // Truncated sequences, keeping whatever cut them short
+        (b"a\xe4\xb8b", "a\u{fffd}b"),
+        (b"a\xf0\x9f\x98b", "a\u{fffd}b"),
+        (b"a\xe4\xb8\xe4\xb8\xadb", "a\u{fffd}\u{4e2d}b"),
+        (b"a\xc4\x1b[Cb", "a\u{fffd} b"),
+        // Surrogates and code points past U+10FFFF have invalid continuation bytes
+        (b"a\xed\xa0\x80b", "a\u{fffd}\u{fffd}\u{fffd}b"),
+        (b"a\xf4\x90\x80\x80b", "a\u{fffd}\u{fffd}\u{fffd}\u{fffd}b"),
+        ("a\u{10ffff}ě".as_bytes(), "a\u{10ffff}ě"),
+    ] {
+        let mut performer = TerminalPerformer::new(1, 12, &config);
+        feed_decoded(&mut performer, &mut OutputDecoder::new(false), bytes);
+        assert_eq!(text(&performer), expected, "{:?}", bytes);
+    }
+    
+    // A character split between reads is still whole
+    let mut performer = TerminalPerformer::new(1, 12, &config);
+    let mut decoder = OutputDecoder::new(false);
+    feed_decoded(&mut performer, &mut decoder, b"a\xe4");
+    feed_decoded(&mut performer, &mut decoder, b"\xb8\xad");
+    assert_eq!(text(&performer), "a\u{4e2d}");
+    
+    // Titles keep what they can, and the semicolons in them
+    let mut performer = TerminalPerformer::new(1, 12, &config);
+    feed(&mut performer, b"\x1b]0;caf\xc3 ; menu\x07");
+    assert_eq!(performer.title, "caf\u{fffd} ; menu");
+}
+
+#[test]
+fn test_nul_and_del_are_ignored() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(1, 12, &config);
+    
+    // Neither takes a cell nor moves the cursor, in text or amid a sequence
+    feed(&mut performer, b"a\x00b\x7fc\x00\x7f\x1b[3\x7f1md");
+    assert_eq!(text(&performer), "abcd");
+    assert_eq!(performer.cursor.col, 4);
+    assert_eq!(performer.grid.cells[0][3].fg, performer.palette.normal[1]);
+    assert!(performer.grid.cells[0][4..].iter().all(|cell| cell.c == ' '));
+}
+
+#[test]
+fn test_title_changed() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(1, 12, &config);
+    assert!(!performer.title_changed);
+    
+    feed(&mut performer, b"\x1b]0;vim notes.txt\x07");
+    assert!(performer.title_changed);
+    assert_eq!(performer.title, "vim notes.txt");
+    
+    // Setting the same title again is no change
+    performer.title_changed = false;
+    feed(&mut performer, b"\x1b]0;vim notes.txt\x1b\\");
+    assert!(!performer.title_changed);
+    feed(&mut performer, b"\x1b]0;\x07");
+    assert!(performer.title_changed);
+}
+
+#[test]
+fn test_title_and_cwd_reports() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(2, 12, &config);
+    
+    // OSC 2 sets the title as OSC 0 does, and the alternate screen keeps it
+    feed(&mut performer, b"\x1b]2;htop\x07\x1b[?1049h");
+    assert_eq!(performer.title, "htop");
+    feed(&mut performer, b"\x1b]2;htop - busy\x07\x1b[?1049l");
+    assert_eq!(performer.title, "htop - busy");
+    
+    // CSI 22 t saves the title and CSI 23 t brings it back
+    feed(&mut performer, b"\x1b[22t\x1b]2;vim\x07");
+    performer.title_changed = false;
+    feed(&mut performer, b"\x1b[23t");
+    assert_eq!(performer.title, "htop - busy");
+    assert!(performer.title_changed);
+    feed(&mut performer, b"\x1b[23t");
+    assert_eq!(performer.title, "htop - busy");
+    
+    // OSC 7 reports the directory, without the host and percent-decoded
+    performer.title_changed = false;
+    feed(&mut performer, b"\x1b]7;file://laptop/home/me/my%20files\x1b\\");
+    assert_eq!(performer.cwd.as_deref(), Some("/home/me/my files"));
+    assert!(performer.title_changed);
+    feed(&mut performer, b"\x1b]7;not a url\x07");
+    assert_eq!(performer.cwd.as_deref(), Some("/home/me/my files"));
+    
+    assert_eq!(cwd_from_url("file:///tmp/%e4%b8%ad%2"), Some("/tmp/\u{4e2d}%2".to_string()));
+    assert_eq!(cwd_from_url("file://host"), None);
+    assert_eq!(cwd_from_url("http://host/tmp"), None);
+}
+
+#[test]
+fn test_reverse_video_snapshot() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(1, 10, &config);
+    feed(&mut performer, b"\x1b[7ma\x1b[0mb");
+    
+    let black = rgb::RGB8::new(0, 0, 0);
+    let white = rgb::RGB8::new(0xff, 0xff, 0xff);
+    let snapshot = performer.styled_snapshot();
+    assert_eq!((snapshot[0][0].fg, snapshot[0][0].bg), (black, white));
+    assert_eq!((snapshot[0][1].fg, snapshot[0][1].bg), (white, black));
+    
+    // Selecting reverse video text shows it the right way round again
+    performer.selection = Some(Selection::new(Point::new(0, 0), Point::new(0, 0)));
+    let snapshot = performer.styled_snapshot();
+    assert_eq!((snapshot[0][0].fg, snapshot[0][0].bg), (white, black));
+    
+    // Hidden text takes the background, faint text is halfway to it, and the applied
+    // attributes are cleared so the renderer doesn't apply them twice
+    feed(&mut performer, b"\x1b[8mc\x1b[28;2;7md\x1b[0m");
+    let snapshot = performer.styled_snapshot();
+    assert_eq!((snapshot[0][2].fg, snapshot[0][2].bg), (black, black));
+    assert!(snapshot[0][2].flags.contains(CellFlags::HIDDEN));
+    assert_eq!((snapshot[0][3].fg, snapshot[0][3].bg), (rgb::RGB8::new(0x80, 0x80, 0x80), white));
+    assert_eq!(snapshot[0][3].flags, CellFlags::empty());
+}
+
+#[test]
+fn test_reverse_screen_mode() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(1, 10, &config);
+    feed(&mut performer, b"a\x1b[7mb\x1b[0;31mc\x1b[0m");
+    let (black, white, red) = (rgb::RGB8::new(0, 0, 0), rgb::RGB8::new(0xff, 0xff, 0xff), performer.palette.normal[1]);
+    
+    // DECSCNM swaps the default colors, cells in other colors keeping theirs
+    feed(&mut performer, b"\x1b[?5h");
+    assert!(performer.modes.reverse_video);
+    let snapshot = performer.styled_snapshot();
+    assert_eq!((snapshot[0][0].fg, snapshot[0][0].bg), (black, white));
+    assert_eq!((snapshot[0][1].fg, snapshot[0][1].bg), (white, black));
+    assert_eq!((snapshot[0][2].fg, snapshot[0][2].bg), (red, white));
+    assert_eq!((snapshot[0][5].fg, snapshot[0][5].bg), (black, white));
+    
+    // Text set in black stays black, though that's the default background's color
+    feed(&mut performer, b"\x1b[30md\x1b[0m");
+    let snapshot = performer.styled_snapshot();
+    assert_eq!((snapshot[0][3].fg, snapshot[0][3].bg), (black, white));
+    let palette = performer.screen_palette();
+    assert_eq!((palette.foreground, palette.background), (black, white));
+    
+    // The stored cells are left alone, so resetting it shows them as before
+    assert_eq!((performer.grid.cells[0][0].fg, performer.grid.cells[0][0].bg), (white, black));
+    feed(&mut performer, b"\x1b[?5$p\x1b[?5l");
+    assert_eq!(performer.responses, b"\x1b[?5;1$y");
+    let snapshot = performer.styled_snapshot();
+    assert_eq!((snapshot[0][0].fg, snapshot[0][0].bg), (white, black));
+    assert_eq!(performer.screen_palette(), performer.palette);
+}
+
+#[test]
+fn test_selection_spans_lines() {
+    let selection = Selection::new(Point::new(2, 3), Point::new(0, 5));
+    
+    assert!(!selection.contains(Point::new(0, 4)));
+    assert!(selection.contains(Point::new(0, 5)));
+    assert!(selection.contains(Point::new(1, 0)));
+    assert!(selection.contains(Point::new(1, 79)));
+    assert!(selection.contains(Point::new(2, 3)));
+    assert!(!selection.contains(Point::new(2, 4)));
+}
+
+#[test]
+fn test_grid_diff_single_char() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(3, 10, &config);
+    let (_, token) = performer.grid.diff_since(DiffToken::default());
+    
+    feed(&mut performer, b"x");
+    let (diff, next) = performer.grid.diff_since(token);
+    assert!(diff.full_frame.is_none());
+    assert_eq!(diff.changes.len(), 1);
+    assert_eq!((diff.changes[0].row, diff.changes[0].col), (0, 0));
+    assert_eq!(diff.changes[0].cell.c, 'x');
+    
+    // Nothing changed since the returned token
+    assert!(next > token);
+    let (diff, again) = performer.grid.diff_since(next);
+    assert!(diff.changes.is_empty());
+    assert_eq!(again, next);
+}
+
+#[test]
+fn test_grid_diff_full_frame() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(3, 10, &config);
+    
+    // The default token always gets the whole screen
+    let (diff, token) = performer.grid.diff_since(DiffToken::default());
+    assert_eq!(diff.full_frame, Some((3, 10)));
+    assert_eq!(diff.changes.len(), 30);
+    
+    performer.resize(4, 12);
+    let (diff, token) = performer.grid.diff_since(token);
+    assert_eq!(diff.full_frame, Some((4, 12)));
+    assert_eq!(diff.changes.len(), 48);
+    
+    // Switching screens invalidates the consumer's copy too
+    feed(&mut performer, b"\x1b[?1049h");
+    let (diff, token) = performer.grid.diff_since(token);
+    assert_eq!(diff.full_frame, Some((4, 12)));
+    
+    feed(&mut performer, b"\x1b[?1049l");
+    let (diff, _) = performer.grid.diff_since(token);
+    assert!(diff.full_frame.is_some());
+}
+
+#[test]
+fn test_decscusr() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(24, 80, &config);
+    assert_eq!(performer.cursor.shape, CursorShape::Block);
+    assert!(performer.cursor.blink);
+    
+    feed(&mut performer, b"\x1b[4 q");
+    assert_eq!(performer.cursor.shape, CursorShape::Underline);
+    assert!(!performer.cursor.blink);
+    
+    feed(&mut performer, b"\x1b[5 q");
+    assert_eq!(performer.cursor.shape, CursorShape::Beam);
+    assert!(performer.cursor.blink);
+    
+    // Unknown styles are ignored, and 0 is a blinking block
+    feed(&mut performer, b"\x1b[9 q");
+    assert_eq!(performer.cursor.shape, CursorShape::Beam);
+    feed(&mut performer, b"\x1b[0 q");
+    assert_eq!(performer.cursor.shape, CursorShape::Block);
+    assert!(performer.cursor.blink);
+    
+    // Without the space intermediate this is not DECSCUSR
+    feed(&mut performer, b"\x1b[4q");
+    assert_eq!(performer.cursor.shape, CursorShape::Block);
+    
+    feed(&mut performer, b"\x1b[6 q\x1bP$q q\x1b\\");
+    assert_eq!(performer.responses, b"\x1bP1$r6 q\x1b\\");
+}
+
+#[test]
+fn test_decrqss() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(24, 80, &config);
+    
+    feed(&mut performer, b"\x1b[1;31m\x1bP$qm\x1b\\");
+    assert_eq!(performer.responses, b"\x1bP1$r0;1;31m\x1b\\");
+    performer.responses.clear();
+    
+    // Colors outside the palette are reported as direct RGB
+    feed(&mut performer, b"\x1b[22;48;2;1;2;3m\x1bP$qm\x1b\\");
+    assert_eq!(performer.responses, b"\x1bP1$r0;31;48;2;1;2;3m\x1b\\");
+    performer.responses.clear();
+    
+    feed(&mut performer, b"\x1bP$qr\x1b\\");
+    assert_eq!(performer.responses, b"\x1bP1$r1;24r\x1b\\");
+    performer.responses.clear();
+    
+    // Blinking block cursor by default
+    feed(&mut performer, b"\x1bP$q q\x1b\\");
+    assert_eq!(performer.responses, b"\x1bP1$r1 q\x1b\\");
+    performer.responses.clear();
+    
+    feed(&mut performer, b"\x1bP$qx\x1b\\");
+    assert_eq!(performer.responses, b"\x1bP0$r\x1b\\");
+}
+
+#[test]
+fn test_sixel_image_placement() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(10, 20, &config);
+    
+    // A 16x20 pixel image covers 2x2 cells of 8x16 pixels
+    feed(&mut performer, b"ab\x1bPq\"1;1;16;20#0~~~~~~~~~~~~~~~~-~~~~~~~~~~~~~~~~-~~~~~~~~~~~~~~~~-~~~~~~~~~~~~~~~~\x1b\\");
+    assert_eq!(performer.grid.images.len(), 1);
+    let image = &performer.grid.images[0];
+    assert_eq!((image.row, image.col, image.rows, image.cols), (0, 2, 2, 2));
+    assert_eq!((image.image.width, image.image.height), (16, 24));
+    
+    // The cursor continues below the image at the same column
+    assert_eq!((performer.cursor.row, performer.cursor.col), (2, 2));
+    
+    // Other DCS sequences are not images
+    feed(&mut performer, b"\x1bP$qm\x1b\\");
+    assert_eq!(performer.grid.images.len(), 1);
+}
+
+#[test]
+fn test_ambiguous_width() {
+    let mut config = Config::default();
+    let mut performer = TerminalPerformer::new(2, 10, &config);
+    
+    // Narrow by default: ± takes one cell, while CJK is wide and ASCII narrow either way
+    feed(&mut performer, "±".as_bytes());
+    assert_eq!(performer.cursor.col, 1);
+    assert!(!performer.grid.cells[0][0].flags.contains(CellFlags::WIDE));
+    feed(&mut performer, "中a".as_bytes());
+    assert_eq!(performer.cursor.col, 4);
+    
+    config.terminal.ambiguous_width = AmbiguousWidth::Wide;
+    let mut performer = TerminalPerformer::new(2, 10, &config);
+    feed(&mut performer, "±".as_bytes());
+    assert_eq!(performer.cursor.col, 2);
+    assert!(performer.grid.cells[0][0].flags.contains(CellFlags::WIDE));
+    assert!(performer.grid.cells[0][1].flags.contains(CellFlags::WIDE_SPACER));
+    feed(&mut performer, "中a".as_bytes());
+    assert_eq!(performer.cursor.col, 5);
+}
+
+#[test]
+fn test_device_attributes() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(4, 20, &config);
+    
+    // Sixel support is announced with the 4
+    feed(&mut performer, b"\x1b[c");
+    assert_eq!(performer.responses, b"\x1b[?62;4;22c");
+    performer.responses.clear();
+    feed(&mut performer, b"\x1b[0c");
+    assert_eq!(performer.responses, b"\x1b[?62;4;22c");
+    
+    // Secondary attributes aren't answered as primary ones
+    performer.responses.clear();
+    feed(&mut performer, b"\x1b[>c\x1b[1c");
+    assert!(performer.responses.is_empty());
+}
+
+#[test]
+fn test_sixel_image_eviction() {
+    let mut config = Config::default();
+    config.terminal.scrollback_lines = Some(2);
+    let mut performer = TerminalPerformer::new(4, 20, &config);
+    let sixel = b"\x1bPq#0~~-~~-~~-~~\x1b\\";
+    
+    // Scrolling moves the image up into the scrollback, until its last row leaves that too
+    feed(&mut performer, b"\x1b[2;1H");
+    feed(&mut performer, sixel);
+    assert_eq!(performer.grid.images[0].row, 1);
+    feed(&mut performer, b"\n\n");
+    assert_eq!(performer.grid.images[0].row, -1);
+    feed(&mut performer, b"\n\n");
+    assert_eq!(performer.grid.images[0].row, -3);
+    feed(&mut performer, b"\n");
+    assert!(performer.grid.images.is_empty());
+    
+    // Clearing the scrollback drops images that were only in it
+    feed(&mut performer, b"\x1b[1;1H");
+    feed(&mut performer, sixel);
+    feed(&mut performer, b"\x1b[4;1H\n\n");
+    assert_eq!(performer.grid.images[0].row, -2);
+    feed(&mut performer, b"\x1b[3J");
+    assert!(performer.grid.images.is_empty());
+    
+    // Erasing below the cursor drops images in that region only
+    feed(&mut performer, b"\x1b[1;1H");
+    feed(&mut performer, sixel);
+    feed(&mut performer, b"\x1b[4;1H\x1b[J");
+    assert_eq!(performer.grid.images.len(), 1);
+    feed(&mut performer, b"\x1b[2;1H\x1b[J");
+    assert!(performer.grid.images.is_empty());
+    
+    feed(&mut performer, sixel);
+    feed(&mut performer, b"\x1b[2J");
+    assert!(performer.grid.images.is_empty());
+}
+
+#[test]
+fn test_sixel_image_budget() {
+    let mut config = Config::default();
+    config.terminal.scrollback_lines = None;
+    let mut performer = TerminalPerformer::new(4, 20, &config);
+    // 2x24 pixels of four bytes each
+    let sixel = b"\x1bPq#0~~-~~-~~-~~\x1b\\";
+    performer.image_budget = 2 * 2 * 24 * 4;
+    
+    // Unlimited scrollback keeps every image until the budget drops the oldest
+    for col in 1..=3 {
+        feed(&mut performer, format!("\x1b[1;{col}H").as_bytes());
+        feed(&mut performer, sixel);
+    }
+    let cols: Vec<usize> = performer.grid.images.iter().map(|image| image.col).collect();
+    assert_eq!(cols, [1, 2]);
+    
+    // Images of the screen not shown go first
+    feed(&mut performer, b"\x1b[?1049h");
+    feed(&mut performer, sixel);
+    feed(&mut performer, b"\x1b[?1049l");
+    feed(&mut performer, sixel);
+    assert!(performer.inactive_grid.images.is_empty());
+    assert_eq!(performer.grid.images.len(), 2);
+    assert_eq!(performer.grid.images[0].col, 2);
+}
+
+#[test]
+fn test_min_window_size() {
+    use myterm::config::Padding;
+    use myterm::terminal::{window_size_for, MIN_COLUMNS, MIN_ROWS};
+    
+    assert_eq!(window_size_for(MIN_COLUMNS, MIN_ROWS, (8, 16), Padding::default()), (80, 48));
+    
+    let padding = Padding { top: 2, bottom: 4, left: 5, right: 7 };
+    assert_eq!(window_size_for(10, 3, (9, 18), padding), (102, 60));
+}
+
+#[test]
+fn test_grid_padding() {
+    use myterm::config::Padding;
+    use myterm::terminal::grid_padding;
+    
+    let sides = |top, bottom, left, right| Padding { top, bottom, left, right };
+    let none = Padding::default();
+    
+    // An exact fit leaves nothing over, centered or not
+    assert_eq!(grid_padding((800, 480), (8, 16), none, true), none);
+    
+    // What doesn't fit a cell stays along the right and bottom edges...
+    assert_eq!(grid_padding((805, 487), (8, 16), none, false), sides(0, 7, 0, 5));
+    // ...or is split between the sides, the odd pixel going right and down
+    assert_eq!(grid_padding((805, 487), (8, 16), none, true), sides(3, 4, 2, 3));
+    assert_eq!(grid_padding((807, 494), (8, 16), none, true), sides(7, 7, 3, 4));
+    
+    // Configured padding is kept, and the leftover is what remains inside it
+    let padding = sides(2, 4, 5, 7);
+    assert_eq!(grid_padding((102, 60), (9, 18), padding, true), padding);
+    assert_eq!(grid_padding((110, 75), (9, 18), padding, true), sides(9, 12, 9, 11));
+    
+    // A window narrower than a cell still has one column, with nothing to center
+    assert_eq!(grid_padding((5, 10), (8, 16), none, true), none);
+    assert_eq!(grid_padding((0, 0), (8, 16), sides(4, 4, 4, 4), true), sides(4, 4, 4, 4));
+}
+
+#[test]
+fn test_grid_geometry() {
+    use myterm::config::Padding;
+    use myterm::terminal::{grid_geometry, grid_padding};
+    
+    let cell_size = (9, 18);
+    let paddings = [
+        Padding::default(),
+        Padding { top: 2, bottom: 4, left: 5, right: 7 },
+        Padding { top: 40, bottom: 40, left: 40, right: 40 },
+    ];
+    for padding in paddings {
+        for centered in [false, true] {
+            for width in (0..400).step_by(7) {
+                for height in (0..300).step_by(11) {
+                    let geometry = grid_geometry((width, height), cell_size, padding, centered);
+                    let context = format!("{}x{} in {:?}, centered {}", width, height, padding, centered);
+                    assert_eq!(geometry.padding, grid_padding((width, height), cell_size, padding, centered), "{}", context);
+                    assert!(geometry.padding.left >= padding.left && geometry.padding.top >= padding.top, "{}", context);
+                    
+                    // Windows with room for a cell are filled exactly by the cells and padding,
+                    // with less than a cell left over; smaller ones still get a cell
+                    let (cols, rows) = (geometry.cols as u32, geometry.rows as u32);
+                    if width >= padding.horizontal() + cell_size.0 {
+                        assert_eq!(cols * cell_size.0 + geometry.padding.horizontal(), width, "{}", context);
+                        assert!(geometry.padding.horizontal() - padding.horizontal() < cell_size.0, "{}", context);
+                    } else {
+                        assert_eq!(cols, 1, "{}", context);
+                    }
+                    if height >= padding.vertical() + cell_size.1 {
+                        assert_eq!(rows * cell_size.1 + geometry.padding.vertical(), height, "{}", context);
+                        assert!(geometry.padding.vertical() - padding.vertical() < cell_size.1, "{}", context);
+                    } else {
+                        assert_eq!(rows, 1, "{}", context);
+                    }
+                }
+            }
+        }
+    }
+    
+    // Degenerate cells and huge windows clamp rather than dividing by zero or overflowing
+    let geometry = grid_geometry((100, 100), (0, 0), Padding::default(), true);
+    assert_eq!((geometry.cols, geometry.rows), (100, 100));
+    let geometry = grid_geometry((u32::MAX, u32::MAX), (1, 1), Padding::default(), false);
+    assert_eq!((geometry.cols, geometry.rows), (u16::MAX, u16::MAX));
+}
+
+#[test]
+fn test_snap_to_cells() {
+    use myterm::config::Padding;
+    use myterm::terminal::{grid_padding, snap_to_cells};
+    
+    // Sizes round down to whole cells inside the padding, and whole ones stay
+    let padding = Padding { top: 2, bottom: 4, left: 5, right: 7 };
+    assert_eq!(snap_to_cells((812, 490), (8, 16), padding), (812, 486));
+    assert_eq!(snap_to_cells((819, 501), (8, 16), padding), (812, 486));
+    assert_eq!(snap_to_cells((800, 480), (8, 16), Padding::default()), (800, 480));
+    
+    // Nothing is left over to pad once snapped
+    for width in 20..200 {
+        let size = snap_to_cells((width, width), (9, 18), padding);
+        assert!(size.0 <= width && size.1 <= width);
+        assert_eq!(grid_padding(size, (9, 18), padding, true), padding, "{}", width);
+    }
+    
+    // Sizes with no room for a cell, the padding taken out, are kept
+    assert_eq!(snap_to_cells((19, 21), (8, 16), padding), (19, 21));
+    assert_eq!(snap_to_cells((0, 0), (0, 0), Padding::default()), (0, 0));
+}
+
 #[cfg(test)]
 mod integration_tests {
     use super::*;
@@ -151,4 +1454,446 @@ mod integration_tests {
         let result = terminal.resize(1024, 768);
         assert!(result.is_ok());
     }
+    
+    #[tokio::test]
+    async fn test_terminal_resize_with_padding() {
+        use myterm::terminal::{window_size_for, MIN_COLUMNS, MIN_ROWS};
+        
+        let mut config = Config::default();
+        config.display.padding = myterm::config::Padding { top: 8, bottom: 8, left: 12, right: 4 };
+        let mut terminal = myterm::terminal::Terminal::new(&config).unwrap();
+        
+        // 8x16 cells: (816 - 16) / 8 columns, (416 - 16) / 16 rows
+        terminal.resize(816, 416).unwrap();
+        assert_eq!(terminal.grid().cols, 100);
+        assert_eq!(terminal.grid().rows, 25);
+        
+        // Padding larger than the window clamps to a single cell
+        terminal.resize(10, 10).unwrap();
+        assert_eq!(terminal.grid().cols, 1);
+        assert_eq!(terminal.grid().rows, 1);
+        
+        // The minimum window size, as the display sets it, fits exactly the minimum grid
+        let (width, height) = window_size_for(MIN_COLUMNS, MIN_ROWS, terminal.cell_size(), config.display.padding);
+        terminal.resize(width, height).unwrap();
+        assert_eq!(terminal.grid().cols, 10);
+        assert_eq!(terminal.grid().rows, 3);
+        
+        // A geometry computed elsewhere, as by the display, is taken as it is
+        let padding = myterm::config::Padding { top: 1, bottom: 2, left: 3, right: 4 };
+        terminal.set_geometry(myterm::terminal::GridGeometry { cols: 42, rows: 7, padding }).unwrap();
+        assert_eq!((terminal.grid().cols, terminal.grid().rows), (42, 7));
+        assert_eq!(terminal.grid_padding(), padding);
+    }
+    
+    #[tokio::test]
+    async fn test_query_responses() {
+        let config = Config::default();
+        let mut terminal = myterm::terminal::Terminal::new(&config).unwrap();
+        
+        // The cursor position report counts from 1, and nothing is left after draining
+        terminal.process(b"\x1b[5;12H\x1b[6n");
+        assert_eq!(terminal.drain_responses(), b"\x1b[5;12R");
+        assert!(terminal.drain_responses().is_empty());
+        
+        // Replies to several queries come back in order
+        terminal.process(b"\x1b[5n\x1b[c\x1b[?2026$p\x1b[?6n");
+        assert_eq!(terminal.drain_responses(), b"\x1b[0n\x1b[?62;4;22c\x1b[?2026;2$y\x1b[?5;12;1R");
+        
+        // In origin mode rows count from the top of the scroll region
+        terminal.process(b"\x1b[3;20r\x1b[?6h\x1b[2;1H\x1b[6n");
+        assert_eq!(terminal.drain_responses(), b"\x1b[2;1R");
+        terminal.process(b"\x1b[?6l\x1b[1;75Hhello\x1b[6n");
+        assert_eq!(terminal.drain_responses(), b"\x1b[1;80R");
+        
+        // Output with no queries in it has nothing to reply
+        terminal.process(b"plain text\r\n");
+        assert!(terminal.drain_responses().is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_prompt_marks() {
+        let mut config = Config::default();
+        config.terminal.scrollback_lines = Some(100);
+        let commands = |terminal: &mut myterm::terminal::Terminal| {
+            // On 24 rows, three commands of a prompt line and 20 lines of output each, then a prompt
+            for n in 0..3 {
+                terminal.process(format!("\x1b]133;A\x07$ \x1b]133;B\x07cmd{}\r\n\x1b]133;C\x07", n).as_bytes());
+                terminal.process("out\r\n".repeat(20).as_bytes());
+                terminal.process(b"\x1b]133;D;1\x07");
+            }
+            terminal.process(b"\x1b]133;A\x07$ ");
+        };
+        let mut terminal = myterm::terminal::Terminal::new(&config).unwrap();
+        commands(&mut terminal);
+        assert_eq!(terminal.prompt_marks(), [0, 21, 42, 63]);
+        let kinds: Vec<MarkKind> = terminal.grid().marks().filter(|mark| mark.line == 63).map(|mark| mark.kind).collect();
+        assert_eq!(kinds, [MarkKind::Finished(Some(1)), MarkKind::Prompt]);
+        
+        // Marks go with the lines dropped from the scrollback
+        config.terminal.scrollback_lines = Some(30);
+        let mut terminal = myterm::terminal::Terminal::new(&config).unwrap();
+        commands(&mut terminal);
+        assert_eq!(terminal.prompt_marks(), [21, 42, 63]);
+        
+        // Clearing the screen and prompting at the top drops the marks on it, and the
+        // alternate screen marks nothing
+        terminal.process(b"\x1b[2J\x1b[H\x1b]133;A\x07$ \x1b[?1049h\x1b]133;A\x07\x1b[?1049l");
+        assert_eq!(terminal.prompt_marks(), [21, 40]);
+    }
+    
+    #[tokio::test]
+    async fn test_jump_to_prompt() {
+        let mut config = Config::default();
+        config.terminal.scrollback_lines = Some(100);
+        let mut terminal = myterm::terminal::Terminal::new(&config).unwrap();
+        // On 24 rows, three commands of a prompt line and 20 lines of output each, then a prompt
+        for n in 0..3 {
+            terminal.process(format!("\x1b]133;A\x07$ cmd{}\r\n", n).as_bytes());
+            terminal.process("out\r\n".repeat(20).as_bytes());
+        }
+        terminal.process(b"\x1b]133;A\x07$ ");
+        assert_eq!(terminal.prompt_marks(), [0, 21, 42, 63]);
+        
+        // Jumping back puts each earlier prompt at the top of the view, stopping at the first
+        let top = |terminal: &myterm::terminal::Terminal| terminal.grid().absolute_line(0);
+        assert_eq!(top(&terminal), 40);
+        for expected in [21, 0, 0] {
+            terminal.jump_to_prompt(true);
+            assert_eq!(top(&terminal), expected);
+        }
+        
+        // Jumping forward comes back down, to the live screen past the last prompt
+        for expected in [21, 40, 40] {
+            terminal.jump_to_prompt(false);
+            assert_eq!(top(&terminal), expected);
+        }
+        assert_eq!(terminal.grid().display_offset, 0);
+    }
+    
+    #[tokio::test]
+    async fn test_clear_all() {
+        let mut config = Config::default();
+        config.terminal.scrollback_lines = Some(100);
+        let mut terminal = myterm::terminal::Terminal::new(&config).unwrap();
+        terminal.process(b"\x1b]133;A\x07$ ");
+        for n in 0..40 {
+            terminal.process(format!("line {}\r\n", n).as_bytes());
+        }
+        terminal.process(b"$ ");
+        terminal.scroll_view_page_up();
+        assert!(!terminal.grid().scrollback.is_empty());
+        
+        // The action, as bound by default, wipes everything, marks and selection included
+        let defaults = myterm::config::KeyBinding::defaults();
+        let bound = defaults.iter().find(|binding| binding.key == "Ctrl+Shift+K");
+        assert_eq!(bound.map(|binding| &binding.action), Some(&myterm::input::Action::ClearAll));
+        terminal.clear_all();
+        let grid = terminal.grid();
+        assert_eq!((grid.scrollback.len(), grid.display_offset), (0, 0));
+        assert!(terminal.styled_snapshot().iter().flatten().all(|cell| cell.c == ' '));
+        assert!(terminal.prompt_marks().is_empty());
+        assert_eq!((terminal.cursor().row, terminal.cursor().col), (0, 0));
+        
+        // The shell, told nothing, carries on from the top
+        terminal.process(b"next");
+        assert_eq!(terminal.grid().cells[0][0].c, 'n');
+        
+        // The primary screen's scrollback goes too when cleared from the alternate screen
+        terminal.process(&b"line\r\n".repeat(40));
+        terminal.process(b"\x1b[?1049hfull screen");
+        terminal.clear_all();
+        terminal.process(b"\x1b[?1049l");
+        assert_eq!(terminal.grid().scrollback.len(), 0);
+    }
+    
+    #[tokio::test]
+    async fn test_jump_to_prompt_without_marks() {
+        let mut config = Config::default();
+        config.terminal.scrollback_lines = Some(100);
+        let mut terminal = myterm::terminal::Terminal::new(&config).unwrap();
+        terminal.process("out\r\n".repeat(40).as_bytes());
+        terminal.scroll_view_page_up();
+        let top = terminal.grid().absolute_line(0);
+        
+        // Output from a shell without OSC 133 leaves the view where it is either way
+        assert!(terminal.prompt_marks().is_empty());
+        terminal.jump_to_prompt(true);
+        assert_eq!(terminal.grid().absolute_line(0), top);
+        terminal.jump_to_prompt(false);
+        assert_eq!(terminal.grid().absolute_line(0), top);
+        
+        // The jumps are there to bind, but nothing binds them by default
+        let jumps = myterm::config::KeyBinding::defaults().into_iter().filter(|binding| matches!(binding.action, myterm::input::Action::JumpToPrevPrompt | myterm::input::Action::JumpToNextPrompt));
+        assert_eq!(jumps.count(), 0);
+    }
+    
+    #[tokio::test]
+    async fn test_terminal_font_size() {
+        let config = Config::default();
+        let mut terminal = myterm::terminal::Terminal::new(&config).unwrap();
+        assert_eq!(terminal.cell_size(), (8, 16));
+        
+        // 9pt is 12px at 96 DPI, so moving to that output fits more cells in the same window
+        terminal.set_font_size(9.0);
+        assert_eq!(terminal.cell_size(), (6, 12));
+        terminal.resize(816, 416).unwrap();
+        assert_eq!(terminal.grid().cols, 136);
+        assert_eq!(terminal.grid().rows, 34);
+    }
+    
+    #[tokio::test]
+    async fn test_terminal_apply_config() {
+        let mut config = Config::default();
+        let mut terminal = myterm::terminal::Terminal::new(&config).unwrap();
+        
+        config.colors.background = "#202020".to_string();
+        config.display.padding = myterm::config::Padding::uniform(8);
+        config.terminal.scrollback_lines = None;
+        terminal.apply_config(&config);
+        terminal.resize(816, 416).unwrap();
+        
+        assert_eq!(terminal.palette().background, rgb::RGB8::new(0x20, 0x20, 0x20));
+        assert_eq!(terminal.grid().scrollback_limit, None);
+        assert_eq!(terminal.grid().cols, 100);
+        assert_eq!(terminal.grid().rows, 25);
+        
+        // Shrinking the scrollback waits for a restart
+        config.terminal.scrollback_lines = Some(10);
+        terminal.apply_config(&config);
+        assert_eq!(terminal.grid().scrollback_limit, None);
+        
+        // A cursor style the program set survives reloads that leave the cursor settings alone
+        terminal.process(b"\x1b[6 q");
+        terminal.apply_config(&config);
+        assert_eq!((terminal.cursor().shape.clone(), terminal.cursor().blink), (CursorShape::Beam, false));
+        config.terminal.cursor_shape = CursorShape::Underline;
+        terminal.apply_config(&config);
+        assert_eq!((terminal.cursor().shape.clone(), terminal.cursor().blink), (CursorShape::Underline, true));
+    }
+    
+    #[tokio::test]
+    async fn test_key_event_reaches_terminal() {
+        use myterm::display::{Event, HandlerEvents};
+        use myterm::input::Key;
+        
+        let mut config = Config::default();
+        config.terminal.shell = Some("/bin/cat".to_string());
+        let mut terminal = myterm::terminal::Terminal::new(&config).unwrap();
+        terminal.start_shell(&config).await.unwrap();
+        
+        // The Wayland keyboard handler sends key presses on the channel the display hands
+        // events out from
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut events = HandlerEvents::new(receiver);
+        sender.send(Event::Key(Key::char('x'))).unwrap();
+        
+        match events.pop() {
+            Some(Event::Key(key)) => assert!(terminal.handle_key(&key).await.unwrap()),
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert!(events.pop().is_none());
+        
+        // The PTY echoes the key back, so it ends up in the grid
+        let echoed = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while terminal.grid().cells[0][0].c != 'x' {
+                if terminal.next_output().await.unwrap().is_none() {
+                    break;
+                }
+            }
+        }).await;
+        
+        assert!(echoed.is_ok());
+        assert_eq!(terminal.grid().cells[0][0].c, 'x');
+    }
+    
+    #[tokio::test]
+    async fn test_send_text_and_key() {
+        use myterm::input::{BindingMode, Key, KeyCode, Modifiers};
+        
+        let mut config = Config::default();
+        config.terminal.shell = Some("/bin/cat".to_string());
+        let mut terminal = myterm::terminal::Terminal::new(&config).unwrap();
+        terminal.start_shell(&config).await.unwrap();
+        
+        // cat writes the line back once it's complete, switching on application cursor mode
+        terminal.send_text("\x1b[?1h").await.unwrap();
+        terminal.send_key(Key::new(KeyCode::Enter, Modifiers::empty())).await.unwrap();
+        let switched = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while !terminal.binding_mode().contains(BindingMode::APP_CURSOR) {
+                if terminal.next_output().await.unwrap().is_none() {
+                    break;
+                }
+            }
+        }).await;
+        
+        assert!(switched.is_ok());
+        assert!(terminal.binding_mode().contains(BindingMode::APP_CURSOR));
+    }
+    
+    #[tokio::test]
+    async fn test_write_after_cancelled_read() {
+        let mut config = Config::default();
+        config.terminal.shell = Some("/bin/cat".to_string());
+        let mut terminal = myterm::terminal::Terminal::new(&config).unwrap();
+        terminal.start_shell(&config).await.unwrap();
+        
+        // The event loop drops a pending read whenever another event wins the select
+        let pending = tokio::time::timeout(std::time::Duration::from_millis(50), terminal.next_output()).await;
+        assert!(pending.is_err());
+        
+        terminal.write_to_pty(b"y").await.unwrap();
+        let echoed = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while terminal.grid().cells[0][0].c != 'y' {
+                if terminal.next_output().await.unwrap().is_none() {
+                    break;
+                }
+            }
+        }).await;
+        
+        assert!(echoed.is_ok());
+        assert_eq!(terminal.grid().cells[0][0].c, 'y');
+    }
+}
+
+/// Stands in for the display, drawing whenever the main loop's `DamageWatch` asks for a frame.
+#[derive(Default)]
+struct CountingDisplay {
+    damage: DamageWatch,
+    renders: usize,
+}
+
+impl CountingDisplay {
+    fn draw_if_changed(&mut self, performer: &mut TerminalPerformer) {
+        if self.damage.needs_frame(performer.damage_generation()) {
+            self.renders += 1;
+        }
+    }
+}
+
+#[test]
+fn test_render_only_on_damage() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(5, 20, &config);
+    let mut display = CountingDisplay::default();
+    
+    // The first frame is drawn, and an idle loop draws nothing more
+    display.draw_if_changed(&mut performer);
+    display.draw_if_changed(&mut performer);
+    assert_eq!(display.renders, 1);
+    
+    // A key press only goes to the PTY, so nothing is drawn until its echo is parsed, which is
+    // drawn on the next pass through the loop
+    display.draw_if_changed(&mut performer);
+    assert_eq!(display.renders, 1);
+    feed(&mut performer, b"l");
+    display.draw_if_changed(&mut performer);
+    assert_eq!(display.renders, 2);
+    
+    // Echoes parsed together are drawn together
+    feed(&mut performer, b"s");
+    feed(&mut performer, b"\r\n");
+    display.draw_if_changed(&mut performer);
+    assert_eq!(display.renders, 3);
+    
+    // Output that leaves the screen as it was draws nothing: a query, or moving the cursor away
+    // and back
+    feed(&mut performer, b"\x1b[6n\x1b[4;4H\x1b[2;1H");
+    display.draw_if_changed(&mut performer);
+    assert_eq!(display.renders, 3);
+    
+    // The view, the selection and the colors, as a config reload sets them, are drawn when they
+    // change
+    feed(&mut performer, b"\r\n\r\n\r\n\r\n");
+    display.draw_if_changed(&mut performer);
+    performer.grid.scroll_view_up(1);
+    display.draw_if_changed(&mut performer);
+    performer.selection = Some(Selection::new(Point::new(0, 0), Point::new(0, 1)));
+    display.draw_if_changed(&mut performer);
+    performer.palette.background = rgb::RGB8::new(16, 32, 48);
+    display.draw_if_changed(&mut performer);
+    assert_eq!(display.renders, 7);
+    display.draw_if_changed(&mut performer);
+    assert_eq!(display.renders, 7);
+}
+
+#[test]
+fn test_pan_over_line_past_right_margin() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(24, 80, &config);
+    let line: String = (0..200).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+    feed(&mut performer, format!("\x1b[?7l{}", line).as_bytes());
+    
+    // The grid shows the last character in the last column, and the line keeps the rest
+    let snapshot = performer.styled_snapshot();
+    assert_eq!(snapshot[0][79].c, line.chars().nth(199).unwrap());
+    assert_eq!(snapshot[1][0].c, ' ');
+    let full: String = performer.grid.full_row(0).iter().map(|cell| cell.c).collect();
+    assert_eq!(full, line);
+    assert_eq!(performer.grid.full_width(), 200);
+    
+    // Panning right reveals column 150, and stops where the line ends
+    performer.grid.scroll_view_right(40);
+    performer.grid.scroll_view_right(40);
+    assert_eq!(performer.grid.col_offset, 80);
+    assert_eq!(performer.styled_snapshot()[0][70].c, line.chars().nth(150).unwrap());
+    performer.grid.scroll_view_right(80);
+    assert_eq!(performer.grid.col_offset, 120);
+    assert_eq!(performer.styled_snapshot()[0][79].c, line.chars().nth(199).unwrap());
+    performer.grid.scroll_view_left(200);
+    assert_eq!(performer.grid.col_offset, 0);
+    
+    // Writing in the columns standing in for the rest of the line draws it anew
+    feed(&mut performer, b"\r\x1b[K");
+    assert_eq!(performer.grid.full_width(), 80);
+}
+
+#[test]
+fn test_line_past_right_margin_kept_in_scrollback() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(3, 10, &config);
+    let line: String = (0..30).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+    feed(&mut performer, format!("\x1b[?7l{}\r\n\n\n\n", line).as_bytes());
+    
+    let kept: String = performer.grid.line(0).unwrap().iter().map(|cell| cell.c).collect();
+    assert_eq!(kept, line);
+    
+    // Scrolled up to it, the line is cut at the right edge and can be panned over
+    performer.grid.scroll_view_up(2);
+    let row: String = performer.grid.panned_row(0).iter().map(|cell| cell.c).collect();
+    assert_eq!(row, line[..10]);
+    assert_eq!(performer.grid.full_width(), 30);
+    performer.grid.scroll_view_right(20);
+    let row: String = performer.grid.panned_row(0).iter().map(|cell| cell.c).collect();
+    assert_eq!(row, line[20..]);
+    
+    // Brought back onto the screen, it shows its latest character in the last column again
+    performer.grid.scroll_view_to_bottom();
+    performer.grid.scroll_view_left(20);
+    performer.grid.scroll_down(2);
+    assert_eq!(performer.grid.cells[0][9].c, line.chars().last().unwrap());
+    let full: String = performer.grid.full_row(0).iter().map(|cell| cell.c).collect();
+    assert_eq!(full, line);
+}
+
+#[test]
+fn test_line_past_right_margin_is_capped() {
+    use myterm::terminal::MAX_LINE_COLUMNS;
+    
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(3, 10, &config);
+    feed(&mut performer, b"\x1b[?7l");
+    feed(&mut performer, &vec![b'a'; 3 * MAX_LINE_COLUMNS]);
+    assert_eq!(performer.grid.full_width(), MAX_LINE_COLUMNS);
+    
+    // Past the cap the last column shows the latest character, wide ones taking the last two
+    feed(&mut performer, b"b");
+    let full = performer.grid.full_row(0);
+    assert_eq!(full.len(), MAX_LINE_COLUMNS);
+    assert_eq!((full[MAX_LINE_COLUMNS - 2].c, full[MAX_LINE_COLUMNS - 1].c), ('a', 'b'));
+    feed(&mut performer, "中".as_bytes());
+    let full = performer.grid.full_row(0);
+    assert_eq!(full.len(), MAX_LINE_COLUMNS);
+    assert_eq!(full[MAX_LINE_COLUMNS - 2].c, '中');
+    assert_eq!(full[MAX_LINE_COLUMNS - 3].c, 'a');
 }
\ No newline at end of file
@@ -0,0 +1,81 @@
+use myterm::config::Config;
+use myterm::terminal::TerminalPerformer;
+use myterm::pacing::Throttle;
+use myterm::title::{format_title, shows_command, TitleParts};
+use std::time::{Duration, Instant};
+use vte::Parser;
+
+#[test]
+fn test_format_title() {
+    let parts = TitleParts { title: "notes.txt", cwd: Some("/home/me/src"), command: Some("vim") };
+    assert_eq!(format_title("{command}: {title} in {cwd}", &parts, "MyTerm"), "vim: notes.txt in /home/me/src");
+    
+    // What isn't known is left out, and unknown placeholders are kept
+    let parts = TitleParts { title: "", cwd: Some("~"), command: None };
+    assert_eq!(format_title("{command} {cwd}", &parts, "MyTerm"), " ~");
+    assert_eq!(format_title("{host} {cwd} {", &parts, "MyTerm"), "{host} ~ {");
+    
+    // A title that comes out blank falls back on the static one
+    let parts = TitleParts::default();
+    assert_eq!(format_title("{title} {cwd}", &parts, "MyTerm"), "MyTerm");
+    assert_eq!(format_title("", &parts, "MyTerm"), "MyTerm");
+    
+    assert!(shows_command("{command} — MyTerm"));
+    assert!(!shows_command("{title}"));
+}
+
+#[test]
+fn test_title_throttle() {
+    let interval = Duration::from_millis(100);
+    let mut throttle = Throttle::new(interval);
+    let start = Instant::now();
+    
+    // The first title shows right away, and the same one again isn't shown twice
+    assert_eq!(throttle.update("one".to_string(), start), Some("one".to_string()));
+    assert_eq!(throttle.update("one".to_string(), start + interval), None);
+    assert_eq!(throttle.deadline(), None);
+    
+    // Titles coming faster are held back, the latest one showing once it's due
+    let soon = start + interval + Duration::from_millis(10);
+    assert_eq!(throttle.update("two".to_string(), soon), Some("two".to_string()));
+    assert_eq!(throttle.update("three".to_string(), soon + Duration::from_millis(10)), None);
+    assert_eq!(throttle.update("four".to_string(), soon + Duration::from_millis(20)), None);
+    assert_eq!(throttle.deadline(), Some(soon + interval));
+    assert_eq!(throttle.flush(soon + Duration::from_millis(50)), None);
+    assert_eq!(throttle.flush(soon + interval), Some("four".to_string()));
+    assert_eq!(throttle.deadline(), None);
+    assert_eq!(throttle.flush(soon + interval * 3), None);
+    
+    // Going back to the title shown drops the one held back
+    let later = soon + interval + Duration::from_millis(10);
+    assert_eq!(throttle.update("five".to_string(), later), None);
+    assert_eq!(throttle.update("four".to_string(), later), None);
+    assert_eq!(throttle.deadline(), None);
+}
+
+#[test]
+fn test_title_burst_shows_last() {
+    let config = Config::default();
+    let mut performer = TerminalPerformer::new(2, 20, &config);
+    let mut parser = Parser::new();
+    let mut throttle = Throttle::new(Duration::from_millis(config.display.title_interval_ms));
+    let start = Instant::now();
+    
+    // A progress bar setting the title every millisecond changes the window's a few times
+    let mut shown = Vec::new();
+    for percent in 0..=250 {
+        for &byte in format!("\x1b]0;{}%\x07", percent).as_bytes() {
+            parser.advance(&mut performer, byte);
+        }
+        let now = start + Duration::from_millis(percent);
+        shown.extend(throttle.flush(now));
+        if std::mem::take(&mut performer.title_changed) {
+            shown.extend(throttle.update(performer.title.clone(), now));
+        }
+    }
+    assert_eq!(shown, ["0%", "99%", "199%"]);
+    
+    // Once the updates stop, the last one is still applied
+    let deadline = throttle.deadline().unwrap();
+    assert_eq!(throttle.flush(deadline), Some("250%".to_string()));
+}
\ No newline at end of file
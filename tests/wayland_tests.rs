@@ -0,0 +1,159 @@
+use myterm::config::{DecorationMode, Decorations};
+use myterm::display::{effective_output, Event, HandlerEvents, OutputInfo};
+use myterm::input::Key;
+use myterm::wayland::{draws_titlebar, pointer_icon, requested_decorations, window_title, CursorChange, PointerCursor};
+use smithay_client_toolkit::seat::pointer::CursorIcon;
+use smithay_client_toolkit::shell::xdg::window::{DecorationMode as Granted, WindowDecorations};
+use std::process::Command;
+use tempfile::tempdir;
+
+#[test]
+fn test_missing_display_is_reported_as_such() {
+    // A runtime directory without any compositor socket in it, given only to the child so
+    // tests running alongside keep their environment
+    let dir = tempdir().expect("Failed to create temp dir");
+    let output = Command::new(env!("CARGO_BIN_EXE_myterm"))
+        .env_remove("WAYLAND_DISPLAY")
+        .env_remove("WAYLAND_SOCKET")
+        .env_remove("MYTERM_CONFIG")
+        .env("XDG_RUNTIME_DIR", dir.path())
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("HOME", dir.path())
+        .output()
+        .expect("Failed to run myterm");
+    
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("no Wayland display available"), "{}", stderr);
+    assert!(stderr.contains("--headless"), "{}", stderr);
+}
+
+#[test]
+fn test_window_title() {
+    // A title the program set wins over whatever runs
+    assert_eq!(window_title("~/src", Some("vim"), "MyTerm"), "~/src");
+    assert_eq!(window_title("", Some("vim"), "MyTerm"), "vim — MyTerm");
+    assert_eq!(window_title("", None, "MyTerm"), "MyTerm");
+}
+
+#[test]
+fn test_decoration_negotiation() {
+    // Server decorations are asked for unless we draw them, or want none at all
+    assert_eq!(requested_decorations(Decorations::Full, DecorationMode::Auto), WindowDecorations::RequestServer);
+    assert_eq!(requested_decorations(Decorations::Full, DecorationMode::Server), WindowDecorations::RequestServer);
+    assert_eq!(requested_decorations(Decorations::Full, DecorationMode::Client), WindowDecorations::RequestClient);
+    assert_eq!(requested_decorations(Decorations::None, DecorationMode::Auto), WindowDecorations::RequestClient);
+    
+    // The titlebar falls back on what the compositor granted, which may override the request
+    assert!(!draws_titlebar(Decorations::Full, DecorationMode::Auto, Granted::Server));
+    assert!(draws_titlebar(Decorations::Full, DecorationMode::Auto, Granted::Client));
+    assert!(draws_titlebar(Decorations::Full, DecorationMode::Client, Granted::Client));
+    assert!(!draws_titlebar(Decorations::Full, DecorationMode::Client, Granted::Server));
+    
+    // Insisting on server decorations, or none, never draws one
+    assert!(!draws_titlebar(Decorations::Full, DecorationMode::Server, Granted::Client));
+    assert!(!draws_titlebar(Decorations::None, DecorationMode::Auto, Granted::Client));
+}
+
+#[test]
+fn test_pointer_icon() {
+    // An arrow over our titlebar, and an I-beam over the text
+    assert_eq!(pointer_icon((50.0, 10.0), 24), CursorIcon::Default);
+    assert_eq!(pointer_icon((50.0, 24.0), 24), CursorIcon::Text);
+    assert_eq!(pointer_icon((0.0, 0.0), 0), CursorIcon::Text);
+}
+
+#[test]
+fn test_pointer_cursor_hides_while_typing() {
+    let mut cursor = PointerCursor::default();
+    let show = |icon| Some(CursorChange::Show(icon));
+    
+    // Typing with the pointer elsewhere has nothing to hide
+    assert_eq!(cursor.typed(), None);
+    
+    // Entering always sets a cursor, and moving only when what it's over changes
+    assert_eq!(cursor.enter((10.0, 50.0), 24), show(CursorIcon::Text));
+    assert_eq!(cursor.motion((20.0, 60.0), 24), None);
+    assert_eq!(cursor.motion((20.0, 10.0), 24), show(CursorIcon::Default));
+    assert_eq!(cursor.motion((20.0, 60.0), 24), show(CursorIcon::Text));
+    
+    // Typing hides it once, and moving brings back the shape for where it is
+    assert_eq!(cursor.typed(), Some(CursorChange::Hide));
+    assert_eq!(cursor.typed(), None);
+    assert_eq!(cursor.motion((20.0, 10.0), 24), show(CursorIcon::Default));
+    
+    // So do buttons and the wheel, which otherwise change nothing
+    assert_eq!(cursor.used(24), None);
+    assert_eq!(cursor.typed(), Some(CursorChange::Hide));
+    assert_eq!(cursor.used(24), show(CursorIcon::Default));
+    
+    // Losing focus brings it back too, as typing then goes to another window
+    assert_eq!(cursor.unfocused(24), None);
+    assert_eq!(cursor.typed(), Some(CursorChange::Hide));
+    assert_eq!(cursor.unfocused(24), show(CursorIcon::Default));
+    
+    // Leaving while hidden forgets it, and entering again shows the cursor
+    assert_eq!(cursor.typed(), Some(CursorChange::Hide));
+    cursor.leave();
+    assert_eq!(cursor.unfocused(24), None);
+    assert_eq!(cursor.used(24), None);
+    assert_eq!(cursor.enter((5.0, 5.0), 0), show(CursorIcon::Text));
+}
+
+#[test]
+fn test_handler_events_delivered_in_order() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let mut events = HandlerEvents::new(receiver);
+    assert!(events.pop().is_none());
+    
+    // Everything one dispatch sends comes out, in the order it was sent
+    for event in [Event::Key(Key::char('l')), Event::Key(Key::char('s')), Event::Resize(800, 600), Event::Key(Key::char('\r'))] {
+        sender.send(event).unwrap();
+    }
+    assert!(events.resize_pending());
+    assert!(matches!(events.pop(), Some(Event::Key(key)) if key == Key::char('l')));
+    assert!(matches!(events.pop(), Some(Event::Key(key)) if key == Key::char('s')));
+    assert!(matches!(events.pop(), Some(Event::Resize(800, 600))));
+    assert!(!events.resize_pending());
+    
+    // Events sent while some are still queued go after them
+    sender.send(Event::Focus(false)).unwrap();
+    assert!(matches!(events.pop(), Some(Event::Key(key)) if key == Key::char('\r')));
+    assert!(matches!(events.pop(), Some(Event::Focus(false))));
+    assert!(events.pop().is_none());
+}
+
+fn output(name: &str, physical_size: (i32, i32), mode_size: (i32, i32), scale_factor: i32) -> OutputInfo {
+    OutputInfo { name: Some(name.to_string()), physical_size, mode_size, scale_factor }
+}
+
+#[test]
+fn test_effective_output_as_outputs_enter_and_leave() {
+    let laptop = output("eDP-1", (310, 174), (3840, 2160), 2);
+    let external = output("HDMI-A-1", (531, 299), (1920, 1080), 1);
+    let projector = output("DP-2", (0, 0), (1920, 1080), 1);
+    
+    let mut entered = Vec::new();
+    assert_eq!(effective_output(&entered), None);
+    
+    entered.push(external.clone());
+    assert_eq!(effective_output(&entered), Some(&external));
+    
+    // Dragged halfway onto the laptop panel, the higher scale wins whichever came first
+    entered.push(laptop.clone());
+    assert_eq!(effective_output(&entered), Some(&laptop));
+    entered.retain(|output| output != &external);
+    assert_eq!(effective_output(&entered), Some(&laptop));
+    entered.insert(0, external.clone());
+    assert_eq!(effective_output(&entered), Some(&laptop));
+    
+    // Back down to scale 1, and between outputs of equal scale the one entered last
+    entered.retain(|output| output != &laptop);
+    assert_eq!(effective_output(&entered), Some(&external));
+    entered.push(projector.clone());
+    assert_eq!(effective_output(&entered), Some(&projector));
+    entered.remove(1);
+    assert_eq!(effective_output(&entered), Some(&external));
+    entered.clear();
+    assert_eq!(effective_output(&entered), None);
+}
\ No newline at end of file